@@ -2,26 +2,63 @@ use aorist_primitives::AOption;
 use aorist_primitives::AVec;
 mod ancestor_record;
 mod assignment_target;
+#[cfg(feature = "python")]
 mod extract_arg;
+mod python_printer;
+mod r_printer;
 mod string_literal;
 
 pub use ancestor_record::*;
 pub use assignment_target::*;
+#[cfg(feature = "python")]
 pub use extract_arg::*;
+pub use python_printer::*;
+pub use r_printer::*;
 pub use string_literal::*;
 
 use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
 use abi_stable::std_types::{RArc, ROption};
 use aorist_derive::Optimizable;
+#[cfg(feature = "r")]
 use aorist_extendr_api::prelude::*;
 use aorist_primitives::AString;
 use aorist_primitives::{define_ast_node, register_ast_nodes};
 use linked_hash_map::LinkedHashMap;
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
+#[cfg(feature = "python")]
 use pyo3::types::{PyList, PyModule, PyString, PyTuple};
 use std::collections::VecDeque;
 use std::hash::{Hash, Hasher};
 
+/// Braced R block running the statements in order.
+#[cfg(feature = "r")]
+fn r_block(statements: &AVec<AST>, depth: usize) -> Result<Robj> {
+    Ok(r_call(
+        "{",
+        statements
+            .iter()
+            .map(|x| x.to_r_ast_node(depth))
+            .collect::<Result<Vec<_>>>()?,
+    ))
+}
+/// R call of the function or operator `name`.
+#[cfg(feature = "r")]
+fn r_call(name: &str, args: Vec<Robj>) -> Robj {
+    let values = vec![r!(Symbol::from_string(name))]
+        .into_iter()
+        .chain(args.into_iter())
+        .collect::<Vec<_>>();
+    r!(Language::from_values(&values))
+}
+/// R language object parsed from the node's R source, for nodes defining
+/// functions, whose formal arguments are easier to parse than to build.
+/// Fails with R's parse error if the source is not valid R.
+#[cfg(feature = "r")]
+fn r_parsed(node: &AST) -> Result<Robj> {
+    call!("str2lang", node.to_r_code().trim_end())
+}
+
 define_ast_node!(
     ImportNode,
     |import: &ImportNode| vec![import.inner.clone()].into_iter().collect(),
@@ -29,10 +66,10 @@ define_ast_node!(
         import.to_python_ast_node(py, ast_module, depth)
     },
     |import: &ImportNode, depth: usize| {
-        r!(Language::from_values(&[
+        Ok(r!(Language::from_values(&[
             r!(Symbol::from_string("library")),
-            import.inner.to_r_ast_node(depth)
-        ]))
+            import.inner.to_r_ast_node(depth)?
+        ])))
     },
     inner: AST,
 );
@@ -47,10 +84,6 @@ define_ast_node!(
         let body_ast = for_loop
             .body
             .iter()
-            .map(|x| match &x {
-                AST::Assignment(_) | AST::Expression(_) => x,
-                _ => panic!("AST node of type {} found in for loop body", x.name()),
-            })
             .map(|x| x.to_python_ast_node(py, ast_module, depth + 1))
             .collect::<PyResult<Vec<_>>>()?;
         let body_list = PyList::new(py, body_ast);
@@ -68,27 +101,76 @@ define_ast_node!(
             let res = make_lang("call");
             let mut tail = res.get();
             tail = append_with_name(tail, r!("for"), "name");
-            tail = append(tail, for_loop.target.to_r_ast_node(depth));
-            tail = append(tail, for_loop.iter.to_r_ast_node(depth));
+            tail = append(tail, for_loop.target.to_r_ast_node(depth)?);
+            tail = append(tail, for_loop.iter.to_r_ast_node(depth)?);
 
             let body = make_lang("call");
             let mut body_tail = body.get();
             body_tail = append_with_name(body_tail, r!("{"), "name");
             for x in for_loop.body.iter() {
-                body_tail = append(body_tail, x.to_r_ast_node(depth));
+                body_tail = append(body_tail, x.to_r_ast_node(depth)?);
             }
             let _ = body_tail;
 
             tail = append(tail, body);
 
             let _ = tail;
-            res
+            Ok(res)
         }
     },
     target: AST,
     iter: AST,
     body: AVec<AST>,
 );
+define_ast_node!(
+    With,
+    |with: &With| with
+        .items
+        .clone()
+        .into_iter()
+        .chain(with.body.clone().into_iter())
+        .collect(),
+    |with: &With, py: Python, ast_module: &'a PyModule, depth: usize| {
+        let items = with
+            .items
+            .iter()
+            .map(|x| {
+                ast_module.getattr("withitem")?.call1((
+                    x.to_python_ast_node(py, ast_module, depth)?,
+                    py.None().as_ref(py),
+                ))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let body = with
+            .body
+            .iter()
+            .map(|x| x.to_python_ast_node(py, ast_module, depth + 1))
+            .collect::<PyResult<Vec<_>>>()?;
+        ast_module
+            .getattr("With")?
+            .call1((PyList::new(py, items), PyList::new(py, body)))
+    },
+    // R has no context managers: the items are evaluated at the start of a
+    // local scope running the body. Their cleanup is dropped: nothing runs
+    // when the body exits, whether normally or on an error.
+    |with: &With, depth: usize| {
+        let block = r_block(
+            &with
+                .items
+                .iter()
+                .cloned()
+                .chain(with.body.iter().cloned())
+                .collect(),
+            depth,
+        )?;
+        Ok(r!(Language::from_values(&[
+            r!(Symbol::from_string("local")),
+            block
+        ])))
+    },
+    items: AVec<AST>,
+    body: AVec<AST>,
+);
 define_ast_node!(
     Assignment,
     |assign: &Assignment| vec![assign.target.clone(), assign.call.clone()]
@@ -117,10 +199,10 @@ define_ast_node!(
             let res = make_lang("call");
             let mut tail = res.get();
             tail = append_with_name(tail, r!("<-"), "name");
-            tail = append(tail, assign.target.to_r_ast_node(depth));
-            tail = append(tail, assign.call.to_r_ast_node(depth));
+            tail = append(tail, assign.target.to_r_ast_node(depth)?);
+            tail = append(tail, assign.call.to_r_ast_node(depth)?);
             let _ = tail;
-            res
+            Ok(res)
         }
     },
     target: AST,
@@ -162,11 +244,11 @@ define_ast_node!(
             AST::Add(_) => "+",
             _ => panic!("AST node not supported as R operator"),
         };
-        r!(Language::from_values(&[
+        Ok(r!(Language::from_values(&[
             r!(Symbol::from_string(op_str)),
-            binop.left.to_r_ast_node(depth),
-            binop.right.to_r_ast_node(depth)
-        ]))
+            binop.left.to_r_ast_node(depth)?,
+            binop.right.to_r_ast_node(depth)?
+        ])))
     },
     left: AST,
     op: AST,
@@ -197,9 +279,9 @@ define_ast_node!(
             .elems
             .iter()
             .map(|x| x.to_r_ast_node(depth))
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>>>()?;
         elems.insert(0, r!(Symbol::from_string("list")));
-        r!(Language::from_values(&elems))
+        Ok(r!(Language::from_values(&elems)))
     },
     elems: AVec<AST>,
     store: bool,
@@ -241,11 +323,11 @@ define_ast_node!(
             .elems
             .values()
             .map(|x| x.to_r_ast_node(depth))
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>>>()?;
         let obj = r!(aorist_extendr_api::List::from_values(&elems));
         obj.set_names(dict.elems.keys().map(|x| x.as_str()))
             .unwrap();
-        obj
+        Ok(obj)
     },
     elems: LinkedHashMap<AString, AST>,
 );
@@ -367,13 +449,13 @@ define_ast_node!(
             let mut tail = res.get();
             tail = append_with_name(tail, r!(fn_name.as_str()), "name");
             for arg in call.args.iter() {
-                tail = append(tail, arg.to_r_ast_node(depth));
+                tail = append(tail, arg.to_r_ast_node(depth)?);
             }
             for (k, v) in &call.keywords {
-                tail = append_with_name(tail, v.to_r_ast_node(depth), k.as_str());
+                tail = append_with_name(tail, v.to_r_ast_node(depth)?, k.as_str());
             }
             let _ = tail;
-            res
+            Ok(res)
         }
     },
     function: AST,
@@ -424,12 +506,12 @@ define_ast_node!(
             let res = make_lang("call");
             let mut tail = res.get();
             tail = append_with_name(tail, r!("glue"), "name");
-            tail = append(tail, formatted.fmt.to_r_ast_node(depth));
+            tail = append(tail, formatted.fmt.to_r_ast_node(depth)?);
             for (k, v) in &formatted.keywords {
-                tail = append_with_name(tail, v.to_r_ast_node(depth), k.as_str());
+                tail = append_with_name(tail, v.to_r_ast_node(depth)?, k.as_str());
             }
             let _ = tail;
-            res
+            Ok(res)
         }
     },
     fmt: AST,
@@ -453,8 +535,8 @@ define_ast_node!(
         ast_module.getattr("Subscript")?.call1((value, idx, mode))
     },
     |subscript: &Subscript, depth: usize| {
-        let a_node = subscript.a.to_r_ast_node(depth);
-        let b_node = subscript.b.to_r_ast_node(depth);
+        let a_node = subscript.a.to_r_ast_node(depth)?;
+        let b_node = subscript.b.to_r_ast_node(depth)?;
         unsafe {
             let res = make_lang("call");
             let mut tail = res.get();
@@ -462,7 +544,7 @@ define_ast_node!(
             tail = append(tail, a_node);
             tail = append(tail, b_node);
             let _ = tail;
-            res
+            Ok(res)
         }
     },
     a: AST,
@@ -488,7 +570,7 @@ define_ast_node!(
             .call1((PyString::new(py, simple_identifier.name.as_str()).as_ref(),))
     },
     |simple_identifier: &SimpleIdentifier, _depth: usize| {
-        call!("call", r!("as.name"), r!(simple_identifier.name.as_str())).unwrap()
+        call!("call", r!("as.name"), r!(simple_identifier.name.as_str()))
     },
     name: AString,
 );
@@ -499,7 +581,7 @@ define_ast_node!(
     |lit: &BooleanLiteral, _py: Python, ast_module: &'a PyModule, _depth: usize| {
         ast_module.getattr("Constant")?.call1((lit.val,))
     },
-    |lit: &BooleanLiteral, _depth: usize| { Robj::from(lit.val) },
+    |lit: &BooleanLiteral, _depth: usize| { Ok(Robj::from(lit.val)) },
     val: bool,
 );
 
@@ -517,19 +599,11 @@ define_ast_node!(
         let body_ast = if_else
             .body
             .iter()
-            .map(|x| match &x {
-                AST::Assignment(_) | AST::Expression(_) => x,
-                _ => panic!("AST node of type {} found in if body", x.name()),
-            })
             .map(|x| x.to_python_ast_node(py, ast_module, depth + 1))
             .collect::<PyResult<Vec<_>>>()?;
         let orelse_ast = match &if_else.orelse {
             AOption(ROption::RSome(x)) => x
                 .iter()
-                .map(|x| match &x {
-                    AST::Assignment(_) | AST::Expression(_) => x,
-                    _ => panic!("AST node of type {} found in if orelse", x.name()),
-                })
                 .map(|x| x.to_python_ast_node(py, ast_module, depth + 1))
                 .collect::<PyResult<Vec<_>>>()?,
             AOption(ROption::RNone) => Vec::new(),
@@ -544,8 +618,16 @@ define_ast_node!(
             orelse_list.as_ref(),
         ))
     },
-    |_if_else: &If, _depth: usize| {
-        panic!("if - else unimplemented in R");
+    |if_else: &If, depth: usize| {
+        let mut values = vec![
+            r!(Symbol::from_string("if")),
+            if_else.test.to_r_ast_node(depth)?,
+            r_block(&if_else.body, depth)?,
+        ];
+        if let AOption(ROption::RSome(ref orelse)) = if_else.orelse {
+            values.push(r_block(orelse, depth)?);
+        }
+        Ok(r!(Language::from_values(&values)))
     },
     test: AST,
     body: AVec<AST>,
@@ -557,7 +639,7 @@ define_ast_node!(
     |lit: &BigIntLiteral, _py: Python, ast_module: &'a PyModule, _depth: usize| {
         ast_module.getattr("Constant")?.call1((lit.val,))
     },
-    |lit: &BigIntLiteral, _depth: usize| { r!(lit.val) },
+    |lit: &BigIntLiteral, _depth: usize| { Ok(r!(lit.val)) },
     // TODO: deprecate use of BigInt when removing rustpython
     val: i64,
 );
@@ -567,7 +649,7 @@ define_ast_node!(
     |lit: &FloatLiteral, _py: Python, ast_module: &'a PyModule, _depth: usize| {
         ast_module.getattr("Constant")?.call1((lit.val.as_f64(),))
     },
-    |lit: &FloatLiteral, _depth: usize| { r!(lit.val.as_f64()) },
+    |lit: &FloatLiteral, _depth: usize| { Ok(r!(lit.val.as_f64())) },
     val: aorist_attributes::FloatValue,
 );
 define_ast_node!(
@@ -578,54 +660,421 @@ define_ast_node!(
             .getattr("Constant")?
             .call1((py.None().as_ref(py),))
     },
-    |_none, _depth| { r!(NULL) },
+    |_none, _depth| { Ok(r!(NULL)) },
 );
 define_ast_node!(
     FunctionDef,
     |_fun: &FunctionDef| AVec::new(),
     |fun: &FunctionDef, py: Python, ast_module: &'a PyModule, depth: usize| {
+        let arg_name = |x: &AST| match x {
+            AST::SimpleIdentifier(ref x) => x.read().name(),
+            _ => panic!("Function arguments should be simple identifiers"),
+        };
         let mut args_py = Vec::new();
-        let mut kwargs_py = Vec::new();
-        let mut kwargs_defaults = Vec::new();
+        let mut defaults_py = Vec::new();
         for arg in fun.args.iter() {
-            let arg_py = ast_module.getattr("arg")?.call1((
-                arg.to_python_ast_node(py, ast_module, depth)?,
-                py.None().as_ref(py),
-            ))?;
-            args_py.push(arg_py);
+            args_py.push(
+                ast_module
+                    .getattr("arg")?
+                    .call1((arg_name(arg).as_str(), py.None().as_ref(py)))?,
+            );
         }
         for (k, v) in &fun.kwargs {
-            let arg_py = ast_module.getattr("arg")?.call1((
-                v.to_python_ast_node(py, ast_module, depth)?,
-                py.None().as_ref(py),
-            ))?;
-            kwargs_py.push(arg_py);
-            let default_py = AST::StringLiteral(StringLiteral::new_wrapped(k.clone(), false))
-                .to_python_ast_node(py, ast_module, depth)?;
-            kwargs_defaults.push(default_py);
+            args_py.push(
+                ast_module
+                    .getattr("arg")?
+                    .call1((k.as_str(), py.None().as_ref(py)))?,
+            );
+            defaults_py.push(v.to_python_ast_node(py, ast_module, depth)?);
         }
+        let empty: Vec<&PyAny> = Vec::new();
         let arguments = ast_module.getattr("arguments")?.call1((
-            args_py,
+            PyList::new(py, empty.clone()),
+            PyList::new(py, args_py),
             py.None().as_ref(py),
-            kwargs_py,
+            PyList::new(py, empty.clone()),
+            PyList::new(py, empty),
             py.None().as_ref(py),
-            py.None().as_ref(py),
-            kwargs_defaults,
+            PyList::new(py, defaults_py),
         ))?;
         let body_py = fun
             .body
             .iter()
+            .map(|x| x.to_python_ast_node(py, ast_module, depth + 1))
+            .collect::<PyResult<Vec<_>>>()?;
+        let decorators_py = fun
+            .decorators
+            .iter()
             .map(|x| x.to_python_ast_node(py, ast_module, depth))
             .collect::<PyResult<Vec<_>>>()?;
-        ast_module
-            .getattr("FunctionDef")?
-            .call1((fun.name.as_str(), arguments, body_py))
+        ast_module.getattr("FunctionDef")?.call1((
+            fun.name.as_str(),
+            arguments,
+            PyList::new(py, body_py),
+            PyList::new(py, decorators_py),
+            py.None().as_ref(py),
+        ))
     },
     |_fun: &FunctionDef, _depth: usize| { panic!("Function defs not supported in R") },
     name: AString,
     args: AVec<AST>,
     kwargs: LinkedHashMap<AString, AST>,
     body: AVec<AST>,
+    decorators: AVec<AST>,
+);
+
+define_ast_node!(
+    While,
+    |while_loop: &While| vec![while_loop.test.clone()]
+        .into_iter()
+        .chain(while_loop.body.clone().into_iter())
+        .collect(),
+    |while_loop: &While, py: Python, ast_module: &'a PyModule, depth: usize| {
+        let body_ast = while_loop
+            .body
+            .iter()
+            .map(|x| x.to_python_ast_node(py, ast_module, depth + 1))
+            .collect::<PyResult<Vec<_>>>()?;
+        let empty: Vec<&PyAny> = Vec::new();
+        ast_module.getattr("While")?.call1((
+            while_loop.test.to_python_ast_node(py, ast_module, depth)?,
+            PyList::new(py, body_ast),
+            PyList::new(py, empty),
+        ))
+    },
+    |while_loop: &While, depth: usize| {
+        Ok(r!(Language::from_values(&[
+            r!(Symbol::from_string("while")),
+            while_loop.test.to_r_ast_node(depth)?,
+            r_block(&while_loop.body, depth)?
+        ])))
+    },
+    test: AST,
+    body: AVec<AST>,
+);
+define_ast_node!(
+    Try,
+    |try_except: &Try| try_except
+        .body
+        .clone()
+        .into_iter()
+        .chain(try_except.exception.clone().0.into_option().into_iter())
+        .chain(try_except.handler.clone().into_iter())
+        .collect(),
+    |try_except: &Try, py: Python, ast_module: &'a PyModule, depth: usize| {
+        let body_ast = try_except
+            .body
+            .iter()
+            .map(|x| x.to_python_ast_node(py, ast_module, depth + 1))
+            .collect::<PyResult<Vec<_>>>()?;
+        let handler_ast = try_except
+            .handler
+            .iter()
+            .map(|x| x.to_python_ast_node(py, ast_module, depth + 1))
+            .collect::<PyResult<Vec<_>>>()?;
+        let exception = match try_except.exception {
+            AOption(ROption::RSome(ref x)) => x.to_python_ast_node(py, ast_module, depth)?,
+            AOption(ROption::RNone) => py.None().into_ref(py),
+        };
+        let name = match try_except.name {
+            AOption(ROption::RSome(ref x)) => PyString::new(py, x.as_str()).as_ref(),
+            AOption(ROption::RNone) => py.None().into_ref(py),
+        };
+        let handler = ast_module.getattr("ExceptHandler")?.call1((
+            exception,
+            name,
+            PyList::new(py, handler_ast),
+        ))?;
+        let empty: Vec<&PyAny> = Vec::new();
+        ast_module.getattr("Try")?.call1((
+            PyList::new(py, body_ast),
+            PyList::new(py, vec![handler]),
+            PyList::new(py, empty.clone()),
+            PyList::new(py, empty),
+        ))
+    },
+    // lowered to `tryCatch` with an `error` handler, which catches every R
+    // error: the exception class is not checked, so errors the Python code
+    // would let through are handled as well
+    |try_except: &Try, _depth: usize| {
+        r_parsed(&AST::Try(RArc::new(RRwLock::new(try_except.clone()))))
+    },
+    body: AVec<AST>,
+    // exception class caught, all exceptions if None. Ignored in R.
+    exception: AOption<AST>,
+    // name the exception is bound to in the handler
+    name: AOption<AString>,
+    handler: AVec<AST>,
+);
+define_ast_node!(
+    Return,
+    |ret: &Return| ret.value.clone().0.into_option().into_iter().collect(),
+    |ret: &Return, py: Python, ast_module: &'a PyModule, depth: usize| {
+        let value = match ret.value {
+            AOption(ROption::RSome(ref x)) => x.to_python_ast_node(py, ast_module, depth)?,
+            AOption(ROption::RNone) => py.None().into_ref(py),
+        };
+        ast_module.getattr("Return")?.call1((value,))
+    },
+    |ret: &Return, depth: usize| {
+        let mut values = vec![r!(Symbol::from_string("return"))];
+        if let AOption(ROption::RSome(ref x)) = ret.value {
+            values.push(x.to_r_ast_node(depth)?);
+        }
+        Ok(r!(Language::from_values(&values)))
+    },
+    value: AOption<AST>,
+);
+define_ast_node!(
+    Lambda,
+    |lambda: &Lambda| vec![lambda.body.clone()].into_iter().collect(),
+    |lambda: &Lambda, py: Python, ast_module: &'a PyModule, depth: usize| {
+        let mut args_py = Vec::new();
+        for arg in lambda.args.iter() {
+            let name = match arg {
+                AST::SimpleIdentifier(ref x) => x.read().name(),
+                _ => panic!("Lambda arguments should be simple identifiers"),
+            };
+            args_py.push(
+                ast_module
+                    .getattr("arg")?
+                    .call1((name.as_str(), py.None().as_ref(py)))?,
+            );
+        }
+        let empty: Vec<&PyAny> = Vec::new();
+        let arguments = ast_module.getattr("arguments")?.call1((
+            PyList::new(py, empty.clone()),
+            PyList::new(py, args_py),
+            py.None().as_ref(py),
+            PyList::new(py, empty.clone()),
+            PyList::new(py, empty.clone()),
+            py.None().as_ref(py),
+            PyList::new(py, empty),
+        ))?;
+        ast_module.getattr("Lambda")?.call1((
+            arguments,
+            lambda.body.to_python_ast_node(py, ast_module, depth)?,
+        ))
+    },
+    |lambda: &Lambda, _depth: usize| {
+        r_parsed(&AST::Lambda(RArc::new(RRwLock::new(lambda.clone()))))
+    },
+    args: AVec<AST>,
+    body: AST,
+);
+
+/// Defines a node only used as the operator of a `Compare` or `BoolOp`,
+/// lowered to the Python `ast` class of the same name.
+macro_rules! define_operator_node {
+    ($name:ident) => {
+        define_ast_node!(
+            $name,
+            |_node: &$name| AVec::new(),
+            |_node: &$name, _py: Python, ast_module: &'a PyModule, _depth: usize| {
+                ast_module.getattr(stringify!($name))?.call0()
+            },
+            |_node: &$name, _depth: usize| {
+                panic!(
+                    "Should not call to_r_ast_node on {} objects directly",
+                    stringify!($name)
+                )
+            },
+        );
+    };
+}
+define_operator_node!(Eq);
+define_operator_node!(NotEq);
+define_operator_node!(Lt);
+define_operator_node!(LtE);
+define_operator_node!(Gt);
+define_operator_node!(GtE);
+define_operator_node!(Is);
+define_operator_node!(IsNot);
+define_operator_node!(In);
+define_operator_node!(NotIn);
+define_operator_node!(And);
+define_operator_node!(Or);
+
+define_ast_node!(
+    Compare,
+    |node: &Compare| vec![node.left.clone(), node.right.clone()]
+        .into_iter()
+        .collect(),
+    |node: &Compare, py: Python, ast_module: &'a PyModule, depth: usize| {
+        ast_module.getattr("Compare")?.call1((
+            node.left.to_python_ast_node(py, ast_module, depth)?,
+            PyList::new(py, vec![node.op.to_python_ast_node(py, ast_module, depth)?]),
+            PyList::new(
+                py,
+                vec![node.right.to_python_ast_node(py, ast_module, depth)?],
+            ),
+        ))
+    },
+    |node: &Compare, depth: usize| {
+        let left = node.left.to_r_ast_node(depth)?;
+        let right = node.right.to_r_ast_node(depth)?;
+        // `is` only has an R counterpart for comparisons with NULL
+        let identical = |left: Robj, right: Robj| match node.right {
+            AST::None(_) => r_call("is.null", vec![left]),
+            _ => r_call("identical", vec![left, right]),
+        };
+        Ok(match node.op {
+            AST::Eq(_) => r_call("==", vec![left, right]),
+            AST::NotEq(_) => r_call("!=", vec![left, right]),
+            AST::Lt(_) => r_call("<", vec![left, right]),
+            AST::LtE(_) => r_call("<=", vec![left, right]),
+            AST::Gt(_) => r_call(">", vec![left, right]),
+            AST::GtE(_) => r_call(">=", vec![left, right]),
+            AST::In(_) => r_call("%in%", vec![left, right]),
+            AST::NotIn(_) => r_call("!", vec![r_call("%in%", vec![left, right])]),
+            AST::Is(_) => identical(left, right),
+            AST::IsNot(_) => r_call("!", vec![identical(left, right)]),
+            _ => panic!("AST node not supported as R comparison operator"),
+        })
+    },
+    left: AST,
+    op: AST,
+    right: AST,
+);
+define_ast_node!(
+    BoolOp,
+    |node: &BoolOp| node.values.clone(),
+    |node: &BoolOp, py: Python, ast_module: &'a PyModule, depth: usize| {
+        let values = node
+            .values
+            .iter()
+            .map(|x| x.to_python_ast_node(py, ast_module, depth))
+            .collect::<PyResult<Vec<_>>>()?;
+        ast_module.getattr("BoolOp")?.call1((
+            node.op.to_python_ast_node(py, ast_module, depth)?,
+            PyList::new(py, values),
+        ))
+    },
+    |node: &BoolOp, depth: usize| {
+        let op_str = match node.op {
+            AST::And(_) => "&&",
+            AST::Or(_) => "||",
+            _ => panic!("AST node not supported as R boolean operator"),
+        };
+        let mut values = node
+            .values
+            .iter()
+            .map(|x| x.to_r_ast_node(depth))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter();
+        let first = values.next().expect("Boolean operations need operands");
+        Ok(values.fold(first, |left, right| {
+            r!(Language::from_values(&[
+                r!(Symbol::from_string(op_str)),
+                left,
+                right
+            ]))
+        }))
+    },
+    op: AST,
+    values: AVec<AST>,
+);
+define_ast_node!(
+    Not,
+    |node: &Not| vec![node.operand.clone()].into_iter().collect(),
+    |node: &Not, py: Python, ast_module: &'a PyModule, depth: usize| {
+        ast_module.getattr("UnaryOp")?.call1((
+            ast_module.getattr("Not")?.call0()?,
+            node.operand.to_python_ast_node(py, ast_module, depth)?,
+        ))
+    },
+    |node: &Not, depth: usize| {
+        Ok(r!(Language::from_values(&[
+            r!(Symbol::from_string("!")),
+            node.operand.to_r_ast_node(depth)?
+        ])))
+    },
+    operand: AST,
+);
+/// Python `comprehension` node for a single `for` clause with an optional
+/// `if` clause.
+#[cfg(feature = "python")]
+fn python_comprehension<'a>(
+    py: Python,
+    ast_module: &'a PyModule,
+    target: &AST,
+    iter: &AST,
+    condition: &AOption<AST>,
+    depth: usize,
+) -> PyResult<&'a PyAny> {
+    let ifs = match condition {
+        AOption(ROption::RSome(x)) => vec![x.to_python_ast_node(py, ast_module, depth)?],
+        AOption(ROption::RNone) => Vec::new(),
+    };
+    ast_module.getattr("comprehension")?.call1((
+        target.to_python_ast_node(py, ast_module, depth)?,
+        iter.to_python_ast_node(py, ast_module, depth)?,
+        PyList::new(py, ifs),
+        0,
+    ))
+}
+define_ast_node!(
+    ListComp,
+    |comp: &ListComp| vec![comp.elt.clone(), comp.target.clone(), comp.iter.clone()]
+        .into_iter()
+        .chain(comp.condition.clone().0.into_option().into_iter())
+        .collect(),
+    |comp: &ListComp, py: Python, ast_module: &'a PyModule, depth: usize| {
+        let generator = python_comprehension(
+            py,
+            ast_module,
+            &comp.target,
+            &comp.iter,
+            &comp.condition,
+            depth,
+        )?;
+        ast_module.getattr("ListComp")?.call1((
+            comp.elt.to_python_ast_node(py, ast_module, depth)?,
+            PyList::new(py, vec![generator]),
+        ))
+    },
+    |comp: &ListComp, _depth: usize| {
+        r_parsed(&AST::ListComp(RArc::new(RRwLock::new(comp.clone()))))
+    },
+    elt: AST,
+    target: AST,
+    iter: AST,
+    condition: AOption<AST>,
+);
+define_ast_node!(
+    DictComp,
+    |comp: &DictComp| vec![
+        comp.key.clone(),
+        comp.value.clone(),
+        comp.target.clone(),
+        comp.iter.clone()
+    ]
+    .into_iter()
+    .chain(comp.condition.clone().0.into_option().into_iter())
+    .collect(),
+    |comp: &DictComp, py: Python, ast_module: &'a PyModule, depth: usize| {
+        let generator = python_comprehension(
+            py,
+            ast_module,
+            &comp.target,
+            &comp.iter,
+            &comp.condition,
+            depth,
+        )?;
+        ast_module.getattr("DictComp")?.call1((
+            comp.key.to_python_ast_node(py, ast_module, depth)?,
+            comp.value.to_python_ast_node(py, ast_module, depth)?,
+            PyList::new(py, vec![generator]),
+        ))
+    },
+    |comp: &DictComp, _depth: usize| {
+        r_parsed(&AST::DictComp(RArc::new(RRwLock::new(comp.clone()))))
+    },
+    key: AST,
+    value: AST,
+    target: AST,
+    iter: AST,
+    condition: AOption<AST>,
 );
 
 register_ast_nodes!(
@@ -645,11 +1094,34 @@ register_ast_nodes!(
     Expression,
     Assignment,
     ForLoop,
+    With,
     ImportNode,
     Add,
     BinOp,
     FunctionDef,
     FloatLiteral,
+    If,
+    While,
+    Try,
+    Return,
+    Lambda,
+    Eq,
+    NotEq,
+    Lt,
+    LtE,
+    Gt,
+    GtE,
+    Is,
+    IsNot,
+    In,
+    NotIn,
+    And,
+    Or,
+    Compare,
+    BoolOp,
+    Not,
+    ListComp,
+    DictComp,
 );
 
 impl Formatted {
@@ -679,6 +1151,7 @@ impl AST {
         }
     }
 
+    #[cfg(feature = "python")]
     pub fn to_python_source(&self) -> PyResult<String> {
         let gil = Python::acquire_gil();
         let py = gil.python();
@@ -700,6 +1173,7 @@ impl AST {
     }
 }
 
+#[cfg(feature = "r")]
 #[allow(unused_imports)]
 mod r_ast_tests {
     use crate::*;
@@ -709,14 +1183,14 @@ mod r_ast_tests {
     fn test_string_literal() {
         test! {
             let s = StringLiteral::new_wrapped("test".to_string(), false);
-            assert_eq!(s.read().to_r_ast_node(0), r!("test"));
+            assert_eq!(s.read().to_r_ast_node(0)?, r!("test"));
         }
     }
     #[test]
     fn test_simple_identifier() {
         test! {
             let s = SimpleIdentifier::new_wrapped("test".to_string());
-            assert_eq!(s.read().to_r_ast_node(0), sym!(test));
+            assert_eq!(s.read().to_r_ast_node(0)?, sym!(test));
         }
     }
     #[test]
@@ -725,7 +1199,7 @@ mod r_ast_tests {
             let sym = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("a".to_string()));
             let val = AST::StringLiteral(StringLiteral::new_wrapped("b".to_string(), false));
             let assign = AST::Assignment(Assignment::new_wrapped(sym, val));
-            let r_node = assign.to_r_ast_node(0);
+            let r_node = assign.to_r_ast_node(0)?;
             assert_eq!(r_node, eval_string("call('<-', rlang::sym('a'), 'b')").unwrap());
         }
     }
@@ -734,7 +1208,7 @@ mod r_ast_tests {
         test! {
             let sym = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("ggplot".to_string()));
             let import = AST::ImportNode(ImportNode::new_wrapped(sym));
-            let r_node = import.to_r_ast_node(0);
+            let r_node = import.to_r_ast_node(0)?;
             assert_eq!(r_node, eval_string("call('library', rlang::sym('ggplot'))").unwrap());
         }
     }
@@ -747,7 +1221,7 @@ mod r_ast_tests {
             let sym = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("a".to_string()));
             let assign = AST::Assignment(Assignment::new_wrapped(sym, it.clone()));
             let for_loop = AST::ForLoop(ForLoop::new_wrapped(it, vec, vec![assign]));
-            let r_node = for_loop.to_r_ast_node(0);
+            let r_node = for_loop.to_r_ast_node(0)?;
             assert_eq!(r_node, eval_string(
                 "call('for', rlang::sym('i'), rlang::sym('vec'), call('{', list(call('<-', rlang::sym('a'), rlang::sym('i')))))"
             ).unwrap());
@@ -758,7 +1232,7 @@ mod r_ast_tests {
         test! {
             let sym = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("ggplot".to_string()));
             let expr = AST::Expression(crate::python::Expression::new_wrapped(sym));
-            assert_eq!(expr.to_r_ast_node(0), sym!(ggplot));
+            assert_eq!(expr.to_r_ast_node(0)?, sym!(ggplot));
         }
     }
     #[test]
@@ -768,7 +1242,7 @@ mod r_ast_tests {
             let sym_b = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("b".to_string()));
             let op = AST::Add(Add::new_wrapped());
             let binop = AST::BinOp(BinOp::new_wrapped(sym_a, op, sym_b));
-            let r_node = binop.to_r_ast_node(0);
+            let r_node = binop.to_r_ast_node(0)?;
             assert_eq!(r_node, eval_string("call('+', rlang::sym('a'), rlang::sym('b'))").unwrap());
         }
     }
@@ -778,7 +1252,7 @@ mod r_ast_tests {
             let sym_a = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("a".to_string()));
             let sym_b = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("b".to_string()));
             let list = AST::List(crate::python::List::new_wrapped(vec![sym_a, sym_b].into_iter().collect(), false));
-            let r_node = list.to_r_ast_node(0);
+            let r_node = list.to_r_ast_node(0)?;
             assert_eq!(r_node, eval_string("call('list', rlang::sym('a'), rlang::sym('b'))").unwrap());
         }
     }
@@ -791,7 +1265,7 @@ mod r_ast_tests {
             map.insert("x".to_string(), sym_a);
             map.insert("y".to_string(), sym_b);
             let dict = AST::Dict(crate::python::Dict::new_wrapped(map));
-            let r_node = dict.to_r_ast_node(0);
+            let r_node = dict.to_r_ast_node(0)?;
             assert_eq!(r_node, eval_string("list(x=rlang::sym('a'), y=rlang::sym('b'))").unwrap());
             // N.B.: this also evaluates as correct -- names don't seem to matter
             assert_eq!(r_node, eval_string("list(z=rlang::sym('a'), y=rlang::sym('b'))").unwrap());
@@ -807,7 +1281,7 @@ mod r_ast_tests {
             map.insert("x".to_string(), sym_a);
             map.insert("y".to_string(), sym_b);
             let dict = AST::Call(crate::python::Call::new_wrapped(sym_fun, AVec::new(), map));
-            let r_node = dict.to_r_ast_node(0);
+            let r_node = dict.to_r_ast_node(0)?;
             assert_eq!(r_node, eval_string("call('call', name='fun', x=rlang::sym('a'), y=rlang::sym('b'))").unwrap());
         }
     }
@@ -821,7 +1295,7 @@ mod r_ast_tests {
             map.insert("x".to_string(), sym_a);
             map.insert("y".to_string(), sym_b);
             let dict = AST::Formatted(crate::python::Formatted::new_wrapped(fmt, map));
-            let r_node = dict.to_r_ast_node(0);
+            let r_node = dict.to_r_ast_node(0)?;
             assert_eq!(r_node, eval_string("call('call', name='glue', fmt='{x} {y}', x=rlang::sym('a'), y=rlang::sym('b'))").unwrap());
         }
     }
@@ -831,7 +1305,7 @@ mod r_ast_tests {
             let sym_a = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("a".to_string()));
             let sym_b = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("b".to_string()));
             let subscript = AST::Subscript(crate::python::Subscript::new_wrapped(sym_a, sym_b, false));
-            let r_node = subscript.to_r_ast_node(0);
+            let r_node = subscript.to_r_ast_node(0)?;
             assert_eq!(r_node, eval_string("quote(a[[b]])").unwrap());
 
         }
@@ -841,7 +1315,7 @@ mod r_ast_tests {
     fn test_boolean_literal() {
         test! {
             let sym = AST::BooleanLiteral(BooleanLiteral::new_wrapped(true));
-            let r_node = sym.to_r_ast_node(0);
+            let r_node = sym.to_r_ast_node(0)?;
             assert_eq!(r_node, eval_string("quote(TRUE)").unwrap());
         }
     }
@@ -850,7 +1324,7 @@ mod r_ast_tests {
     fn test_bigint_literal() {
         test! {
             let sym = AST::BigIntLiteral(BigIntLiteral::new_wrapped(1));
-            let r_node = sym.to_r_ast_node(0);
+            let r_node = sym.to_r_ast_node(0)?;
             assert_eq!(r_node, eval_string("as.integer(1)").unwrap());
         }
     }
@@ -859,8 +1333,867 @@ mod r_ast_tests {
     fn test_none() {
         test! {
             let sym = AST::None(None::new_wrapped());
-            let r_node = sym.to_r_ast_node(0);
+            let r_node = sym.to_r_ast_node(0)?;
             assert_eq!(r_node, eval_string("quote(NULL)").unwrap());
         }
     }
 }
+
+#[cfg(test)]
+#[allow(unused_imports)]
+mod python_printer_tests {
+    use crate::*;
+    use aorist_primitives::AString;
+    use linked_hash_map::LinkedHashMap;
+
+    fn string(value: &str) -> AST {
+        AST::StringLiteral(StringLiteral::new_wrapped(value.into(), false))
+    }
+    fn identifier(name: &str) -> AST {
+        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(name.into()))
+    }
+    fn call(function: &str, args: Vec<AST>, keywords: Vec<(&str, AST)>) -> AST {
+        AST::Call(Call::new_wrapped(
+            identifier(function),
+            args.into_iter().collect(),
+            keywords.into_iter().map(|(k, v)| (k.into(), v)).collect(),
+        ))
+    }
+    fn assign(target: &str, value: AST) -> AST {
+        AST::Assignment(Assignment::new_wrapped(identifier(target), value))
+    }
+
+    #[test]
+    fn test_short_call() {
+        let node = assign("x", call("f", vec![string("a")], vec![("b", string("c"))]));
+        assert_eq!(node.to_python_code(), "x = f(\"a\", b=\"c\")\n");
+    }
+
+    #[test]
+    fn test_arguments_fitting_on_one_line() {
+        let node = assign(
+            "task_name_really_long",
+            call(
+                "download_data_from_remote_web_location",
+                vec![],
+                vec![
+                    ("url", string("https://example.com/some/path")),
+                    ("dest", string("/tmp/x")),
+                ],
+            ),
+        );
+        assert_eq!(
+            node.to_python_code(),
+            "task_name_really_long = download_data_from_remote_web_location(\n    \
+             url=\"https://example.com/some/path\", dest=\"/tmp/x\"\n)\n"
+        );
+    }
+
+    #[test]
+    fn test_arguments_one_per_line() {
+        let node = assign(
+            "task_name_really_long",
+            call(
+                "download_data_from_remote_web_location",
+                vec![],
+                vec![
+                    (
+                        "url",
+                        string("https://example.com/some/path/that/is/very/long/indeed/really.csv"),
+                    ),
+                    ("dest", string("/tmp/x/aaaaaaaaaaaaaaaaaaaaaaaa")),
+                ],
+            ),
+        );
+        assert_eq!(
+            node.to_python_code(),
+            "task_name_really_long = download_data_from_remote_web_location(\n    \
+             url=\"https://example.com/some/path/that/is/very/long/indeed/really.csv\",\n    \
+             dest=\"/tmp/x/aaaaaaaaaaaaaaaaaaaaaaaa\",\n)\n"
+        );
+    }
+
+    #[test]
+    fn test_nested_dicts_exploded() {
+        let inner: LinkedHashMap<AString, AST> = vec![
+            ("x".into(), string("yyyyyyyyyyyyyyyyyyyyyyyy")),
+            ("z".into(), string("wwwwwwwwwwwwwwwwwwwwwww")),
+        ]
+        .into_iter()
+        .collect();
+        let outer: LinkedHashMap<AString, AST> = vec![
+            ("a".into(), string("b")),
+            (
+                "cccccccccccccccccccc".into(),
+                AST::Dict(Dict::new_wrapped(inner)),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let node = assign("params", AST::Dict(Dict::new_wrapped(outer)));
+        assert_eq!(
+            node.to_python_code(),
+            "params = {\n    \
+             \"a\": \"b\",\n    \
+             \"cccccccccccccccccccc\": {\n        \
+             \"x\": \"yyyyyyyyyyyyyyyyyyyyyyyy\",\n        \
+             \"z\": \"wwwwwwwwwwwwwwwwwwwwwww\",\n    \
+             },\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_sql_query() {
+        let query = AST::StringLiteral(StringLiteral::new_wrapped("SELECT * FROM t".into(), true));
+        let node = assign("x", call("presto", vec![query], vec![("a", string("b"))]));
+        assert_eq!(
+            node.to_python_code(),
+            "x = presto(\"SELECT * FROM t\", a=\"b\")\n"
+        );
+    }
+
+    #[test]
+    fn test_multiline_sql_query() {
+        let query = AST::StringLiteral(StringLiteral::new_wrapped("SELECT *\nFROM t".into(), true));
+        let node = assign("x", call("presto", vec![query], vec![("a", string("b"))]));
+        let code = node.to_python_code();
+        assert!(code.starts_with("x = presto(\n    \"\"\"\n"));
+        assert!(code.ends_with("\"\"\",\n    a=\"b\",\n)\n"));
+    }
+
+    #[test]
+    fn test_binary_operation_split() {
+        let node = assign(
+            "x",
+            AST::BinOp(BinOp::new_wrapped(
+                string("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"),
+                AST::Add(Add::new_wrapped()),
+                string("bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"),
+            )),
+        );
+        assert_eq!(
+            node.to_python_code(),
+            "x = (\n    \
+             \"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\"\n    \
+             + \"bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\"\n)\n"
+        );
+    }
+
+    #[test]
+    fn test_string_quotes() {
+        assert_eq!(string("it's").to_python_code(), "\"it's\"\n");
+        assert_eq!(string("say \"hi\"").to_python_code(), "'say \"hi\"'\n");
+        assert_eq!(string("a\tb\n").to_python_code(), "\"a\\tb\\n\"\n");
+    }
+
+    #[test]
+    fn test_literals() {
+        let float = |x: f64| {
+            AST::FloatLiteral(FloatLiteral::new_wrapped(
+                aorist_attributes::FloatValue::from_f64(x),
+            ))
+        };
+        assert_eq!(float(1.0).to_python_code(), "1.0\n");
+        assert_eq!(float(1e-5).to_python_code(), "1e-05\n");
+        assert_eq!(float(1.5e16).to_python_code(), "1.5e+16\n");
+        assert_eq!(
+            AST::BigIntLiteral(BigIntLiteral::new_wrapped(42)).to_python_code(),
+            "42\n"
+        );
+        assert_eq!(AST::None(None::new_wrapped()).to_python_code(), "None\n");
+    }
+
+    #[test]
+    fn test_function_def_and_for_loop() {
+        let for_loop = AST::ForLoop(ForLoop::new_wrapped(
+            AST::Tuple(Tuple::new_wrapped(
+                vec![identifier("k"), identifier("v")].into_iter().collect(),
+                true,
+            )),
+            call("items", vec![], vec![]),
+            vec![AST::Expression(Expression::new_wrapped(call(
+                "print",
+                vec![identifier("k")],
+                vec![],
+            )))]
+            .into_iter()
+            .collect(),
+        ));
+        let fun = AST::FunctionDef(FunctionDef::new_wrapped(
+            "run".into(),
+            vec![identifier("x")].into_iter().collect(),
+            vec![("y".into(), string("z"))].into_iter().collect(),
+            vec![for_loop].into_iter().collect(),
+            vec![call("flow", vec![], vec![("name", string("f"))])]
+                .into_iter()
+                .collect(),
+        ));
+        let statements = vec![
+            assign("a", string("b")),
+            fun,
+            AST::Expression(Expression::new_wrapped(call("run", vec![], vec![]))),
+        ];
+        assert_eq!(
+            to_python_source_lines(&statements),
+            "a = \"b\"\n\n\n\
+             @flow(name=\"f\")\n\
+             def run(x, y=\"z\"):\n    \
+             for k, v in items():\n        \
+             print(k)\n\n\n\
+             run()\n"
+        );
+    }
+
+    fn compare(left: AST, op: AST, right: AST) -> AST {
+        AST::Compare(Compare::new_wrapped(left, op, right))
+    }
+    fn statements(nodes: Vec<AST>) -> AVec<AST> {
+        nodes.into_iter().collect()
+    }
+
+    #[test]
+    fn test_guards_and_retries() {
+        let guard = AST::If(If::new_wrapped(
+            compare(
+                identifier("x"),
+                AST::Is(Is::new_wrapped()),
+                AST::None(None::new_wrapped()),
+            ),
+            statements(vec![AST::Return(Return::new_wrapped(AOption(
+                ROption::RNone,
+            )))]),
+            AOption(ROption::RSome(statements(vec![AST::If(If::new_wrapped(
+                compare(
+                    identifier("x"),
+                    AST::NotIn(NotIn::new_wrapped()),
+                    identifier("done"),
+                ),
+                statements(vec![AST::Expression(Expression::new_wrapped(call(
+                    "run",
+                    vec![identifier("x")],
+                    vec![],
+                )))]),
+                AOption(ROption::RNone),
+            ))]))),
+        ));
+        let retry = AST::While(While::new_wrapped(
+            compare(
+                identifier("attempts"),
+                AST::Lt(Lt::new_wrapped()),
+                AST::BigIntLiteral(BigIntLiteral::new_wrapped(3)),
+            ),
+            statements(vec![AST::Try(Try::new_wrapped(
+                statements(vec![
+                    AST::Expression(Expression::new_wrapped(call("run", vec![], vec![]))),
+                    AST::Return(Return::new_wrapped(AOption(ROption::RSome(
+                        AST::BooleanLiteral(BooleanLiteral::new_wrapped(true)),
+                    )))),
+                ]),
+                AOption(ROption::RSome(identifier("Exception"))),
+                AOption(ROption::RSome("e".into())),
+                statements(vec![assign(
+                    "attempts",
+                    AST::BinOp(BinOp::new_wrapped(
+                        identifier("attempts"),
+                        AST::Add(Add::new_wrapped()),
+                        AST::BigIntLiteral(BigIntLiteral::new_wrapped(1)),
+                    )),
+                )]),
+            ))]),
+        ));
+        assert_eq!(
+            to_python_source_lines(&[guard, retry]),
+            "if x is None:\n    \
+             return\n\
+             elif x not in done:\n    \
+             run(x)\n\
+             while attempts < 3:\n    \
+             try:\n        \
+             run()\n        \
+             return True\n    \
+             except Exception as e:\n        \
+             attempts = attempts + 1\n"
+        );
+    }
+
+    #[test]
+    fn test_boolean_precedence() {
+        let or = AST::BoolOp(BoolOp::new_wrapped(
+            AST::Or(Or::new_wrapped()),
+            statements(vec![identifier("b"), identifier("c")]),
+        ));
+        let and = AST::BoolOp(BoolOp::new_wrapped(
+            AST::And(And::new_wrapped()),
+            statements(vec![
+                identifier("a"),
+                or.clone(),
+                AST::Not(Not::new_wrapped(or)),
+            ]),
+        ));
+        assert_eq!(and.to_python_code(), "a and (b or c) and not (b or c)\n");
+    }
+
+    #[test]
+    fn test_comprehensions_and_lambda() {
+        let pending = AST::ListComp(ListComp::new_wrapped(
+            identifier("task_name_long"),
+            identifier("task_name_long"),
+            identifier("all_the_tasks_in_the_flow_we_have"),
+            AOption(ROption::RSome(compare(
+                identifier("task_name_long"),
+                AST::NotIn(NotIn::new_wrapped()),
+                identifier("done"),
+            ))),
+        ));
+        assert_eq!(
+            assign("pending", pending).to_python_code(),
+            "pending = [\n    \
+             task_name_long\n    \
+             for task_name_long in all_the_tasks_in_the_flow_we_have\n    \
+             if task_name_long not in done\n\
+             ]\n"
+        );
+        let sizes = AST::DictComp(DictComp::new_wrapped(
+            identifier("k"),
+            call("len", vec![identifier("k")], vec![]),
+            identifier("k"),
+            identifier("names"),
+            AOption(ROption::RNone),
+        ));
+        assert_eq!(
+            assign("sizes", sizes).to_python_code(),
+            "sizes = {k: len(k) for k in names}\n"
+        );
+        let lambda = AST::Lambda(Lambda::new_wrapped(
+            statements(vec![identifier("x")]),
+            compare(
+                identifier("x"),
+                AST::Gt(Gt::new_wrapped()),
+                AST::BigIntLiteral(BigIntLiteral::new_wrapped(1)),
+            ),
+        ));
+        assert_eq!(
+            assign("check", lambda).to_python_code(),
+            "check = lambda x: x > 1\n"
+        );
+    }
+
+    #[test]
+    fn test_join_python_blocks() {
+        let blocks = vec![
+            "import os\nimport sys\n".to_string(),
+            "def f():\n    return 1\n".to_string(),
+            "x = f(\n    1,\n)\n".to_string(),
+            "# Tasks\ny = 2\n\n".to_string(),
+            String::new(),
+            "# Helpers\n@flow\ndef g():\n    pass\n\n".to_string(),
+            "# Run\ng()\n\n".to_string(),
+        ];
+        assert_eq!(
+            join_python_blocks(&blocks),
+            "import os\nimport sys\n\n\n\
+             def f():\n    return 1\n\n\n\
+             x = f(\n    1,\n)\n\
+             # Tasks\ny = 2\n\n\n\
+             # Helpers\n@flow\ndef g():\n    pass\n\n\n\
+             # Run\ng()\n"
+        );
+    }
+
+    fn ancestors(object_type: &str) -> AVec<AncestorRecord> {
+        vec![AncestorRecord::new(
+            uuid::Uuid::new_v4(),
+            object_type.into(),
+            AOption(ROption::RNone),
+            0,
+        )]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn test_source_ranges() {
+        let task = assign(
+            "x",
+            call(
+                "download_data_from_remote_web_location",
+                vec![],
+                vec![("url", string("https://example.com/some/long/path.csv"))],
+            ),
+        );
+        let task_ancestors = ancestors("Download");
+        task.set_ancestors(task_ancestors.clone());
+        let nested = AST::Expression(Expression::new_wrapped(call("g", vec![], vec![])));
+        let nested_ancestors = ancestors("Run");
+        nested.set_ancestors(nested_ancestors.clone());
+        let fun = AST::FunctionDef(FunctionDef::new_wrapped(
+            "f".into(),
+            AVec::new(),
+            LinkedHashMap::new(),
+            vec![assign("y", string("a")), nested].into_iter().collect(),
+            AVec::new(),
+        ));
+        let (source, ranges) = to_python_source_with_ranges(&[task, fun]);
+        assert_eq!(
+            source,
+            "x = download_data_from_remote_web_location(\n    \
+             url=\"https://example.com/some/long/path.csv\"\n)\n\n\n\
+             def f():\n    y = \"a\"\n    g()\n"
+        );
+        assert_eq!(
+            ranges,
+            vec![
+                SourceRange {
+                    start_line: 1,
+                    end_line: 3,
+                    ancestors: task_ancestors.clone(),
+                },
+                SourceRange {
+                    start_line: 8,
+                    end_line: 8,
+                    ancestors: nested_ancestors,
+                },
+            ]
+        );
+        let (joined, ranges) = join_python_blocks_with_ranges(&[
+            ("import os\n".to_string(), Vec::new()),
+            (
+                "\n# Tasks\nx = 1\n".to_string(),
+                vec![SourceRange {
+                    start_line: 3,
+                    end_line: 3,
+                    ancestors: task_ancestors.clone(),
+                }],
+            ),
+        ]);
+        assert_eq!(joined, "import os\n\n# Tasks\nx = 1\n");
+        assert_eq!(
+            ranges,
+            vec![SourceRange {
+                start_line: 4,
+                end_line: 4,
+                ancestors: task_ancestors,
+            }]
+        );
+    }
+
+    /// Statements rendered by each golden fixture.
+    fn golden_cases() -> Vec<(&'static str, Vec<AST>)> {
+        let long_call = call(
+            "download_data_from_remote_web_location",
+            vec![],
+            vec![
+                (
+                    "url",
+                    string("https://example.com/some/path/that/is/very/long/indeed/really.csv"),
+                ),
+                ("dest", string("/tmp/x/aaaaaaaaaaaaaaaaaaaaaaaa")),
+            ],
+        );
+        let params: LinkedHashMap<AString, AST> = vec![
+            (
+                "download_location_3a1c".into(),
+                AST::Dict(Dict::new_wrapped(
+                    vec![
+                        ("url".into(), string("https://example.com/data.csv")),
+                        ("dest".into(), string("/tmp/probprog/data.csv")),
+                    ]
+                    .into_iter()
+                    .collect(),
+                )),
+            ),
+            (
+                "download_location_7f02".into(),
+                AST::Dict(Dict::new_wrapped(
+                    vec![("url".into(), string("https://example.com/other.csv"))]
+                        .into_iter()
+                        .collect(),
+                )),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let task_loop = AST::ForLoop(ForLoop::new_wrapped(
+            AST::Tuple(Tuple::new_wrapped(
+                vec![identifier("t"), identifier("params")]
+                    .into_iter()
+                    .collect(),
+                true,
+            )),
+            AST::Call(Call::new_wrapped(
+                AST::Attribute(Attribute::new_wrapped(
+                    identifier("params_download"),
+                    "items".into(),
+                    false,
+                )),
+                AVec::new(),
+                LinkedHashMap::new(),
+            )),
+            statements(vec![AST::Assignment(Assignment::new_wrapped(
+                AST::Subscript(Subscript::new_wrapped(
+                    identifier("tasks"),
+                    identifier("t"),
+                    true,
+                )),
+                call(
+                    "download",
+                    vec![],
+                    vec![(
+                        "url",
+                        AST::Subscript(Subscript::new_wrapped(
+                            identifier("params"),
+                            string("url"),
+                            false,
+                        )),
+                    )],
+                ),
+            ))]),
+        ));
+        let query = AST::StringLiteral(StringLiteral::new_wrapped(
+            "SELECT *\nFROM table_with_a_long_name\nWHERE x > 1".into(),
+            true,
+        ));
+        let guard = AST::If(If::new_wrapped(
+            compare(
+                identifier("x"),
+                AST::Is(Is::new_wrapped()),
+                AST::None(None::new_wrapped()),
+            ),
+            statements(vec![AST::Return(Return::new_wrapped(AOption(
+                ROption::RNone,
+            )))]),
+            AOption(ROption::RSome(statements(vec![AST::Expression(
+                Expression::new_wrapped(call("run", vec![identifier("x")], vec![])),
+            )]))),
+        ));
+        let run = AST::FunctionDef(FunctionDef::new_wrapped(
+            "run_once".into(),
+            vec![identifier("x")].into_iter().collect(),
+            vec![(
+                "retries".into(),
+                AST::BigIntLiteral(BigIntLiteral::new_wrapped(3)),
+            )]
+            .into_iter()
+            .collect(),
+            statements(vec![guard]),
+            AVec::new(),
+        ));
+        vec![
+            (
+                "call_arguments",
+                vec![
+                    assign(
+                        "short",
+                        call("f", vec![string("a")], vec![("b", string("c"))]),
+                    ),
+                    assign("task_name_really_long", long_call),
+                ],
+            ),
+            (
+                "compressed_tasks",
+                vec![
+                    assign("params_download", AST::Dict(Dict::new_wrapped(params))),
+                    task_loop,
+                ],
+            ),
+            (
+                "sql_query",
+                vec![assign(
+                    "result",
+                    call("presto", vec![query], vec![("catalog", string("hive"))]),
+                )],
+            ),
+            (
+                "function_def",
+                vec![
+                    assign("a", string("it's")),
+                    run,
+                    AST::Expression(Expression::new_wrapped(call(
+                        "run_once",
+                        vec![identifier("a")],
+                        vec![],
+                    ))),
+                ],
+            ),
+        ]
+    }
+    fn golden_fixture_path(name: &str) -> std::path::PathBuf {
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/data/python_printer")
+            .join(format!("{}.py", name))
+    }
+
+    /// The fixtures are frozen: they hold the output of `ast`, astor and
+    /// Black, which flows were rendered with before the printer existed.
+    #[test]
+    fn test_golden_fixtures() {
+        for (name, statements) in golden_cases() {
+            let expected = std::fs::read_to_string(golden_fixture_path(name)).unwrap();
+            assert_eq!(to_python_source_lines(&statements), expected, "{}", name);
+        }
+    }
+
+    /// Checks that the fixtures still match the output of `ast`, astor and
+    /// Black, when astor and Black are installed. With
+    /// AORIST_REGENERATE_FIXTURES set, rewrites the fixtures instead:
+    /// AORIST_REGENERATE_FIXTURES=1 cargo test -p aorist_ast --features python
+    #[cfg(feature = "python")]
+    #[test]
+    fn test_golden_fixtures_match_black() {
+        use pyo3::types::{IntoPyDict, PyModule};
+        pyo3::Python::with_gil(|py| {
+            let ast = PyModule::import(py, "ast").unwrap();
+            let (astor, black) =
+                match (PyModule::import(py, "astor"), PyModule::import(py, "black")) {
+                    (Ok(astor), Ok(black)) => (astor, black),
+                    _ => {
+                        eprintln!("astor or Black is not installed, not checking the fixtures");
+                        return;
+                    }
+                };
+            let mode = black
+                .getattr("FileMode")
+                .unwrap()
+                .call(
+                    (),
+                    Some(vec![("line_length", PYTHON_LINE_LENGTH)].into_py_dict(py)),
+                )
+                .unwrap();
+            for (name, statements) in golden_cases() {
+                let source = statements
+                    .iter()
+                    .map(|x| {
+                        let node = x.to_python_ast_node(py, ast, 0).unwrap();
+                        let expression = ast.getattr("Expression").unwrap().call1((node,)).unwrap();
+                        astor
+                            .getattr("to_source")
+                            .unwrap()
+                            .call1((expression,))
+                            .unwrap()
+                            .extract::<String>()
+                            .unwrap()
+                    })
+                    .collect::<String>();
+                let formatted = black
+                    .getattr("format_str")
+                    .unwrap()
+                    .call((source,), Some(vec![("mode", mode)].into_py_dict(py)))
+                    .unwrap()
+                    .extract::<String>()
+                    .unwrap();
+                match std::env::var_os("AORIST_REGENERATE_FIXTURES") {
+                    Some(_) => std::fs::write(golden_fixture_path(name), formatted).unwrap(),
+                    None => assert_eq!(
+                        std::fs::read_to_string(golden_fixture_path(name)).unwrap(),
+                        formatted,
+                        "{}",
+                        name
+                    ),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+#[allow(unused_imports)]
+mod r_printer_tests {
+    use crate::*;
+    use aorist_primitives::AString;
+    use linked_hash_map::LinkedHashMap;
+
+    fn string(value: &str) -> AST {
+        AST::StringLiteral(StringLiteral::new_wrapped(value.into(), false))
+    }
+    fn identifier(name: &str) -> AST {
+        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(name.into()))
+    }
+    fn call(function: &str, args: Vec<AST>, keywords: Vec<(&str, AST)>) -> AST {
+        AST::Call(Call::new_wrapped(
+            identifier(function),
+            args.into_iter().collect(),
+            keywords.into_iter().map(|(k, v)| (k.into(), v)).collect(),
+        ))
+    }
+
+    #[test]
+    fn test_assignment_and_call() {
+        let node = AST::Assignment(Assignment::new_wrapped(
+            identifier("x"),
+            call(
+                "read.csv",
+                vec![string("a.csv")],
+                vec![("header", string("yes"))],
+            ),
+        ));
+        assert_eq!(
+            node.to_r_code(),
+            "x <- read.csv(\"a.csv\", header = \"yes\")\n"
+        );
+    }
+
+    #[test]
+    fn test_formatted_and_dict() {
+        let keywords: LinkedHashMap<AString, AST> =
+            vec![("name".into(), string("world"))].into_iter().collect();
+        let formatted = AST::Formatted(Formatted::new_wrapped(
+            string("hello {name}"),
+            keywords.clone(),
+        ));
+        assert_eq!(
+            formatted.to_r_code(),
+            "glue(\"hello {name}\", name = \"world\")\n"
+        );
+        let elems: LinkedHashMap<AString, AST> = vec![
+            (
+                "a".into(),
+                AST::BigIntLiteral(BigIntLiteral::new_wrapped(1)),
+            ),
+            (
+                "b c".into(),
+                AST::BooleanLiteral(BooleanLiteral::new_wrapped(true)),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            AST::Dict(Dict::new_wrapped(elems)).to_r_code(),
+            "list(a = 1L, `b c` = TRUE)\n"
+        );
+    }
+
+    #[test]
+    fn test_for_loop_and_subscript() {
+        let node = AST::ForLoop(ForLoop::new_wrapped(
+            identifier("k"),
+            call("names", vec![identifier("tasks")], vec![]),
+            vec![AST::Expression(Expression::new_wrapped(call(
+                "print",
+                vec![AST::Subscript(Subscript::new_wrapped(
+                    identifier("tasks"),
+                    identifier("k"),
+                    false,
+                ))],
+                vec![],
+            )))]
+            .into_iter()
+            .collect(),
+        ));
+        assert_eq!(
+            node.to_r_code(),
+            "for (k in names(tasks)) {\n    print(tasks[[k]])\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_literals() {
+        let float = |x: f64| {
+            AST::FloatLiteral(FloatLiteral::new_wrapped(
+                aorist_attributes::FloatValue::from_f64(x),
+            ))
+            .to_r_code()
+        };
+        assert_eq!(float(1.0), "1\n");
+        assert_eq!(float(0.1), "0.1\n");
+        assert_eq!(float(1e-5), "1e-05\n");
+        assert_eq!(float(100000.0), "1e+05\n");
+        assert_eq!(float(123456.0), "123456\n");
+        assert_eq!(float(-2.5), "-2.5\n");
+        assert_eq!(AST::None(None::new_wrapped()).to_r_code(), "NULL\n");
+        assert_eq!(
+            string("say \"hi\"\n").to_r_code(),
+            "\"say \\\"hi\\\"\\n\"\n"
+        );
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let is_none = AST::Compare(Compare::new_wrapped(
+            identifier("x"),
+            AST::Is(Is::new_wrapped()),
+            AST::None(None::new_wrapped()),
+        ));
+        let guard = AST::If(If::new_wrapped(
+            is_none,
+            vec![AST::Return(Return::new_wrapped(AOption(ROption::RNone)))]
+                .into_iter()
+                .collect(),
+            AOption(ROption::RSome(
+                vec![AST::Expression(Expression::new_wrapped(call(
+                    "run",
+                    vec![identifier("x")],
+                    vec![],
+                )))]
+                .into_iter()
+                .collect(),
+            )),
+        ));
+        let retry = AST::While(While::new_wrapped(
+            AST::Not(Not::new_wrapped(identifier("done"))),
+            vec![AST::Try(Try::new_wrapped(
+                vec![AST::Assignment(Assignment::new_wrapped(
+                    identifier("done"),
+                    call("run", vec![], vec![]),
+                ))]
+                .into_iter()
+                .collect(),
+                AOption(ROption::RNone),
+                AOption(ROption::RNone),
+                vec![AST::Expression(Expression::new_wrapped(call(
+                    "message",
+                    vec![identifier("e")],
+                    vec![],
+                )))]
+                .into_iter()
+                .collect(),
+            ))]
+            .into_iter()
+            .collect(),
+        ));
+        assert_eq!(
+            to_r_source_lines(&[guard, retry]),
+            "if (is.null(x)) {\n    \
+             return()\n\
+             } else {\n    \
+             run(x)\n\
+             }\n\
+             while (!done) {\n    \
+             tryCatch({\n        \
+             done <- run()\n    \
+             }, error = function(e) {\n        \
+             message(e)\n    \
+             })\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_comprehensions() {
+        let condition = AST::Compare(Compare::new_wrapped(
+            identifier("x"),
+            AST::GtE(GtE::new_wrapped()),
+            AST::BigIntLiteral(BigIntLiteral::new_wrapped(1)),
+        ));
+        let squares = AST::ListComp(ListComp::new_wrapped(
+            call("f", vec![identifier("x")], vec![]),
+            identifier("x"),
+            identifier("xs"),
+            AOption(ROption::RSome(condition)),
+        ));
+        assert_eq!(
+            squares.to_r_code(),
+            "lapply(Filter(function(x) x >= 1L, xs), function(x) f(x))\n"
+        );
+        let named = AST::DictComp(DictComp::new_wrapped(
+            identifier("x"),
+            call("f", vec![identifier("x")], vec![]),
+            identifier("x"),
+            identifier("xs"),
+            AOption(ROption::RNone),
+        ));
+        assert_eq!(
+            named.to_r_code(),
+            "setNames(lapply(xs, function(x) f(x)), sapply(xs, function(x) x))\n"
+        );
+    }
+}
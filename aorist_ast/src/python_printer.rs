@@ -0,0 +1,996 @@
+//! Renders AST nodes as Python source, laid out the way Black lays out the
+//! output of `ast` and astor, without going through an interpreter.
+//!
+//! Only the subset of Black's rules needed for generated code is
+//! implemented: a line that does not fit is split at its last bracket pair
+//! (trying earlier ones if the line before the bracket would still be too
+//! long), the optional parentheses around a right-hand side are only added
+//! when splitting inside brackets is impossible, collection literals are
+//! exploded one element per line with a trailing comma, and arguments are
+//! kept on a single line when they fit.
+use crate::{AncestorRecord, If, StringLiteral, AST};
+use abi_stable::std_types::ROption;
+use aorist_primitives::{AOption, AString, AVec};
+use linked_hash_map::LinkedHashMap;
+
+/// Maximum length of a line of generated Python code, as given to Black.
+pub const PYTHON_LINE_LENGTH: usize = 80;
+const INDENT: &str = "    ";
+
+/// Part of a line of Python code, before it is laid out.
+#[derive(Clone, Debug)]
+enum Doc {
+    /// Text that is never split. May span several lines, for triple-quoted
+    /// strings.
+    Text(String),
+    Seq(Vec<Doc>),
+    Group(Group),
+    /// Operands of a binary operator, split before the operator.
+    Operation(&'static str, Vec<Doc>),
+}
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GroupKind {
+    /// List, dict or tuple literal, exploded one element per line when
+    /// split. Tuples of one element have a trailing comma.
+    Collection { trailing_comma: bool },
+    /// Arguments of a call, parameters of a function or a subscript, kept
+    /// on one line when they fit.
+    Trailer,
+    /// Optional parentheses around a right-hand side, only shown when the
+    /// line is split there.
+    Invisible,
+    /// List or dict comprehension, split before its `for` and `if` clauses.
+    Comprehension,
+}
+#[derive(Clone, Debug)]
+struct Group {
+    open: &'static str,
+    close: &'static str,
+    items: Vec<Doc>,
+    kind: GroupKind,
+}
+
+fn text<T: Into<String>>(value: T) -> Doc {
+    Doc::Text(value.into())
+}
+fn group(open: &'static str, close: &'static str, items: Vec<Doc>, kind: GroupKind) -> Doc {
+    Doc::Group(Group {
+        open,
+        close,
+        items,
+        kind,
+    })
+}
+
+impl Doc {
+    fn flat(&self) -> String {
+        match self {
+            Doc::Text(x) => x.clone(),
+            Doc::Seq(parts) => parts.iter().map(|x| x.flat()).collect(),
+            Doc::Group(g) => match g.kind {
+                GroupKind::Invisible => g.items.iter().map(|x| x.flat()).collect(),
+                _ => format!("{}{}{}", g.open, flat_items(g), g.close),
+            },
+            Doc::Operation(op, operands) => operands
+                .iter()
+                .map(|x| x.flat())
+                .collect::<Vec<_>>()
+                .join(&format!(" {} ", op)),
+        }
+    }
+    /// Appends the parts of nested sequences to `out`.
+    fn flatten_into(self, out: &mut Vec<Doc>) {
+        match self {
+            Doc::Seq(parts) => {
+                for part in parts {
+                    part.flatten_into(out);
+                }
+            }
+            x => out.push(x),
+        }
+    }
+    /// Whether the line can be split at this part.
+    fn is_splittable_group(&self) -> bool {
+        match self {
+            Doc::Group(g) => !g.items.is_empty(),
+            _ => false,
+        }
+    }
+}
+fn flat_items(g: &Group) -> String {
+    let separator = match g.kind {
+        GroupKind::Comprehension => " ",
+        _ => ", ",
+    };
+    let mut out = g
+        .items
+        .iter()
+        .map(|x| x.flat())
+        .collect::<Vec<_>>()
+        .join(separator);
+    if let GroupKind::Collection {
+        trailing_comma: true,
+    } = g.kind
+    {
+        out.push(',');
+    }
+    out
+}
+fn flat_all(parts: &[Doc]) -> String {
+    parts.iter().map(|x| x.flat()).collect()
+}
+fn indent(depth: usize) -> String {
+    INDENT.repeat(depth)
+}
+fn fits(depth: usize, line: &str) -> bool {
+    !line.contains('\n') && INDENT.len() * depth + line.chars().count() <= PYTHON_LINE_LENGTH
+}
+fn flatten(parts: Vec<Doc>) -> Vec<Doc> {
+    let mut out = Vec::new();
+    for part in parts {
+        part.flatten_into(&mut out);
+    }
+    out
+}
+
+/// Lays out a logical line, splitting it as needed.
+fn format_line(parts: Vec<Doc>, depth: usize, out: &mut Vec<String>) {
+    let parts = flatten(parts);
+    let line = flat_all(&parts);
+    if fits(depth, &line) {
+        out.push(format!("{}{}", indent(depth), line));
+        return;
+    }
+    let groups = parts
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| x.is_splittable_group())
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    // try splitting at the last bracket first, then at earlier ones while
+    // whatever follows them still fits on a line
+    for pos in groups.iter().rev() {
+        let tail = flat_all(&parts[*pos + 1..]);
+        if tail.chars().count() > PYTHON_LINE_LENGTH {
+            break;
+        }
+        if let Some(lines) = split_at(&parts, *pos, depth, true) {
+            out.extend(lines);
+            return;
+        }
+    }
+    if let Some(pos) = groups.last() {
+        if let Some(lines) = split_at(&parts, *pos, depth, false) {
+            out.extend(lines);
+            return;
+        }
+    }
+    out.push(format!("{}{}", indent(depth), line));
+}
+/// Splits a line at the group at `pos`. Returns None if the split is not
+/// worth it, or, when `require_short_head` is set, if the line before the
+/// bracket does not fit.
+fn split_at(
+    parts: &[Doc],
+    pos: usize,
+    depth: usize,
+    require_short_head: bool,
+) -> Option<Vec<String>> {
+    let g = match &parts[pos] {
+        Doc::Group(g) => g,
+        _ => return None,
+    };
+    if g.kind == GroupKind::Invisible {
+        let body = flatten(g.items.clone());
+        // split inside the right-hand side's own brackets if it ends with
+        // some, rather than wrapping it in parentheses
+        if body.last().map_or(false, |x| x.is_splittable_group()) {
+            let inlined = parts[..pos]
+                .iter()
+                .cloned()
+                .chain(body.iter().cloned())
+                .chain(parts[pos + 1..].iter().cloned())
+                .collect::<Vec<_>>();
+            let inner = inlined.len() - (parts.len() - pos - 1) - 1;
+            if let Some(lines) = split_at(&inlined, inner, depth, true) {
+                return Some(lines);
+            }
+        }
+        let body_line = flat_all(&body);
+        let can_split_body = body.iter().any(|x| x.is_splittable_group())
+            || matches!(body.as_slice(), [Doc::Operation(_, _)]);
+        if !fits(depth + 1, &body_line) && !can_split_body {
+            return None;
+        }
+    }
+    let head = flat_all(&parts[..pos]) + g.open;
+    if require_short_head && !fits(depth, &head) {
+        return None;
+    }
+    let mut lines = Vec::new();
+    let mut head_parts = parts[..pos].to_vec();
+    head_parts.push(text(g.open));
+    format_line(head_parts, depth, &mut lines);
+    format_body(g, depth + 1, &mut lines);
+    let mut tail_parts = vec![text(g.close)];
+    tail_parts.extend(parts[pos + 1..].iter().cloned());
+    format_line(tail_parts, depth, &mut lines);
+    Some(lines)
+}
+/// Lays out the elements between a pair of brackets.
+fn format_body(g: &Group, depth: usize, out: &mut Vec<String>) {
+    let line = flat_items(g);
+    let explode = match g.kind {
+        GroupKind::Collection { .. } => g.items.len() > 1,
+        _ => false,
+    };
+    if fits(depth, &line) && !explode {
+        out.push(format!("{}{}", indent(depth), line));
+        return;
+    }
+    if g.kind == GroupKind::Comprehension {
+        for item in g.items.iter() {
+            format_line(vec![item.clone()], depth, out);
+        }
+        return;
+    }
+    let has_commas = g.items.len() > 1
+        || g.kind
+            == GroupKind::Collection {
+                trailing_comma: true,
+            };
+    if has_commas {
+        for item in g.items.iter() {
+            format_line(vec![item.clone(), text(",")], depth, out);
+        }
+        return;
+    }
+    match g.items.first() {
+        Some(Doc::Operation(op, operands)) if !fits(depth, &line) => {
+            for (i, operand) in operands.iter().enumerate() {
+                let prefix = match i {
+                    0 => text(""),
+                    _ => text(format!("{} ", op)),
+                };
+                format_line(vec![prefix, operand.clone()], depth, out);
+            }
+        }
+        Some(item) => format_line(vec![item.clone()], depth, out),
+        None => {}
+    }
+}
+
+/// Python representation of a string, quoted the way Black normalizes it:
+/// double quotes, unless that would need more escapes than single quotes.
+fn quote_string(value: &str) -> String {
+    let singles = value.matches('\'').count();
+    let doubles = value.matches('"').count();
+    let quote = match doubles > singles {
+        true => '\'',
+        false => '"',
+    };
+    let mut out = String::new();
+    out.push(quote);
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c == quote => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                out.push_str(&format!("\\x{:02x}", c as u32));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push(quote);
+    out
+}
+/// Multi-line SQL queries are laid out between triple quotes.
+fn triple_quote_string(value: &str) -> String {
+    format!(
+        "\"\"\"{}\"\"\"",
+        value.replace('\\', "\\\\").replace("\"\"\"", "\"\"\\\"")
+    )
+}
+/// Python's `repr` of a float: the shortest representation that round-trips,
+/// in scientific notation for exponents below -4 or from 16 up.
+fn float_repr(value: f64) -> String {
+    if value.is_nan() {
+        return "float(\"nan\")".to_string();
+    }
+    if value.is_infinite() {
+        return match value > 0.0 {
+            true => "float(\"inf\")".to_string(),
+            false => "float(\"-inf\")".to_string(),
+        };
+    }
+    let scientific = format!("{:e}", value);
+    let (mantissa, exponent) = scientific.split_at(scientific.find('e').unwrap());
+    let exponent = exponent[1..].parse::<i32>().unwrap();
+    if value != 0.0 && (exponent < -4 || exponent >= 16) {
+        return format!(
+            "{}e{}{:02}",
+            mantissa,
+            match exponent < 0 {
+                true => '-',
+                false => '+',
+            },
+            exponent.abs()
+        );
+    }
+    let positional = format!("{}", value);
+    match positional.contains('.') {
+        true => positional,
+        false => format!("{}.0", positional),
+    }
+}
+fn string_doc(literal: &StringLiteral, depth: usize) -> Doc {
+    match literal.is_sql() {
+        true => {
+            let value = literal.pretty_sql_value(depth);
+            match value.as_str().contains('\n') {
+                true => text(triple_quote_string(value.as_str())),
+                false => text(quote_string(value.as_str())),
+            }
+        }
+        false => text(quote_string(literal.value().as_str())),
+    }
+}
+fn keyword_docs(keywords: &LinkedHashMap<AString, AST>, depth: usize) -> Vec<Doc> {
+    keywords
+        .iter()
+        .map(|(k, v)| Doc::Seq(vec![text(format!("{}=", k)), expression_doc(v, depth)]))
+        .collect()
+}
+/// Python precedence of the node, higher binding tighter.
+fn precedence(node: &AST) -> u8 {
+    match node {
+        AST::Lambda(_) => 1,
+        AST::BoolOp(rw) => match rw.read().op() {
+            AST::Or(_) => 2,
+            _ => 3,
+        },
+        AST::Not(_) => 4,
+        AST::Compare(_) => 5,
+        AST::BinOp(_) => 6,
+        _ => 10,
+    }
+}
+/// Operand of an operator binding at least as tight as `min_precedence`,
+/// parenthesized if needed.
+fn operand_doc(node: &AST, min_precedence: u8, depth: usize) -> Doc {
+    match precedence(node) < min_precedence {
+        true => group(
+            "(",
+            ")",
+            vec![expression_doc(node, depth)],
+            GroupKind::Trailer,
+        ),
+        false => expression_doc(node, depth),
+    }
+}
+/// Operand of an attribute access, a call or a subscript.
+fn primary_doc(node: &AST, depth: usize) -> Doc {
+    operand_doc(node, 10, depth)
+}
+/// The `for` and `if` clauses of a comprehension.
+fn comprehension_clauses(
+    target: &AST,
+    iter: &AST,
+    condition: &AOption<AST>,
+    depth: usize,
+) -> Vec<Doc> {
+    let mut clauses = vec![Doc::Seq(vec![
+        text("for "),
+        target_doc(target, depth),
+        text(" in "),
+        operand_doc(iter, 2, depth),
+    ])];
+    if let AOption(ROption::RSome(condition)) = condition {
+        clauses.push(Doc::Seq(vec![
+            text("if "),
+            operand_doc(condition, 2, depth),
+        ]));
+    }
+    clauses
+}
+/// Assignment targets are rendered without the parentheses of tuples.
+fn target_doc(node: &AST, depth: usize) -> Doc {
+    match node {
+        AST::Tuple(rw) => {
+            let tuple = rw.read();
+            let elems = tuple.elems();
+            let mut parts = Vec::new();
+            for (i, elem) in elems.iter().enumerate() {
+                if i > 0 {
+                    parts.push(text(", "));
+                }
+                parts.push(expression_doc(elem, depth + 1));
+            }
+            if elems.len() == 1 {
+                parts.push(text(","));
+            }
+            Doc::Seq(parts)
+        }
+        _ => expression_doc(node, depth),
+    }
+}
+/// The depths passed down mirror those of `to_python_ast_node`, since they
+/// determine the indentation of SQL queries.
+fn expression_doc(node: &AST, depth: usize) -> Doc {
+    match node {
+        AST::StringLiteral(rw) => string_doc(&rw.read(), depth),
+        AST::SimpleIdentifier(rw) => text(rw.read().name().as_str()),
+        AST::BooleanLiteral(rw) => text(match rw.read().val() {
+            true => "True",
+            false => "False",
+        }),
+        AST::BigIntLiteral(rw) => text(rw.read().val().to_string()),
+        AST::FloatLiteral(rw) => text(float_repr(rw.read().val().as_f64())),
+        AST::None(_) => text("None"),
+        AST::Subscript(rw) => {
+            let subscript = rw.read();
+            Doc::Seq(vec![
+                primary_doc(&subscript.a(), depth + 1),
+                group(
+                    "[",
+                    "]",
+                    vec![expression_doc(&subscript.b(), depth + 1)],
+                    GroupKind::Trailer,
+                ),
+            ])
+        }
+        AST::Attribute(rw) => {
+            let attribute = rw.read();
+            Doc::Seq(vec![
+                primary_doc(&attribute.value(), depth),
+                text(format!(".{}", attribute.name())),
+            ])
+        }
+        AST::Call(rw) => {
+            let call = rw.read();
+            let items = call
+                .args()
+                .iter()
+                .map(|x| expression_doc(x, depth + 1))
+                .chain(keyword_docs(&call.keywords(), depth + 1).into_iter())
+                .collect();
+            Doc::Seq(vec![
+                primary_doc(&call.function(), depth),
+                group("(", ")", items, GroupKind::Trailer),
+            ])
+        }
+        AST::Formatted(rw) => {
+            let formatted = rw.read();
+            Doc::Seq(vec![
+                primary_doc(&formatted.fmt(), depth),
+                text(".format"),
+                group(
+                    "(",
+                    ")",
+                    keyword_docs(&formatted.keywords(), depth + 1),
+                    GroupKind::Trailer,
+                ),
+            ])
+        }
+        AST::List(rw) => {
+            let list = rw.read();
+            group(
+                "[",
+                "]",
+                list.elems()
+                    .iter()
+                    .map(|x| expression_doc(x, depth))
+                    .collect(),
+                GroupKind::Collection {
+                    trailing_comma: false,
+                },
+            )
+        }
+        AST::Tuple(rw) => {
+            let tuple = rw.read();
+            let elems = tuple.elems();
+            group(
+                "(",
+                ")",
+                elems.iter().map(|x| expression_doc(x, depth + 1)).collect(),
+                GroupKind::Collection {
+                    trailing_comma: elems.len() == 1,
+                },
+            )
+        }
+        AST::Dict(rw) => {
+            let dict = rw.read();
+            group(
+                "{",
+                "}",
+                dict.elems()
+                    .iter()
+                    .map(|(k, v)| {
+                        Doc::Seq(vec![
+                            text(quote_string(k.as_str())),
+                            text(": "),
+                            expression_doc(v, depth + 1),
+                        ])
+                    })
+                    .collect(),
+                GroupKind::Collection {
+                    trailing_comma: false,
+                },
+            )
+        }
+        AST::BinOp(rw) => {
+            let binop = rw.read();
+            let op = match binop.op() {
+                AST::Add(_) => "+",
+                ref x => panic!("AST node {} is not a binary operator", x.name()),
+            };
+            let right = operand_doc(&binop.right(), 7, depth);
+            let mut operands = match operand_doc(&binop.left(), 6, depth) {
+                Doc::Operation(left_op, left) if left_op == op => left,
+                x => vec![x],
+            };
+            operands.push(right);
+            Doc::Operation(op, operands)
+        }
+        AST::Compare(rw) => {
+            let compare = rw.read();
+            let op = match compare.op() {
+                AST::Eq(_) => "==",
+                AST::NotEq(_) => "!=",
+                AST::Lt(_) => "<",
+                AST::LtE(_) => "<=",
+                AST::Gt(_) => ">",
+                AST::GtE(_) => ">=",
+                AST::Is(_) => "is",
+                AST::IsNot(_) => "is not",
+                AST::In(_) => "in",
+                AST::NotIn(_) => "not in",
+                ref x => panic!("AST node {} is not a comparison operator", x.name()),
+            };
+            Doc::Operation(
+                op,
+                vec![
+                    operand_doc(&compare.left(), 6, depth),
+                    operand_doc(&compare.right(), 6, depth),
+                ],
+            )
+        }
+        AST::BoolOp(rw) => {
+            let bool_op = rw.read();
+            let (op, min_precedence) = match bool_op.op() {
+                AST::And(_) => ("and", 4),
+                AST::Or(_) => ("or", 3),
+                ref x => panic!("AST node {} is not a boolean operator", x.name()),
+            };
+            Doc::Operation(
+                op,
+                bool_op
+                    .values()
+                    .iter()
+                    .map(|x| operand_doc(x, min_precedence, depth))
+                    .collect(),
+            )
+        }
+        AST::Not(rw) => Doc::Seq(vec![
+            text("not "),
+            operand_doc(&rw.read().operand(), 4, depth),
+        ]),
+        AST::Lambda(rw) => {
+            let lambda = rw.read();
+            let args = lambda
+                .args()
+                .iter()
+                .map(|x| expression_doc(x, depth).flat())
+                .collect::<Vec<_>>();
+            let head = match args.is_empty() {
+                true => "lambda: ".to_string(),
+                false => format!("lambda {}: ", args.join(", ")),
+            };
+            Doc::Seq(vec![text(head), expression_doc(&lambda.body(), depth)])
+        }
+        AST::ListComp(rw) => {
+            let comp = rw.read();
+            let mut items = vec![operand_doc(&comp.elt(), 2, depth)];
+            items.extend(comprehension_clauses(
+                &comp.target(),
+                &comp.iter(),
+                &comp.condition(),
+                depth,
+            ));
+            group("[", "]", items, GroupKind::Comprehension)
+        }
+        AST::DictComp(rw) => {
+            let comp = rw.read();
+            let mut items = vec![Doc::Seq(vec![
+                operand_doc(&comp.key(), 2, depth),
+                text(": "),
+                operand_doc(&comp.value(), 2, depth),
+            ])];
+            items.extend(comprehension_clauses(
+                &comp.target(),
+                &comp.iter(),
+                &comp.condition(),
+                depth,
+            ));
+            group("{", "}", items, GroupKind::Comprehension)
+        }
+        _ => panic!("AST node {} is not an expression", node.name()),
+    }
+}
+
+/// Lines of rendered source holding a statement with ancestors, numbered
+/// from 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceRange {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub ancestors: AVec<AncestorRecord>,
+}
+impl SourceRange {
+    /// The same lines, after `lines` more lines were added before them.
+    pub fn shift(&self, lines: usize) -> Self {
+        Self {
+            start_line: self.start_line + lines,
+            end_line: self.end_line + lines,
+            ancestors: self.ancestors.clone(),
+        }
+    }
+}
+
+/// Rendered statement, with whether it is a function definition (which
+/// Black surrounds with blank lines), and the ranges of its lines holding
+/// statements with ancestors, itself included.
+struct Statement {
+    lines: Vec<String>,
+    is_def: bool,
+    ranges: Vec<SourceRange>,
+}
+
+fn format_block(
+    statements: &[AST],
+    depth: usize,
+    out: &mut Vec<String>,
+    ranges: &mut Vec<SourceRange>,
+) {
+    let rendered = statements
+        .iter()
+        .map(|x| statement(x, depth))
+        .collect::<Vec<_>>();
+    if rendered.is_empty() {
+        out.push(format!("{}pass", indent(depth)));
+    }
+    let blank_lines = match depth {
+        0 => 2,
+        _ => 1,
+    };
+    for (i, statement) in rendered.iter().enumerate() {
+        if i > 0 && (statement.is_def || rendered[i - 1].is_def) {
+            for _ in 0..blank_lines {
+                out.push(String::new());
+            }
+        }
+        ranges.extend(statement.ranges.iter().map(|x| x.shift(out.len())));
+        out.extend(statement.lines.iter().cloned());
+    }
+}
+fn statement(node: &AST, depth: usize) -> Statement {
+    let mut lines = Vec::new();
+    let mut is_def = false;
+    let mut ranges = Vec::new();
+    match node {
+        AST::Assignment(rw) => {
+            let assignment = rw.read();
+            format_line(
+                vec![
+                    target_doc(&assignment.target(), depth),
+                    text(" = "),
+                    group(
+                        "(",
+                        ")",
+                        vec![expression_doc(&assignment.call(), depth)],
+                        GroupKind::Invisible,
+                    ),
+                ],
+                depth,
+                &mut lines,
+            );
+        }
+        AST::Expression(rw) => {
+            format_line(
+                vec![expression_doc(&rw.read().inner(), depth)],
+                depth,
+                &mut lines,
+            );
+        }
+        AST::ImportNode(rw) => {
+            format_line(
+                vec![text("import "), expression_doc(&rw.read().inner(), depth)],
+                depth,
+                &mut lines,
+            );
+        }
+        AST::ForLoop(rw) => {
+            let for_loop = rw.read();
+            format_line(
+                vec![
+                    text("for "),
+                    target_doc(&for_loop.target(), depth),
+                    text(" in "),
+                    group(
+                        "(",
+                        ")",
+                        vec![expression_doc(&for_loop.iter(), depth)],
+                        GroupKind::Invisible,
+                    ),
+                    text(":"),
+                ],
+                depth,
+                &mut lines,
+            );
+            format_block(&for_loop.body(), depth + 1, &mut lines, &mut ranges);
+        }
+        AST::If(rw) => format_if(&rw.read(), "if", depth, &mut lines, &mut ranges),
+        AST::While(rw) => {
+            let while_loop = rw.read();
+            format_line(
+                vec![
+                    text("while "),
+                    group(
+                        "(",
+                        ")",
+                        vec![expression_doc(&while_loop.test(), depth)],
+                        GroupKind::Invisible,
+                    ),
+                    text(":"),
+                ],
+                depth,
+                &mut lines,
+            );
+            format_block(&while_loop.body(), depth + 1, &mut lines, &mut ranges);
+        }
+        AST::Try(rw) => {
+            let try_except = rw.read();
+            lines.push(format!("{}try:", indent(depth)));
+            format_block(&try_except.body(), depth + 1, &mut lines, &mut ranges);
+            let mut handler = vec![text("except")];
+            if let AOption(ROption::RSome(exception)) = try_except.exception() {
+                handler.push(text(" "));
+                handler.push(expression_doc(&exception, depth));
+                if let AOption(ROption::RSome(name)) = try_except.name() {
+                    handler.push(text(format!(" as {}", name)));
+                }
+            }
+            handler.push(text(":"));
+            format_line(handler, depth, &mut lines);
+            format_block(&try_except.handler(), depth + 1, &mut lines, &mut ranges);
+        }
+        AST::Return(rw) => match rw.read().value() {
+            AOption(ROption::RSome(value)) => format_line(
+                vec![
+                    text("return "),
+                    group(
+                        "(",
+                        ")",
+                        vec![expression_doc(&value, depth)],
+                        GroupKind::Invisible,
+                    ),
+                ],
+                depth,
+                &mut lines,
+            ),
+            AOption(ROption::RNone) => lines.push(format!("{}return", indent(depth))),
+        },
+        AST::With(rw) => {
+            let with = rw.read();
+            let mut parts = vec![text("with ")];
+            for (i, item) in with.items().iter().enumerate() {
+                if i > 0 {
+                    parts.push(text(", "));
+                }
+                parts.push(expression_doc(item, depth));
+            }
+            parts.push(text(":"));
+            format_line(parts, depth, &mut lines);
+            format_block(&with.body(), depth + 1, &mut lines, &mut ranges);
+        }
+        AST::FunctionDef(rw) => {
+            let fun = rw.read();
+            is_def = true;
+            for decorator in fun.decorators().iter() {
+                format_line(
+                    vec![text("@"), expression_doc(decorator, depth)],
+                    depth,
+                    &mut lines,
+                );
+            }
+            let params = fun
+                .args()
+                .iter()
+                .map(|x| expression_doc(x, depth))
+                .chain(keyword_docs(&fun.kwargs(), depth).into_iter())
+                .collect();
+            format_line(
+                vec![
+                    text(format!("def {}", fun.name())),
+                    group("(", ")", params, GroupKind::Trailer),
+                    text(":"),
+                ],
+                depth,
+                &mut lines,
+            );
+            format_block(&fun.body(), depth + 1, &mut lines, &mut ranges);
+        }
+        _ => format_line(vec![expression_doc(node, depth)], depth, &mut lines),
+    }
+    if let AOption(ROption::RSome(ancestors)) = node.get_ancestors() {
+        ranges.insert(
+            0,
+            SourceRange {
+                start_line: 1,
+                end_line: lines.len(),
+                ancestors,
+            },
+        );
+    }
+    Statement {
+        lines,
+        is_def,
+        ranges,
+    }
+}
+
+/// An `if` statement, or an `elif` clause when `keyword` is "elif". An
+/// `else` block holding a single `if` statement is rendered as `elif`.
+fn format_if(
+    if_else: &If,
+    keyword: &str,
+    depth: usize,
+    lines: &mut Vec<String>,
+    ranges: &mut Vec<SourceRange>,
+) {
+    format_line(
+        vec![
+            text(format!("{} ", keyword)),
+            group(
+                "(",
+                ")",
+                vec![expression_doc(&if_else.test(), depth)],
+                GroupKind::Invisible,
+            ),
+            text(":"),
+        ],
+        depth,
+        lines,
+    );
+    format_block(&if_else.body(), depth + 1, lines, ranges);
+    if let AOption(ROption::RSome(orelse)) = if_else.orelse() {
+        match orelse.iter().collect::<Vec<_>>().as_slice() {
+            [AST::If(rw)] => format_if(&rw.read(), "elif", depth, lines, ranges),
+            _ => {
+                lines.push(format!("{}else:", indent(depth)));
+                format_block(&orelse, depth + 1, lines, ranges);
+            }
+        }
+    }
+}
+
+/// Python source of a sequence of statements, ending with a newline.
+/// Expressions are rendered as expression statements.
+pub fn to_python_source_lines(statements: &[AST]) -> String {
+    to_python_source_with_ranges(statements).0
+}
+/// Like `to_python_source_lines`, along with the lines holding each
+/// statement with ancestors, nested statements included, in the order they
+/// start.
+pub fn to_python_source_with_ranges(statements: &[AST]) -> (String, Vec<SourceRange>) {
+    if statements.is_empty() {
+        return (String::new(), Vec::new());
+    }
+    let mut lines = Vec::new();
+    let mut ranges = Vec::new();
+    format_block(statements, 0, &mut lines, &mut ranges);
+    (format!("{}\n", lines.join("\n")), ranges)
+}
+
+/// Whether a top-level statement starting with `line` defines a function
+/// or a class.
+fn is_definition(line: &str) -> bool {
+    ["def ", "async def ", "class ", "@"]
+        .iter()
+        .any(|x| line.starts_with(x))
+}
+/// Whether the first top-level statement of `block`, after the comments
+/// attached to it, is a definition.
+fn starts_with_definition(block: &str) -> bool {
+    block
+        .lines()
+        .find(|x| !x.trim().is_empty() && !x.starts_with('#'))
+        .map_or(false, is_definition)
+}
+/// Whether the last top-level statement of `block` is a definition. Lines
+/// at column 0 start a statement, unless they are inside a triple-quoted
+/// string, close brackets, or continue a compound statement.
+fn ends_with_definition(block: &str) -> bool {
+    let mut in_string = false;
+    let mut last = None;
+    for line in block.lines() {
+        if !in_string
+            && !line.is_empty()
+            && !line.starts_with(|c: char| c.is_whitespace() || ")]}#\"".contains(c))
+            && !["else:", "elif ", "except", "finally:"]
+                .iter()
+                .any(|x| line.starts_with(x))
+        {
+            last = Some(line);
+        }
+        if line.matches("\"\"\"").count() % 2 == 1 {
+            in_string = !in_string;
+        }
+    }
+    last.map_or(false, is_definition)
+}
+
+/// Concatenates blocks of Python source holding whole top-level statements,
+/// separated the way Black separates them: by two blank lines when either
+/// side of the boundary is a definition, otherwise by the blank lines the
+/// blocks start and end with, at most two.
+pub fn join_python_blocks(blocks: &[String]) -> String {
+    join_python_blocks_with_ranges(
+        &blocks
+            .iter()
+            .map(|x| (x.clone(), Vec::new()))
+            .collect::<Vec<_>>(),
+    )
+    .0
+}
+/// Like `join_python_blocks`, for blocks given with the ranges of their
+/// lines holding statements with ancestors. Returns the ranges moved to the
+/// lines the blocks end up on.
+pub fn join_python_blocks_with_ranges(
+    blocks: &[(String, Vec<SourceRange>)],
+) -> (String, Vec<SourceRange>) {
+    let mut out = String::new();
+    let mut ranges = Vec::new();
+    let mut previous: Option<(bool, usize)> = None;
+    for (block, block_ranges) in blocks {
+        let content = block.trim_matches('\n');
+        if content.trim().is_empty() {
+            continue;
+        }
+        let leading = block.len() - block.trim_start_matches('\n').len();
+        if let Some((ends_with_def, trailing)) = previous {
+            let blank_lines = match ends_with_def || starts_with_definition(content) {
+                true => 2,
+                false => (trailing + leading).min(2),
+            };
+            out.push_str(&"\n".repeat(blank_lines + 1));
+        }
+        // lines before the block, minus the blank lines it was trimmed of
+        let offset = out.matches('\n').count();
+        ranges.extend(block_ranges.iter().map(|x| SourceRange {
+            start_line: x.start_line - leading + offset,
+            end_line: x.end_line - leading + offset,
+            ancestors: x.ancestors.clone(),
+        }));
+        out.push_str(content);
+        let trailing = block.len() - block.trim_end_matches('\n').len();
+        previous = Some((ends_with_definition(content), trailing.saturating_sub(1)));
+    }
+    match out.is_empty() {
+        true => (out, ranges),
+        false => (format!("{}\n", out), ranges),
+    }
+}
+
+impl AST {
+    /// Python source of the node, as a statement, without an interpreter.
+    pub fn to_python_code(&self) -> String {
+        to_python_source_lines(&[self.clone()])
+    }
+}
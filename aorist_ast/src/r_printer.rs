@@ -0,0 +1,474 @@
+//! Renders AST nodes as R source, the way `deparse` prints the language
+//! objects returned by `to_r_ast_node`, without an R installation.
+//!
+//! Statements are printed one per line, with the bodies of loops and
+//! functions between braces and indented by four spaces. Identifiers are
+//! printed as names, backquoted when they are not syntactic.
+//!
+//! Two Python statements have no exact R counterpart and lose part of their
+//! meaning: `try` becomes a `tryCatch` whose `error` handler runs on every
+//! R error, whatever exception class the node names, and `with` becomes a
+//! `local` block that evaluates its items but never runs their cleanup.
+use crate::{If, StringLiteral, AST};
+use abi_stable::std_types::ROption;
+use aorist_primitives::{AOption, AString};
+use linked_hash_map::LinkedHashMap;
+
+const INDENT: &str = "    ";
+const RESERVED_WORDS: [&str; 19] = [
+    "if",
+    "else",
+    "repeat",
+    "while",
+    "function",
+    "for",
+    "next",
+    "break",
+    "TRUE",
+    "FALSE",
+    "NULL",
+    "Inf",
+    "NaN",
+    "NA",
+    "NA_integer_",
+    "NA_real_",
+    "NA_character_",
+    "NA_complex_",
+    "in",
+];
+
+/// Whether `name` can be used in R code without backquotes.
+fn is_syntactic_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let valid_start = match chars.next() {
+        Some('.') => !name[1..].starts_with(|c: char| c.is_ascii_digit()),
+        Some(c) => c.is_alphabetic(),
+        None => false,
+    };
+    valid_start
+        && chars.all(|c| c.is_alphanumeric() || c == '.' || c == '_')
+        && !RESERVED_WORDS.contains(&name)
+        && !(name.starts_with("..") && name[2..].chars().all(|c| c.is_ascii_digit()))
+}
+fn r_name(name: &str) -> String {
+    match is_syntactic_name(name) {
+        true => name.to_string(),
+        false => format!("`{}`", name.replace('\\', "\\\\").replace('`', "\\`")),
+    }
+}
+/// R string literal, escaped the way `deparse` escapes it.
+fn r_string(value: &str) -> String {
+    let mut out = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                out.push_str(&format!("\\{:03o}", c as u32));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+/// A double as R prints it: up to 15 significant digits, in fixed notation
+/// unless scientific notation is shorter.
+fn r_double(value: f64) -> String {
+    if value.is_nan() {
+        return "NaN".to_string();
+    }
+    if value.is_infinite() {
+        return match value > 0.0 {
+            true => "Inf".to_string(),
+            false => "-Inf".to_string(),
+        };
+    }
+    if value == 0.0 {
+        return "0".to_string();
+    }
+    let reference = format!("{:.14e}", value).parse::<f64>().unwrap();
+    let scientific = (0..15)
+        .map(|precision| format!("{:.*e}", precision, value))
+        .find(|x| x.parse::<f64>().unwrap() == reference)
+        .unwrap();
+    let (mantissa, exponent) = scientific.split_at(scientific.find('e').unwrap());
+    let exponent = exponent[1..].parse::<i32>().unwrap();
+    let sign = match value < 0.0 {
+        true => "-",
+        false => "",
+    };
+    let digits = mantissa.trim_start_matches('-').replace('.', "");
+    let fixed = if exponent < 0 {
+        format!("0.{}{}", "0".repeat((-exponent - 1) as usize), digits)
+    } else if digits.len() as i32 > exponent + 1 {
+        let (int, frac) = digits.split_at((exponent + 1) as usize);
+        format!("{}.{}", int, frac)
+    } else {
+        format!(
+            "{}{}",
+            digits,
+            "0".repeat((exponent + 1) as usize - digits.len())
+        )
+    };
+    let scientific = format!(
+        "{}e{}{:02}",
+        mantissa.trim_start_matches('-'),
+        match exponent < 0 {
+            true => '-',
+            false => '+',
+        },
+        exponent.abs()
+    );
+    match fixed.len() <= scientific.len() {
+        true => format!("{}{}", sign, fixed),
+        false => format!("{}{}", sign, scientific),
+    }
+}
+fn string_code(literal: &StringLiteral, depth: usize) -> String {
+    match literal.is_sql() {
+        true => r_string(literal.pretty_sql_value(depth).as_str()),
+        false => r_string(literal.value().as_str()),
+    }
+}
+fn arguments_code(args: &[AST], keywords: &LinkedHashMap<AString, AST>, depth: usize) -> String {
+    args.iter()
+        .map(|x| expression_code(x, depth))
+        .chain(
+            keywords
+                .iter()
+                .map(|(k, v)| format!("{} = {}", r_name(k.as_str()), expression_code(v, depth))),
+        )
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+/// R precedence of the node, higher binding tighter.
+fn precedence(node: &AST) -> u8 {
+    match node {
+        AST::Lambda(_) => 1,
+        AST::BoolOp(rw) => match rw.read().op() {
+            AST::Or(_) => 2,
+            _ => 3,
+        },
+        AST::Not(_) => 4,
+        AST::Compare(_) => 5,
+        AST::BinOp(_) => 6,
+        _ => 10,
+    }
+}
+/// Operand of an operator binding at least as tight as `min_precedence`,
+/// parenthesized if needed.
+fn operand_code(node: &AST, min_precedence: u8, depth: usize) -> String {
+    match precedence(node) < min_precedence {
+        true => format!("({})", expression_code(node, depth)),
+        false => expression_code(node, depth),
+    }
+}
+/// Operand of a subscript or of `$`.
+fn primary_code(node: &AST, depth: usize) -> String {
+    operand_code(node, 10, depth)
+}
+/// Elements a comprehension iterates over, filtered by its condition.
+fn comprehension_items(target: &AST, iter: &AST, condition: &AOption<AST>, depth: usize) -> String {
+    let iter = expression_code(iter, depth);
+    match condition {
+        AOption(ROption::RSome(condition)) => format!(
+            "Filter(function({}) {}, {})",
+            expression_code(target, depth),
+            expression_code(condition, depth),
+            iter
+        ),
+        AOption(ROption::RNone) => iter,
+    }
+}
+/// The depths passed down mirror those of `to_r_ast_node`, since they
+/// determine the indentation of SQL queries.
+fn expression_code(node: &AST, depth: usize) -> String {
+    match node {
+        AST::StringLiteral(rw) => string_code(&rw.read(), depth),
+        AST::SimpleIdentifier(rw) => r_name(rw.read().name().as_str()),
+        AST::BooleanLiteral(rw) => match rw.read().val() {
+            true => "TRUE".to_string(),
+            false => "FALSE".to_string(),
+        },
+        AST::BigIntLiteral(rw) => format!("{}L", rw.read().val()),
+        AST::FloatLiteral(rw) => r_double(rw.read().val().as_f64()),
+        AST::None(_) => "NULL".to_string(),
+        AST::Subscript(rw) => {
+            let subscript = rw.read();
+            format!(
+                "{}[[{}]]",
+                primary_code(&subscript.a(), depth),
+                expression_code(&subscript.b(), depth)
+            )
+        }
+        AST::Attribute(rw) => {
+            let attribute = rw.read();
+            format!(
+                "{}${}",
+                primary_code(&attribute.value(), depth),
+                r_name(attribute.name().as_str())
+            )
+        }
+        AST::Call(rw) => {
+            let call = rw.read();
+            let name = match call.function() {
+                AST::SimpleIdentifier(x) => x.read().name(),
+                _ => panic!("function name must be SimpleIdentifier"),
+            };
+            format!(
+                "{}({})",
+                r_name(name.as_str()),
+                arguments_code(&call.args(), &call.keywords(), depth)
+            )
+        }
+        AST::Formatted(rw) => {
+            let formatted = rw.read();
+            format!(
+                "glue({})",
+                arguments_code(&[formatted.fmt()], &formatted.keywords(), depth)
+            )
+        }
+        AST::List(rw) => format!(
+            "list({})",
+            arguments_code(&rw.read().elems(), &LinkedHashMap::new(), depth)
+        ),
+        AST::Tuple(rw) => format!(
+            "list({})",
+            arguments_code(&rw.read().elems(), &LinkedHashMap::new(), depth)
+        ),
+        AST::Dict(rw) => format!("list({})", arguments_code(&[], &rw.read().elems(), depth)),
+        AST::BinOp(rw) => {
+            let binop = rw.read();
+            let op = match binop.op() {
+                AST::Add(_) => "+",
+                _ => panic!("AST node not supported as R operator"),
+            };
+            format!(
+                "{} {} {}",
+                operand_code(&binop.left(), 6, depth),
+                op,
+                operand_code(&binop.right(), 7, depth)
+            )
+        }
+        AST::Compare(rw) => {
+            let compare = rw.read();
+            let left = operand_code(&compare.left(), 6, depth);
+            let right = operand_code(&compare.right(), 6, depth);
+            // `is` only has an R counterpart for comparisons with NULL
+            let identical = || match compare.right() {
+                AST::None(_) => format!("is.null({})", expression_code(&compare.left(), depth)),
+                _ => format!(
+                    "identical({}, {})",
+                    expression_code(&compare.left(), depth),
+                    expression_code(&compare.right(), depth)
+                ),
+            };
+            let op = match compare.op() {
+                AST::Eq(_) => "==",
+                AST::NotEq(_) => "!=",
+                AST::Lt(_) => "<",
+                AST::LtE(_) => "<=",
+                AST::Gt(_) => ">",
+                AST::GtE(_) => ">=",
+                AST::In(_) => "%in%",
+                AST::NotIn(_) => return format!("!({} %in% {})", left, right),
+                AST::Is(_) => return identical(),
+                AST::IsNot(_) => return format!("!{}", identical()),
+                _ => panic!("AST node not supported as R comparison operator"),
+            };
+            format!("{} {} {}", left, op, right)
+        }
+        AST::BoolOp(rw) => {
+            let bool_op = rw.read();
+            let (op, min_precedence) = match bool_op.op() {
+                AST::And(_) => ("&&", 4),
+                AST::Or(_) => ("||", 3),
+                _ => panic!("AST node not supported as R boolean operator"),
+            };
+            bool_op
+                .values()
+                .iter()
+                .map(|x| operand_code(x, min_precedence, depth))
+                .collect::<Vec<_>>()
+                .join(&format!(" {} ", op))
+        }
+        AST::Not(rw) => format!("!{}", operand_code(&rw.read().operand(), 10, depth)),
+        AST::Lambda(rw) => {
+            let lambda = rw.read();
+            format!(
+                "function({}) {}",
+                arguments_code(&lambda.args(), &LinkedHashMap::new(), depth),
+                expression_code(&lambda.body(), depth)
+            )
+        }
+        AST::ListComp(rw) => {
+            let comp = rw.read();
+            format!(
+                "lapply({}, function({}) {})",
+                comprehension_items(&comp.target(), &comp.iter(), &comp.condition(), depth),
+                expression_code(&comp.target(), depth),
+                expression_code(&comp.elt(), depth)
+            )
+        }
+        AST::DictComp(rw) => {
+            let comp = rw.read();
+            let items = comprehension_items(&comp.target(), &comp.iter(), &comp.condition(), depth);
+            let target = expression_code(&comp.target(), depth);
+            format!(
+                "setNames(lapply({}, function({}) {}), sapply({}, function({}) {}))",
+                items,
+                target,
+                expression_code(&comp.value(), depth),
+                items,
+                target,
+                expression_code(&comp.key(), depth)
+            )
+        }
+        _ => panic!("AST node {} is not an R expression", node.name()),
+    }
+}
+
+fn format_block(statements: &[AST], depth: usize, out: &mut Vec<String>) {
+    for statement in statements {
+        format_statement(statement, depth, out);
+    }
+}
+fn format_statement(node: &AST, depth: usize, out: &mut Vec<String>) {
+    let indent = INDENT.repeat(depth);
+    match node {
+        AST::Assignment(rw) => {
+            let assignment = rw.read();
+            out.push(format!(
+                "{}{} <- {}",
+                indent,
+                expression_code(&assignment.target(), depth),
+                expression_code(&assignment.call(), depth)
+            ));
+        }
+        AST::Expression(rw) => {
+            out.push(format!(
+                "{}{}",
+                indent,
+                expression_code(&rw.read().inner(), depth)
+            ));
+        }
+        AST::ImportNode(rw) => {
+            out.push(format!(
+                "{}library({})",
+                indent,
+                expression_code(&rw.read().inner(), depth)
+            ));
+        }
+        AST::ForLoop(rw) => {
+            let for_loop = rw.read();
+            out.push(format!(
+                "{}for ({} in {}) {{",
+                indent,
+                expression_code(&for_loop.target(), depth),
+                expression_code(&for_loop.iter(), depth)
+            ));
+            format_block(&for_loop.body(), depth + 1, out);
+            out.push(format!("{}}}", indent));
+        }
+        AST::FunctionDef(rw) => {
+            let fun = rw.read();
+            if fun.decorators().len() > 0 {
+                panic!("Function decorators not supported in R");
+            }
+            out.push(format!(
+                "{}{} <- function({}) {{",
+                indent,
+                r_name(fun.name().as_str()),
+                arguments_code(&fun.args(), &fun.kwargs(), depth)
+            ));
+            format_block(&fun.body(), depth + 1, out);
+            out.push(format!("{}}}", indent));
+        }
+        AST::If(rw) => {
+            format_if(&rw.read(), &indent, depth, out);
+            out.push(format!("{}}}", indent));
+        }
+        AST::While(rw) => {
+            let while_loop = rw.read();
+            out.push(format!(
+                "{}while ({}) {{",
+                indent,
+                expression_code(&while_loop.test(), depth)
+            ));
+            format_block(&while_loop.body(), depth + 1, out);
+            out.push(format!("{}}}", indent));
+        }
+        // the exception class is ignored: the handler catches every R error
+        AST::Try(rw) => {
+            let try_except = rw.read();
+            let name = match try_except.name() {
+                AOption(ROption::RSome(x)) => r_name(x.as_str()),
+                AOption(ROption::RNone) => "e".to_string(),
+            };
+            out.push(format!("{}tryCatch({{", indent));
+            format_block(&try_except.body(), depth + 1, out);
+            out.push(format!("{}}}, error = function({}) {{", indent, name));
+            format_block(&try_except.handler(), depth + 1, out);
+            out.push(format!("{}}})", indent));
+        }
+        AST::Return(rw) => {
+            let value = match rw.read().value() {
+                AOption(ROption::RSome(x)) => expression_code(&x, depth),
+                AOption(ROption::RNone) => String::new(),
+            };
+            out.push(format!("{}return({})", indent, value));
+        }
+        // R has no context managers: the items are evaluated at the start of
+        // a local scope running the body, and no cleanup runs when it exits
+        AST::With(rw) => {
+            let with = rw.read();
+            out.push(format!("{}local({{", indent));
+            format_block(&with.items(), depth + 1, out);
+            format_block(&with.body(), depth + 1, out);
+            out.push(format!("{}}})", indent));
+        }
+        _ => out.push(format!("{}{}", indent, expression_code(node, depth))),
+    }
+}
+
+/// An `if` statement and its `else` clauses, without the closing brace. An
+/// `else` block holding a single `if` statement is rendered as `else if`.
+fn format_if(if_else: &If, prefix: &str, depth: usize, out: &mut Vec<String>) {
+    let indent = INDENT.repeat(depth);
+    out.push(format!(
+        "{}if ({}) {{",
+        prefix,
+        expression_code(&if_else.test(), depth)
+    ));
+    format_block(&if_else.body(), depth + 1, out);
+    if let AOption(ROption::RSome(orelse)) = if_else.orelse() {
+        match orelse.iter().collect::<Vec<_>>().as_slice() {
+            [AST::If(rw)] => format_if(&rw.read(), &format!("{}}} else ", indent), depth, out),
+            _ => {
+                out.push(format!("{}}} else {{", indent));
+                format_block(&orelse, depth + 1, out);
+            }
+        }
+    }
+}
+
+/// R source of a sequence of statements, ending with a newline.
+pub fn to_r_source_lines(statements: &[AST]) -> String {
+    if statements.is_empty() {
+        return String::new();
+    }
+    let mut lines = Vec::new();
+    format_block(statements, 0, &mut lines);
+    format!("{}\n", lines.join("\n"))
+}
+
+impl AST {
+    /// R source of the node, as a statement, without an R installation.
+    pub fn to_r_code(&self) -> String {
+        to_r_source_lines(&[self.clone()])
+    }
+}
@@ -1,11 +1,14 @@
 use crate::{AncestorRecord, AST};
 use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
 use abi_stable::std_types::{RArc, ROption};
+#[cfg(feature = "r")]
 use aorist_extendr_api::prelude::*;
 use aorist_primitives::AOption;
 use aorist_primitives::AString;
 use aorist_primitives::AVec;
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
+#[cfg(feature = "python")]
 use pyo3::types::PyModule;
 use std::hash::Hash;
 
@@ -86,6 +89,7 @@ impl StringLiteral {
         AString::new(&out)
     }
 
+    #[cfg(feature = "python")]
     pub fn to_python_ast_node<'a>(
         &self,
         _py: Python,
@@ -99,12 +103,13 @@ impl StringLiteral {
         ast_module.getattr("Constant")?.call1((value.as_str(),))
     }
 
-    pub fn to_r_ast_node(&self, depth: usize) -> Robj {
+    #[cfg(feature = "r")]
+    pub fn to_r_ast_node(&self, depth: usize) -> Result<Robj> {
         let value = match self.is_sql {
             false => self.value.clone(),
             true => self.pretty_sql_value(depth),
         };
-        Robj::from(vec![value.as_str()])
+        Ok(Robj::from(vec![value.as_str()]))
     }
 
     pub fn new_wrapped(value: AString, is_sql: bool) -> RArc<RRwLock<Self>> {
@@ -113,6 +118,9 @@ impl StringLiteral {
     pub fn value(&self) -> AString {
         self.value.clone()
     }
+    pub fn is_sql(&self) -> bool {
+        self.is_sql
+    }
     pub fn len(&self) -> usize {
         self.value.len()
     }
@@ -0,0 +1,5 @@
+short = f("a", b="c")
+task_name_really_long = download_data_from_remote_web_location(
+    url="https://example.com/some/path/that/is/very/long/indeed/really.csv",
+    dest="/tmp/x/aaaaaaaaaaaaaaaaaaaaaaaa",
+)
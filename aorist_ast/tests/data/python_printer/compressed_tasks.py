@@ -0,0 +1,9 @@
+params_download = {
+    "download_location_3a1c": {
+        "url": "https://example.com/data.csv",
+        "dest": "/tmp/probprog/data.csv",
+    },
+    "download_location_7f02": {"url": "https://example.com/other.csv"},
+}
+for t, params in params_download.items():
+    tasks[t] = download(url=params["url"])
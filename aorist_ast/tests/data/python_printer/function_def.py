@@ -0,0 +1,11 @@
+a = "it's"
+
+
+def run_once(x, retries=3):
+    if x is None:
+        return
+    else:
+        run(x)
+
+
+run_once(a)
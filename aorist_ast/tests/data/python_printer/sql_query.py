@@ -0,0 +1,8 @@
+result = presto(
+    """
+    SELECT *
+    FROM table_with_a_long_name
+    WHERE x > 1
+    """,
+    catalog="hive",
+)
@@ -29,7 +29,6 @@ fn process_attributes_py(raw_objects: &Vec<HashMap<String, Value>>) -> AResult<(
 fn process_attributes(raw_objects: &Vec<HashMap<String, Value>>) -> AResult<()> {
     let attributes = get_raw_objects_of_type(raw_objects, "Attribute".into())?;
     let mut scope = Scope::new();
-    #[cfg(feature = "python")]
     scope.import("aorist_primitives", "define_attribute");
     scope.import("serde", "Serialize");
     scope.import("serde", "Deserialize");
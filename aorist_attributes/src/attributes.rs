@@ -96,6 +96,7 @@ impl pyo3::conversion::FromPyObject<'_> for FloatValue {
         Ok(FloatValue::from(ob))
     }
 }
+#[cfg(feature = "python")]
 impl pyo3::conversion::IntoPy<PyObject> for FloatValue {
     fn into_py(self, py: Python) -> PyObject {
         self.as_f64().into_py(py)
@@ -4,4 +4,5 @@ pub use attributes::*;
 
 #[cfg(feature = "python")]
 mod python;
+#[cfg(feature = "python")]
 pub use python::attributes_module;
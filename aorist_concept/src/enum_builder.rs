@@ -189,6 +189,13 @@ impl Builder for EnumBuilder {
                       )*
                   }
               }
+              fn get_concept_name(&self) -> AOption<AString> {
+                  match self {
+                      #(
+                        #enum_name::#variant(x) => x.get_concept_name(),
+                      )*
+                  }
+              }
               fn compute_uuids(&mut self) {
                   match self {
                       #(
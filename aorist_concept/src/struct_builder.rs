@@ -396,6 +396,24 @@ impl Builder for StructBuilder {
             .collect::<AResult<Vec<_>>>()?;
         let py_class_name = format!("{}", struct_name);
         let types = self.get_all_types()?;
+        let has_name = unconstrainable_name
+            .iter()
+            .zip(unconstrainable_type.iter())
+            .any(|(ident, ty)| {
+                ident == "name"
+                    && match ty {
+                        Type::Path(path) => path
+                            .path
+                            .segments
+                            .last()
+                            .map_or(false, |x| x.ident == "AString" && x.arguments.is_empty()),
+                        _ => false,
+                    }
+            });
+        let name_getter = match has_name {
+            true => quote! { AOption(ROption::RSome(self.name.clone())) },
+            false => quote! { AOption(ROption::RNone) },
+        };
         Ok(TokenStream::from(quote! { paste! {
             pub enum [<#struct_name Children>] {
                 #(
@@ -927,6 +945,9 @@ impl Builder for StructBuilder {
                 fn get_tag(&self) -> AOption<AString> {
                     self.tag.clone()
                 }
+                fn get_concept_name(&self) -> AOption<AString> {
+                    #name_getter
+                }
                 fn get_children(&self) -> AVec<(
                     // struct name
                     AString,
@@ -75,6 +75,11 @@ where
     fn get_tasks_dict(&self) -> AOption<AST>;
     fn get_identifiers(&self) -> HashMap<Uuid, AST>;
     fn get_params(&self) -> HashMap<AString, AOption<ParameterTuple>>;
+    /// Ids of the tasks rendered as a single mapped task, by the key of
+    /// every task the mapped task replaces.
+    fn get_mapped_task_ids(&self, _endpoints: U::TEndpoints) -> HashMap<AString, AString> {
+        HashMap::new()
+    }
 
     fn create_standalone_tasks(
         members: AVec<RArc<RRwLock<ConstraintState<'a, C, P>>>>,
@@ -115,6 +120,7 @@ where
                 dependencies,
                 x.get_preamble(),
                 x.get_dialect(),
+                x.get_task_ancestors()?,
             ));
         }
         Ok((tasks, task_identifiers, params))
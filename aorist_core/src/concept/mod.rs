@@ -219,3 +219,63 @@ register_concept!(
     PolygonCollectionStatsUnionSchema,
     PolygonCollectionStatsFilterSchema
 );
+/// Whether tasks read from (`Source`) or write to (`Target`) a storage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageRole {
+    Source,
+    Target,
+}
+impl Concept {
+    /// Storages held by a storage setup, with their role: the source of a
+    /// replication and remote storage are read from, all other storages are
+    /// written to.
+    pub fn get_storage_roles(&self) -> AVec<(Uuid, StorageRole)> {
+        let roles = match self {
+            Concept::ReplicationStorageSetup((ref x, _, _)) => {
+                let setup = x.0.read();
+                std::iter::once((setup.source.get_uuid(), StorageRole::Source))
+                    .chain(
+                        setup
+                            .targets
+                            .iter()
+                            .map(|x| (x.get_uuid(), StorageRole::Target)),
+                    )
+                    .collect()
+            }
+            Concept::RemoteStorageSetup((ref x, _, _)) => {
+                vec![(x.0.read().remote.get_uuid(), StorageRole::Source)]
+            }
+            Concept::LocalStorageSetup((ref x, _, _)) => {
+                vec![(x.0.read().local.get_uuid(), StorageRole::Target)]
+            }
+            Concept::ComputedFromLocalData((ref x, _, _)) => {
+                vec![(x.0.read().target.get_uuid(), StorageRole::Target)]
+            }
+            Concept::TwoTierStorageSetup((ref x, _, _)) => {
+                let setup = x.0.read();
+                vec![
+                    (setup.scratch.get_uuid(), StorageRole::Target),
+                    (setup.persistent.get_uuid(), StorageRole::Target),
+                ]
+            }
+            _ => Vec::new(),
+        };
+        roles
+            .into_iter()
+            .filter_map(|(uuid, role)| match uuid {
+                AOption(ROption::RSome(uuid)) => Some((uuid, role)),
+                AOption(ROption::RNone) => None,
+            })
+            .collect()
+    }
+    /// Path of local file system locations, used by flows whose tasks read
+    /// and write local files.
+    pub fn get_local_path(&self) -> AOption<AString> {
+        match self {
+            Concept::LocalFileSystemLocation((ref x, _, _)) => {
+                AOption(ROption::RSome(x.0.read().path.clone()))
+            }
+            _ => AOption(ROption::RNone),
+        }
+    }
+}
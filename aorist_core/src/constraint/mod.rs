@@ -11,6 +11,9 @@ use std::marker::PhantomData;
 use tracing::info;
 use uuid::Uuid;
 
+mod overrides;
+pub use overrides::*;
+
 use abi_stable::{
     declare_root_module_statics,
     library::RootModule,
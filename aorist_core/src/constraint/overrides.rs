@@ -0,0 +1,269 @@
+use crate::program::TOuterProgram;
+use abi_stable::std_types::ROption;
+use anyhow::{bail, Result};
+use aorist_ast::AncestorRecord;
+use aorist_primitives::{AOption, AString, AVec};
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+/// Per-concept override of constraint attachment: either skip the constraint
+/// on concepts carrying `tag` (or descending from one that does), or pin the
+/// program, identified by its entrypoint, used to satisfy it there.
+#[cfg_attr(feature = "python", pyclass)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConstraintOverride {
+    constraint: AString,
+    tag: AString,
+    program: AOption<AString>,
+}
+#[cfg(feature = "python")]
+#[pymethods]
+impl ConstraintOverride {
+    #[staticmethod]
+    pub fn skip(constraint: &str, tag: &str) -> Self {
+        Self {
+            constraint: constraint.into(),
+            tag: tag.into(),
+            program: AOption(ROption::RNone),
+        }
+    }
+    #[staticmethod]
+    pub fn pin(constraint: &str, tag: &str, program: &str) -> Self {
+        Self {
+            constraint: constraint.into(),
+            tag: tag.into(),
+            program: AOption(ROption::RSome(program.into())),
+        }
+    }
+}
+impl ConstraintOverride {
+    fn applies_to(&self, constraint_name: &AString, tags: &AVec<AString>) -> bool {
+        self.constraint == *constraint_name && tags.iter().any(|x| *x == self.tag)
+    }
+}
+
+/// Tags of a concept and of its ancestors, nearest first. A tag can hold
+/// several comma-separated entries.
+pub fn get_concept_tags(tag: AOption<AString>, ancestors: &AVec<AncestorRecord>) -> AVec<AString> {
+    std::iter::once(tag)
+        .chain(ancestors.iter().rev().map(|x| x.tag.clone()))
+        .filter_map(|x| x.0.into_option())
+        .map(|x| {
+            x.as_str()
+                .split(',')
+                .map(|y| AString::from(y.trim()))
+                .collect::<Vec<_>>()
+        })
+        .flatten()
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct ConstraintOverrides {
+    overrides: AVec<ConstraintOverride>,
+}
+impl ConstraintOverrides {
+    pub fn new(overrides: AVec<ConstraintOverride>) -> Self {
+        Self { overrides }
+    }
+    pub fn empty() -> Self {
+        Self::new(AVec::new())
+    }
+    pub fn should_skip(&self, constraint_name: &AString, tags: &AVec<AString>) -> bool {
+        self.overrides
+            .iter()
+            .any(|x| x.program.is_none() && x.applies_to(constraint_name, tags))
+    }
+    pub fn get_pinned_program(
+        &self,
+        constraint_name: &AString,
+        tags: &AVec<AString>,
+    ) -> Option<AString> {
+        self.overrides
+            .iter()
+            .filter(|x| x.applies_to(constraint_name, tags))
+            .filter_map(|x| x.program.clone().0.into_option())
+            .next()
+    }
+    /// Programs that may be used for a constraint on a concept with the
+    /// given tags: all of them, unless an override pins one.
+    pub fn filter_programs<P: TOuterProgram>(
+        &self,
+        constraint_name: &AString,
+        tags: &AVec<AString>,
+        programs: &AVec<P>,
+    ) -> Result<AVec<P>> {
+        match self.get_pinned_program(constraint_name, tags) {
+            None => Ok(programs.clone()),
+            Some(entrypoint) => {
+                let pinned = programs
+                    .iter()
+                    .filter(|x| x.get_entrypoint() == entrypoint)
+                    .cloned()
+                    .collect::<AVec<_>>();
+                if pinned.is_empty() {
+                    bail!(
+                        "Program {} pinned for constraint {} was not provided.",
+                        entrypoint,
+                        constraint_name
+                    );
+                }
+                Ok(pinned)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::concept::ConceptAncestry;
+    use crate::constraint::OuterConstraint;
+    use crate::dialect::{Bash, Dialect};
+    use crate::parameter_tuple::ParameterTuple;
+    use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
+    use abi_stable::std_types::RArc;
+    use aorist_primitives::{Ancestry, Context};
+    use linked_hash_map::LinkedHashMap;
+    use uuid::Uuid;
+
+    #[derive(Clone)]
+    struct TestProgram {
+        entrypoint: &'static str,
+    }
+    impl TOuterProgram for TestProgram {
+        type TAncestry = ConceptAncestry;
+        fn get_dialect(&self) -> Dialect {
+            Dialect::Bash(Bash::new())
+        }
+        fn get_code(&self) -> AString {
+            "".into()
+        }
+        fn get_entrypoint(&self) -> AString {
+            self.entrypoint.into()
+        }
+        fn get_arg_functions(&self) -> AVec<(AVec<AString>, AString)> {
+            AVec::new()
+        }
+        fn get_kwarg_functions(&self) -> LinkedHashMap<AString, (AVec<AString>, AString)> {
+            LinkedHashMap::new()
+        }
+        fn compute_args<'a, T: OuterConstraint<'a>>(
+            &self,
+            _root: <Self::TAncestry as Ancestry>::TConcept,
+            _ancestry: &Self::TAncestry,
+            _context: &mut Context,
+            _constraint: RArc<RRwLock<T>>,
+        ) -> (AString, AString, ParameterTuple, Dialect) {
+            unreachable!()
+        }
+    }
+
+    fn skip(constraint: &str, tag: &str) -> ConstraintOverride {
+        ConstraintOverride {
+            constraint: constraint.into(),
+            tag: tag.into(),
+            program: AOption(ROption::RNone),
+        }
+    }
+    fn pin(constraint: &str, tag: &str, program: &str) -> ConstraintOverride {
+        ConstraintOverride {
+            constraint: constraint.into(),
+            tag: tag.into(),
+            program: AOption(ROption::RSome(program.into())),
+        }
+    }
+    fn tags(tags: &[&str]) -> AVec<AString> {
+        tags.iter().map(|x| (*x).into()).collect()
+    }
+    fn programs() -> AVec<TestProgram> {
+        vec![
+            TestProgram {
+                entrypoint: "download_with_curl",
+            },
+            TestProgram {
+                entrypoint: "download_with_wget",
+            },
+        ]
+        .into_iter()
+        .collect()
+    }
+    fn entrypoints(programs: &AVec<TestProgram>) -> Vec<AString> {
+        programs.iter().map(|x| x.get_entrypoint()).collect()
+    }
+
+    #[test]
+    fn test_get_concept_tags() {
+        let ancestors = vec![
+            AncestorRecord::new(
+                Uuid::new_v4(),
+                "DataSet".into(),
+                AOption(ROption::RSome("raw".into())),
+                0,
+            ),
+            AncestorRecord::new(Uuid::new_v4(), "Asset".into(), AOption(ROption::RNone), 0),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            get_concept_tags(AOption(ROption::RSome("events, daily".into())), &ancestors),
+            tags(&["events", "daily", "raw"])
+        );
+    }
+
+    #[test]
+    fn test_skip() {
+        let overrides = ConstraintOverrides::new(
+            vec![
+                skip("Upload", "raw"),
+                pin("Download", "raw", "download_with_curl"),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert!(overrides.should_skip(&"Upload".into(), &tags(&["events", "raw"])));
+        assert!(!overrides.should_skip(&"Upload".into(), &tags(&["events"])));
+        // pins restrict the programs, but keep the constraint
+        assert!(!overrides.should_skip(&"Download".into(), &tags(&["raw"])));
+        assert!(!ConstraintOverrides::empty().should_skip(&"Upload".into(), &tags(&["raw"])));
+    }
+
+    #[test]
+    fn test_pin() {
+        let overrides = ConstraintOverrides::new(
+            vec![pin("Download", "raw", "download_with_wget")]
+                .into_iter()
+                .collect(),
+        );
+        let pinned = overrides
+            .filter_programs(&"Download".into(), &tags(&["raw"]), &programs())
+            .unwrap();
+        assert_eq!(
+            entrypoints(&pinned),
+            vec![AString::from("download_with_wget")]
+        );
+        // concepts without the tag can use any program
+        let all = overrides
+            .filter_programs(&"Download".into(), &tags(&["curated"]), &programs())
+            .unwrap();
+        assert_eq!(entrypoints(&all), entrypoints(&programs()));
+    }
+
+    #[test]
+    fn test_pin_missing_program() {
+        let overrides = ConstraintOverrides::new(
+            vec![pin("Download", "raw", "download_with_ftp")]
+                .into_iter()
+                .collect(),
+        );
+        let err = match overrides.filter_programs(&"Download".into(), &tags(&["raw"]), &programs())
+        {
+            Ok(_) => panic!("A missing pinned program should be an error."),
+            Err(err) => err,
+        };
+        assert_eq!(
+            err.to_string(),
+            "Program download_with_ftp pinned for constraint Download was not provided."
+        );
+    }
+}
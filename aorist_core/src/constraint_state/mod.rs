@@ -1,6 +1,7 @@
 use crate::concept::Ancestry;
-use crate::constraint::OuterConstraint;
-use crate::dialect::Dialect;
+use crate::constraint::{get_concept_tags, OuterConstraint};
+use crate::dialect::{Dialect, DialectPreferences, ResolvedPreferences};
+use crate::driver::{get_concept_content, get_concept_own_content};
 use crate::parameter_tuple::ParameterTuple;
 use crate::program::TOuterProgram;
 use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
@@ -13,7 +14,9 @@ use aorist_primitives::{AString, AVec, Context, ToplineConcept};
 use inflector::cases::snakecase::to_snake_case;
 use linked_hash_map::LinkedHashMap;
 use linked_hash_set::LinkedHashSet;
-use std::collections::{HashMap, HashSet};
+use siphasher::sip128::{Hasher128, SipHasher};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::hash::Hash;
 use tracing::{debug, level_enabled, trace, Level};
 use uuid::Uuid;
 
@@ -29,11 +32,17 @@ pub struct ConstraintState<'a, T: OuterConstraint<'a>, P: TOuterProgram<TAncestr
     // these are concept ancestors
     // TODO: change this to AVec<Concept<'a>>
     ancestors: AVec<AncestorRecord>,
+    // type and tag (or name, or index) of each ancestor, stable across runs
+    lineage: AVec<AString>,
+    // content of each ancestor, without its descendants
+    ancestor_contents: AVec<AString>,
     preamble: AOption<AString>,
     call: AOption<AString>,
     params: AOption<ParameterTuple>,
     task_name: AOption<AString>,
     context: Context,
+    fingerprint: AOption<AString>,
+    preferences: AOption<ResolvedPreferences>,
 }
 impl<'a, T: OuterConstraint<'a>, P: TOuterProgram<TAncestry = T::TAncestry>>
     ConstraintState<'a, T, P>
@@ -137,6 +146,10 @@ impl<'a, T: OuterConstraint<'a>, P: TOuterProgram<TAncestry = T::TAncestry>>
     pub fn get_task_name(&self) -> AString {
         self.task_name.as_ref().unwrap().clone()
     }
+    /// False for states deduplicated into an identical state's task.
+    pub fn has_task_name(&self) -> bool {
+        self.task_name.is_some()
+    }
     pub fn get_satisfied_dependency_keys(&self) -> AVec<AString> {
         self.satisfied_dependencies
             .iter()
@@ -163,6 +176,22 @@ impl<'a, T: OuterConstraint<'a>, P: TOuterProgram<TAncestry = T::TAncestry>>
     pub fn get_ancestors(&self) -> AVec<AncestorRecord> {
         self.ancestors.clone()
     }
+    /// Ancestors of the root concept, followed by the constraint itself:
+    /// attached to the statements of the task rendered from this state.
+    pub fn get_task_ancestors(&self) -> Result<AVec<AncestorRecord>> {
+        let mut ancestors = self.ancestors.clone();
+        ancestors.push(AncestorRecord::new(
+            self.get_constraint_uuid()?,
+            self.get_name(),
+            AOption(ROption::RNone),
+            0,
+        ));
+        Ok(ancestors)
+    }
+    /// Tags of the root concept and its ancestors, used to match overrides.
+    pub fn get_tags(&self) -> AVec<AString> {
+        get_concept_tags(self.root.get_tag(), &self.ancestors)
+    }
     pub fn get_preamble(&self) -> AOption<AString> {
         self.preamble.clone()
     }
@@ -191,13 +220,46 @@ impl<'a, T: OuterConstraint<'a>, P: TOuterProgram<TAncestry = T::TAncestry>>
         }
         None
     }
+    /// Dialect ordering for this state, taking into account overrides for
+    /// the constraint and `prefer:` tags on the root concept or its
+    /// ancestors.
+    pub fn resolve_preferences(&self, preferences: &DialectPreferences) -> ResolvedPreferences {
+        let tagged_concepts = std::iter::once((self.get_root_type(), self.root.get_tag())).chain(
+            self.ancestors
+                .iter()
+                .rev()
+                .map(|x| (x.object_type.clone(), x.tag.clone())),
+        );
+        preferences.resolve(&self.name, tagged_concepts)
+    }
+    pub fn get_resolved_preferences(&self) -> AOption<ResolvedPreferences> {
+        self.preferences.clone()
+    }
+    /// Comment describing why the dialect was chosen, if the ordering
+    /// differs from the global one.
+    pub fn get_preference_note(&self) -> AOption<AString> {
+        match (&self.preferences, &self.dialect) {
+            (AOption(ROption::RSome(resolved)), AOption(ROption::RSome(dialect)))
+                if !resolved.is_default() =>
+            {
+                AOption(ROption::RSome(
+                    format!("{} chosen from {}", dialect.get_name(), resolved.describe())
+                        .as_str()
+                        .into(),
+                ))
+            }
+            _ => AOption(ROption::RNone),
+        }
+    }
     pub fn satisfy(
         &mut self,
-        preferences: &AVec<Dialect>,
+        preferences: &DialectPreferences,
         ancestry: &<T as OuterConstraint<'a>>::TAncestry,
         programs: &AVec<P>,
-    ) {
-        let best_program = Self::find_best_program(preferences, programs);
+    ) -> Result<()> {
+        let resolved = self.resolve_preferences(preferences);
+        let best_program = Self::find_best_program(&resolved.dialects, programs);
+        self.preferences = AOption(ROption::RSome(resolved));
         if let Some(program) = best_program {
             let (preamble, call, params, dialect) = program.compute_args(
                 self.root.clone(),
@@ -209,13 +271,73 @@ impl<'a, T: OuterConstraint<'a>, P: TOuterProgram<TAncestry = T::TAncestry>>
             self.call = AOption(ROption::RSome(call));
             self.params = AOption(ROption::RSome(params));
             self.dialect = AOption(ROption::RSome(dialect));
+            Ok(())
         } else {
-            panic!(
+            bail!(
                 "Could not find any program for constraint {}.",
                 self.constraint.read().get_name()
-            );
+            )
         }
     }
+    /// Key under which the state is stored in the fingerprint cache. Uuids
+    /// of leaf concepts are random, so the root is identified by the tags
+    /// or names of its ancestors instead.
+    pub fn get_cache_key(&self) -> AString {
+        format!(
+            "{}/{}/{}",
+            self.name,
+            self.get_root_type(),
+            self.lineage
+                .iter()
+                .map(|x| x.as_str())
+                .collect::<Vec<_>>()
+                .join("/")
+        )
+        .as_str()
+        .into()
+    }
+    pub fn get_fingerprint(&self) -> AOption<AString> {
+        self.fingerprint.clone()
+    }
+    pub fn get_context(&self) -> Context {
+        self.context.clone()
+    }
+    /// Hashes the content of the root concept, its ancestors, the program
+    /// that will be used (if any) and the fingerprints of all satisfied
+    /// dependencies. Must be called after all dependencies were satisfied.
+    pub fn compute_fingerprint(&mut self, program: Option<&P>) -> Result<AString> {
+        let fingerprint = compute_fingerprint(
+            &self.name,
+            &self.get_root_type(),
+            &get_concept_content(&self.root)?,
+            &self.lineage,
+            &self.ancestor_contents,
+            program.map(|x| x.get_fingerprint()),
+            &self
+                .satisfied_dependencies
+                .iter()
+                .map(|x| x.read().get_fingerprint())
+                .collect(),
+        );
+        self.fingerprint = AOption(ROption::RSome(fingerprint.clone()));
+        Ok(fingerprint)
+    }
+    /// Satisfies the state with previously computed arguments instead of
+    /// calling the program.
+    pub fn satisfy_from_cache(
+        &mut self,
+        program: &P,
+        params: ParameterTuple,
+        context: Context,
+        resolved: ResolvedPreferences,
+    ) {
+        self.preferences = AOption(ROption::RSome(resolved));
+        self.preamble = AOption(ROption::RSome(program.get_code()));
+        self.call = AOption(ROption::RSome(program.get_entrypoint()));
+        self.params = AOption(ROption::RSome(params));
+        self.dialect = AOption(ROption::RSome(program.get_dialect()));
+        self.context = context;
+    }
     pub fn get_dedup_key(&self) -> (AString, AString, ParameterTuple, AOption<Dialect>) {
         (
             self.preamble.as_ref().unwrap().clone(),
@@ -235,6 +357,7 @@ impl<'a, T: OuterConstraint<'a>, P: TOuterProgram<TAncestry = T::TAncestry>>
             >,
         >,
         concept_ancestors: &HashMap<(Uuid, AString), AVec<AncestorRecord>>,
+        concept_contents: &mut HashMap<(Uuid, AString), AString>,
     ) -> Result<Self> {
         let arc = constraint.clone();
         let x = arc.read();
@@ -265,6 +388,36 @@ impl<'a, T: OuterConstraint<'a>, P: TOuterProgram<TAncestry = T::TAncestry>>
             .get(&(root_uuid, x.get_root()))
             .unwrap()
             .clone();
+        let lineage = ancestors
+            .iter()
+            .map(|record| {
+                let label = match guard.get(&record.get_key()) {
+                    Some(concept) => match concept.get_tag() {
+                        AOption(ROption::RSome(tag)) => Some(tag),
+                        AOption(ROption::RNone) => concept.get_concept_name().0.into_option(),
+                    },
+                    None => None,
+                };
+                match label {
+                    Some(label) => format!("{}:{}", record.object_type, label),
+                    None => format!("{}#{}", record.object_type, record.ix),
+                }
+                .as_str()
+                .into()
+            })
+            .collect();
+        let mut ancestor_contents = AVec::new();
+        for record in ancestors.iter() {
+            let key = record.get_key();
+            if !concept_contents.contains_key(&key) {
+                let content = match guard.get(&key) {
+                    Some(concept) => get_concept_own_content(concept)?.as_str().into(),
+                    None => AString::from(""),
+                };
+                concept_contents.insert(key.clone(), content);
+            }
+            ancestor_contents.push(concept_contents.get(&key).unwrap().clone());
+        }
         Ok(Self {
             dialect: AOption(ROption::RNone),
             key: AOption(ROption::RNone),
@@ -275,12 +428,16 @@ impl<'a, T: OuterConstraint<'a>, P: TOuterProgram<TAncestry = T::TAncestry>>
             constraint,
             root,
             ancestors: ancestors.clone(),
+            lineage,
+            ancestor_contents,
             preamble: AOption(ROption::RNone),
             call: AOption(ROption::RNone),
             params: AOption(ROption::RNone),
             task_name: AOption(ROption::RNone),
             // will accumulate dependencies' contexts as they are satisfied
             context: Context::new(),
+            fingerprint: AOption(ROption::RNone),
+            preferences: AOption(ROption::RNone),
         })
     }
     pub fn compute_task_key(&mut self) -> AString {
@@ -344,3 +501,126 @@ impl<'a, T: OuterConstraint<'a>, P: TOuterProgram<TAncestry = T::TAncestry>>
         }*/
     }
 }
+
+/// Hashes everything the arguments of a constraint state are computed from.
+fn compute_fingerprint(
+    name: &AString,
+    root_type: &AString,
+    root_content: &str,
+    lineage: &AVec<AString>,
+    ancestor_contents: &AVec<AString>,
+    program_fingerprint: Option<u128>,
+    dependency_fingerprints: &BTreeSet<AOption<AString>>,
+) -> AString {
+    let mut hasher = SipHasher::new();
+    name.hash(&mut hasher);
+    root_type.hash(&mut hasher);
+    root_content.hash(&mut hasher);
+    lineage.hash(&mut hasher);
+    ancestor_contents.hash(&mut hasher);
+    if let Some(program_fingerprint) = program_fingerprint {
+        program_fingerprint.hash(&mut hasher);
+    }
+    dependency_fingerprints.hash(&mut hasher);
+    format!("{:032x}", hasher.finish128().as_u128())
+        .as_str()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::FingerprintCache;
+    use crate::{Encoding, InlineBlobStorage, ORCEncoding};
+    use aorist_primitives::AoristRef;
+    use std::fs;
+
+    /// Uuids are random, as for leaf concepts.
+    fn storage(tag: &str, data: &str) -> AoristRef<InlineBlobStorage> {
+        AoristRef(RArc::new(RRwLock::new(InlineBlobStorage {
+            encoding: AoristRef(RArc::new(RRwLock::new(Encoding::ORCEncoding(AoristRef(
+                RArc::new(RRwLock::new(ORCEncoding {
+                    tag: AOption(ROption::RNone),
+                    uuid: AOption(ROption::RSome(Uuid::new_v4())),
+                })),
+            ))))),
+            data: vec![data.into()].into_iter().collect(),
+            tag: AOption(ROption::RSome(tag.into())),
+            uuid: AOption(ROption::RSome(Uuid::new_v4())),
+        })))
+    }
+    /// Fingerprint of a state on the encoding of `storage`, computed as
+    /// for a state of the driver, with the storage as the only ancestor.
+    fn fingerprint(storage: &AoristRef<InlineBlobStorage>) -> AString {
+        let read = storage.0.read();
+        compute_fingerprint(
+            &"Decode".into(),
+            &"Encoding".into(),
+            &get_concept_content(&read.encoding).unwrap(),
+            &vec![
+                format!("InlineBlobStorage:{}", read.tag.as_ref().unwrap())
+                    .as_str()
+                    .into(),
+                "Encoding#0".into(),
+            ]
+            .into_iter()
+            .collect(),
+            &vec![
+                get_concept_own_content(storage).unwrap().as_str().into(),
+                get_concept_own_content(&read.encoding)
+                    .unwrap()
+                    .as_str()
+                    .into(),
+            ]
+            .into_iter()
+            .collect(),
+            Some(1),
+            &BTreeSet::new(),
+        )
+    }
+
+    #[test]
+    fn test_own_content_leaves_out_descendants() {
+        let content = get_concept_own_content(&storage("blob", "a,b")).unwrap();
+        assert!(content.contains("a,b"));
+        assert!(content.contains("\"encoding\":null"));
+        assert!(!content.contains("uuid"));
+    }
+
+    #[test]
+    fn test_fingerprint_follows_ancestors() {
+        let original = fingerprint(&storage("blob", "a,b"));
+        // uuids are random, but the fingerprint only depends on content
+        assert_eq!(original, fingerprint(&storage("blob", "a,b")));
+        assert_ne!(original, fingerprint(&storage("other_blob", "a,b")));
+        assert_ne!(original, fingerprint(&storage("blob", "a,c")));
+    }
+
+    #[test]
+    fn test_unchanged_universe_hits_cache() {
+        let dir = std::env::temp_dir().join(format!("aorist_state_{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cache.json");
+        let key: AString = "Decode/Encoding/InlineBlobStorage:blob".into();
+        let params = ParameterTuple {
+            args: AVec::new(),
+            kwargs: LinkedHashMap::new(),
+        };
+        let mut cache = FingerprintCache::load(&path).unwrap();
+        cache.begin_block("Decode".into());
+        let first = fingerprint(&storage("blob", "a,b"));
+        assert!(cache.lookup(&key, &first).is_none());
+        cache.store(&key, &first, &params, Context::new());
+        cache.save().unwrap();
+
+        let mut cache = FingerprintCache::load(&path).unwrap();
+        cache.begin_block("Decode".into());
+        assert!(cache
+            .lookup(&key, &fingerprint(&storage("blob", "a,b")))
+            .is_some());
+        assert!(cache
+            .lookup(&key, &fingerprint(&storage("blob", "a,c")))
+            .is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
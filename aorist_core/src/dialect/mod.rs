@@ -5,6 +5,9 @@ use pyo3::prelude::*;
 #[cfg(feature = "python")]
 use std::collections::BTreeSet;
 
+mod preferences;
+pub use preferences::*;
+
 #[repr(C)]
 #[cfg_attr(feature = "python", pyclass)]
 #[derive(Debug, Clone, PartialEq, Eq, Hash, StableAbi)]
@@ -74,6 +77,28 @@ pub enum Dialect {
     Bash(Bash),
     Presto(Presto),
 }
+impl Dialect {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Dialect::Python(_) => "python",
+            Dialect::R(_) => "r",
+            Dialect::Bash(_) => "bash",
+            Dialect::Presto(_) => "presto",
+        }
+    }
+    /// Dialect with the given name, without any pip requirements.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "python" => Some(Dialect::Python(Python {
+                pip_requirements: RVec::new(),
+            })),
+            "r" => Some(Dialect::R(R {})),
+            "bash" => Some(Dialect::Bash(Bash {})),
+            "presto" => Some(Dialect::Presto(Presto {})),
+            _ => None,
+        }
+    }
+}
 
 #[cfg(feature = "python")]
 pub fn dialects_module(
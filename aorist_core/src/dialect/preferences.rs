@@ -0,0 +1,250 @@
+use crate::dialect::Dialect;
+use abi_stable::std_types::ROption;
+use aorist_primitives::{AOption, AString, AVec};
+use linked_hash_map::LinkedHashMap;
+use std::fmt;
+
+/// Concept tags of this form (e.g. `prefer:r`) move the named dialect to the
+/// front of the preference ordering for constraints on the concept and on
+/// its descendants. Several preferences can be given as a comma-separated
+/// list, e.g. `prefer:presto,prefer:python`.
+pub const PREFERENCE_TAG_PREFIX: &str = "prefer:";
+
+/// Where the dialect ordering used to satisfy a constraint state came from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PreferenceSource {
+    Default,
+    Constraint(AString),
+    ConceptTag {
+        concept_type: AString,
+        tag: AString,
+    },
+}
+impl fmt::Display for PreferenceSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreferenceSource::Default => write!(f, "default preferences"),
+            PreferenceSource::Constraint(name) => write!(f, "preferences for constraint {}", name),
+            PreferenceSource::ConceptTag { concept_type, tag } => {
+                write!(f, "tag `{}` on {}", tag, concept_type)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ResolvedPreferences {
+    pub dialects: AVec<Dialect>,
+    pub source: PreferenceSource,
+}
+impl ResolvedPreferences {
+    pub fn is_default(&self) -> bool {
+        self.source == PreferenceSource::Default
+    }
+    pub fn describe(&self) -> AString {
+        format!(
+            "{} ({})",
+            self.dialects
+                .iter()
+                .map(|x| x.get_name())
+                .collect::<Vec<_>>()
+                .join(" > "),
+            self.source
+        )
+        .as_str()
+        .into()
+    }
+}
+
+/// Dialect preferences for a run: a global ordering, optionally overridden
+/// per constraint name and, through `prefer:` tags, per concept.
+#[derive(Debug, Clone)]
+pub struct DialectPreferences {
+    default: AVec<Dialect>,
+    by_constraint: LinkedHashMap<AString, AVec<Dialect>>,
+}
+impl DialectPreferences {
+    pub fn new(default: AVec<Dialect>) -> Self {
+        Self {
+            default,
+            by_constraint: LinkedHashMap::new(),
+        }
+    }
+    pub fn with_constraint_preferences(
+        mut self,
+        by_constraint: LinkedHashMap<AString, AVec<Dialect>>,
+    ) -> Self {
+        self.by_constraint = by_constraint;
+        self
+    }
+    pub fn get_default(&self) -> &AVec<Dialect> {
+        &self.default
+    }
+    fn parse_tag(tag: &AString) -> AVec<AString> {
+        tag.as_str()
+            .split(',')
+            .filter_map(|x| x.trim().strip_prefix(PREFERENCE_TAG_PREFIX))
+            .map(|x| x.trim().to_lowercase().as_str().into())
+            .collect()
+    }
+    /// Resolves the ordering for a constraint. `tagged_concepts` are
+    /// (concept type, tag) pairs, nearest concept first: the first `prefer:`
+    /// tag found wins over a constraint override, which wins over the
+    /// global ordering. Dialects not named in the tag keep their relative
+    /// order after the preferred ones.
+    pub fn resolve<I>(&self, constraint_name: &AString, tagged_concepts: I) -> ResolvedPreferences
+    where
+        I: IntoIterator<Item = (AString, AOption<AString>)>,
+    {
+        let (base, source) = match self.by_constraint.get(constraint_name) {
+            Some(dialects) => (
+                dialects.clone(),
+                PreferenceSource::Constraint(constraint_name.clone()),
+            ),
+            None => (self.default.clone(), PreferenceSource::Default),
+        };
+        for (concept_type, tag) in tagged_concepts {
+            if let AOption(ROption::RSome(tag)) = tag {
+                let preferred = Self::parse_tag(&tag);
+                if preferred.is_empty() {
+                    continue;
+                }
+                let mut dialects = AVec::new();
+                for name in preferred.iter() {
+                    match base.iter().find(|x| x.get_name() == name.as_str()) {
+                        Some(dialect) => dialects.push(dialect.clone()),
+                        None => match Dialect::from_name(name.as_str()) {
+                            Some(dialect) => dialects.push(dialect),
+                            None => continue,
+                        },
+                    }
+                }
+                for dialect in base.iter() {
+                    if !preferred.iter().any(|x| x.as_str() == dialect.get_name()) {
+                        dialects.push(dialect.clone());
+                    }
+                }
+                return ResolvedPreferences {
+                    dialects,
+                    source: PreferenceSource::ConceptTag { concept_type, tag },
+                };
+            }
+        }
+        ResolvedPreferences {
+            dialects: base,
+            source,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dialects(names: &[&str]) -> AVec<Dialect> {
+        names
+            .iter()
+            .map(|x| Dialect::from_name(x).unwrap())
+            .collect()
+    }
+    fn names(resolved: &ResolvedPreferences) -> Vec<&'static str> {
+        resolved.dialects.iter().map(|x| x.get_name()).collect()
+    }
+    fn tagged(concepts: &[(&str, Option<&str>)]) -> Vec<(AString, AOption<AString>)> {
+        concepts
+            .iter()
+            .map(|(concept_type, tag)| {
+                (
+                    (*concept_type).into(),
+                    AOption(match tag {
+                        Some(tag) => ROption::RSome((*tag).into()),
+                        None => ROption::RNone,
+                    }),
+                )
+            })
+            .collect()
+    }
+    fn preferences() -> DialectPreferences {
+        DialectPreferences::new(dialects(&["python", "bash", "presto"]))
+            .with_constraint_preferences(
+                vec![("ComputeStats".into(), dialects(&["presto", "python"]))]
+                    .into_iter()
+                    .collect(),
+            )
+    }
+
+    #[test]
+    fn test_default_preferences() {
+        let resolved = preferences().resolve(
+            &"Download".into(),
+            tagged(&[("StaticDataTable", None), ("DataSet", Some("raw"))]),
+        );
+        assert_eq!(names(&resolved), vec!["python", "bash", "presto"]);
+        assert!(resolved.is_default());
+    }
+
+    #[test]
+    fn test_constraint_preferences_win_over_default() {
+        let resolved = preferences().resolve(&"ComputeStats".into(), tagged(&[("DataSet", None)]));
+        assert_eq!(names(&resolved), vec!["presto", "python"]);
+        assert_eq!(
+            resolved.source,
+            PreferenceSource::Constraint("ComputeStats".into())
+        );
+    }
+
+    #[test]
+    fn test_concept_tag_wins_over_constraint_preferences() {
+        let resolved = preferences().resolve(
+            &"ComputeStats".into(),
+            tagged(&[
+                ("StaticDataTable", None),
+                ("DataSet", Some("prefer:python")),
+            ]),
+        );
+        assert_eq!(names(&resolved), vec!["python", "presto"]);
+        assert_eq!(
+            resolved.source,
+            PreferenceSource::ConceptTag {
+                concept_type: "DataSet".into(),
+                tag: "prefer:python".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_nearest_concept_tag_wins() {
+        let resolved = preferences().resolve(
+            &"Download".into(),
+            tagged(&[
+                ("StaticDataTable", Some("prefer:bash")),
+                ("DataSet", Some("prefer:presto")),
+            ]),
+        );
+        assert_eq!(names(&resolved), vec!["bash", "python", "presto"]);
+    }
+
+    #[test]
+    fn test_concept_tag_adds_missing_dialect() {
+        let resolved = preferences().resolve(
+            &"ComputeStats".into(),
+            tagged(&[("DataSet", Some("prefer:R, prefer:python"))]),
+        );
+        assert_eq!(names(&resolved), vec!["r", "python", "presto"]);
+    }
+
+    #[test]
+    fn test_unknown_dialect_names_are_ignored() {
+        let resolved = preferences().resolve(
+            &"Download".into(),
+            tagged(&[("DataSet", Some("prefer:cobol,prefer:bash"))]),
+        );
+        assert_eq!(names(&resolved), vec!["bash", "python", "presto"]);
+
+        let resolved = preferences().resolve(
+            &"Download".into(),
+            tagged(&[("DataSet", Some("prefer:cobol"))]),
+        );
+        assert_eq!(names(&resolved), vec!["python", "bash", "presto"]);
+    }
+}
@@ -0,0 +1,460 @@
+use crate::parameter_tuple::ParameterTuple;
+use anyhow::Result;
+use aorist_ast::{
+    BigIntLiteral, BooleanLiteral, Dict, FloatLiteral, List, None, StringLiteral, Tuple, AST,
+};
+use aorist_attributes::FloatValue;
+use aorist_primitives::{AString, AVec, Context};
+use linked_hash_map::LinkedHashMap;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// Serializable form of the literal ASTs that programs can return as
+/// arguments. Anything else is not cached and always recomputed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CachedValue {
+    String(String, bool),
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    None,
+    List(Vec<CachedValue>),
+    Tuple(Vec<CachedValue>),
+    Dict(Vec<(String, CachedValue)>),
+}
+impl CachedValue {
+    pub fn from_ast(ast: &AST) -> Option<Self> {
+        match ast {
+            AST::StringLiteral(x) => {
+                let read = x.read();
+                Some(Self::String(
+                    read.value().as_str().to_string(),
+                    read.is_sql(),
+                ))
+            }
+            AST::BooleanLiteral(x) => Some(Self::Boolean(x.read().val())),
+            AST::BigIntLiteral(x) => Some(Self::Integer(x.read().val())),
+            AST::FloatLiteral(x) => Some(Self::Float(x.read().val().as_f64())),
+            AST::None(_) => Some(Self::None),
+            AST::List(x) => Some(Self::List(
+                x.read()
+                    .elems()
+                    .iter()
+                    .map(Self::from_ast)
+                    .collect::<Option<Vec<_>>>()?,
+            )),
+            AST::Tuple(x) => Some(Self::Tuple(
+                x.read()
+                    .elems()
+                    .iter()
+                    .map(Self::from_ast)
+                    .collect::<Option<Vec<_>>>()?,
+            )),
+            AST::Dict(x) => Some(Self::Dict(
+                x.read()
+                    .elems()
+                    .iter()
+                    .map(|(k, v)| Some((k.as_str().to_string(), Self::from_ast(v)?)))
+                    .collect::<Option<Vec<_>>>()?,
+            )),
+            _ => Option::None,
+        }
+    }
+    pub fn to_ast(&self) -> AST {
+        match self {
+            Self::String(value, is_sql) => {
+                AST::StringLiteral(StringLiteral::new_wrapped(value.as_str().into(), *is_sql))
+            }
+            Self::Boolean(val) => AST::BooleanLiteral(BooleanLiteral::new_wrapped(*val)),
+            Self::Integer(val) => AST::BigIntLiteral(BigIntLiteral::new_wrapped(*val)),
+            Self::Float(val) => {
+                AST::FloatLiteral(FloatLiteral::new_wrapped(FloatValue::from_f64(*val)))
+            }
+            Self::None => AST::None(None::new_wrapped()),
+            Self::List(elems) => AST::List(List::new_wrapped(
+                elems.iter().map(|x| x.to_ast()).collect(),
+                false,
+            )),
+            Self::Tuple(elems) => AST::Tuple(Tuple::new_wrapped(
+                elems.iter().map(|x| x.to_ast()).collect(),
+                false,
+            )),
+            Self::Dict(elems) => AST::Dict(Dict::new_wrapped(
+                elems
+                    .iter()
+                    .map(|(k, v)| (k.as_str().into(), v.to_ast()))
+                    .collect(),
+            )),
+        }
+    }
+}
+
+/// Removes the uuids from a serialized concept. With `own`, the concepts
+/// nested in it (objects with a uuid of their own, possibly wrapped in an
+/// enum variant) are removed as well, so that adding or removing one does
+/// not change the content. Returns whether `value` itself was removed.
+fn strip_uuids(value: &mut serde_json::Value, own: bool, in_concept: bool) -> bool {
+    match value {
+        serde_json::Value::Object(map) => {
+            let is_concept = map.remove("uuid").is_some();
+            if own && in_concept && is_concept {
+                *value = serde_json::Value::Null;
+                return true;
+            }
+            let removed = map
+                .values_mut()
+                .map(|x| strip_uuids(x, own, in_concept || is_concept))
+                .collect::<Vec<_>>();
+            if own && in_concept && removed == vec![true] {
+                *value = serde_json::Value::Null;
+                return true;
+            }
+            false
+        }
+        serde_json::Value::Array(elems) => {
+            let removed = elems
+                .iter_mut()
+                .map(|x| strip_uuids(x, own, in_concept))
+                .collect::<Vec<_>>();
+            let mut removed = removed.into_iter();
+            elems.retain(|_| !removed.next().unwrap());
+            false
+        }
+        _ => false,
+    }
+}
+
+/// Serialized content of a concept, without the uuids of the concepts in it:
+/// those of leaf concepts are random, so they differ between runs.
+pub fn get_concept_content<T: Serialize>(concept: &T) -> Result<String> {
+    let mut value = serde_json::to_value(concept)?;
+    strip_uuids(&mut value, false, false);
+    Ok(value.to_string())
+}
+
+/// Like `get_concept_content`, leaving out the concepts in the concept:
+/// used for ancestors, whose other descendants do not affect a state.
+pub fn get_concept_own_content<T: Serialize>(concept: &T) -> Result<String> {
+    let mut value = serde_json::to_value(concept)?;
+    strip_uuids(&mut value, true, false);
+    Ok(value.to_string())
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CachedConstraintState {
+    pub fingerprint: String,
+    pub args: Vec<CachedValue>,
+    pub kwargs: Vec<(String, CachedValue)>,
+    pub context: Context,
+}
+impl CachedConstraintState {
+    fn new(fingerprint: &AString, params: &ParameterTuple, context: Context) -> Option<Self> {
+        Some(Self {
+            fingerprint: fingerprint.as_str().to_string(),
+            args: params
+                .args
+                .iter()
+                .map(CachedValue::from_ast)
+                .collect::<Option<Vec<_>>>()?,
+            kwargs: params
+                .kwargs
+                .iter()
+                .map(|(k, v)| Some((k.as_str().to_string(), CachedValue::from_ast(v)?)))
+                .collect::<Option<Vec<_>>>()?,
+            context,
+        })
+    }
+    fn get_params(&self) -> ParameterTuple {
+        ParameterTuple {
+            args: self.args.iter().map(|x| x.to_ast()).collect::<AVec<_>>(),
+            kwargs: self
+                .kwargs
+                .iter()
+                .map(|(k, v)| (k.as_str().into(), v.to_ast()))
+                .collect::<LinkedHashMap<_, _>>(),
+        }
+    }
+}
+
+/// How many constraint states in a block were reused from the cache and how
+/// many had to be recomputed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BlockCacheStatus {
+    pub constraint: AString,
+    pub reused: usize,
+    pub recomputed: usize,
+}
+/// Returned to Python by `dag` and `dag_files` when `cache_report=True`.
+#[cfg_attr(feature = "python", pyclass)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CacheReport {
+    pub blocks: Vec<BlockCacheStatus>,
+}
+#[cfg(feature = "python")]
+#[pymethods]
+impl CacheReport {
+    /// Constraints whose blocks were entirely reused from the cache.
+    #[getter]
+    pub fn reused(&self) -> Vec<String> {
+        self.get_reused_blocks()
+            .iter()
+            .map(|x| x.as_str().to_string())
+            .collect()
+    }
+    /// Constraints whose blocks had at least one state recomputed.
+    #[getter]
+    pub fn recomputed(&self) -> Vec<String> {
+        self.get_recomputed_blocks()
+            .iter()
+            .map(|x| x.as_str().to_string())
+            .collect()
+    }
+    /// (constraint, reused states, recomputed states) for each block, in
+    /// the order in which the blocks were satisfied.
+    #[getter(blocks)]
+    pub fn get_blocks(&self) -> Vec<(String, usize, usize)> {
+        self.blocks
+            .iter()
+            .map(|x| (x.constraint.as_str().to_string(), x.reused, x.recomputed))
+            .collect()
+    }
+}
+impl CacheReport {
+    pub fn get_reused_blocks(&self) -> Vec<AString> {
+        self.blocks
+            .iter()
+            .filter(|x| x.recomputed == 0)
+            .map(|x| x.constraint.clone())
+            .collect()
+    }
+    pub fn get_recomputed_blocks(&self) -> Vec<AString> {
+        self.blocks
+            .iter()
+            .filter(|x| x.recomputed > 0)
+            .map(|x| x.constraint.clone())
+            .collect()
+    }
+}
+
+/// Local cache of satisfied constraint states, keyed on constraint name and
+/// root concept, used to skip calling programs whose fingerprint did not
+/// change since the previous run.
+pub struct FingerprintCache {
+    path: PathBuf,
+    previous: BTreeMap<String, CachedConstraintState>,
+    current: BTreeMap<String, CachedConstraintState>,
+    report: CacheReport,
+}
+impl FingerprintCache {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let previous = match path.exists() {
+            true => match serde_json::from_str(&fs::read_to_string(&path)?) {
+                Ok(x) => x,
+                Err(err) => {
+                    warn!("Ignoring unreadable cache file {}: {}", path.display(), err);
+                    BTreeMap::new()
+                }
+            },
+            false => BTreeMap::new(),
+        };
+        Ok(Self {
+            path,
+            previous,
+            current: BTreeMap::new(),
+            report: CacheReport::default(),
+        })
+    }
+    pub fn begin_block(&mut self, constraint: AString) {
+        self.report.blocks.push(BlockCacheStatus {
+            constraint,
+            reused: 0,
+            recomputed: 0,
+        });
+    }
+    fn current_block(&mut self) -> Option<&mut BlockCacheStatus> {
+        self.report.blocks.last_mut()
+    }
+    pub fn lookup(
+        &mut self,
+        key: &AString,
+        fingerprint: &AString,
+    ) -> Option<(ParameterTuple, Context)> {
+        let found = match self.previous.get(key.as_str()) {
+            Some(cached) if cached.fingerprint == fingerprint.as_str() => Some(cached.clone()),
+            _ => Option::None,
+        };
+        match found {
+            Some(cached) => {
+                debug!("Reusing cached state for {}", key);
+                if let Some(block) = self.current_block() {
+                    block.reused += 1;
+                }
+                let out = (cached.get_params(), cached.context.clone());
+                self.current.insert(key.as_str().to_string(), cached);
+                Some(out)
+            }
+            Option::None => {
+                if let Some(block) = self.current_block() {
+                    block.recomputed += 1;
+                }
+                Option::None
+            }
+        }
+    }
+    /// Whether `lookup` would reuse a cached state, without counting it in
+    /// the report.
+    pub fn contains(&self, key: &AString, fingerprint: &AString) -> bool {
+        match self.previous.get(key.as_str()) {
+            Some(cached) => cached.fingerprint == fingerprint.as_str(),
+            Option::None => false,
+        }
+    }
+    pub fn store(
+        &mut self,
+        key: &AString,
+        fingerprint: &AString,
+        params: &ParameterTuple,
+        context: Context,
+    ) {
+        match CachedConstraintState::new(fingerprint, params, context) {
+            Some(cached) => {
+                self.current.insert(key.as_str().to_string(), cached);
+            }
+            Option::None => debug!("Parameters for {} cannot be cached.", key),
+        }
+    }
+    /// Writes the states used in this run to the cache file, dropping
+    /// entries that were not encountered.
+    pub fn save(&self) -> Result<()> {
+        fs::write(&self.path, serde_json::to_string_pretty(&self.current)?)?;
+        let report = self.get_report();
+        info!(
+            "Fingerprint cache: {} blocks reused, {} blocks recomputed.",
+            report.get_reused_blocks().len(),
+            report.get_recomputed_blocks().len()
+        );
+        Ok(())
+    }
+    pub fn get_report(&self) -> CacheReport {
+        self.report.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SQLiteLocation;
+    use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
+    use abi_stable::std_types::{RArc, ROption};
+    use aorist_primitives::{AOption, AoristConcept, AoristRef};
+
+    fn location(file_name: &str) -> AoristRef<SQLiteLocation> {
+        let mut location = AoristRef(RArc::new(RRwLock::new(SQLiteLocation {
+            file_name: file_name.into(),
+            tag: AOption(ROption::RNone),
+            uuid: AOption(ROption::RNone),
+        })));
+        location.compute_uuids();
+        location
+    }
+    fn params(file_name: &str) -> ParameterTuple {
+        ParameterTuple {
+            args: vec![AST::StringLiteral(StringLiteral::new_wrapped(
+                file_name.into(),
+                false,
+            ))]
+            .into_iter()
+            .collect(),
+            kwargs: LinkedHashMap::new(),
+        }
+    }
+    /// Looks up one state in a fresh cache loaded from `path`, storing its
+    /// parameters on a miss, as the driver does, and returns the report.
+    fn run(path: &Path, file_name: &str) -> CacheReport {
+        let mut cache = FingerprintCache::load(path).unwrap();
+        let key: AString = "ReplicateToLocal/SQLiteLocation/Universe:test".into();
+        let fingerprint: AString = get_concept_content(&location(file_name))
+            .unwrap()
+            .as_str()
+            .into();
+        cache.begin_block("ReplicateToLocal".into());
+        if cache.lookup(&key, &fingerprint).is_none() {
+            cache.store(&key, &fingerprint, &params(file_name), Context::new());
+        }
+        cache.save().unwrap();
+        cache.get_report()
+    }
+
+    #[test]
+    fn test_concept_content_ignores_uuids() {
+        let first = location("test.db");
+        let second = location("test.db");
+        assert_ne!(first.get_uuid(), second.get_uuid());
+        assert_eq!(
+            get_concept_content(&first).unwrap(),
+            get_concept_content(&second).unwrap()
+        );
+        assert_ne!(
+            get_concept_content(&first).unwrap(),
+            get_concept_content(&location("other.db")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_own_content_ignores_nested_concepts() {
+        let dataset = |assets: Vec<serde_json::Value>| {
+            serde_json::json!({"DataSet": {
+                "uuid": uuid::Uuid::new_v4().to_string(),
+                "name": "dataset",
+                "assets": assets,
+            }})
+        };
+        let asset = || {
+            serde_json::json!({"StaticDataTable": {
+                "uuid": uuid::Uuid::new_v4().to_string(),
+                "name": "table",
+            }})
+        };
+        let content = get_concept_own_content(&dataset(vec![asset()])).unwrap();
+        assert_eq!(
+            content,
+            get_concept_own_content(&dataset(vec![asset(), asset()])).unwrap()
+        );
+        assert!(content.contains("\"name\":\"dataset\""));
+        assert!(!content.contains("table"));
+    }
+
+    #[test]
+    fn test_cache_reused_across_runs() {
+        let dir = std::env::temp_dir().join(format!("aorist_cache_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cache.json");
+
+        let first = run(&path, "test.db");
+        assert_eq!(
+            first.get_recomputed_blocks(),
+            vec![AString::from("ReplicateToLocal")]
+        );
+        let second = run(&path, "test.db");
+        assert_eq!(
+            second.get_reused_blocks(),
+            vec![AString::from("ReplicateToLocal")]
+        );
+        assert!(second.get_recomputed_blocks().is_empty());
+        // editing a field of the root concept changes its fingerprint
+        let edited = run(&path, "edited.db");
+        assert!(edited.get_reused_blocks().is_empty());
+        assert_eq!(
+            edited.get_recomputed_blocks(),
+            vec![AString::from("ReplicateToLocal")]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,406 @@
+use crate::constraint::OuterConstraint;
+use crate::constraint_state::ConstraintState;
+use crate::dialect::Dialect;
+use crate::program::TOuterProgram;
+use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
+use abi_stable::std_types::{RArc, ROption};
+use anyhow::Result;
+use aorist_primitives::{AOption, AString, AVec};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+const PYTHON_VERSION: &str = "3.9";
+const CRAN_MIRROR: &str = "https://cloud.r-project.org";
+/// Base image, pinned so that rebuilding the image gives the same conda.
+const BASE_IMAGE: &str = "continuumio/miniconda3:4.10.3";
+
+/// Top-level modules of the Python standard library, which are never
+/// installed, separated by whitespace.
+const PYTHON_STDLIB_MODULES: &str =
+    "abc aifc antigravity argparse array ast asynchat asyncio asyncore atexit audioop base64 \
+     bdb binascii bisect builtins bz2 cProfile calendar cgi cgitb chunk cmath cmd code codecs \
+     codeop collections colorsys compileall concurrent configparser contextlib contextvars \
+     copy copyreg crypt csv ctypes curses dataclasses datetime dbm decimal difflib dis \
+     distutils doctest email encodings ensurepip enum errno faulthandler fcntl filecmp \
+     fileinput fnmatch fractions ftplib functools gc genericpath getopt getpass gettext glob \
+     graphlib grp gzip hashlib heapq hmac html http idlelib imaplib imghdr imp importlib \
+     inspect io ipaddress itertools json keyword lib2to3 linecache locale logging lzma mailbox \
+     mailcap marshal math mimetypes mmap modulefinder msilib msvcrt multiprocessing netrc nis \
+     nntplib nt ntpath nturl2path numbers opcode operator optparse os ossaudiodev pathlib pdb \
+     pickle pickletools pipes pkgutil platform plistlib poplib posix posixpath pprint profile \
+     pstats pty pwd py_compile pyclbr pydoc pydoc_data pyexpat queue quopri random re readline \
+     reprlib resource rlcompleter runpy sched secrets select selectors shelve shlex shutil \
+     signal site smtpd smtplib sndhdr socket socketserver spwd sqlite3 sre_compile \
+     sre_constants sre_parse ssl stat statistics string stringprep struct subprocess sunau \
+     symtable sys sysconfig syslog tabnanny tarfile telnetlib tempfile termios textwrap this \
+     threading time timeit tkinter token tokenize tomllib trace traceback tracemalloc tty \
+     turtle turtledemo types typing unicodedata unittest urllib uu uuid venv warnings wave \
+     weakref webbrowser winreg winsound wsgiref xdrlib xml xmlrpc zipapp zipfile zipimport \
+     zlib zoneinfo";
+/// Distributions to install for modules imported under another name.
+const PYTHON_DISTRIBUTIONS: &[(&str, &str)] = &[
+    ("IPython", "ipython"),
+    ("PIL", "pillow"),
+    ("bs4", "beautifulsoup4"),
+    ("cv2", "opencv-python"),
+    ("dateutil", "python-dateutil"),
+    ("psycopg2", "psycopg2-binary"),
+    ("sklearn", "scikit-learn"),
+    ("yaml", "pyyaml"),
+];
+/// Namespace packages shared by many distributions, which one cannot be
+/// derived from the import. Programs importing them must declare the
+/// distribution among their pip requirements.
+const PYTHON_NAMESPACE_PACKAGES: &[&str] = &["azure", "google"];
+
+/// Name of the distribution a pip requirement refers to, normalized so that
+/// it can be compared with other names.
+fn get_distribution_name(requirement: &str) -> String {
+    requirement
+        .split(|c: char| "<>=!~[;@ ".contains(c))
+        .next()
+        .unwrap_or("")
+        .to_lowercase()
+        .replace(|c: char| c == '_' || c == '.', "-")
+}
+/// Distribution to install so that `module` can be imported, or None if it
+/// is in the standard library or cannot be derived from the module name.
+fn get_python_distribution(module: &str) -> Option<&str> {
+    if PYTHON_STDLIB_MODULES
+        .split_whitespace()
+        .any(|x| x == module)
+        || PYTHON_NAMESPACE_PACKAGES.contains(&module)
+    {
+        return None;
+    }
+    Some(
+        PYTHON_DISTRIBUTIONS
+            .iter()
+            .find(|(x, _)| *x == module)
+            .map_or(module, |(_, x)| x),
+    )
+}
+
+/// Top-level modules imported by a Python preamble. Only unindented
+/// imports are considered, and relative imports are skipped.
+fn get_python_modules(preamble: &str) -> Vec<String> {
+    let mut modules = Vec::new();
+    for line in preamble.lines() {
+        let names = if let Some(rest) = line.strip_prefix("import ") {
+            rest.split(',')
+                .map(|x| x.trim().split(' ').next().unwrap_or(""))
+                .collect::<Vec<_>>()
+        } else if let Some(rest) = line.strip_prefix("from ") {
+            vec![rest.trim().split(' ').next().unwrap_or("")]
+        } else {
+            continue;
+        };
+        for name in names {
+            let module = name.split('.').next().unwrap_or("");
+            if !module.is_empty() && module != "__future__" {
+                modules.push(module.to_string());
+            }
+        }
+    }
+    modules
+}
+/// Packages an R program attaches with `library` or `require`, or whose
+/// functions it calls as `package::function`.
+fn get_r_packages(program: &str) -> Vec<String> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '.' || c == '_';
+    let mut packages = Vec::new();
+    for function in &["library(", "require(", "requireNamespace("] {
+        for (pos, _) in program.match_indices(function) {
+            if program[..pos].ends_with(is_name_char) {
+                continue;
+            }
+            let argument = program[pos + function.len()..]
+                .trim_start()
+                .trim_start_matches(|c: char| c == '"' || c == '\'');
+            let end = argument
+                .find(|c: char| !is_name_char(c))
+                .unwrap_or(argument.len());
+            if end > 0 {
+                packages.push(argument[..end].to_string());
+            }
+        }
+    }
+    for (pos, _) in program.match_indices("::") {
+        let start = program[..pos]
+            .rfind(|c: char| !is_name_char(c))
+            .map_or(0, |x| x + 1);
+        if start < pos {
+            packages.push(program[start..pos].to_string());
+        }
+    }
+    packages
+}
+
+/// Environment the programs of a flow need, from which a Dockerfile, a
+/// conda environment and an R install script are generated.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContainerImage {
+    pub pip_requirements: BTreeSet<AString>,
+    /// Top-level modules imported by the preambles of Python programs.
+    /// Those not provided by a pip requirement are installed, and all of
+    /// them are checked when the image is built.
+    pub python_modules: BTreeSet<AString>,
+    pub r_packages: BTreeSet<AString>,
+    pub uses_r: bool,
+}
+
+impl ContainerImage {
+    pub fn from_states<'a, T, P>(
+        states: impl Iterator<Item = RArc<RRwLock<ConstraintState<'a, T, P>>>>,
+    ) -> Self
+    where
+        T: OuterConstraint<'a>,
+        P: TOuterProgram<TAncestry = T::TAncestry>,
+    {
+        let mut image = Self::default();
+        for rw in states {
+            let state = rw.read();
+            let preamble = match state.get_preamble() {
+                AOption(ROption::RSome(x)) => x.to_string(),
+                AOption(ROption::RNone) => String::new(),
+            };
+            match state.get_dialect() {
+                AOption(ROption::RSome(Dialect::Python(x))) => {
+                    image.pip_requirements.extend(x.get_pip_requirements());
+                    image.python_modules.extend(
+                        get_python_modules(&preamble)
+                            .into_iter()
+                            .map(|x| x.as_str().into()),
+                    );
+                }
+                AOption(ROption::RSome(Dialect::R(_))) => {
+                    image.uses_r = true;
+                    let call = match state.get_call() {
+                        AOption(ROption::RSome(x)) => x.to_string(),
+                        AOption(ROption::RNone) => String::new(),
+                    };
+                    image.r_packages.extend(
+                        get_r_packages(&format!("{}\n{}", preamble, call))
+                            .into_iter()
+                            .filter(|x| x != "base")
+                            .map(|x| x.as_str().into()),
+                    );
+                }
+                _ => {}
+            }
+        }
+        image
+    }
+    /// Adds the packages the flow itself needs, besides those of its tasks.
+    pub fn add_flow_requirements(
+        &mut self,
+        pip_requirements: AVec<AString>,
+        r_packages: AVec<AString>,
+    ) {
+        self.pip_requirements.extend(pip_requirements);
+        if !r_packages.is_empty() {
+            self.uses_r = true;
+            self.r_packages.extend(r_packages);
+        }
+    }
+    /// Pip requirements declared by the programs and the flow, followed by
+    /// the distributions providing the imported modules none of them cover.
+    fn get_all_pip_requirements(&self) -> Vec<AString> {
+        let declared = self
+            .pip_requirements
+            .iter()
+            .map(|x| get_distribution_name(x.as_str()))
+            .collect::<BTreeSet<_>>();
+        let mut requirements = self.pip_requirements.iter().cloned().collect::<Vec<_>>();
+        let mut installed = BTreeSet::new();
+        for module in self.python_modules.iter() {
+            if let Some(distribution) = get_python_distribution(module.as_str()) {
+                let name = get_distribution_name(distribution);
+                if !declared.contains(&name)
+                    && !declared.contains(&get_distribution_name(module.as_str()))
+                    && installed.insert(name)
+                {
+                    requirements.push(distribution.into());
+                }
+            }
+        }
+        requirements
+    }
+    /// Name of the conda environment, derived from the flow's name.
+    fn get_environment_name(flow_name: &str) -> String {
+        let name = flow_name
+            .chars()
+            .map(|c| match c.is_ascii_alphanumeric() || c == '-' {
+                true => c,
+                false => '_',
+            })
+            .collect::<String>();
+        match name.is_empty() {
+            true => "aorist".to_string(),
+            false => name,
+        }
+    }
+    pub fn get_conda_environment(&self, flow_name: &str) -> String {
+        let mut lines = vec![
+            format!("name: {}", Self::get_environment_name(flow_name)),
+            "channels:".to_string(),
+            "  - conda-forge".to_string(),
+            "dependencies:".to_string(),
+            format!("  - python={}", PYTHON_VERSION),
+            "  - pip".to_string(),
+        ];
+        if self.uses_r {
+            // Python flows run R programs through rpy2
+            lines.push("  - r-base".to_string());
+            lines.push("  - rpy2".to_string());
+        }
+        let pip_requirements = self.get_all_pip_requirements();
+        if !pip_requirements.is_empty() {
+            lines.push("  - pip:".to_string());
+            for requirement in pip_requirements.iter() {
+                lines.push(format!("    - {}", requirement));
+            }
+        }
+        format!("{}\n", lines.join("\n"))
+    }
+    /// Script installing the R packages, if any R program is used.
+    pub fn get_r_install_script(&self) -> Option<String> {
+        if !self.uses_r {
+            return None;
+        }
+        let packages = self
+            .r_packages
+            .iter()
+            .map(|x| format!("\"{}\"", x))
+            .collect::<Vec<_>>();
+        let mut script = format!(
+            "packages <- c({})\n\
+             missing <- packages[!(packages %in% rownames(installed.packages()))]\n\
+             if (length(missing) > 0) {{\n    \
+                 install.packages(missing, repos = \"{}\")\n\
+             }}\n\
+             for (package in packages) {{\n    \
+                 library(package, character.only = TRUE)\n\
+             }}\n",
+            packages.join(", "),
+            CRAN_MIRROR,
+        );
+        if self.r_packages.contains(&AString::from("IRkernel")) {
+            // Makes the kernel visible to Jupyter and papermill
+            script.push_str("IRkernel::installspec(user = FALSE)\n");
+        }
+        Some(script)
+    }
+    pub fn get_dockerfile(&self, flow_name: &str) -> String {
+        let environment = Self::get_environment_name(flow_name);
+        let mut blocks = vec![
+            format!(
+                "# Image running the {} flow. Build it from the directory holding the\n\
+                 # flow and this file: docker build -t {} .\n\
+                 FROM {}",
+                flow_name,
+                environment.to_lowercase(),
+                BASE_IMAGE,
+            ),
+            format!(
+                "COPY environment.yml /tmp/environment.yml\n\
+                 RUN conda env create -f /tmp/environment.yml \\\n  \
+                 && conda clean -afy\n\
+                 ENV PATH=/opt/conda/envs/{}/bin:$PATH",
+                environment
+            ),
+        ];
+        if self.uses_r {
+            blocks.push("COPY install.R /tmp/install.R\nRUN Rscript /tmp/install.R".to_string());
+        }
+        if !self.python_modules.is_empty() {
+            blocks.push(format!(
+                "# Fail early if a module imported by the flow is missing\n\
+                 RUN python -c \"import {}\"",
+                self.python_modules
+                    .iter()
+                    .map(|x| x.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        blocks.push("COPY . /flow\nWORKDIR /flow".to_string());
+        format!("{}\n", blocks.join("\n\n"))
+    }
+    /// Content of every file defining the image, by file name.
+    pub fn get_files(&self, flow_name: &str) -> Vec<(&'static str, String)> {
+        let mut files = vec![
+            ("Dockerfile", self.get_dockerfile(flow_name)),
+            ("environment.yml", self.get_conda_environment(flow_name)),
+        ];
+        if let Some(script) = self.get_r_install_script() {
+            files.push(("install.R", script));
+        }
+        files
+    }
+    pub fn write<P: AsRef<Path>>(&self, dir: P, flow_name: &str) -> Result<()> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        for (name, content) in self.get_files(flow_name) {
+            std::fs::write(dir.as_ref().join(name), content)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_image(pip_requirements: &[&str], python_modules: &[&str]) -> ContainerImage {
+        ContainerImage {
+            pip_requirements: pip_requirements.iter().map(|x| AString::from(*x)).collect(),
+            python_modules: python_modules.iter().map(|x| AString::from(*x)).collect(),
+            ..ContainerImage::default()
+        }
+    }
+
+    #[test]
+    fn test_imported_modules_are_installed() {
+        let image = get_image(
+            &["PyYAML==6.0", "pandas"],
+            &["google", "json", "numpy", "pandas", "sklearn", "yaml"],
+        );
+        assert_eq!(
+            image.get_all_pip_requirements(),
+            vec!["PyYAML==6.0", "pandas", "numpy", "scikit-learn"]
+                .into_iter()
+                .map(AString::from)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_flow_requirements_are_installed() {
+        let mut image = get_image(&[], &["dask"]);
+        image.add_flow_requirements(
+            vec!["dask[distributed]"]
+                .into_iter()
+                .map(AString::from)
+                .collect(),
+            AVec::new(),
+        );
+        let environment = image.get_conda_environment("flow");
+        assert!(environment.ends_with("  - pip:\n    - dask[distributed]\n"));
+        assert!(!image.uses_r);
+        image.add_flow_requirements(
+            AVec::new(),
+            vec!["IRkernel"].into_iter().map(AString::from).collect(),
+        );
+        assert!(image.uses_r);
+        assert!(image
+            .get_r_install_script()
+            .unwrap()
+            .ends_with("IRkernel::installspec(user = FALSE)\n"));
+    }
+
+    #[test]
+    fn test_dockerfile_pins_base_image() {
+        let dockerfile = get_image(&[], &[]).get_dockerfile("flow");
+        assert!(dockerfile.contains(&format!("\nFROM {}\n", BASE_IMAGE)));
+    }
+}
@@ -1,20 +1,30 @@
 use abi_stable::std_types::ROption;
 use aorist_primitives::AOption;
+use aorist_util::find_cycles;
 
 use crate::code::CodeBlock;
 use crate::code::CodeBlockWithDefaultConstructor;
 use crate::constraint::TConstraintEnum;
-use crate::constraint::{OuterConstraint, TBuilder};
+use crate::constraint::{get_concept_tags, ConstraintOverrides, OuterConstraint, TBuilder};
 use crate::constraint_block::ConstraintBlock;
+use crate::concept::StorageRole;
 use crate::constraint_state::ConstraintState;
-use crate::dialect::Dialect;
-use crate::flow::{FlowBuilderBase, FlowBuilderMaterialize};
+use crate::dialect::{Dialect, DialectPreferences};
+use crate::driver::{
+    find_unreachable, map_in_order, remove_dangling_dummy_tasks,
+    remove_superfluous_dummy_tasks, AttachmentStatus, CacheReport, ConstraintBlockQueue,
+    ConstraintDependency, ConstraintGraph, ConstraintGraphReport, ContainerImage, DummyTaskGraph,
+    ExplainReport, ExternalDependency, FingerprintCache, FlowGroup, FlowPartition,
+    MissingConstraint, PartitionKey, SourceMap, SourceMapTask, UniverseSelection,
+    DEFAULT_FLOW_GROUP,
+};
+use crate::flow::{FlowBuilderBase, FlowBuilderMaterialize, IsolatedTask, TaskPaths};
 use crate::parameter_tuple::ParameterTuple;
 use crate::program::TOuterProgram;
 //use crate::task_name_shortener::TaskNameShortener;
 use abi_stable::external_types::parking_lot::rw_lock::{RReadGuard, RRwLock};
 use abi_stable::std_types::RArc;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use aorist_ast::{AncestorRecord, SimpleIdentifier, AST};
 use aorist_primitives::{AString, AVec, TAoristObject};
 use aorist_primitives::{Ancestry, AoristConcept, AoristUniverse, ToplineConcept};
@@ -25,6 +35,19 @@ use std::collections::{HashMap, HashSet, VecDeque};
 use tracing::{debug, level_enabled, trace, Level};
 use uuid::Uuid;
 
+/// Returned along with generated flows, to write the files accompanying them.
+pub struct FlowMetadata {
+    pub image: ContainerImage,
+    /// Tasks of the flows, to map generated code back to them.
+    pub tasks: Vec<SourceMapTask>,
+    /// Source maps of the generated files, by file name, or by flow name for
+    /// flows generated as a single file. Empty for flows not generated by
+    /// the driver.
+    pub source_maps: LinkedHashMap<AString, SourceMap>,
+    /// What was reused from the fingerprint cache, if it was enabled.
+    pub cache_report: Option<CacheReport>,
+}
+
 pub type ConstraintsBlockMap<'a, C, P> = LinkedHashMap<
     AString,
     (
@@ -49,17 +72,62 @@ where
         >>::BuilderInputType,
     >,
     <D as FlowBuilderBase<U>>::T: 'a,
-    A: Ancestry,
-    C: ToplineConcept<TUniverse = U>,
-    <B as TBuilder<'a>>::OuterType: OuterConstraint<'a, TAncestry = A>,
+    A: Ancestry + Sync,
+    C: ToplineConcept<TUniverse = U> + Send + Sync,
+    <B as TBuilder<'a>>::OuterType: OuterConstraint<'a, TAncestry = A> + Send + Sync,
     <<B as TBuilder<'a>>::OuterType as OuterConstraint<'a>>::TAncestry: Ancestry<TConcept = C>,
     <<<B as TBuilder<'a>>::OuterType as OuterConstraint<'a>>::TAncestry as Ancestry>::TConcept:
         ToplineConcept<TUniverse = U>,
-    P: TOuterProgram<TAncestry = A>,
+    P: TOuterProgram<TAncestry = A> + Send + Sync,
 {
     type CB: ConstraintBlock<'a, <D as FlowBuilderBase<U>>::T, B::OuterType, U, P>;
 
-    fn get_relevant_builders(topline_constraint_names: &LinkedHashSet<AString>) -> AVec<B> {
+    /// Checks that every constraint reachable from the topline constraints
+    /// has a builder and that their dependencies form a DAG.
+    fn validate_constraint_graph(
+        topline_constraint_names: &LinkedHashSet<AString>,
+    ) -> ConstraintGraphReport {
+        let builders = B::builders()
+            .into_iter()
+            .map(|x| (x.get_constraint_name(), x))
+            .collect::<LinkedHashMap<AString, _>>();
+        let mut report = ConstraintGraphReport::default();
+        let mut g: LinkedHashMap<AString, LinkedHashSet<AString>> = LinkedHashMap::new();
+        let mut queue: VecDeque<(AString, Option<AString>)> = topline_constraint_names
+            .iter()
+            .map(|x| (x.clone(), None))
+            .collect();
+        let mut visited: HashSet<AString> = HashSet::new();
+        while let Some((name, required_by)) = queue.pop_front() {
+            if visited.contains(&name) {
+                continue;
+            }
+            visited.insert(name.clone());
+            match builders.get(&name) {
+                Some(builder) => {
+                    let edges = g.entry(name.clone()).or_insert(LinkedHashSet::new());
+                    for req in builder.get_required_constraint_names() {
+                        edges.insert(req.clone());
+                        queue.push_back((req, Some(name.clone())));
+                    }
+                }
+                None => report.missing_constraints.push(MissingConstraint {
+                    name,
+                    required_by,
+                }),
+            }
+        }
+        report.cycles = find_cycles(g.iter());
+        report.unreachable_constraints = find_unreachable(&g, &report.cycles);
+        report
+    }
+    fn get_relevant_builders(
+        topline_constraint_names: &LinkedHashSet<AString>,
+    ) -> Result<AVec<B>> {
+        let report = Self::validate_constraint_graph(topline_constraint_names);
+        if !report.is_empty() {
+            return Err(report.into());
+        }
         let mut visited = HashSet::new();
         let mut relevant_builders = LinkedHashMap::new();
         let mut g: LinkedHashMap<AString, LinkedHashSet<AString>> = LinkedHashMap::new();
@@ -71,9 +139,10 @@ where
                 .map(|x| (x.get_constraint_name(), x))
                 .collect::<LinkedHashMap<AString, _>>();
 
-            let constraint = builders
-                .remove(start)
-                .expect(format!("Missing constraint named {}", start).as_str());
+            let constraint = match builders.remove(start) {
+                Some(x) => x,
+                None => continue,
+            };
             let mut builder_q = vec![(start.clone(), constraint)]
                 .into_iter()
                 .collect::<VecDeque<_>>();
@@ -87,7 +156,7 @@ where
                     if !visited.contains(&req) {
                         let another = match builders.remove(&req) {
                             Some(x) => x,
-                            None => panic!("Cannot find {} in builders.", req),
+                            None => continue,
                         };
                         builder_q.push_back((req.clone(), another));
                         visited.insert(req.clone());
@@ -101,31 +170,48 @@ where
         }
         let mut sorted_builders = AVec::new();
         while g.len() > 0 {
-            let leaf = g
+            let leaf = match g
                 .iter()
                 .filter(|(_, v)| v.len() == 0)
                 .map(|(k, _)| k)
                 .next()
-                .unwrap()
-                .clone();
+            {
+                Some(x) => x.clone(),
+                None => {
+                    return Err(ConstraintGraphReport {
+                        cycles: find_cycles(g.iter()),
+                        ..Default::default()
+                    }
+                    .into())
+                }
+            };
 
-            let builder = relevant_builders.remove(&leaf).unwrap();
+            let builder = match relevant_builders.remove(&leaf) {
+                Some(x) => x,
+                None => bail!("No builder found for constraint {}.", leaf),
+            };
             if let Some(parents) = rev.remove(&leaf) {
                 for parent in parents {
-                    g.get_mut(&parent).unwrap().remove(&leaf);
+                    match g.get_mut(&parent) {
+                        Some(edges) => {
+                            edges.remove(&leaf);
+                        }
+                        None => bail!("Constraint {} required by unknown {}.", leaf, parent),
+                    }
                 }
             }
             sorted_builders.push(builder);
             g.remove(&leaf);
         }
 
-        sorted_builders
+        Ok(sorted_builders)
     }
     fn init_unsatisfied_constraints(&self) -> Result<ConstraintsBlockMap<'a, B::OuterType, P>>;
 
     fn find_satisfiable_constraint_block(
         &self,
         unsatisfied_constraints: &mut ConstraintsBlockMap<'a, B::OuterType, P>,
+        queue: &mut ConstraintBlockQueue,
     ) -> Option<(
         LinkedHashMap<(Uuid, AString), RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>>,
         AString,
@@ -134,27 +220,14 @@ where
             "There are {} unsatisfied constraints.",
             unsatisfied_constraints.len()
         );
-        let constraint_block_name = unsatisfied_constraints
-            .iter()
-            .filter(|(_, v)| v.0.len() == 0)
-            .map(|(k, _)| k.clone())
-            .next();
-        match constraint_block_name {
-            Some(name) => {
-                let (_dependency_names, constraints) =
-                    unsatisfied_constraints.remove(&name).unwrap();
-                debug!(
-                    "Found satisfiable constraint block with name {} and size {}",
-                    name,
-                    constraints.len()
-                );
-                for (_, (v, _)) in unsatisfied_constraints.iter_mut() {
-                    v.remove(&name);
-                }
-                Some((constraints, name.as_str().into()))
-            }
-            None => None,
-        }
+        let name = queue.pop()?;
+        let (_dependency_names, constraints) = unsatisfied_constraints.remove(&name).unwrap();
+        debug!(
+            "Found satisfiable constraint block with name {} and size {}",
+            name,
+            constraints.len()
+        );
+        Some((constraints, name))
     }
     fn init_tasks_dict(
         block: &LinkedHashMap<(Uuid, AString), RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>>,
@@ -168,7 +241,8 @@ where
         }
     }
     fn get_constraint_rwlock(&self, uuid: &(Uuid, AString)) -> RArc<RRwLock<B::OuterType>>;
-    fn get_preferences(&self) -> AVec<Dialect>;
+    fn get_preferences(&self) -> DialectPreferences;
+    fn get_overrides(&self) -> &ConstraintOverrides;
     fn get_ancestry(&self) -> &A;
     fn process_constraint_with_program(
         &mut self,
@@ -176,26 +250,136 @@ where
         uuid: (Uuid, AString),
         calls: &mut HashMap<(AString, AString, AString), AVec<(AString, ParameterTuple)>>,
         state: RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>,
-        programs: &AVec<P>,
-    ) {
+    ) -> Result<()> {
         let name = constraint.get_name().clone();
         drop(constraint);
-        // TODO: turn into a reference to a field on self
-        let preferences = self.get_preferences();
-        let mut write = state.write();
-        // TODO: remove dummy hash map
-        write.satisfy(&preferences, self.get_ancestry(), programs);
-        drop(write);
 
         // TODO: preambles and calls are superflous
         if let AOption(ROption::RSome(key)) = state.read().key.as_ref() {
             calls
                 .entry((state.read().get_call().unwrap(), name, uuid.1.clone()))
                 .or_insert(AVec::new())
-                .push((key.clone(), state.read().get_params().unwrap()))
+                .push((key.clone(), state.read().get_params().unwrap()));
+            Ok(())
         } else {
-            panic!("No key found for constraint state: {:?}", uuid);
-        };
+            bail!("No key found for constraint state: {:?}", uuid)
+        }
+    }
+    fn get_parallelism(&self) -> usize;
+    fn set_parallelism(&mut self, parallelism: usize);
+    /// Calls the programs of the states of every block whose dependencies
+    /// are satisfied, on up to `get_parallelism()` threads, before these
+    /// blocks are processed. States that the cache will provide are left
+    /// alone, as are states whose program failed: they are satisfied again,
+    /// and fail, when their block is processed. Blocks are still processed
+    /// one at a time in queue order, so the output is the same as without
+    /// parallelism.
+    fn satisfy_ready_blocks(
+        &mut self,
+        unsatisfied_constraints: &ConstraintsBlockMap<'a, B::OuterType, P>,
+        queue: &ConstraintBlockQueue,
+    ) -> Result<()> {
+        if self.get_parallelism() <= 1 {
+            return Ok(());
+        }
+        let preferences = self.get_preferences();
+        let mut pending = Vec::new();
+        for name in queue.get_ready() {
+            let states = match unsatisfied_constraints.get(&name) {
+                Some((_, states)) => states,
+                None => continue,
+            };
+            let programs = self.get_programs_for(&name);
+            for state in states.values() {
+                let mut write = state.write();
+                if write.get_params().is_some() || !write.requires_program()? {
+                    continue;
+                }
+                let programs = self.get_overrides().filter_programs(
+                    &write.get_name(),
+                    &write.get_tags(),
+                    &programs,
+                )?;
+                let resolved = write.resolve_preferences(&preferences);
+                let program = ConstraintState::<'a, B::OuterType, P>::find_best_program(
+                    &resolved.dialects,
+                    &programs,
+                );
+                if let (Some(_), Some(cache)) = (program, self.get_cache()) {
+                    let fingerprint = write.compute_fingerprint(program)?;
+                    if cache.contains(&write.get_cache_key(), &fingerprint) {
+                        continue;
+                    }
+                }
+                drop(write);
+                pending.push((state.clone(), programs));
+            }
+        }
+        let ancestry = self.get_ancestry();
+        map_in_order(&pending, self.get_parallelism(), |(state, programs)| {
+            if let Err(err) = state.write().satisfy(&preferences, ancestry, programs) {
+                debug!("Deferring failed constraint state: {}", err);
+            }
+        });
+        Ok(())
+    }
+    /// Satisfies the states of a block in block order, reusing cached
+    /// arguments where possible. States already satisfied by
+    /// `satisfy_ready_blocks` are only stored in the cache.
+    fn satisfy_constraint_states(
+        &mut self,
+        states: AVec<RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>>,
+        programs: &AVec<P>,
+    ) -> Result<()> {
+        // TODO: turn into a reference to a field on self
+        let preferences = self.get_preferences();
+        let mut pending = Vec::new();
+        for state in states.iter() {
+            let mut write = state.write();
+            if !write.requires_program()? {
+                write.compute_fingerprint(None)?;
+                continue;
+            }
+            let programs = self.get_overrides().filter_programs(
+                &write.get_name(),
+                &write.get_tags(),
+                programs,
+            )?;
+            let resolved = write.resolve_preferences(&preferences);
+            let program = ConstraintState::<'a, B::OuterType, P>::find_best_program(
+                &resolved.dialects,
+                &programs,
+            );
+            let fingerprint = write.compute_fingerprint(program)?;
+            let cache_key = write.get_cache_key();
+            let cached = match (program, self.get_cache()) {
+                (Some(_), Some(cache)) => cache.lookup(&cache_key, &fingerprint),
+                _ => None,
+            };
+            match (program, cached) {
+                (Some(program), Some((params, context))) => {
+                    write.satisfy_from_cache(program, params, context, resolved)
+                }
+                _ => pending.push((state.clone(), cache_key, fingerprint, programs.clone())),
+            }
+        }
+        for (state, cache_key, fingerprint, programs) in pending.iter() {
+            if state.read().get_params().is_none() {
+                state
+                    .write()
+                    .satisfy(&preferences, self.get_ancestry(), programs)?;
+            }
+            if let Some(cache) = self.get_cache() {
+                let read = state.read();
+                cache.store(
+                    cache_key,
+                    fingerprint,
+                    &read.get_params().unwrap(),
+                    read.get_context(),
+                );
+            }
+        }
+        Ok(())
     }
     fn process_constraint_state(
         &mut self,
@@ -204,34 +388,41 @@ where
         calls: &mut HashMap<(AString, AString, AString), AVec<(AString, ParameterTuple)>>,
         reverse_dependencies: &HashMap<(Uuid, AString), HashSet<(AString, Uuid, AString)>>,
         unsatisfied_constraints: &ConstraintsBlockMap<'a, B::OuterType, P>,
-        programs: &AVec<P>,
     ) -> Result<()> {
         let read = state.read();
-        assert!(!read.satisfied);
-        assert_eq!(read.unsatisfied_dependencies.len(), 0);
+        if read.satisfied {
+            bail!("Constraint {:?} was already satisfied.", uuid);
+        }
+        if read.unsatisfied_dependencies.len() > 0 {
+            bail!(
+                "Constraint {:?} still has {} unsatisfied dependencies.",
+                uuid,
+                read.unsatisfied_dependencies.len()
+            );
+        }
         drop(read);
 
         let rw = self.get_constraint_rwlock(&uuid);
         let constraint = rw.read();
 
         if constraint.requires_program()? {
-            self.process_constraint_with_program(
-                constraint,
-                uuid.clone(),
-                calls,
-                state.clone(),
-                programs,
-            );
+            self.process_constraint_with_program(constraint, uuid.clone(), calls, state.clone())?;
         }
 
         if let Some(v) = reverse_dependencies.get(&uuid) {
             for (dependency_name, dependency_uuid, dependency_root_type) in v {
-                let rw = unsatisfied_constraints
+                let rw = match unsatisfied_constraints
                     .get(dependency_name)
-                    .unwrap()
-                    .1
-                    .get(&(*dependency_uuid, dependency_root_type.clone()))
-                    .unwrap();
+                    .and_then(|x| x.1.get(&(*dependency_uuid, dependency_root_type.clone())))
+                {
+                    Some(x) => x,
+                    None => bail!(
+                        "Constraint {:?} is required by unknown {} on {:?}.",
+                        uuid,
+                        dependency_name,
+                        (dependency_uuid, dependency_root_type)
+                    ),
+                };
                 let mut write = rw.write();
                 write.mark_dependency_as_satisfied(&state, &uuid);
                 drop(write);
@@ -288,20 +479,21 @@ where
         let mut calls: HashMap<(AString, AString, AString), AVec<(AString, ParameterTuple)>> =
             HashMap::new();
         let mut blocks = AVec::new();
-        let mut by_dialect: HashMap<AOption<Dialect>, AVec<_>> = HashMap::new();
+        let mut by_dialect: LinkedHashMap<AOption<Dialect>, AVec<_>> = LinkedHashMap::new();
+        if let Some(cache) = self.get_cache() {
+            cache.begin_block(constraint_name.clone());
+        }
+        for state in block.values() {
+            state.write().compute_task_key();
+        }
+        self.satisfy_constraint_states(block.values().cloned().collect(), programs)?;
         for (id, state) in block.clone() {
-            let mut write = state.write();
-
-            write.compute_task_key();
-            drop(write);
-
             self.process_constraint_state(
                 id.clone(),
                 state.clone(),
                 &mut calls,
                 reverse_dependencies,
                 unsatisfied_constraints,
-                programs,
             )?;
             self.mark_constraint_state_as_satisfied(id.clone(), state.clone());
             by_dialect
@@ -309,11 +501,11 @@ where
                 .or_insert(AVec::new())
                 .push((state.clone(), id));
         }
-        let mut processed = HashMap::new();
+        let mut processed = LinkedHashMap::new();
         let mut reduced_block = LinkedHashMap::new();
         for (dialect, satisfied) in by_dialect.into_iter() {
             if dialect.is_some() {
-                let mut unique: HashMap<_, AVec<_>> = HashMap::new();
+                let mut unique: LinkedHashMap<_, AVec<_>> = LinkedHashMap::new();
                 for (c, id) in satisfied.into_iter() {
                     let key = c.read().get_dedup_key();
                     trace!("Dedup key: {:?}", key);
@@ -393,11 +585,150 @@ where
         Ok((blocks, tasks_dict))
     }
     fn get_render_dependencies(&self) -> bool;
+    fn get_cache(&mut self) -> Option<&mut FingerprintCache>;
+    fn set_cache(&mut self, cache: FingerprintCache);
+    /// Reuses program arguments from the cache file at `path` for constraint
+    /// states whose fingerprint did not change since the last run.
+    fn enable_cache(&mut self, path: &str) -> Result<()> {
+        self.set_cache(FingerprintCache::load(path)?);
+        Ok(())
+    }
+    fn get_cache_report(&mut self) -> Option<CacheReport> {
+        self.get_cache().map(|x| x.get_report())
+    }
     fn get_constraint_explanation(
         &self,
         constraint_name: &AString,
     ) -> (AOption<AString>, AOption<AString>);
+    /// Appends to the block comment how dialects were chosen for states
+    /// that did not use the global preference ordering.
+    fn add_preference_notes<'c, I>(body: AOption<AString>, states: I) -> AOption<AString>
+    where
+        I: Iterator<Item = &'c RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>>,
+        'a: 'c,
+        B::OuterType: 'c,
+        P: 'c,
+    {
+        let mut notes: LinkedHashMap<AString, usize> = LinkedHashMap::new();
+        for state in states {
+            if let AOption(ROption::RSome(note)) = state.read().get_preference_note() {
+                *notes.entry(note).or_insert(0) += 1;
+            }
+        }
+        if notes.is_empty() {
+            return body;
+        }
+        let notes = notes
+            .into_iter()
+            .map(|(note, count)| format!("Dialect: {} [{} task(s)]", note, count))
+            .collect::<Vec<_>>()
+            .join("\n");
+        AOption(ROption::RSome(
+            match body {
+                AOption(ROption::RSome(body)) => format!("{}\n{}", body, notes),
+                AOption(ROption::RNone) => notes,
+            }
+            .as_str()
+            .into(),
+        ))
+    }
     fn add_block(&mut self, constraint_block: Self::CB);
+    fn get_concepts(&self) -> RArc<RRwLock<HashMap<(Uuid, AString), C>>>;
+    fn get_partition(&self) -> Option<&FlowPartition>;
+    fn set_partition(&mut self, partition: FlowPartition);
+    fn get_flow_groups(&self) -> &LinkedHashMap<AString, FlowGroup>;
+    fn get_flow_groups_mut(&mut self) -> &mut LinkedHashMap<AString, FlowGroup>;
+    fn get_partition_key(&self, state: &ConstraintState<'a, B::OuterType, P>) -> PartitionKey {
+        let ancestors = state.get_ancestors();
+        let concepts = self.get_concepts();
+        let concept_map = concepts.read();
+        let lineage = std::iter::once(state.get_root())
+            .chain(
+                ancestors
+                    .iter()
+                    .rev()
+                    .filter(|x| x.uuid != state.get_root_uuid())
+                    .filter_map(|x| concept_map.get(&x.get_key()).cloned()),
+            )
+            .collect::<Vec<_>>();
+        PartitionKey::new(state.get_name(), &lineage, &ancestors)
+    }
+    /// Splits a block of constraint states by flow group, keeping the order
+    /// of states within each group. Without a partition everything goes to
+    /// the default group.
+    fn partition_constraint_block(
+        &self,
+        block: &LinkedHashMap<(Uuid, AString), RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>>,
+    ) -> Result<
+        LinkedHashMap<
+            AString,
+            LinkedHashMap<(Uuid, AString), RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>>,
+        >,
+    > {
+        let mut groups = LinkedHashMap::new();
+        for (id, state) in block.iter() {
+            let group = match self.get_partition() {
+                Some(partition) => partition.get_group(&self.get_partition_key(&state.read()))?,
+                None => DEFAULT_FLOW_GROUP.into(),
+            };
+            groups
+                .entry(group)
+                .or_insert(LinkedHashMap::new())
+                .insert(id.clone(), state.clone());
+        }
+        Ok(groups)
+    }
+    /// Task id of a task, given the identifier it was assigned to.
+    fn get_task_id(identifier: &AST) -> Option<AString> {
+        match identifier {
+            AST::SimpleIdentifier(x) => Some(x.read().name()),
+            AST::Subscript(x) => match x.read().b() {
+                AST::StringLiteral(y) => Some(y.read().value()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+    /// Makes dependencies on tasks in other flow groups resolve to tasks
+    /// waiting for them, which the flow builder creates when materializing
+    /// the group's flow.
+    fn add_external_dependencies(
+        &mut self,
+        group: &AString,
+        block: &LinkedHashMap<(Uuid, AString), RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>>,
+        identifiers: &mut HashMap<Uuid, AST>,
+        task_ids: &HashMap<Uuid, (AString, AString)>,
+    ) -> Result<()> {
+        let builder = D::new();
+        for state in block.values() {
+            for dependency in state.read().get_dependencies()? {
+                if identifiers.contains_key(&dependency) {
+                    continue;
+                }
+                let (upstream, task_id) = match task_ids.get(&dependency) {
+                    Some(x) => x.clone(),
+                    None => bail!("Could not find the task for constraint {}.", dependency),
+                };
+                let name = builder.get_external_dependency_name(&upstream, &task_id);
+                identifiers.insert(
+                    dependency,
+                    AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(name.clone())),
+                );
+                self.get_flow_groups_mut()
+                    .entry(group.clone())
+                    .or_insert_with(FlowGroup::new)
+                    .external_dependencies
+                    .entry(name.clone())
+                    .or_insert(ExternalDependency {
+                        group: upstream,
+                        flow_name: AString::from(""),
+                        task_id,
+                        identifier: name,
+                    });
+            }
+        }
+        Ok(())
+    }
     fn satisfy_constraints(&mut self) -> Result<()> {
         let mut unsatisfied_constraints = self.init_unsatisfied_constraints()?;
         let mut reverse_dependencies: HashMap<(Uuid, AString), HashSet<(AString, Uuid, AString)>> =
@@ -415,12 +746,17 @@ where
             }
         }
 
+        let mut queue = ConstraintBlockQueue::new(&unsatisfied_constraints);
         let mut existing_names = HashSet::new();
-        let mut identifiers = HashMap::new();
+        // each flow group only sees the identifiers of its own tasks
+        let mut group_identifiers: HashMap<AString, HashMap<Uuid, AST>> = HashMap::new();
+        // constraint uuid => (group, task id), only tracked when partitioning
+        let mut task_ids: HashMap<Uuid, (AString, AString)> = HashMap::new();
         // find at least one satisfiable constraint
         loop {
+            self.satisfy_ready_blocks(&unsatisfied_constraints, &queue)?;
             let mut satisfiable =
-                self.find_satisfiable_constraint_block(&mut unsatisfied_constraints);
+                self.find_satisfiable_constraint_block(&mut unsatisfied_constraints, &mut queue);
             if let Some((ref mut block, ref constraint_name)) = satisfiable {
                 debug!(
                     "Processing constraint {} with block size {}.",
@@ -430,31 +766,83 @@ where
                 let programs = self.get_programs_for(constraint_name);
                 let snake_case_name = to_snake_case(constraint_name.as_str().into());
                 if block.len() > 0 {
-                    let (members, tasks_dict) = self.process_constraint_block(
-                        &mut block.clone(),
-                        &reverse_dependencies,
-                        snake_case_name.as_str().into(),
-                        &unsatisfied_constraints,
-                        &mut identifiers,
-                        &programs,
-                        &mut existing_names,
-                    )?;
+                    for (group, mut group_block) in self.partition_constraint_block(block)? {
+                        let identifiers = group_identifiers
+                            .entry(group.clone())
+                            .or_insert(HashMap::new());
+                        self.add_external_dependencies(
+                            &group,
+                            &group_block,
+                            identifiers,
+                            &task_ids,
+                        )?;
+                        let (members, tasks_dict) = self.process_constraint_block(
+                            &mut group_block,
+                            &reverse_dependencies,
+                            snake_case_name.as_str().into(),
+                            &unsatisfied_constraints,
+                            identifiers,
+                            &programs,
+                            &mut existing_names,
+                        )?;
+                        if self.get_partition().is_some() {
+                            // tasks replaced by a mapped task are waited
+                            // for through the mapped task's id
+                            let endpoints = self.get_endpoints();
+                            let mapped_task_ids = members
+                                .iter()
+                                .flat_map(|x| x.get_mapped_task_ids(endpoints.clone()))
+                                .collect::<HashMap<_, _>>();
+                            for (uuid, identifier) in identifiers.iter() {
+                                if !task_ids.contains_key(uuid) {
+                                    if let Some(task_id) = Self::get_task_id(identifier) {
+                                        let task_id = match mapped_task_ids.get(&task_id) {
+                                            Some(x) => x.clone(),
+                                            None => task_id,
+                                        };
+                                        task_ids.insert(*uuid, (group.clone(), task_id));
+                                    }
+                                }
+                            }
+                        }
 
-                    let (title, body) = self.get_constraint_explanation(constraint_name);
-                    let constraint_block = Self::CB::new(
-                        snake_case_name.as_str().into(),
-                        title.and_then(|x| ROption::RSome(x.as_str().into())),
-                        body.and_then(|x| ROption::RSome(x.as_str().into())),
-                        members,
-                        tasks_dict,
-                    );
-                    /*for (key, val) in constraint_block.get_identifiers() {
-                        identifiers.insert(key, val);
-                    }*/
-                    self.add_block(constraint_block);
+                        let (title, body) = self.get_constraint_explanation(constraint_name);
+                        let body = Self::add_preference_notes(body, group_block.values());
+                        let constraint_block = Self::CB::new(
+                            snake_case_name.as_str().into(),
+                            title.and_then(|x| ROption::RSome(x.as_str().into())),
+                            body.and_then(|x| ROption::RSome(x.as_str().into())),
+                            members,
+                            tasks_dict,
+                        );
+                        /*for (key, val) in constraint_block.get_identifiers() {
+                            identifiers.insert(key, val);
+                        }*/
+                        let index = self.get_blocks().len();
+                        self.add_block(constraint_block);
+                        self.get_flow_groups_mut()
+                            .entry(group)
+                            .or_insert_with(FlowGroup::new)
+                            .blocks
+                            .push(index);
+                    }
                 }
             } else {
-                assert_eq!(unsatisfied_constraints.len(), 0);
+                if unsatisfied_constraints.len() > 0 {
+                    let leftover = unsatisfied_constraints
+                        .iter()
+                        .map(|(k, (v, _))| {
+                            (
+                                k.clone(),
+                                v.iter().filter(|x| !queue.is_processed(x)).cloned().collect(),
+                            )
+                        })
+                        .collect();
+                    return Err(ConstraintGraphReport::from_leftover_blocks(&leftover).into());
+                }
+                if let Some(cache) = self.get_cache() {
+                    cache.save()?;
+                }
                 return Ok(());
             }
         }
@@ -462,7 +850,29 @@ where
     fn get_programs_for(&self, constraint_name: &AString) -> AVec<P>;
     fn get_endpoints(&self) -> U::TEndpoints;
     fn get_dependencies(&self) -> AVec<AString>;
-    fn run(&mut self, flow_name: AOption<AString>) -> Result<(AString, AVec<AString>)> {
+    /// Environment the programs of the flow need. Must be called after the
+    /// constraints were satisfied.
+    fn get_container_image(&self) -> ContainerImage {
+        let builder = D::new();
+        let mut image =
+            ContainerImage::from_states(self.get_satisfied_constraint_states().into_iter());
+        image.add_flow_requirements(builder.get_pip_requirements(), builder.get_r_packages());
+        image
+    }
+    /// Everything generated alongside the flow. Must be called after the
+    /// constraints were satisfied.
+    fn get_flow_metadata(&mut self) -> Result<FlowMetadata> {
+        Ok(FlowMetadata {
+            image: self.get_container_image(),
+            tasks: SourceMapTask::from_states(self.get_satisfied_constraint_states().into_iter())?,
+            source_maps: LinkedHashMap::new(),
+            cache_report: self.get_cache_report(),
+        })
+    }
+    fn run(&mut self, flow_name: AOption<AString>) -> Result<(AString, FlowMetadata)> {
+        if self.get_partition().is_some() {
+            bail!("Flows are partitioned, use run_split to generate them.");
+        }
         self.satisfy_constraints()?;
         let etl = D::new();
         let endpoints = self.get_endpoints().clone();
@@ -471,13 +881,178 @@ where
             .iter()
             .map(|x| x.get_statements(endpoints.clone()))
             .collect::<AVec<_>>();
-
-        Ok((
-            etl.materialize(statements_and_preambles, flow_name)?,
-            self.get_dependencies(),
-        ))
+        let name = match flow_name {
+            AOption(ROption::RSome(ref x)) => x.clone(),
+            AOption(ROption::RNone) => "flow".into(),
+        };
+        let (content, ranges) =
+            etl.materialize_with_source_ranges(statements_and_preambles, AVec::new(), flow_name)?;
+        let mut metadata = self.get_flow_metadata()?;
+        let source_map = SourceMap::new(name.clone(), &ranges, &metadata.tasks);
+        metadata.source_maps.insert(name, source_map);
+        Ok((content, metadata))
+    }
+    /// Generates one flow per flow group, named `<flow_name>_<group>`.
+    /// Returns the content of each flow by file name.
+    fn run_split(
+        &mut self,
+        flow_name: AOption<AString>,
+    ) -> Result<(LinkedHashMap<AString, AString>, FlowMetadata)> {
+        self.satisfy_constraints()?;
+        let etl = D::new();
+        let endpoints = self.get_endpoints().clone();
+        let base_name = match flow_name {
+            AOption(ROption::RSome(x)) => x,
+            AOption(ROption::RNone) => "flow".into(),
+        };
+        let get_flow_name =
+            |group: &AString| -> AString { format!("{}_{}", base_name, group).as_str().into() };
+        let mut files = LinkedHashMap::new();
+        let mut metadata = self.get_flow_metadata()?;
+        for (group, flow_group) in self.get_flow_groups().iter() {
+            let name = get_flow_name(group);
+            let statements_and_preambles = flow_group
+                .blocks
+                .iter()
+                .map(|x| self.get_blocks()[*x].get_statements(endpoints.clone()))
+                .collect::<AVec<_>>();
+            let external_dependencies = flow_group
+                .external_dependencies
+                .values()
+                .map(|x| ExternalDependency {
+                    flow_name: get_flow_name(&x.group),
+                    ..x.clone()
+                })
+                .collect::<AVec<_>>();
+            let (content, ranges) = etl.materialize_with_source_ranges(
+                statements_and_preambles,
+                external_dependencies,
+                AOption(ROption::RSome(name.clone())),
+            )?;
+            let file = etl.get_file_name(&name);
+            let source_map = SourceMap::new(file.clone(), &ranges, &metadata.tasks);
+            metadata.source_maps.insert(file.clone(), source_map);
+            files.insert(file, content);
+        }
+        Ok((files, metadata))
     }
     fn get_blocks(&self) -> &AVec<Self::CB>;
+    fn get_satisfied_constraint_states(
+        &self,
+    ) -> AVec<RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>>;
+    /// Exports the graph of satisfied constraint states. Must be called
+    /// after the constraints were satisfied.
+    fn get_constraint_graph(&self) -> Result<ConstraintGraph> {
+        ConstraintGraph::from_states(self.get_satisfied_constraint_states().into_iter())
+    }
+    /// The satisfied constraint states rendered as tasks, in the order they
+    /// were satisfied, with dependencies on deduplicated states pointing at
+    /// the task they were merged into. Must be called after the constraints
+    /// were satisfied.
+    fn get_isolated_tasks(&self) -> Result<AVec<IsolatedTask>> {
+        let states = self.get_satisfied_constraint_states();
+        let mut deduped_names = HashMap::new();
+        for rw in states.iter() {
+            let state = rw.read();
+            if state.has_task_name() && state.get_dialect().is_some() {
+                deduped_names.insert(
+                    (state.get_name(), state.get_dedup_key()),
+                    state.get_task_name(),
+                );
+            }
+        }
+        let get_task_name = |state: &ConstraintState<'a, B::OuterType, P>| -> Result<AString> {
+            if state.has_task_name() {
+                return Ok(state.get_task_name());
+            }
+            match deduped_names.get(&(state.get_name(), state.get_dedup_key())) {
+                Some(name) => Ok(name.clone()),
+                None => bail!("No task was rendered for {}", state.get_name()),
+            }
+        };
+        let mut tasks = AVec::new();
+        for rw in states.iter() {
+            let state = rw.read();
+            if !state.has_task_name() {
+                continue;
+            }
+            let mut dependencies = LinkedHashSet::new();
+            for dependency in state.satisfied_dependencies.iter() {
+                dependencies.insert(get_task_name(&dependency.read())?);
+            }
+            tasks.push(IsolatedTask {
+                name: state.get_task_name(),
+                constraint: state.get_name(),
+                dialect: state.get_dialect(),
+                call: state.get_call(),
+                params: state.get_params(),
+                preamble: state.get_preamble(),
+                dependencies: dependencies.into_iter().collect(),
+                ancestors: state.get_task_ancestors()?,
+            });
+        }
+        Ok(tasks)
+    }
+    /// Paths of the concepts below the root of every task, keyed by task
+    /// name, for flow types whose tasks read and write local files.
+    /// `get_path` returns the path of a concept, if it has one, and
+    /// `get_storage_roles` the storages a storage setup reads from and
+    /// writes to. Paths are split by the role of the closest storage holding
+    /// them; paths outside of storage setups are left out. Tasks rooted at
+    /// the universe get no paths. Must be called after the constraints were
+    /// satisfied.
+    fn get_task_paths<F, G>(
+        &self,
+        get_path: F,
+        get_storage_roles: G,
+    ) -> LinkedHashMap<AString, TaskPaths>
+    where
+        F: Fn(&C) -> AOption<AString>,
+        G: Fn(&C) -> AVec<(Uuid, StorageRole)>,
+    {
+        let concepts = self.get_concepts();
+        let roles = concepts
+            .read()
+            .values()
+            .flat_map(|x| get_storage_roles(x).into_iter())
+            .collect::<HashMap<_, _>>();
+        let mut task_paths = LinkedHashMap::new();
+        for rw in self.get_satisfied_constraint_states().iter() {
+            let state = rw.read();
+            if !state.has_task_name() {
+                continue;
+            }
+            let mut paths = TaskPaths::default();
+            let root = state.get_root();
+            if root.get_type().as_str() != "Universe" {
+                // ancestors are ordered from the universe down
+                let role = state
+                    .get_ancestors()
+                    .iter()
+                    .rev()
+                    .filter_map(|x| roles.get(&x.uuid).cloned())
+                    .next();
+                let mut queue: VecDeque<(C, Option<StorageRole>)> =
+                    vec![(root, role)].into_iter().collect();
+                while let Some((concept, role)) = queue.pop_front() {
+                    let role = match roles.get(&concept.get_uuid()) {
+                        Some(x) => Some(*x),
+                        None => role,
+                    };
+                    if let AOption(ROption::RSome(path)) = get_path(&concept) {
+                        match role {
+                            Some(StorageRole::Source) => paths.add_source(path),
+                            Some(StorageRole::Target) => paths.add_target(path),
+                            None => {}
+                        }
+                    }
+                    queue.extend(concept.get_child_concepts().into_iter().map(|x| (x, role)));
+                }
+            }
+            task_paths.insert(state.get_task_name(), paths);
+        }
+        task_paths
+    }
     fn _new(
         concepts: RArc<RRwLock<HashMap<(Uuid, AString), C>>>,
         constraints: LinkedHashMap<(Uuid, AString), RArc<RRwLock<B::OuterType>>>,
@@ -486,7 +1061,8 @@ where
         ancestors: HashMap<(Uuid, AString), AVec<AncestorRecord>>,
         topline_constraint_names: LinkedHashSet<AString>,
         programs: LinkedHashMap<AString, AVec<P>>,
-        preferences: AVec<Dialect>,
+        preferences: DialectPreferences,
+        overrides: ConstraintOverrides,
         render_dependencies: bool,
     ) -> Self;
 
@@ -504,6 +1080,8 @@ where
     ) -> Result<LinkedHashMap<(Uuid, AString), RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>>>
     {
         let mut states_map = LinkedHashMap::new();
+        // content of each ancestor, shared by the states on its descendants
+        let mut concept_contents = HashMap::new();
         debug!(
             "Generating constraint states map from constraints with size: {}.",
             constraints.len()
@@ -519,162 +1097,12 @@ where
                     rw.clone(),
                     concepts.clone(),
                     ancestors,
+                    &mut concept_contents,
                 )?)),
             );
         }
         Ok(states_map)
     }
-    fn remove_redundant_dependencies(
-        raw_unsatisfied_constraints: &mut LinkedHashMap<
-            (Uuid, AString),
-            RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>,
-        >,
-    ) {
-        /* Remove redundant dependencies */
-        // constraint key => constraint key dependency on it
-        let mut changes_made = true;
-        while changes_made {
-            changes_made = false;
-            let mut reverse_dependencies: LinkedHashMap<(Uuid, AString), AVec<(Uuid, AString)>> =
-                LinkedHashMap::new();
-            for (k, v) in raw_unsatisfied_constraints.iter() {
-                for dep in v.read().unsatisfied_dependencies.iter() {
-                    reverse_dependencies
-                        .entry(dep.clone())
-                        .or_insert(AVec::new())
-                        .push(k.clone());
-                }
-            }
-
-            let tips = raw_unsatisfied_constraints
-                .iter()
-                .filter(|(k, _v)| !reverse_dependencies.contains_key(k));
-            for tip in tips {
-                let mut visits: HashMap<(Uuid, AString), (Uuid, AString)> = HashMap::new();
-                let mut queue: VecDeque<((Uuid, AString), RArc<RRwLock<_>>)> = VecDeque::new();
-                queue.push_back((tip.0.clone(), tip.1.clone()));
-                while queue.len() > 0 {
-                    let (key, elem) = queue.pop_front().unwrap();
-                    let new_deps = elem.read().unsatisfied_dependencies.clone();
-                    for dep in new_deps {
-                        let dep_constraint = raw_unsatisfied_constraints.get(&dep).unwrap().clone();
-                        // we have already visited this dependency
-                        if visits.contains_key(&dep) {
-                            // this is the key of the constraint through which we have already visited
-                            let prev = visits.remove(&dep).unwrap();
-                            if prev != key {
-                                let prev_constraint =
-                                    raw_unsatisfied_constraints.get(&prev).unwrap();
-                                let mut write = prev_constraint.write();
-                                if write.get_name() != elem.read().get_name() {
-                                    assert!(write.unsatisfied_dependencies.remove(&dep));
-                                    changes_made = true;
-                                }
-                            }
-                        }
-                        visits.insert(dep.clone(), key.clone());
-                        queue.push_back((dep, dep_constraint));
-                    }
-                }
-            }
-        }
-    }
-    fn remove_superfluous_dummy_tasks(
-        raw_unsatisfied_constraints: &mut LinkedHashMap<
-            (Uuid, AString),
-            RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>,
-        >,
-    ) -> Result<()> {
-        /* Remove superfluous dummy tasks */
-        loop {
-            let mut superfluous = AVec::new();
-            for (k, v) in raw_unsatisfied_constraints.iter() {
-                let x = v.read();
-                if x.unsatisfied_dependencies.len() == 1 && !(x.requires_program()?) {
-                    superfluous.push(k.clone());
-                }
-            }
-            if let Some(elem) = superfluous.into_iter().next() {
-                let mut reverse_dependencies: LinkedHashMap<
-                    (Uuid, AString),
-                    AVec<(Uuid, AString)>,
-                > = LinkedHashMap::new();
-                for (k, v) in raw_unsatisfied_constraints.iter() {
-                    for dep in v.read().unsatisfied_dependencies.iter() {
-                        reverse_dependencies
-                            .entry(dep.clone())
-                            .or_insert(AVec::new())
-                            .push(k.clone());
-                    }
-                }
-                let arc = raw_unsatisfied_constraints.remove(&elem).unwrap();
-                let dep = arc
-                    .read()
-                    .unsatisfied_dependencies
-                    .iter()
-                    .next()
-                    .unwrap()
-                    .clone();
-
-                if let Some(rev_deps) = reverse_dependencies.get(&elem) {
-                    for rev in rev_deps.iter() {
-                        let mut write = raw_unsatisfied_constraints.get(rev).unwrap().write();
-                        assert!(write.unsatisfied_dependencies.remove(&elem));
-                        write.unsatisfied_dependencies.insert(dep.clone());
-                    }
-                }
-            } else {
-                break;
-            }
-        }
-        Ok(())
-    }
-    fn remove_dangling_dummy_tasks(
-        raw_unsatisfied_constraints: &mut LinkedHashMap<
-            (Uuid, AString),
-            RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>,
-        >,
-    ) -> Result<()> {
-        /* Remove dangling dummy tasks */
-        let mut changes_made = true;
-        while changes_made {
-            changes_made = false;
-            let mut dangling = AVec::new();
-            let mut reverse_dependencies: LinkedHashMap<(Uuid, AString), AVec<(Uuid, AString)>> =
-                LinkedHashMap::new();
-            for (k, v) in raw_unsatisfied_constraints.iter() {
-                let x = v.read();
-                if x.unsatisfied_dependencies.len() == 0 && !(x.requires_program()?) {
-                    dangling.push(k.clone());
-                }
-
-                trace!("Reverse dependencies for {:?}", k);
-                for dep in x.unsatisfied_dependencies.iter() {
-                    reverse_dependencies
-                        .entry(dep.clone())
-                        .or_insert(AVec::new())
-                        .push(k.clone());
-                    trace!("- {:?}", dep);
-                }
-            }
-            for k in dangling {
-                assert!(raw_unsatisfied_constraints.remove(&k).is_some());
-                trace!("Dangling: {:?}", k);
-                if let Some(v) = reverse_dependencies.get(&k) {
-                    for rev in v.iter() {
-                        assert!(raw_unsatisfied_constraints
-                            .get(rev)
-                            .unwrap()
-                            .write()
-                            .unsatisfied_dependencies
-                            .remove(&k));
-                    }
-                }
-                changes_made = true;
-            }
-        }
-        Ok(())
-    }
     fn get_unsatisfied_constraints(
         constraints: &LinkedHashMap<(Uuid, AString), RArc<RRwLock<B::OuterType>>>,
         concepts: RArc<
@@ -688,13 +1116,28 @@ where
         ancestors: &HashMap<(Uuid, AString), AVec<AncestorRecord>>,
         _topline_constraint_names: LinkedHashSet<AString>,
     ) -> Result<ConstraintsBlockMap<'a, B::OuterType, P>> {
-        let mut raw_unsatisfied_constraints: LinkedHashMap<
+        let raw_unsatisfied_constraints: LinkedHashMap<
             (Uuid, AString),
             RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>,
         > = Self::generate_constraint_states_map(constraints, concepts, ancestors)?;
-        //Self::remove_redundant_dependencies(&mut raw_unsatisfied_constraints);
-        Self::remove_superfluous_dummy_tasks(&mut raw_unsatisfied_constraints)?;
-        Self::remove_dangling_dummy_tasks(&mut raw_unsatisfied_constraints)?;
+        let mut graph: DummyTaskGraph<(Uuid, AString)> = LinkedHashMap::new();
+        for (k, v) in raw_unsatisfied_constraints.iter() {
+            let read = v.read();
+            graph.insert(
+                k.clone(),
+                (read.requires_program()?, read.unsatisfied_dependencies.clone()),
+            );
+        }
+        remove_superfluous_dummy_tasks(&mut graph);
+        remove_dangling_dummy_tasks(&mut graph);
+        let raw_unsatisfied_constraints: LinkedHashMap<_, _> = raw_unsatisfied_constraints
+            .into_iter()
+            .filter_map(|(k, v)| {
+                let (_, dependencies) = graph.remove(&k)?;
+                v.write().unsatisfied_dependencies = dependencies;
+                Some((k, v))
+            })
+            .collect();
 
         let mut unsatisfied_constraints: LinkedHashMap<_, _> = <<B::OuterType as OuterConstraint<
             'a,
@@ -780,21 +1223,26 @@ where
         }
         ancestors
     }
-    fn new(
+    /// Builds the constraint graph for the universe without satisfying it,
+    /// recording every attachment decision in `explanation`.
+    fn generate_constraints(
         universe: U,
-        topline_constraint_names: LinkedHashSet<AString>,
-        programs: LinkedHashMap<AString, AVec<P>>,
-        preferences: AVec<Dialect>,
-        render_dependencies: bool,
-    ) -> Result<Self>
-    where
-        Self: Sized,
-    {
+        topline_constraint_names: &LinkedHashSet<AString>,
+        overrides: &ConstraintOverrides,
+        explanation: &mut ExplainReport,
+    ) -> Result<(
+        RArc<RRwLock<HashMap<(Uuid, AString), C>>>,
+        LinkedHashMap<(Uuid, AString), RArc<RRwLock<B::OuterType>>>,
+        A,
+        <U as AoristUniverse>::TEndpoints,
+        HashMap<(Uuid, AString), AVec<AncestorRecord>>,
+    )> {
         let endpoints = universe.get_endpoints();
-        let sorted_builders = Self::get_relevant_builders(&topline_constraint_names);
+        let sorted_builders = Self::get_relevant_builders(topline_constraint_names)?;
         let mut concept_map: HashMap<(Uuid, AString), C> = HashMap::new();
         let concept = C::from_universe(universe);
         concept.populate_child_concept_map(&mut concept_map);
+        explanation.add_concept_tree(&concept);
         let by_object_type = Self::get_concept_map_by_object_type(concept_map.clone());
 
         let ancestors = Self::compute_all_ancestors(concept, &concept_map);
@@ -815,8 +1263,11 @@ where
                 &by_object_type,
                 &family_trees,
                 &ancestry,
+                &ancestors,
+                overrides,
                 &mut generated_constraints,
                 &mut visited_constraint_names,
+                explanation,
             )?;
         }
 
@@ -827,6 +1278,56 @@ where
             }
         }
         debug!("There are {} generated_constraints.", constraints.len());
+        Ok((concepts, constraints, ancestry, endpoints, ancestors))
+    }
+    /// Reports which constraints were considered for every concept in the
+    /// universe, which attached, which were rejected by their attachIf
+    /// closure and which constraints they depend on.
+    fn explain(
+        universe: U,
+        topline_constraint_names: LinkedHashSet<AString>,
+        overrides: ConstraintOverrides,
+    ) -> Result<ExplainReport>
+    where
+        Self: Sized,
+    {
+        let mut explanation = ExplainReport::new(&topline_constraint_names);
+        Self::generate_constraints(
+            universe,
+            &topline_constraint_names,
+            &overrides,
+            &mut explanation,
+        )?;
+        Ok(explanation)
+    }
+    fn new(
+        universe: U,
+        topline_constraint_names: LinkedHashSet<AString>,
+        programs: LinkedHashMap<AString, AVec<P>>,
+        preferences: DialectPreferences,
+        overrides: ConstraintOverrides,
+        selection: UniverseSelection,
+        render_dependencies: bool,
+    ) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut explanation = ExplainReport::new(&topline_constraint_names);
+        let (concepts, mut constraints, ancestry, endpoints, ancestors) =
+            Self::generate_constraints(
+                universe,
+                &topline_constraint_names,
+                &overrides,
+                &mut explanation,
+            )?;
+        if !selection.is_empty() {
+            constraints = Self::select_constraints(
+                constraints,
+                concepts.clone(),
+                &ancestors,
+                &selection,
+            )?;
+        }
         Ok(Self::_new(
             concepts,
             constraints,
@@ -836,9 +1337,75 @@ where
             topline_constraint_names,
             programs,
             preferences,
+            overrides,
             render_dependencies,
         ))
     }
+    /// Keeps the constraints rooted in the selected part of the universe,
+    /// together with all constraints they transitively depend on, and drops
+    /// concepts that are neither roots of kept constraints nor their
+    /// ancestors.
+    fn select_constraints(
+        constraints: LinkedHashMap<(Uuid, AString), RArc<RRwLock<B::OuterType>>>,
+        concepts: RArc<RRwLock<HashMap<(Uuid, AString), C>>>,
+        ancestors: &HashMap<(Uuid, AString), AVec<AncestorRecord>>,
+        selection: &UniverseSelection,
+    ) -> Result<LinkedHashMap<(Uuid, AString), RArc<RRwLock<B::OuterType>>>> {
+        let no_ancestors = AVec::new();
+        let mut frontier = VecDeque::new();
+        let concept_map = concepts.read();
+        for (key, rw) in constraints.iter() {
+            let constraint = rw.read();
+            let root_key = (constraint.get_root_uuid()?, constraint.get_root());
+            let records = ancestors.get(&root_key).unwrap_or(&no_ancestors);
+            let lineage = concept_map
+                .get(&root_key)
+                .into_iter()
+                .chain(
+                    records
+                        .iter()
+                        .rev()
+                        .filter_map(|x| concept_map.get(&x.get_key())),
+                )
+                .cloned()
+                .collect::<Vec<_>>();
+            if !lineage.is_empty() && selection.matches(&lineage, records) {
+                frontier.push_back(key.clone());
+            }
+        }
+        drop(concept_map);
+        let mut selected = HashSet::new();
+        while let Some(key) = frontier.pop_front() {
+            if !selected.insert(key.clone()) {
+                continue;
+            }
+            if let Some(rw) = constraints.get(&key) {
+                for downstream in rw.read().get_downstream_constraints()? {
+                    let read = downstream.read();
+                    frontier.push_back((read.get_uuid()?, read.get_root()));
+                }
+            }
+        }
+        let mut kept_concepts = HashSet::new();
+        let mut selected_constraints = LinkedHashMap::new();
+        for (key, rw) in constraints.into_iter() {
+            if selected.contains(&key) {
+                let root_key = (rw.read().get_root_uuid()?, rw.read().get_root());
+                if let Some(records) = ancestors.get(&root_key) {
+                    kept_concepts.extend(records.iter().map(|x| x.get_key()));
+                }
+                kept_concepts.insert(root_key);
+                selected_constraints.insert(key, rw);
+            }
+        }
+        debug!(
+            "Selected {} constraints on {} concepts.",
+            selected_constraints.len(),
+            kept_concepts.len()
+        );
+        concepts.write().retain(|key, _| kept_concepts.contains(key));
+        Ok(selected_constraints)
+    }
     fn generate_family_trees(
         ancestors: &HashMap<(Uuid, AString), AVec<AncestorRecord>>,
     ) -> HashMap<(Uuid, AString), HashMap<AString, HashSet<Uuid>>> {
@@ -878,11 +1445,14 @@ where
         by_object_type: &HashMap<AString, AVec<C>>,
         family_trees: &HashMap<(Uuid, AString), HashMap<AString, HashSet<Uuid>>>,
         ancestry: &A,
+        ancestors: &HashMap<(Uuid, AString), AVec<AncestorRecord>>,
+        overrides: &ConstraintOverrides,
         generated_constraints: &mut LinkedHashMap<
             AString,
             LinkedHashMap<(Uuid, AString), RArc<RRwLock<B::OuterType>>>,
         >,
         visited_constraint_names: &mut LinkedHashSet<AString>,
+        explanation: &mut ExplainReport,
     ) -> Result<()> {
         let root_object_type = builder.get_root_type_name()?;
         let constraint_name = builder.get_constraint_name();
@@ -897,8 +1467,23 @@ where
 
             for root in root_concepts.iter() {
                 let root_key = (root.get_uuid(), root.get_type());
-                let family_tree = family_trees.get(&root_key).unwrap();
-                if builder.should_add(root.clone(), &ancestry) {
+                let family_tree = match family_trees.get(&root_key) {
+                    Some(x) => x,
+                    None => bail!("No family tree found for {:?}.", root_key),
+                };
+                let tags = match ancestors.get(&root_key) {
+                    Some(records) => get_concept_tags(root.get_tag(), records),
+                    None => get_concept_tags(root.get_tag(), &AVec::new()),
+                };
+                if overrides.should_skip(&constraint_name, &tags) {
+                    debug!("Constraint was skipped by an override.");
+                    explanation.record(
+                        &root_key,
+                        constraint_name.clone(),
+                        AttachmentStatus::SkippedByOverride,
+                        Vec::new(),
+                    );
+                } else if builder.should_add(root.clone(), &ancestry) {
                     let raw_potential_child_constraints = builder
                         .get_required_constraint_names()
                         .into_iter()
@@ -966,7 +1551,13 @@ where
                     let gen_for_constraint = generated_constraints
                         .entry(constraint_name.clone())
                         .or_insert(LinkedHashMap::new());
-                    assert!(!gen_for_constraint.contains_key(&root_key));
+                    if gen_for_constraint.contains_key(&root_key) {
+                        bail!(
+                            "Constraint {} was already attached to {:?}.",
+                            constraint_name,
+                            root_key
+                        );
+                    }
                     if level_enabled!(Level::DEBUG) {
                         debug!(
                             "Added constraint {:?} on root {:?} with the following dependencies:",
@@ -978,9 +1569,30 @@ where
                             debug!(" --  {:?}", (downstream.get_uuid()?, downstream.get_name()));
                         }
                     }
+                    let mut dependencies = Vec::new();
+                    for downstream_rw in constraint.get_downstream_constraints()? {
+                        let downstream = downstream_rw.read();
+                        dependencies.push(ConstraintDependency {
+                            constraint: downstream.get_name().clone(),
+                            root_uuid: downstream.get_root_uuid()?,
+                            root_type: downstream.get_root_type_name()?,
+                        });
+                    }
+                    explanation.record(
+                        &root_key,
+                        constraint_name.clone(),
+                        AttachmentStatus::Attached,
+                        dependencies,
+                    );
                     gen_for_constraint.insert(root_key, RArc::new(RRwLock::new(constraint)));
                 } else {
                     debug!("Constraint was filtered out.");
+                    explanation.record(
+                        &root_key,
+                        constraint_name.clone(),
+                        AttachmentStatus::RejectedByAttachIf,
+                        Vec::new(),
+                    );
                 }
             }
         } else {
@@ -988,9 +1600,16 @@ where
                 "Found no concepts of type {} for {}",
                 root_object_type, constraint_name,
             );
+            explanation.record_unmatched(constraint_name.clone());
         }
         for req in builder.get_required_constraint_names() {
-            assert!(visited_constraint_names.contains(&req));
+            if !visited_constraint_names.contains(&req) {
+                bail!(
+                    "Constraint {} was attached before its requirement {}.",
+                    constraint_name,
+                    req
+                );
+            }
         }
         visited_constraint_names.insert(constraint_name.clone());
         Ok(())
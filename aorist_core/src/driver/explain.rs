@@ -0,0 +1,294 @@
+use anyhow::Result;
+use aorist_primitives::{AString, ToplineConcept};
+use linked_hash_set::LinkedHashSet;
+use serde::Serialize;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum AttachmentStatus {
+    Attached,
+    RejectedByAttachIf,
+    SkippedByOverride,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConstraintDependency {
+    pub constraint: AString,
+    pub root_uuid: Uuid,
+    pub root_type: AString,
+}
+
+/// What happened when a constraint builder was considered for a concept.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConstraintAttachment {
+    pub constraint: AString,
+    pub status: AttachmentStatus,
+    pub dependencies: Vec<ConstraintDependency>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConceptExplanation {
+    pub uuid: Uuid,
+    pub concept_type: AString,
+    pub tag: Option<AString>,
+    pub depth: usize,
+    pub constraints: Vec<ConstraintAttachment>,
+}
+
+/// Record of the decisions taken by the driver while attaching constraints
+/// to concepts, in concept-tree order.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExplainReport {
+    pub topline_constraints: Vec<AString>,
+    pub concepts: Vec<ConceptExplanation>,
+    /// Constraints for which no concept of the root type exists.
+    pub unmatched_constraints: Vec<AString>,
+    #[serde(skip)]
+    index: HashMap<(Uuid, AString), usize>,
+}
+
+impl ExplainReport {
+    pub fn new(topline_constraint_names: &LinkedHashSet<AString>) -> Self {
+        Self {
+            topline_constraints: topline_constraint_names.iter().cloned().collect(),
+            ..Default::default()
+        }
+    }
+    pub fn add_concept_tree<C: ToplineConcept>(&mut self, concept: &C) {
+        self.add_concept(concept, 0);
+    }
+    fn add_concept<C: ToplineConcept>(&mut self, concept: &C, depth: usize) {
+        self.index
+            .insert((concept.get_uuid(), concept.get_type()), self.concepts.len());
+        self.concepts.push(ConceptExplanation {
+            uuid: concept.get_uuid(),
+            concept_type: concept.get_type(),
+            tag: concept.get_tag().0.into_option(),
+            depth,
+            constraints: Vec::new(),
+        });
+        for child in concept.get_child_concepts() {
+            self.add_concept(&child, depth + 1);
+        }
+    }
+    pub fn record(
+        &mut self,
+        root_key: &(Uuid, AString),
+        constraint: AString,
+        status: AttachmentStatus,
+        dependencies: Vec<ConstraintDependency>,
+    ) {
+        if let Some(pos) = self.index.get(root_key) {
+            self.concepts[*pos].constraints.push(ConstraintAttachment {
+                constraint,
+                status,
+                dependencies,
+            });
+        }
+    }
+    pub fn record_unmatched(&mut self, constraint: AString) {
+        self.unmatched_constraints.push(constraint);
+    }
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+    pub fn to_tree(&self) -> String {
+        let mut lines = Vec::new();
+        for concept in self.concepts.iter() {
+            let indent = "  ".repeat(concept.depth);
+            lines.push(match &concept.tag {
+                Some(tag) => format!(
+                    "{}{} [{}] ({})",
+                    indent, concept.concept_type, tag, concept.uuid
+                ),
+                None => format!("{}{} ({})", indent, concept.concept_type, concept.uuid),
+            });
+            for attachment in concept.constraints.iter() {
+                match attachment.status {
+                    AttachmentStatus::Attached => {
+                        lines.push(format!("{}  + {}", indent, attachment.constraint));
+                        for dep in attachment.dependencies.iter() {
+                            lines.push(format!(
+                                "{}      <- {} on {} ({})",
+                                indent, dep.constraint, dep.root_type, dep.root_uuid
+                            ));
+                        }
+                    }
+                    AttachmentStatus::RejectedByAttachIf => lines.push(format!(
+                        "{}  - {} (rejected by attachIf)",
+                        indent, attachment.constraint
+                    )),
+                    AttachmentStatus::SkippedByOverride => lines.push(format!(
+                        "{}  - {} (skipped by override)",
+                        indent, attachment.constraint
+                    )),
+                }
+            }
+        }
+        if self.unmatched_constraints.len() > 0 {
+            lines.push("No matching concepts for:".to_string());
+            for name in self.unmatched_constraints.iter() {
+                lines.push(format!("  - {}", name));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A DataSet with two assets, the first one tagged, each with a
+    /// constraint depending on the DataSet's.
+    fn report() -> ExplainReport {
+        let mut report = ExplainReport::new(&vec!["Publish".into()].into_iter().collect());
+        let concepts = [
+            (1, "DataSet", None, 0),
+            (2, "StaticDataTable", Some("events"), 1),
+            (3, "StaticDataTable", None, 1),
+        ];
+        for (id, concept_type, tag, depth) in concepts.iter() {
+            report.index.insert(
+                (Uuid::from_u128(*id), (*concept_type).into()),
+                report.concepts.len(),
+            );
+            report.concepts.push(ConceptExplanation {
+                uuid: Uuid::from_u128(*id),
+                concept_type: (*concept_type).into(),
+                tag: tag.map(AString::from),
+                depth: *depth,
+                constraints: Vec::new(),
+            });
+        }
+        let dataset = ConstraintDependency {
+            constraint: "Publish".into(),
+            root_uuid: Uuid::from_u128(1),
+            root_type: "DataSet".into(),
+        };
+        report.record(
+            &(Uuid::from_u128(1), "DataSet".into()),
+            "Publish".into(),
+            AttachmentStatus::Attached,
+            Vec::new(),
+        );
+        report.record(
+            &(Uuid::from_u128(2), "StaticDataTable".into()),
+            "Upload".into(),
+            AttachmentStatus::Attached,
+            vec![dataset],
+        );
+        report.record(
+            &(Uuid::from_u128(3), "StaticDataTable".into()),
+            "Upload".into(),
+            AttachmentStatus::RejectedByAttachIf,
+            Vec::new(),
+        );
+        report.record(
+            &(Uuid::from_u128(3), "StaticDataTable".into()),
+            "Replicate".into(),
+            AttachmentStatus::SkippedByOverride,
+            Vec::new(),
+        );
+        report.record_unmatched("Train".into());
+        report
+    }
+
+    #[test]
+    fn test_to_tree() {
+        assert_eq!(
+            report().to_tree(),
+            "DataSet (00000000-0000-0000-0000-000000000001)
+  + Publish
+  StaticDataTable [events] (00000000-0000-0000-0000-000000000002)
+    + Upload
+        <- Publish on DataSet (00000000-0000-0000-0000-000000000001)
+  StaticDataTable (00000000-0000-0000-0000-000000000003)
+    - Upload (rejected by attachIf)
+    - Replicate (skipped by override)
+No matching concepts for:
+  - Train"
+        );
+    }
+
+    #[test]
+    fn test_to_json() {
+        let parsed: serde_json::Value = serde_json::from_str(&report().to_json().unwrap()).unwrap();
+        assert_eq!(
+            parsed,
+            json!({
+                "topline_constraints": ["Publish"],
+                "concepts": [
+                    {
+                        "uuid": "00000000-0000-0000-0000-000000000001",
+                        "concept_type": "DataSet",
+                        "tag": null,
+                        "depth": 0,
+                        "constraints": [
+                            {"constraint": "Publish", "status": "Attached", "dependencies": []}
+                        ]
+                    },
+                    {
+                        "uuid": "00000000-0000-0000-0000-000000000002",
+                        "concept_type": "StaticDataTable",
+                        "tag": "events",
+                        "depth": 1,
+                        "constraints": [
+                            {
+                                "constraint": "Upload",
+                                "status": "Attached",
+                                "dependencies": [
+                                    {
+                                        "constraint": "Publish",
+                                        "root_uuid": "00000000-0000-0000-0000-000000000001",
+                                        "root_type": "DataSet"
+                                    }
+                                ]
+                            }
+                        ]
+                    },
+                    {
+                        "uuid": "00000000-0000-0000-0000-000000000003",
+                        "concept_type": "StaticDataTable",
+                        "tag": null,
+                        "depth": 1,
+                        "constraints": [
+                            {
+                                "constraint": "Upload",
+                                "status": "RejectedByAttachIf",
+                                "dependencies": []
+                            },
+                            {
+                                "constraint": "Replicate",
+                                "status": "SkippedByOverride",
+                                "dependencies": []
+                            }
+                        ]
+                    }
+                ],
+                "unmatched_constraints": ["Train"]
+            })
+        );
+    }
+
+    #[test]
+    fn test_record_ignores_unknown_concepts() {
+        let mut report = report();
+        report.record(
+            &(Uuid::from_u128(4), "DataSet".into()),
+            "Publish".into(),
+            AttachmentStatus::Attached,
+            Vec::new(),
+        );
+        assert_eq!(
+            report
+                .concepts
+                .iter()
+                .map(|x| x.constraints.len())
+                .collect::<Vec<_>>(),
+            vec![1, 1, 2]
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use crate::constraint::OuterConstraint;
+use crate::constraint_state::ConstraintState;
+use crate::program::TOuterProgram;
+use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
+use abi_stable::std_types::RArc;
+use anyhow::Result;
+use aorist_primitives::AString;
+use serde::Serialize;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConstraintGraphNode {
+    pub uuid: Uuid,
+    pub constraint: AString,
+    pub root_type: AString,
+    pub root_uuid: Uuid,
+    pub dialect: Option<AString>,
+    pub program: Option<AString>,
+}
+
+impl ConstraintGraphNode {
+    pub fn from_state<'a, T, P>(state: &ConstraintState<'a, T, P>) -> Result<Self>
+    where
+        T: OuterConstraint<'a>,
+        P: TOuterProgram<TAncestry = T::TAncestry>,
+    {
+        Ok(Self {
+            uuid: state.get_constraint_uuid()?,
+            constraint: state.get_name(),
+            root_type: state.get_root_type(),
+            root_uuid: state.get_root_uuid(),
+            dialect: state
+                .get_dialect()
+                .0
+                .into_option()
+                .map(|x| x.get_name().into()),
+            program: state.get_call().0.into_option(),
+        })
+    }
+}
+
+/// Edge from an upstream constraint to the constraint depending on it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConstraintGraphEdge {
+    pub from: Uuid,
+    pub to: Uuid,
+}
+
+/// The satisfied constraint-state graph, in satisfaction order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ConstraintGraph {
+    pub nodes: Vec<ConstraintGraphNode>,
+    pub edges: Vec<ConstraintGraphEdge>,
+}
+
+impl ConstraintGraph {
+    pub fn from_states<'a, T, P>(
+        states: impl Iterator<Item = RArc<RRwLock<ConstraintState<'a, T, P>>>>,
+    ) -> Result<Self>
+    where
+        T: OuterConstraint<'a>,
+        P: TOuterProgram<TAncestry = T::TAncestry>,
+    {
+        let mut graph = Self::default();
+        let mut seen = HashSet::new();
+        for rw in states {
+            let state = rw.read();
+            let uuid = state.get_constraint_uuid()?;
+            if !seen.insert(uuid) {
+                continue;
+            }
+            graph.nodes.push(ConstraintGraphNode::from_state(&*state)?);
+            for dependency in state.get_dependencies()?.iter() {
+                graph.edges.push(ConstraintGraphEdge {
+                    from: *dependency,
+                    to: uuid,
+                });
+            }
+        }
+        Ok(graph)
+    }
+    fn node_label(node: &ConstraintGraphNode) -> String {
+        let mut label = format!("{}\\n{} {}", node.constraint, node.root_type, node.root_uuid);
+        if let Some(ref dialect) = node.dialect {
+            label = format!("{}\\n{}", label, dialect);
+            if let Some(ref program) = node.program {
+                label = format!("{}: {}", label, program);
+            }
+        }
+        label
+    }
+    pub fn to_dot(&self) -> String {
+        let mut lines = vec!["digraph constraints {".to_string()];
+        for node in self.nodes.iter() {
+            lines.push(format!(
+                "    \"{}\" [label=\"{}\"];",
+                node.uuid,
+                Self::node_label(node).replace('"', "\\\"")
+            ));
+        }
+        for edge in self.edges.iter() {
+            lines.push(format!("    \"{}\" -> \"{}\";", edge.from, edge.to));
+        }
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+    pub fn to_mermaid(&self) -> String {
+        let mut lines = vec!["graph TD".to_string()];
+        for node in self.nodes.iter() {
+            lines.push(format!(
+                "    c{}[\"{}\"]",
+                node.uuid.to_simple(),
+                Self::node_label(node)
+                    .replace("\\n", "<br/>")
+                    .replace('"', "#quot;")
+            ));
+        }
+        for edge in self.edges.iter() {
+            lines.push(format!(
+                "    c{} --> c{}",
+                edge.from.to_simple(),
+                edge.to.to_simple()
+            ));
+        }
+        lines.join("\n")
+    }
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A download whose program name contains quotes, feeding a constraint
+    /// whose name contains dashes and which has no program.
+    fn graph() -> ConstraintGraph {
+        ConstraintGraph {
+            nodes: vec![
+                ConstraintGraphNode {
+                    uuid: Uuid::from_u128(1),
+                    constraint: "DownloadData".into(),
+                    root_type: "StaticDataTable".into(),
+                    root_uuid: Uuid::from_u128(10),
+                    dialect: Some("Bash".into()),
+                    program: Some("curl -o \"data.csv\"".into()),
+                },
+                ConstraintGraphNode {
+                    uuid: Uuid::from_u128(2),
+                    constraint: "Data-Set-Ready".into(),
+                    root_type: "DataSet".into(),
+                    root_uuid: Uuid::from_u128(20),
+                    dialect: None,
+                    program: None,
+                },
+            ],
+            edges: vec![ConstraintGraphEdge {
+                from: Uuid::from_u128(1),
+                to: Uuid::from_u128(2),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_to_dot() {
+        assert_eq!(
+            graph().to_dot(),
+            r#"digraph constraints {
+    "00000000-0000-0000-0000-000000000001" [label="DownloadData\nStaticDataTable 00000000-0000-0000-0000-00000000000a\nBash: curl -o \"data.csv\""];
+    "00000000-0000-0000-0000-000000000002" [label="Data-Set-Ready\nDataSet 00000000-0000-0000-0000-000000000014"];
+    "00000000-0000-0000-0000-000000000001" -> "00000000-0000-0000-0000-000000000002";
+}"#
+        );
+    }
+
+    #[test]
+    fn test_to_mermaid() {
+        // node ids leave out the dashes of uuids, which mermaid would parse
+        // as edges
+        assert_eq!(
+            graph().to_mermaid(),
+            r#"graph TD
+    c00000000000000000000000000000001["DownloadData<br/>StaticDataTable 00000000-0000-0000-0000-00000000000a<br/>Bash: curl -o #quot;data.csv#quot;"]
+    c00000000000000000000000000000002["Data-Set-Ready<br/>DataSet 00000000-0000-0000-0000-000000000014"]
+    c00000000000000000000000000000001 --> c00000000000000000000000000000002"#
+        );
+    }
+
+    #[test]
+    fn test_to_json() {
+        assert_eq!(
+            graph().to_json().unwrap(),
+            r#"{
+  "nodes": [
+    {
+      "uuid": "00000000-0000-0000-0000-000000000001",
+      "constraint": "DownloadData",
+      "root_type": "StaticDataTable",
+      "root_uuid": "00000000-0000-0000-0000-00000000000a",
+      "dialect": "Bash",
+      "program": "curl -o \"data.csv\""
+    },
+    {
+      "uuid": "00000000-0000-0000-0000-000000000002",
+      "constraint": "Data-Set-Ready",
+      "root_type": "DataSet",
+      "root_uuid": "00000000-0000-0000-0000-000000000014",
+      "dialect": null,
+      "program": null
+    }
+  ],
+  "edges": [
+    {
+      "from": "00000000-0000-0000-0000-000000000001",
+      "to": "00000000-0000-0000-0000-000000000002"
+    }
+  ]
+}"#
+        );
+    }
+}
@@ -1,11 +1,27 @@
+mod cache;
+pub use cache::*;
+mod container_image;
+pub use container_image::*;
 mod driver;
 pub use driver::*;
+mod explain;
+pub use explain::*;
+mod graph_export;
+pub use graph_export::*;
+mod partition;
+pub use partition::*;
+mod scheduler;
+pub use scheduler::*;
+mod selection;
+pub use selection::*;
+mod source_map;
+pub use source_map::*;
+mod validation;
+pub use validation::*;
 
 #[cfg(feature = "python")]
 mod python;
 #[cfg(feature = "python")]
 pub use python::*;
-#[cfg(feature = "r")]
 mod r;
-#[cfg(feature = "r")]
 pub use r::*;
@@ -0,0 +1,250 @@
+use crate::constraint::get_concept_tags;
+use anyhow::Result;
+use aorist_ast::AncestorRecord;
+use aorist_primitives::{AOption, AString, AVec, ToplineConcept};
+use inflector::cases::snakecase::to_snake_case;
+use linked_hash_map::LinkedHashMap;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::types::PyDict;
+use std::collections::VecDeque;
+use uuid::Uuid;
+
+/// Group receiving constraint states that a partition cannot place, e.g.
+/// states rooted at the universe when partitioning by data set.
+pub const DEFAULT_FLOW_GROUP: &str = "common";
+
+/// What a flow partition knows about a constraint state.
+#[derive(Debug, Clone)]
+pub struct PartitionKey {
+    pub constraint: AString,
+    pub root_type: AString,
+    pub root_uuid: Uuid,
+    pub tags: AVec<AString>,
+    pub dataset: AOption<AString>,
+    pub storage: AOption<AString>,
+}
+impl PartitionKey {
+    /// `lineage` holds the root concept followed by its ancestors, nearest
+    /// first. The storage is the nearest one in the lineage or, failing
+    /// that, the first one below the root (unless the root is the universe).
+    pub fn new<C: ToplineConcept>(
+        constraint: AString,
+        lineage: &[C],
+        ancestors: &AVec<AncestorRecord>,
+    ) -> Self {
+        let root = &lineage[0];
+        let dataset = lineage
+            .iter()
+            .filter(|x| x.get_type().as_str() == "DataSet")
+            .filter_map(|x| x.get_concept_name().0.into_option())
+            .next();
+        let mut storage = lineage.iter().filter_map(Self::get_storage_type).next();
+        if storage.is_none() && root.get_type().as_str() != "Universe" {
+            let mut queue: VecDeque<C> = root.get_child_concepts().into_iter().collect();
+            while let Some(concept) = queue.pop_front() {
+                if let Some(storage_type) = Self::get_storage_type(&concept) {
+                    storage = Some(storage_type);
+                    break;
+                }
+                queue.extend(concept.get_child_concepts().into_iter());
+            }
+        }
+        Self {
+            constraint,
+            root_type: root.get_type(),
+            root_uuid: root.get_uuid(),
+            tags: get_concept_tags(root.get_tag(), ancestors),
+            dataset: AOption(dataset.into()),
+            storage: AOption(storage.into()),
+        }
+    }
+    fn get_storage_type<C: ToplineConcept>(concept: &C) -> Option<AString> {
+        match concept.get_type().as_str() {
+            "Storage" => concept
+                .get_child_concepts()
+                .into_iter()
+                .next()
+                .map(|x| x.get_type()),
+            _ => None,
+        }
+    }
+    #[cfg(feature = "python")]
+    pub fn to_py_dict<'p>(&self, py: Python<'p>) -> PyResult<&'p PyDict> {
+        let dict = PyDict::new(py);
+        dict.set_item("constraint", self.constraint.as_str())?;
+        dict.set_item("root_type", self.root_type.as_str())?;
+        dict.set_item("root_uuid", self.root_uuid.to_string())?;
+        dict.set_item(
+            "tags",
+            self.tags.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+        )?;
+        dict.set_item(
+            "dataset",
+            self.dataset.clone().0.into_option().map(|x| x.to_string()),
+        )?;
+        dict.set_item(
+            "storage",
+            self.storage.clone().0.into_option().map(|x| x.to_string()),
+        )?;
+        Ok(dict)
+    }
+}
+
+/// Decides which flow a constraint state is rendered into.
+pub enum FlowPartition {
+    ByDataSet,
+    ByStorage,
+    Custom(Box<dyn Fn(&PartitionKey) -> Result<AOption<AString>> + Send + Sync>),
+}
+impl FlowPartition {
+    pub fn get_group(&self, key: &PartitionKey) -> Result<AString> {
+        let group = match self {
+            FlowPartition::ByDataSet => key.dataset.clone(),
+            FlowPartition::ByStorage => key.storage.clone(),
+            FlowPartition::Custom(f) => f(key)?,
+        };
+        Ok(match group.0.into_option() {
+            Some(name) => Self::sanitize(name.as_str()),
+            None => DEFAULT_FLOW_GROUP.into(),
+        })
+    }
+    /// Group names become part of flow and file names.
+    fn sanitize(name: &str) -> AString {
+        let name = to_snake_case(name)
+            .chars()
+            .map(|x| match x.is_ascii_alphanumeric() {
+                true => x,
+                false => '_',
+            })
+            .collect::<String>();
+        match name.is_empty() {
+            true => DEFAULT_FLOW_GROUP.into(),
+            false => name.as_str().into(),
+        }
+    }
+    /// Accepts `"dataset"`, `"storage"` or a callable taking a dict of
+    /// `PartitionKey` fields and returning a group name or `None`.
+    #[cfg(feature = "python")]
+    pub fn from_py(split: &PyAny) -> PyResult<Self> {
+        if let Ok(name) = split.extract::<&str>() {
+            return match name {
+                "dataset" => Ok(FlowPartition::ByDataSet),
+                "storage" => Ok(FlowPartition::ByStorage),
+                _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "Unknown split: {}. Use \"dataset\", \"storage\" or a function.",
+                    name
+                ))),
+            };
+        }
+        if !split.is_callable() {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "split must be \"dataset\", \"storage\" or a function.",
+            ));
+        }
+        let callable: PyObject = split.into();
+        Ok(FlowPartition::Custom(Box::new(move |key| {
+            Python::with_gil(|py| {
+                let group = callable
+                    .call1(py, (key.to_py_dict(py)?,))?
+                    .extract::<Option<String>>(py)?;
+                Ok(AOption(group.map(|x| AString::from(x.as_str())).into()))
+            })
+            .map_err(|e: PyErr| anyhow::anyhow!("Split function failed: {}", e))
+        })))
+    }
+}
+
+/// A task in another flow that a flow waits for.
+#[derive(Debug, Clone)]
+pub struct ExternalDependency {
+    /// Group of the upstream flow.
+    pub group: AString,
+    /// Name of the upstream flow, set when the flows are materialized.
+    pub flow_name: AString,
+    pub task_id: AString,
+    /// Identifier the waiting task is assigned to in this flow.
+    pub identifier: AString,
+}
+
+/// Constraint blocks (as indices into the driver's blocks) rendered into one
+/// flow, and the tasks of other flows they wait for, keyed by identifier.
+#[derive(Debug, Clone)]
+pub struct FlowGroup {
+    pub blocks: Vec<usize>,
+    pub external_dependencies: LinkedHashMap<AString, ExternalDependency>,
+}
+impl FlowGroup {
+    pub fn new() -> Self {
+        Self {
+            blocks: Vec::new(),
+            external_dependencies: LinkedHashMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use abi_stable::std_types::ROption;
+
+    fn key(constraint: &str, dataset: Option<&str>, storage: Option<&str>) -> PartitionKey {
+        PartitionKey {
+            constraint: constraint.into(),
+            root_type: "StaticDataTable".into(),
+            root_uuid: Uuid::from_u128(1),
+            tags: AVec::new(),
+            dataset: AOption(dataset.map(AString::from).into()),
+            storage: AOption(storage.map(AString::from).into()),
+        }
+    }
+
+    #[test]
+    fn test_groups() {
+        let key = key(
+            "UploadDataToSQLite",
+            Some("Probprog Papers"),
+            Some("SQLiteStorage"),
+        );
+        assert_eq!(
+            FlowPartition::ByDataSet.get_group(&key).unwrap(),
+            AString::from("probprog_papers")
+        );
+        assert_eq!(
+            FlowPartition::ByStorage.get_group(&key).unwrap(),
+            AString::from("sq_lite_storage")
+        );
+        let by_constraint = FlowPartition::Custom(Box::new(|x| {
+            Ok(AOption(ROption::RSome(x.constraint.clone())))
+        }));
+        assert_eq!(
+            by_constraint.get_group(&key).unwrap(),
+            AString::from("upload_data_to_sq_lite")
+        );
+    }
+
+    #[test]
+    fn test_unplaced_states_go_to_default_group() {
+        let key = key("ReplicateToLocal", None, None);
+        for partition in vec![
+            FlowPartition::ByDataSet,
+            FlowPartition::ByStorage,
+            FlowPartition::Custom(Box::new(|_| Ok(AOption(ROption::RNone)))),
+            FlowPartition::Custom(Box::new(|_| Ok(AOption(ROption::RSome("...".into()))))),
+        ] {
+            assert_eq!(
+                partition.get_group(&key).unwrap(),
+                AString::from(DEFAULT_FLOW_GROUP)
+            );
+        }
+    }
+
+    #[test]
+    fn test_custom_partition_error() {
+        let failing = FlowPartition::Custom(Box::new(|_| Err(anyhow::anyhow!("no group"))));
+        assert!(failing
+            .get_group(&key("ReplicateToLocal", None, None))
+            .is_err());
+    }
+}
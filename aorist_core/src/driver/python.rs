@@ -2,10 +2,10 @@ use abi_stable::std_types::ROption;
 use aorist_primitives::AOption;
 
 use crate::constraint::TConstraintEnum;
-use crate::constraint::{OuterConstraint, TBuilder};
+use crate::constraint::{ConstraintOverrides, OuterConstraint, TBuilder};
 use crate::constraint_state::ConstraintState;
-use crate::dialect::Dialect;
-use crate::driver::{ConstraintsBlockMap, Driver};
+use crate::dialect::{Dialect, DialectPreferences};
+use crate::driver::{ConstraintsBlockMap, Driver, FingerprintCache, FlowGroup, FlowPartition};
 use crate::flow::{ETLFlow, FlowBuilderBase, PythonBasedFlowBuilder};
 use crate::program::TOuterProgram;
 use crate::python::{PythonBasedConstraintBlock, PythonImport, PythonPreamble};
@@ -39,7 +39,7 @@ where
     pub concepts: RArc<RRwLock<HashMap<(Uuid, AString), C>>>,
     constraints: LinkedHashMap<(Uuid, AString), RArc<RRwLock<B::OuterType>>>,
     satisfied_constraints:
-        HashMap<(Uuid, AString), RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>>,
+        LinkedHashMap<(Uuid, AString), RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>>,
     blocks: AVec<PythonBasedConstraintBlock<'a, D::T, B::OuterType, U, P>>,
     ancestry: A,
     dag_type: PhantomData<D>,
@@ -48,8 +48,13 @@ where
     ancestors: HashMap<(Uuid, AString), AVec<AncestorRecord>>,
     topline_constraint_names: LinkedHashSet<AString>,
     programs: LinkedHashMap<AString, AVec<P>>,
-    preferences: AVec<Dialect>,
+    preferences: DialectPreferences,
+    overrides: ConstraintOverrides,
     render_dependencies: bool,
+    cache: Option<FingerprintCache>,
+    parallelism: usize,
+    partition: Option<FlowPartition>,
+    flow_groups: LinkedHashMap<AString, FlowGroup>,
 }
 impl<'a, B, D, U, C, A, P> Driver<'a, B, D, U, C, A, P> for PythonBasedDriver<'a, B, D, U, C, A, P>
 where
@@ -59,13 +64,13 @@ where
     <D as FlowBuilderBase<U>>::T: 'a,
     <D as FlowBuilderBase<U>>::T:
         ETLFlow<U, ImportType = PythonImport, PreambleType = PythonPreamble> + 'a,
-    A: Ancestry,
-    C: ToplineConcept<TUniverse = U>,
-    <B as TBuilder<'a>>::OuterType: OuterConstraint<'a, TAncestry = A>,
+    A: Ancestry + Sync,
+    C: ToplineConcept<TUniverse = U> + Send + Sync,
+    <B as TBuilder<'a>>::OuterType: OuterConstraint<'a, TAncestry = A> + Send + Sync,
     <<B as TBuilder<'a>>::OuterType as OuterConstraint<'a>>::TAncestry: Ancestry<TConcept = C>,
     <<<B as TBuilder<'a>>::OuterType as OuterConstraint<'a>>::TAncestry as Ancestry>::TConcept:
         ToplineConcept<TUniverse = U>,
-    P: TOuterProgram<TAncestry = A>,
+    P: TOuterProgram<TAncestry = A> + Send + Sync,
 {
     type CB = PythonBasedConstraintBlock<'a, <D as FlowBuilderBase<U>>::T, B::OuterType, U, P>;
 
@@ -75,9 +80,12 @@ where
             None => AVec::new(), //panic!("Cannot find program for {}", constraint_name),
         }
     }
-    fn get_preferences(&self) -> AVec<Dialect> {
+    fn get_preferences(&self) -> DialectPreferences {
         self.preferences.clone()
     }
+    fn get_overrides(&self) -> &ConstraintOverrides {
+        &self.overrides
+    }
     fn get_constraint_rwlock(&self, uuid: &(Uuid, AString)) -> RArc<RRwLock<B::OuterType>> {
         self.constraints.get(uuid).unwrap().clone()
     }
@@ -116,6 +124,21 @@ where
     ) {
         self.blocks.push(constraint_block);
     }
+    fn get_concepts(&self) -> RArc<RRwLock<HashMap<(Uuid, AString), C>>> {
+        self.concepts.clone()
+    }
+    fn get_partition(&self) -> Option<&FlowPartition> {
+        self.partition.as_ref()
+    }
+    fn set_partition(&mut self, partition: FlowPartition) {
+        self.partition = Some(partition);
+    }
+    fn get_flow_groups(&self) -> &LinkedHashMap<AString, FlowGroup> {
+        &self.flow_groups
+    }
+    fn get_flow_groups_mut(&mut self) -> &mut LinkedHashMap<AString, FlowGroup> {
+        &mut self.flow_groups
+    }
     fn get_constraint_explanation(
         &self,
         constraint_name: &AString,
@@ -128,6 +151,11 @@ where
     fn get_blocks(&self) -> &AVec<Self::CB> {
         &self.blocks
     }
+    fn get_satisfied_constraint_states(
+        &self,
+    ) -> AVec<RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>> {
+        self.satisfied_constraints.values().cloned().collect()
+    }
     fn get_dependencies(&self) -> AVec<AString> {
         self.satisfied_constraints
             .values()
@@ -152,13 +180,14 @@ where
         ancestors: HashMap<(Uuid, AString), AVec<AncestorRecord>>,
         topline_constraint_names: LinkedHashSet<AString>,
         programs: LinkedHashMap<AString, AVec<P>>,
-        preferences: AVec<Dialect>,
+        preferences: DialectPreferences,
+        overrides: ConstraintOverrides,
         render_dependencies: bool,
     ) -> Self {
         Self {
             concepts,
             constraints,
-            satisfied_constraints: HashMap::new(),
+            satisfied_constraints: LinkedHashMap::new(),
             blocks: AVec::new(),
             ancestry,
             dag_type: PhantomData,
@@ -170,10 +199,27 @@ where
             topline_constraint_names,
             programs,
             preferences,
-            render_dependencies
+            overrides,
+            render_dependencies,
+            cache: None,
+            parallelism: 1,
+            partition: None,
+            flow_groups: LinkedHashMap::new(),
         }
     }
     fn get_render_dependencies(&self) -> bool {
         self.render_dependencies
     }
+    fn get_cache(&mut self) -> Option<&mut FingerprintCache> {
+        self.cache.as_mut()
+    }
+    fn set_cache(&mut self, cache: FingerprintCache) {
+        self.cache = Some(cache);
+    }
+    fn get_parallelism(&self) -> usize {
+        self.parallelism
+    }
+    fn set_parallelism(&mut self, parallelism: usize) {
+        self.parallelism = std::cmp::max(parallelism, 1);
+    }
 }
@@ -1,85 +1,108 @@
 use abi_stable::std_types::ROption;
 use aorist_primitives::AOption;
 
-use crate::concept::{Concept, ConceptAncestry};
-use crate::constraint::SatisfiableOuterConstraint;
+use crate::constraint::TConstraintEnum;
+use crate::constraint::{ConstraintOverrides, OuterConstraint, TBuilder};
 use crate::constraint_state::ConstraintState;
-use crate::dialect::{Bash, Dialect, Python, R};
-use crate::driver::{ConstraintsBlockMap, Driver};
-use crate::endpoints::EndpointConfig;
-use crate::flow::{ETLFlow, FlowBuilderBase, FlowBuilderMaterialize};
-use crate::r::{RBasedConstraintBlock, RFlowBuilderInput, RImport, RPreamble};
+use crate::dialect::{Dialect, DialectPreferences};
+use crate::driver::{ConstraintsBlockMap, Driver, FingerprintCache, FlowGroup, FlowPartition};
+use crate::flow::{ETLFlow, FlowBuilderBase, RBasedFlowBuilder};
+use crate::program::TOuterProgram;
+use crate::r::{RBasedConstraintBlock, RImport, RPreamble};
 use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
 use abi_stable::std_types::RArc;
 use anyhow::Result;
 use aorist_ast::AncestorRecord;
-use aorist_primitives::OuterConstraint;
-use aorist_primitives::TConstraintEnum;
+use aorist_primitives::{AString, AVec, Ancestry, AoristConcept, AoristUniverse, ToplineConcept};
 use linked_hash_map::LinkedHashMap;
 use linked_hash_set::LinkedHashSet;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::marker::PhantomData;
 use uuid::Uuid;
 
-pub struct RBasedDriver<'a, 'b, D, C>
+pub struct RBasedDriver<'a, B, D, U, C, A, P>
 where
-    D: FlowBuilderBase,
-    <D as FlowBuilderBase>::T: ETLFlow<ImportType = RImport, PreambleType = RPreamble> + 'a,
-    C: OuterConstraint<'a, 'b, TAncestry = ConceptAncestry<'a>>
-        + SatisfiableOuterConstraint<'a, 'b>
-        + 'b,
-    'a: 'b,
+    U: AoristConcept + AoristUniverse,
+    B: TBuilder<'a, TEnum = C, TAncestry = A>,
+    D: FlowBuilderBase<U> + RBasedFlowBuilder<U>,
+    <D as FlowBuilderBase<U>>::T: 'a,
+    <D as FlowBuilderBase<U>>::T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble> + 'a,
+    A: Ancestry,
+    C: ToplineConcept<TUniverse = U>,
+    <B as TBuilder<'a>>::OuterType: OuterConstraint<'a, TAncestry = A>,
+    <<B as TBuilder<'a>>::OuterType as OuterConstraint<'a>>::TAncestry: Ancestry<TConcept = C>,
+    <<<B as TBuilder<'a>>::OuterType as OuterConstraint<'a>>::TAncestry as Ancestry>::TConcept:
+        ToplineConcept<TUniverse = U>,
+    P: TOuterProgram<TAncestry = A>,
 {
-    pub concepts: RArc<RRwLock<HashMap<(Uuid, AString), Concept<'a>>>>,
-    constraints: LinkedHashMap<(Uuid, AString), RArc<RRwLock<C>>>,
-    satisfied_constraints: HashMap<(Uuid, AString), RArc<RRwLock<ConstraintState<'a, 'b, C>>>>,
-    blocks: AVec<RBasedConstraintBlock<'a, 'b, D::T, C>>,
-    ancestry: RArc<ConceptAncestry<'a>>,
+    pub concepts: RArc<RRwLock<HashMap<(Uuid, AString), C>>>,
+    constraints: LinkedHashMap<(Uuid, AString), RArc<RRwLock<B::OuterType>>>,
+    satisfied_constraints:
+        LinkedHashMap<(Uuid, AString), RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>>,
+    blocks: AVec<RBasedConstraintBlock<'a, D::T, B::OuterType, U, P>>,
+    ancestry: A,
     dag_type: PhantomData<D>,
-    endpoints: EndpointConfig,
+    endpoints: <U as AoristUniverse>::TEndpoints,
     constraint_explanations: HashMap<AString, (AOption<AString>, AOption<AString>)>,
     ancestors: HashMap<(Uuid, AString), AVec<AncestorRecord>>,
     topline_constraint_names: LinkedHashSet<AString>,
-    _lt_phantom: PhantomData<&'b ()>,
+    programs: LinkedHashMap<AString, AVec<P>>,
+    preferences: DialectPreferences,
+    overrides: ConstraintOverrides,
+    render_dependencies: bool,
+    cache: Option<FingerprintCache>,
+    parallelism: usize,
+    partition: Option<FlowPartition>,
+    flow_groups: LinkedHashMap<AString, FlowGroup>,
 }
-impl<'a, 'b, D, C> Driver<'a, 'b, D, C> for RBasedDriver<'a, 'b, D, C>
+impl<'a, B, D, U, C, A, P> Driver<'a, B, D, U, C, A, P> for RBasedDriver<'a, B, D, U, C, A, P>
 where
-    'a: 'b,
-    D: FlowBuilderBase,
-    D: FlowBuilderMaterialize<BuilderInputType = RFlowBuilderInput>,
-    <D as FlowBuilderBase>::T: ETLFlow<ImportType = RImport, PreambleType = RPreamble> + 'a,
-    C: OuterConstraint<'a, 'b, TAncestry = ConceptAncestry<'a>>
-        + SatisfiableOuterConstraint<'a, 'b>
-        + 'b,
+    U: AoristConcept + AoristUniverse,
+    B: TBuilder<'a, TEnum = C, TAncestry = A>,
+    D: FlowBuilderBase<U> + RBasedFlowBuilder<U>,
+    <D as FlowBuilderBase<U>>::T: 'a,
+    <D as FlowBuilderBase<U>>::T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble> + 'a,
+    A: Ancestry + Sync,
+    C: ToplineConcept<TUniverse = U> + Send + Sync,
+    <B as TBuilder<'a>>::OuterType: OuterConstraint<'a, TAncestry = A> + Send + Sync,
+    <<B as TBuilder<'a>>::OuterType as OuterConstraint<'a>>::TAncestry: Ancestry<TConcept = C>,
+    <<<B as TBuilder<'a>>::OuterType as OuterConstraint<'a>>::TAncestry as Ancestry>::TConcept:
+        ToplineConcept<TUniverse = U>,
+    P: TOuterProgram<TAncestry = A> + Send + Sync,
 {
-    type CB = RBasedConstraintBlock<'a, 'b, <D as FlowBuilderBase>::T, C>;
+    type CB = RBasedConstraintBlock<'a, <D as FlowBuilderBase<U>>::T, B::OuterType, U, P>;
 
-    fn get_constraint_rwlock(&self, uuid: &(Uuid, AString)) -> RArc<RRwLock<C>> {
-        self.constraints.get(uuid).unwrap().clone()
+    fn get_programs_for(&self, constraint_name: &AString) -> AVec<P> {
+        match self.programs.get(constraint_name) {
+            Some(ref programs) => programs.iter().map(|x| (*x).clone()).collect(),
+            None => AVec::new(), //panic!("Cannot find program for {}", constraint_name),
+        }
+    }
+    fn get_preferences(&self) -> DialectPreferences {
+        self.preferences.clone()
     }
-    fn get_preferences(&self) -> AVec<Dialect> {
-        vec![
-            Dialect::R(R {}),
-            Dialect::Python(Python::new(vec![])),
-            Dialect::Bash(Bash {}),
-        ]
+    fn get_overrides(&self) -> &ConstraintOverrides {
+        &self.overrides
+    }
+    fn get_constraint_rwlock(&self, uuid: &(Uuid, AString)) -> RArc<RRwLock<B::OuterType>> {
+        self.constraints.get(uuid).unwrap().clone()
     }
 
-    fn get_endpoints(&'b self) -> &'b EndpointConfig {
-        &self.endpoints
+    fn get_endpoints(&self) -> <U as AoristUniverse>::TEndpoints {
+        self.endpoints.clone()
     }
 
-    fn get_ancestry(&self) -> RArc<ConceptAncestry<'a>> {
-        self.ancestry.clone()
+    fn get_ancestry(&self) -> &A {
+        &self.ancestry
     }
     fn mark_constraint_state_as_satisfied(
         &mut self,
         id: (Uuid, AString),
-        state: RArc<RRwLock<ConstraintState<'a, 'b, C>>>,
+        state: RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>,
     ) {
         self.satisfied_constraints.insert(id, state.clone());
     }
-    fn init_unsatisfied_constraints(&self) -> Result<ConstraintsBlockMap<'a, 'b, C>> {
+    fn init_unsatisfied_constraints(&self) -> Result<ConstraintsBlockMap<'a, B::OuterType, P>> {
         Self::get_unsatisfied_constraints(
             &self.constraints,
             self.concepts.clone(),
@@ -89,49 +112,112 @@ where
     }
     fn add_block(
         &mut self,
-        constraint_block: RBasedConstraintBlock<'a, 'b, <D as FlowBuilderBase>::T, C>,
+        constraint_block: RBasedConstraintBlock<
+            'a,
+            <D as FlowBuilderBase<U>>::T,
+            B::OuterType,
+            U,
+            P,
+        >,
     ) {
         self.blocks.push(constraint_block);
     }
+    fn get_concepts(&self) -> RArc<RRwLock<HashMap<(Uuid, AString), C>>> {
+        self.concepts.clone()
+    }
+    fn get_partition(&self) -> Option<&FlowPartition> {
+        self.partition.as_ref()
+    }
+    fn set_partition(&mut self, partition: FlowPartition) {
+        self.partition = Some(partition);
+    }
+    fn get_flow_groups(&self) -> &LinkedHashMap<AString, FlowGroup> {
+        &self.flow_groups
+    }
+    fn get_flow_groups_mut(&mut self) -> &mut LinkedHashMap<AString, FlowGroup> {
+        &mut self.flow_groups
+    }
     fn get_constraint_explanation(
         &self,
-        constraint_name: &String,
+        constraint_name: &AString,
     ) -> (AOption<AString>, AOption<AString>) {
         self.constraint_explanations
             .get(constraint_name)
             .unwrap()
             .clone()
     }
-    fn get_blocks(&'b self) -> &'b AVec<Self::CB> {
+    fn get_blocks(&self) -> &AVec<Self::CB> {
         &self.blocks
     }
+    fn get_satisfied_constraint_states(
+        &self,
+    ) -> AVec<RArc<RRwLock<ConstraintState<'a, B::OuterType, P>>>> {
+        self.satisfied_constraints.values().cloned().collect()
+    }
     fn get_dependencies(&self) -> AVec<AString> {
-        // TODO: add libraries
-        vec![]
+        self.satisfied_constraints
+            .values()
+            .map(|x| match x.read().get_dialect() {
+                AOption(ROption::RSome(Dialect::Python(x))) => {
+                    AOption(ROption::RSome(x.get_pip_requirements()))
+                }
+                _ => AOption(ROption::RNone),
+            })
+            .filter(|x| x.is_some())
+            .map(|x| x.unwrap().into_iter())
+            .flatten()
+            .collect::<BTreeSet<AString>>()
+            .into_iter()
+            .collect()
     }
     fn _new(
-        concepts: RArc<RRwLock<HashMap<(Uuid, AString), Concept<'a>>>>,
-        constraints: LinkedHashMap<(Uuid, AString), RArc<RRwLock<C>>>,
-        ancestry: RArc<ConceptAncestry<'a>>,
-        endpoints: EndpointConfig,
+        concepts: RArc<RRwLock<HashMap<(Uuid, AString), C>>>,
+        constraints: LinkedHashMap<(Uuid, AString), RArc<RRwLock<B::OuterType>>>,
+        ancestry: A,
+        endpoints: U::TEndpoints,
         ancestors: HashMap<(Uuid, AString), AVec<AncestorRecord>>,
         topline_constraint_names: LinkedHashSet<AString>,
+        programs: LinkedHashMap<AString, AVec<P>>,
+        preferences: DialectPreferences,
+        overrides: ConstraintOverrides,
+        render_dependencies: bool,
     ) -> Self {
         Self {
             concepts,
             constraints,
-            satisfied_constraints: HashMap::new(),
+            satisfied_constraints: LinkedHashMap::new(),
             blocks: AVec::new(),
             ancestry,
             dag_type: PhantomData,
             endpoints,
-            constraint_explanations: <<C as OuterConstraint<'a, 'b>>::TEnum as TConstraintEnum<
+            constraint_explanations: <<B::OuterType as OuterConstraint<'a>>::TEnum as TConstraintEnum<
                 'a,
-                'b,
             >>::get_explanations(),
             ancestors,
             topline_constraint_names,
-            _lt_phantom: PhantomData,
+            programs,
+            preferences,
+            overrides,
+            render_dependencies,
+            cache: None,
+            parallelism: 1,
+            partition: None,
+            flow_groups: LinkedHashMap::new(),
         }
     }
+    fn get_render_dependencies(&self) -> bool {
+        self.render_dependencies
+    }
+    fn get_cache(&mut self) -> Option<&mut FingerprintCache> {
+        self.cache.as_mut()
+    }
+    fn set_cache(&mut self, cache: FingerprintCache) {
+        self.cache = Some(cache);
+    }
+    fn get_parallelism(&self) -> usize {
+        self.parallelism
+    }
+    fn set_parallelism(&mut self, parallelism: usize) {
+        self.parallelism = std::cmp::max(parallelism, 1);
+    }
 }
@@ -0,0 +1,425 @@
+use aorist_primitives::AString;
+use linked_hash_map::LinkedHashMap;
+use linked_hash_set::LinkedHashSet;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+
+/// Constraint states as seen when pruning dummy tasks: state key =>
+/// (whether the state runs a program, keys of its unsatisfied dependencies).
+pub type DummyTaskGraph<K> = LinkedHashMap<K, (bool, LinkedHashSet<K>)>;
+
+fn get_reverse_dependencies<K: Clone + Eq + Hash>(
+    graph: &DummyTaskGraph<K>,
+) -> HashMap<K, LinkedHashSet<K>> {
+    let mut reverse_dependencies: HashMap<K, LinkedHashSet<K>> = HashMap::new();
+    for (k, (_, dependencies)) in graph.iter() {
+        for dep in dependencies.iter() {
+            reverse_dependencies
+                .entry(dep.clone())
+                .or_insert(LinkedHashSet::new())
+                .insert(k.clone());
+        }
+    }
+    reverse_dependencies
+}
+/// Removes superfluous dummy tasks, i.e. tasks without programs that have
+/// exactly one dependency: their dependents are rewired to that dependency.
+pub fn remove_superfluous_dummy_tasks<K: Clone + Eq + Hash>(graph: &mut DummyTaskGraph<K>) {
+    let mut reverse_dependencies = get_reverse_dependencies(graph);
+    let mut dummies = HashSet::new();
+    let mut queue = VecDeque::new();
+    for (k, (requires_program, dependencies)) in graph.iter() {
+        if !requires_program {
+            dummies.insert(k.clone());
+            if dependencies.len() == 1 {
+                queue.push_back(k.clone());
+            }
+        }
+    }
+    while let Some(elem) = queue.pop_front() {
+        let dep = match graph.get(&elem) {
+            Some((_, dependencies)) if dependencies.len() == 1 => {
+                dependencies.iter().next().unwrap().clone()
+            }
+            _ => continue,
+        };
+        graph.remove(&elem);
+        if let Some(dep_rev) = reverse_dependencies.get_mut(&dep) {
+            dep_rev.remove(&elem);
+        }
+        if let Some(rev_deps) = reverse_dependencies.remove(&elem) {
+            for rev in rev_deps.into_iter() {
+                let (_, dependencies) = graph.get_mut(&rev).unwrap();
+                assert!(dependencies.remove(&elem));
+                dependencies.insert(dep.clone());
+                if dummies.contains(&rev) && dependencies.len() == 1 {
+                    queue.push_back(rev.clone());
+                }
+                reverse_dependencies
+                    .entry(dep.clone())
+                    .or_insert(LinkedHashSet::new())
+                    .insert(rev);
+            }
+        }
+    }
+}
+/// Removes dangling dummy tasks, i.e. tasks without programs and without
+/// dependencies, until none are left.
+pub fn remove_dangling_dummy_tasks<K: Clone + Eq + Hash>(graph: &mut DummyTaskGraph<K>) {
+    let reverse_dependencies = get_reverse_dependencies(graph);
+    let mut dummies = HashSet::new();
+    let mut queue = VecDeque::new();
+    for (k, (requires_program, dependencies)) in graph.iter() {
+        if !requires_program {
+            dummies.insert(k.clone());
+            if dependencies.len() == 0 {
+                queue.push_back(k.clone());
+            }
+        }
+    }
+    while let Some(k) = queue.pop_front() {
+        assert!(graph.remove(&k).is_some());
+        if let Some(v) = reverse_dependencies.get(&k) {
+            for rev in v.iter() {
+                if let Some((_, dependencies)) = graph.get_mut(rev) {
+                    assert!(dependencies.remove(&k));
+                    if dummies.contains(rev) && dependencies.len() == 0 {
+                        queue.push_back(rev.clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Indegree-based ready queue over constraint blocks. A block becomes ready
+/// once every block it depends on has been popped. Among ready blocks the
+/// one declared first is returned, so the resulting order is the same as
+/// repeatedly scanning for the first block without pending dependencies.
+pub struct ConstraintBlockQueue {
+    names: Vec<AString>,
+    indegree: Vec<usize>,
+    dependents: Vec<Vec<usize>>,
+    ready: BTreeSet<usize>,
+    processed: Vec<bool>,
+    positions: HashMap<AString, usize>,
+}
+impl ConstraintBlockQueue {
+    pub fn new<S>(blocks: &LinkedHashMap<AString, (LinkedHashSet<AString>, S)>) -> Self {
+        let names: Vec<AString> = blocks.keys().cloned().collect();
+        let positions: HashMap<AString, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.clone(), i))
+            .collect();
+        let mut indegree = vec![0; names.len()];
+        let mut dependents = vec![Vec::new(); names.len()];
+        for (i, (_, (dependencies, _))) in blocks.iter().enumerate() {
+            for dependency in dependencies.iter() {
+                // dependencies on unknown blocks are never released
+                indegree[i] += 1;
+                if let Some(pos) = positions.get(dependency) {
+                    dependents[*pos].push(i);
+                }
+            }
+        }
+        let ready = indegree
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| **x == 0)
+            .map(|(i, _)| i)
+            .collect();
+        Self {
+            processed: vec![false; names.len()],
+            names,
+            indegree,
+            dependents,
+            ready,
+            positions,
+        }
+    }
+    /// Returns the next block without pending dependencies and releases the
+    /// blocks depending on it.
+    pub fn pop(&mut self) -> Option<AString> {
+        let next = *self.ready.iter().next()?;
+        self.ready.remove(&next);
+        self.processed[next] = true;
+        for dependent in self.dependents[next].iter() {
+            self.indegree[*dependent] -= 1;
+            if self.indegree[*dependent] == 0 {
+                self.ready.insert(*dependent);
+            }
+        }
+        Some(self.names[next].clone())
+    }
+    pub fn is_processed(&self, name: &AString) -> bool {
+        match self.positions.get(name) {
+            Some(pos) => self.processed[*pos],
+            None => false,
+        }
+    }
+    /// Blocks without pending dependencies, in the order they will be
+    /// popped unless blocks released in the meantime come before them.
+    pub fn get_ready(&self) -> Vec<AString> {
+        self.ready.iter().map(|i| self.names[*i].clone()).collect()
+    }
+}
+
+/// Applies `f` to every item on a scoped pool of up to `num_threads`
+/// threads, returning the results in the order of the items regardless of
+/// which thread computed them.
+pub fn map_in_order<T, R, F>(items: &[T], num_threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let num_threads = std::cmp::min(num_threads, items.len());
+    if num_threads <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let f = &f;
+    let mut results = std::thread::scope(|scope| {
+        let handles = (0..num_threads)
+            .map(|thread_ix| {
+                scope.spawn(move || {
+                    items
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, _)| i % num_threads == thread_ix)
+                        .map(|(i, item)| (i, f(item)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("Worker thread panicked"))
+            .collect::<Vec<_>>()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Graph = DummyTaskGraph<usize>;
+
+    /// Deterministic pseudo-random graphs (a linear congruential generator)
+    /// with dependencies only on earlier nodes, as constraint states have.
+    fn random_graph(seed: u64, size: usize) -> Graph {
+        let mut state = seed;
+        let mut next = move |bound: usize| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((state >> 33) as usize) % bound
+        };
+        let mut graph = Graph::new();
+        for i in 0..size {
+            let requires_program = next(3) == 0;
+            let dependencies = match i {
+                0 => LinkedHashSet::new(),
+                _ => (0..next(4)).map(|_| next(i)).collect(),
+            };
+            graph.insert(i, (requires_program, dependencies));
+        }
+        graph
+    }
+
+    /// Implementation before the worklist: rescans the whole graph after
+    /// every removed task.
+    fn remove_superfluous_dummy_tasks_rescan(graph: &mut Graph) {
+        loop {
+            let superfluous = graph
+                .iter()
+                .filter(|(_, (requires_program, deps))| !requires_program && deps.len() == 1)
+                .map(|(k, _)| *k)
+                .next();
+            let elem = match superfluous {
+                Some(x) => x,
+                None => break,
+            };
+            let reverse_dependencies = get_reverse_dependencies(graph);
+            let (_, deps) = graph.remove(&elem).unwrap();
+            let dep = *deps.iter().next().unwrap();
+            if let Some(rev_deps) = reverse_dependencies.get(&elem) {
+                for rev in rev_deps.iter() {
+                    let (_, dependencies) = graph.get_mut(rev).unwrap();
+                    assert!(dependencies.remove(&elem));
+                    dependencies.insert(dep);
+                }
+            }
+        }
+    }
+    fn remove_dangling_dummy_tasks_rescan(graph: &mut Graph) {
+        let mut changes_made = true;
+        while changes_made {
+            changes_made = false;
+            let reverse_dependencies = get_reverse_dependencies(graph);
+            let dangling = graph
+                .iter()
+                .filter(|(_, (requires_program, deps))| !requires_program && deps.len() == 0)
+                .map(|(k, _)| *k)
+                .collect::<Vec<_>>();
+            for k in dangling {
+                assert!(graph.remove(&k).is_some());
+                if let Some(v) = reverse_dependencies.get(&k) {
+                    for rev in v.iter() {
+                        assert!(graph.get_mut(rev).unwrap().1.remove(&k));
+                    }
+                }
+                changes_made = true;
+            }
+        }
+    }
+    /// Ordering before the ready queue: repeatedly scans for the first block
+    /// whose dependencies were all processed.
+    fn block_order_rescan(
+        blocks: &LinkedHashMap<AString, (LinkedHashSet<AString>, ())>,
+    ) -> Vec<AString> {
+        let mut processed: LinkedHashSet<AString> = LinkedHashSet::new();
+        loop {
+            let next = blocks
+                .iter()
+                .filter(|(name, (deps, _))| {
+                    !processed.contains(*name) && deps.iter().all(|x| processed.contains(x))
+                })
+                .map(|(name, _)| name.clone())
+                .next();
+            match next {
+                Some(name) => {
+                    processed.insert(name);
+                }
+                None => return processed.into_iter().collect(),
+            }
+        }
+    }
+    fn as_sorted(graph: &Graph) -> Vec<(usize, bool, Vec<usize>)> {
+        let mut out = graph
+            .iter()
+            .map(|(k, (requires_program, deps))| {
+                let mut deps = deps.iter().cloned().collect::<Vec<_>>();
+                deps.sort();
+                (*k, *requires_program, deps)
+            })
+            .collect::<Vec<_>>();
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn test_remove_superfluous_dummy_tasks_chain() {
+        // 0 <- 1 (dummy) <- 2 (dummy) <- 3
+        let mut graph = Graph::new();
+        graph.insert(0, (true, LinkedHashSet::new()));
+        graph.insert(1, (false, vec![0].into_iter().collect()));
+        graph.insert(2, (false, vec![1].into_iter().collect()));
+        graph.insert(3, (true, vec![2].into_iter().collect()));
+        remove_superfluous_dummy_tasks(&mut graph);
+        assert_eq!(
+            as_sorted(&graph),
+            vec![(0, true, vec![]), (3, true, vec![0])]
+        );
+    }
+
+    #[test]
+    fn test_remove_dangling_dummy_tasks_chain() {
+        // 0 (dummy) <- 1 (dummy) <- 2, 3 (dummy) <- 4 (dummy)
+        let mut graph = Graph::new();
+        graph.insert(0, (false, LinkedHashSet::new()));
+        graph.insert(1, (false, vec![0].into_iter().collect()));
+        graph.insert(2, (true, vec![1].into_iter().collect()));
+        graph.insert(3, (false, LinkedHashSet::new()));
+        graph.insert(4, (false, vec![3].into_iter().collect()));
+        remove_dangling_dummy_tasks(&mut graph);
+        assert_eq!(as_sorted(&graph), vec![(2, true, vec![])]);
+    }
+
+    #[test]
+    fn test_dummy_task_removal_matches_rescan() {
+        for seed in 0..200 {
+            let graph = random_graph(seed, 40);
+            let mut worklist = graph.clone();
+            remove_superfluous_dummy_tasks(&mut worklist);
+            remove_dangling_dummy_tasks(&mut worklist);
+            let mut rescan = graph.clone();
+            remove_superfluous_dummy_tasks_rescan(&mut rescan);
+            remove_dangling_dummy_tasks_rescan(&mut rescan);
+            assert_eq!(worklist, rescan, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn test_constraint_block_queue_matches_rescan() {
+        for seed in 0..200 {
+            let graph = random_graph(seed, 30);
+            let name = |i: &usize| -> AString { format!("Block{}", i).as_str().into() };
+            // declare blocks in reverse so dependencies come after dependents
+            let blocks = graph
+                .iter()
+                .rev()
+                .map(|(k, (_, deps))| (name(k), (deps.iter().map(name).collect(), ())))
+                .collect::<LinkedHashMap<AString, (LinkedHashSet<AString>, ())>>();
+            let mut queue = ConstraintBlockQueue::new(&blocks);
+            let mut order = Vec::new();
+            while let Some(block) = queue.pop() {
+                assert!(queue.is_processed(&block));
+                order.push(block);
+            }
+            assert_eq!(order, block_order_rescan(&blocks), "seed {}", seed);
+            assert_eq!(order.len(), blocks.len());
+        }
+    }
+
+    #[test]
+    fn test_map_in_order_matches_sequential() {
+        let items = (0..100).collect::<Vec<usize>>();
+        let sequential = map_in_order(&items, 1, |x| x * x);
+        for num_threads in vec![2, 3, 8, 200] {
+            let parallel = map_in_order(&items, num_threads, |x| {
+                // finish later items first to shuffle completion order
+                std::thread::sleep(std::time::Duration::from_micros((100 - *x) as u64));
+                x * x
+            });
+            assert_eq!(parallel, sequential, "{} threads", num_threads);
+        }
+        assert!(map_in_order(&Vec::<usize>::new(), 4, |x| *x).is_empty());
+    }
+
+    #[test]
+    fn test_constraint_block_queue_ready_blocks() {
+        let blocks = vec![
+            ("A".into(), (vec!["C".into()].into_iter().collect(), ())),
+            ("B".into(), (LinkedHashSet::new(), ())),
+            ("C".into(), (LinkedHashSet::new(), ())),
+        ]
+        .into_iter()
+        .collect::<LinkedHashMap<AString, (LinkedHashSet<AString>, ())>>();
+        let mut queue = ConstraintBlockQueue::new(&blocks);
+        assert_eq!(queue.get_ready(), vec![AString::from("B"), "C".into()]);
+        assert_eq!(queue.pop(), Some("B".into()));
+        assert_eq!(queue.get_ready(), vec![AString::from("C")]);
+        assert_eq!(queue.pop(), Some("C".into()));
+        assert_eq!(queue.get_ready(), vec![AString::from("A")]);
+    }
+
+    #[test]
+    fn test_constraint_block_queue_unknown_dependency() {
+        let blocks = vec![
+            (
+                "A".into(),
+                (vec!["Missing".into()].into_iter().collect(), ()),
+            ),
+            ("B".into(), (LinkedHashSet::new(), ())),
+        ]
+        .into_iter()
+        .collect::<LinkedHashMap<AString, (LinkedHashSet<AString>, ())>>();
+        let mut queue = ConstraintBlockQueue::new(&blocks);
+        assert_eq!(queue.pop(), Some("B".into()));
+        assert_eq!(queue.pop(), None);
+        assert!(!queue.is_processed(&"A".into()));
+    }
+}
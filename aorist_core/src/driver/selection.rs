@@ -0,0 +1,165 @@
+use crate::constraint::get_concept_tags;
+use aorist_ast::AncestorRecord;
+use aorist_primitives::{AString, AVec, ToplineConcept};
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+/// Restricts flow generation to part of a universe. A constraint is kept if
+/// its root concept, or one of the concept's ancestors, matches every
+/// non-empty criterion: a data set name, an asset name glob (`*` and `?`),
+/// a concept type or a tag. Constraints the kept ones depend on are always
+/// kept as well. An empty selection keeps the whole universe.
+#[cfg_attr(feature = "python", pyclass)]
+#[derive(Debug, Clone, Default)]
+pub struct UniverseSelection {
+    pub datasets: Vec<AString>,
+    pub assets: Vec<AString>,
+    pub concept_types: Vec<AString>,
+    pub tags: Vec<AString>,
+}
+#[cfg(feature = "python")]
+#[pymethods]
+impl UniverseSelection {
+    #[new]
+    #[args(
+        datasets = "Vec::new()",
+        assets = "Vec::new()",
+        concept_types = "Vec::new()",
+        tags = "Vec::new()"
+    )]
+    pub fn new(
+        datasets: Vec<&str>,
+        assets: Vec<&str>,
+        concept_types: Vec<&str>,
+        tags: Vec<&str>,
+    ) -> Self {
+        Self {
+            datasets: datasets.into_iter().map(|x| x.into()).collect(),
+            assets: assets.into_iter().map(|x| x.into()).collect(),
+            concept_types: concept_types.into_iter().map(|x| x.into()).collect(),
+            tags: tags.into_iter().map(|x| x.into()).collect(),
+        }
+    }
+}
+impl UniverseSelection {
+    pub fn is_empty(&self) -> bool {
+        self.datasets.is_empty()
+            && self.assets.is_empty()
+            && self.concept_types.is_empty()
+            && self.tags.is_empty()
+    }
+    /// `lineage` holds the root concept followed by its ancestors.
+    pub fn matches<C: ToplineConcept>(
+        &self,
+        lineage: &[C],
+        ancestors: &AVec<AncestorRecord>,
+    ) -> bool {
+        let named = |concept_type: &str, patterns: &Vec<AString>, glob: bool| {
+            patterns.is_empty()
+                || lineage
+                    .iter()
+                    .filter(|x| x.get_type().as_str() == concept_type)
+                    .filter_map(|x| x.get_concept_name().0.into_option())
+                    .any(|name| {
+                        patterns.iter().any(|pattern| match glob {
+                            true => glob_matches(pattern.as_str(), name.as_str()),
+                            false => *pattern == name,
+                        })
+                    })
+        };
+        let typed = self.concept_types.is_empty()
+            || lineage
+                .iter()
+                .any(|x| self.concept_types.contains(&x.get_type()));
+        let tagged = self.tags.is_empty() || {
+            let tags = get_concept_tags(lineage[0].get_tag(), ancestors);
+            self.tags.iter().any(|x| tags.contains(x))
+        };
+        named("DataSet", &self.datasets, false)
+            && named("Asset", &self.assets, true)
+            && typed
+            && tagged
+    }
+}
+
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` and of the name character it was matched to
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|x| *x == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_matches;
+
+    #[test]
+    fn test_glob_matches_literal() {
+        assert!(glob_matches("wine", "wine"));
+        assert!(!glob_matches("wine", "wines"));
+        assert!(!glob_matches("wines", "wine"));
+    }
+
+    #[test]
+    fn test_glob_matches_star() {
+        assert!(glob_matches("*", "wine"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("wine*", "wine"));
+        assert!(glob_matches("wine*", "wine_quality"));
+        assert!(glob_matches("*_quality", "wine_quality"));
+        assert!(glob_matches("w*e*y", "wine_quality"));
+        assert!(glob_matches("**", "wine"));
+        assert!(!glob_matches("*_red", "wine_quality"));
+    }
+
+    #[test]
+    fn test_glob_matches_question_mark() {
+        assert!(glob_matches("w?ne", "wine"));
+        assert!(glob_matches("????", "wine"));
+        assert!(!glob_matches("???", "wine"));
+        assert!(!glob_matches("?????", "wine"));
+        assert!(glob_matches("*?", "w"));
+        assert!(!glob_matches("*?", ""));
+    }
+
+    #[test]
+    fn test_glob_matches_is_anchored() {
+        // patterns have to match the whole name, not a substring of it
+        assert!(!glob_matches("ine", "wine"));
+        assert!(!glob_matches("win", "wine"));
+        assert!(!glob_matches("in*", "wine"));
+        assert!(!glob_matches("*in", "wine"));
+        assert!(glob_matches("*in*", "wine"));
+    }
+
+    #[test]
+    fn test_glob_matches_empty_pattern() {
+        assert!(glob_matches("", ""));
+        assert!(!glob_matches("", "wine"));
+    }
+
+    #[test]
+    fn test_glob_matches_backtracking() {
+        assert!(glob_matches("*ab", "aab"));
+        assert!(glob_matches("a*b*c", "abbbbc"));
+        assert!(!glob_matches("a*b*c", "abbbb"));
+        assert!(glob_matches("*a*a*a", "aaaa"));
+    }
+}
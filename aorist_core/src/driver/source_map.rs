@@ -0,0 +1,245 @@
+use crate::constraint::OuterConstraint;
+use crate::constraint_state::ConstraintState;
+use crate::driver::ConstraintGraphNode;
+use crate::program::TOuterProgram;
+use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
+use abi_stable::std_types::RArc;
+use anyhow::Result;
+use aorist_ast::SourceRange;
+use aorist_primitives::AString;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Concept on the path from the universe to a task's root concept.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceMapAncestor {
+    pub object_type: AString,
+    pub uuid: Uuid,
+    pub tag: Option<AString>,
+}
+
+/// A task of a generated flow, with the constraint, concept and program it
+/// was generated from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceMapTask {
+    pub task: AString,
+    #[serde(flatten)]
+    pub node: ConstraintGraphNode,
+    pub ancestors: Vec<SourceMapAncestor>,
+}
+
+impl SourceMapTask {
+    /// One task per satisfied constraint state rendered as a task.
+    pub fn from_states<'a, T, P>(
+        states: impl Iterator<Item = RArc<RRwLock<ConstraintState<'a, T, P>>>>,
+    ) -> Result<Vec<Self>>
+    where
+        T: OuterConstraint<'a>,
+        P: TOuterProgram<TAncestry = T::TAncestry>,
+    {
+        let mut tasks = Vec::new();
+        for rw in states {
+            let state = rw.read();
+            if !state.has_task_name() {
+                continue;
+            }
+            tasks.push(Self {
+                task: state.get_task_name(),
+                node: ConstraintGraphNode::from_state(&*state)?,
+                ancestors: state
+                    .get_ancestors()
+                    .iter()
+                    .map(|x| SourceMapAncestor {
+                        object_type: x.object_type.clone(),
+                        uuid: x.uuid,
+                        tag: x.tag.clone().0.into_option(),
+                    })
+                    .collect(),
+            });
+        }
+        Ok(tasks)
+    }
+}
+
+/// Lines of a generated file holding a task, numbered from 1.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceMapEntry {
+    pub start_line: usize,
+    pub end_line: usize,
+    #[serde(flatten)]
+    pub task: SourceMapTask,
+}
+
+/// Maps the lines of a generated file to the tasks they were generated
+/// from. Lines are mapped while the statements of the file are printed,
+/// from the ancestors attached to them: the statements of a task carry the
+/// ancestors of its root concept followed by its constraint, and those of
+/// a for loop running several tasks carry the ancestors of each. Tasks
+/// that do not appear in the file are left out.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceMap {
+    pub file: AString,
+    pub entries: Vec<SourceMapEntry>,
+}
+
+impl SourceMap {
+    pub fn new(file: AString, ranges: &[SourceRange], tasks: &[SourceMapTask]) -> Self {
+        let mut entries = Vec::new();
+        for range in ranges {
+            for task in tasks {
+                if range
+                    .ancestors
+                    .iter()
+                    .any(|x| x.uuid == task.node.uuid && x.object_type == task.node.constraint)
+                {
+                    entries.push(SourceMapEntry {
+                        start_line: range.start_line,
+                        end_line: range.end_line,
+                        task: task.clone(),
+                    });
+                }
+            }
+        }
+        entries.sort_by_key(|x| x.start_line);
+        Self { file, entries }
+    }
+    /// Adds a comment naming its constraint and root concept before each
+    /// task, shifting the entries accordingly: each entry then starts at its
+    /// comment. `comment` is the line comment marker of the file's language.
+    pub fn annotate(&mut self, content: &str, comment: &str) -> String {
+        let mut annotated = Vec::new();
+        // comments inserted up to each line of `content`
+        let mut inserted = Vec::new();
+        let mut next = 0;
+        for (i, line) in content.lines().enumerate() {
+            while let Some(entry) = self.entries.get_mut(next) {
+                if entry.start_line != i + 1 {
+                    break;
+                }
+                let indent = &line[..line.len() - line.trim_start().len()];
+                annotated.push(format!(
+                    "{}{} {}: {} ({}) on {} {}",
+                    indent,
+                    comment,
+                    entry.task.task,
+                    entry.task.node.constraint,
+                    entry.task.node.uuid,
+                    entry.task.node.root_type,
+                    entry.task.node.root_uuid,
+                ));
+                entry.start_line = annotated.len();
+                next += 1;
+            }
+            annotated.push(line.to_string());
+            inserted.push(annotated.len() - (i + 1));
+        }
+        for entry in self.entries.iter_mut() {
+            entry.end_line += inserted[entry.end_line - 1];
+        }
+        format!("{}\n", annotated.join("\n"))
+    }
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use abi_stable::std_types::ROption;
+    use aorist_ast::AncestorRecord;
+    use aorist_primitives::{AOption, AVec};
+
+    fn task(name: &str) -> SourceMapTask {
+        SourceMapTask {
+            task: name.into(),
+            node: ConstraintGraphNode {
+                uuid: Uuid::new_v4(),
+                constraint: "Download".into(),
+                root_type: "StaticDataTable".into(),
+                root_uuid: Uuid::new_v4(),
+                dialect: Some("Bash".into()),
+                program: None,
+            },
+            ancestors: Vec::new(),
+        }
+    }
+    fn ancestors(tasks: &[&SourceMapTask]) -> AVec<AncestorRecord> {
+        tasks
+            .iter()
+            .map(|x| {
+                AncestorRecord::new(
+                    x.node.uuid,
+                    x.node.constraint.clone(),
+                    AOption(ROption::RNone),
+                    0,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_annotated_ranges_cover_tasks() {
+        let (a, b, c) = (task("download_a"), task("download_b"), task("download_c"));
+        let content = "import os\n\
+                       tasks[\"download_a\"] = f(\n    \"a\",\n)\n\
+                       \n\
+                       params = {\"download_b\": {}, \"download_c\": {}}\n\
+                       \n\
+                       for t, params in params.items():\n    tasks[t] = f(t)\n";
+        let ranges = vec![
+            SourceRange {
+                start_line: 2,
+                end_line: 4,
+                ancestors: ancestors(&[&a]),
+            },
+            SourceRange {
+                start_line: 6,
+                end_line: 6,
+                ancestors: ancestors(&[&b, &c]),
+            },
+            SourceRange {
+                start_line: 8,
+                end_line: 9,
+                ancestors: ancestors(&[&b, &c]),
+            },
+        ];
+        let mut source_map = SourceMap::new("flow".into(), &ranges, &[a, b, c]);
+        let mapped = source_map
+            .entries
+            .iter()
+            .map(|x| (x.task.task.as_str(), x.start_line, x.end_line))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            mapped,
+            vec![
+                ("download_a", 2, 4),
+                ("download_b", 6, 6),
+                ("download_c", 6, 6),
+                ("download_b", 8, 9),
+                ("download_c", 8, 9),
+            ]
+        );
+        let annotated = source_map.annotate(content, "#");
+        let lines = annotated.lines().collect::<Vec<_>>();
+        let mapped = source_map
+            .entries
+            .iter()
+            .map(|x| (x.task.task.as_str(), x.start_line, x.end_line))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            mapped,
+            vec![
+                ("download_a", 2, 5),
+                ("download_b", 7, 9),
+                ("download_c", 8, 9),
+                ("download_b", 11, 14),
+                ("download_c", 12, 14),
+            ]
+        );
+        for entry in source_map.entries.iter() {
+            assert!(lines[entry.start_line - 1].starts_with(&format!("# {}: ", entry.task.task)));
+        }
+        assert_eq!(lines[13], "    tasks[t] = f(t)");
+    }
+}
@@ -0,0 +1,249 @@
+use crate::error::AoristError;
+use aorist_primitives::{AString, AVec};
+use aorist_util::find_cycles;
+use linked_hash_map::LinkedHashMap;
+use linked_hash_set::LinkedHashSet;
+#[cfg(feature = "python")]
+use pyo3::create_exception;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+
+#[cfg(feature = "python")]
+create_exception!(aorist, ConstraintGraphError, pyo3::exceptions::PyException);
+
+/// A constraint that was referenced (either as a topline constraint or as a
+/// requirement of another constraint) but for which no builder exists.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MissingConstraint {
+    pub name: AString,
+    /// None if the constraint was requested directly (topline constraint).
+    pub required_by: Option<AString>,
+}
+
+/// A constraint block that was left over after satisfaction finished,
+/// together with the names of the blocks it was still waiting on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnsatisfiableBlock {
+    pub name: AString,
+    pub waiting_on: AVec<AString>,
+}
+
+/// Outcome of validating the constraint graph. An empty report means the
+/// graph can be satisfied; anything else is returned as an error by the
+/// driver instead of panicking.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Default)]
+pub struct ConstraintGraphReport {
+    pub missing_constraints: Vec<MissingConstraint>,
+    /// Each cycle is given as a path whose first and last element coincide,
+    /// e.g. [A, B, C, A].
+    pub cycles: Vec<Vec<AString>>,
+    /// Constraints that are not on a cycle themselves, but (transitively)
+    /// depend on one, so they can never be scheduled.
+    pub unreachable_constraints: Vec<AString>,
+    pub unsatisfiable_blocks: Vec<UnsatisfiableBlock>,
+}
+impl ConstraintGraphReport {
+    pub fn is_empty(&self) -> bool {
+        self.missing_constraints.len() == 0
+            && self.cycles.len() == 0
+            && self.unreachable_constraints.len() == 0
+            && self.unsatisfiable_blocks.len() == 0
+    }
+    pub fn into_result(self) -> Result<(), Self> {
+        match self.is_empty() {
+            true => Ok(()),
+            false => Err(self),
+        }
+    }
+    /// Builds a report from the blocks left unsatisfied by the driver:
+    /// block name => names of blocks it still depends on.
+    pub fn from_leftover_blocks(leftover: &LinkedHashMap<AString, LinkedHashSet<AString>>) -> Self {
+        let cycles = find_cycles(leftover.iter());
+        Self {
+            missing_constraints: Vec::new(),
+            unreachable_constraints: find_unreachable(leftover, &cycles),
+            unsatisfiable_blocks: leftover
+                .iter()
+                .map(|(name, deps)| UnsatisfiableBlock {
+                    name: name.clone(),
+                    waiting_on: deps.iter().cloned().collect(),
+                })
+                .collect(),
+            cycles,
+        }
+    }
+}
+impl fmt::Display for ConstraintGraphReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Constraint graph validation failed:")?;
+        for missing in self.missing_constraints.iter() {
+            match &missing.required_by {
+                Some(parent) => writeln!(
+                    f,
+                    "  - missing constraint {} (required by {})",
+                    missing.name, parent
+                )?,
+                None => writeln!(f, "  - missing constraint {}", missing.name)?,
+            }
+        }
+        for cycle in self.cycles.iter() {
+            writeln!(
+                f,
+                "  - dependency cycle: {}",
+                cycle
+                    .iter()
+                    .map(|x| x.as_str().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            )?;
+        }
+        for name in self.unreachable_constraints.iter() {
+            writeln!(
+                f,
+                "  - unreachable constraint {} (depends on a cycle)",
+                name
+            )?;
+        }
+        for block in self.unsatisfiable_blocks.iter() {
+            writeln!(
+                f,
+                "  - unsatisfiable block {}, waiting on: {}",
+                block.name,
+                block
+                    .waiting_on
+                    .iter()
+                    .map(|x| x.as_str().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+impl std::error::Error for ConstraintGraphReport {}
+
+impl From<ConstraintGraphReport> for AoristError {
+    fn from(report: ConstraintGraphReport) -> Self {
+        AoristError::ConstraintGraphError(report.to_string().as_str().into())
+    }
+}
+
+/// Converts a driver error to a Python exception, raising
+/// ConstraintGraphError for validation failures.
+#[cfg(feature = "python")]
+pub fn driver_error_to_py_err(err: anyhow::Error) -> pyo3::PyErr {
+    match err.downcast_ref::<ConstraintGraphReport>() {
+        Some(report) => ConstraintGraphError::new_err(report.to_string()),
+        None => pyo3::exceptions::PyException::new_err(err.to_string()),
+    }
+}
+
+/// Returns the nodes of a graph of node => dependencies that are not on any
+/// of the given cycles, but (transitively) depend on a node that is.
+pub fn find_unreachable(
+    graph: &LinkedHashMap<AString, LinkedHashSet<AString>>,
+    cycles: &Vec<Vec<AString>>,
+) -> Vec<AString> {
+    let on_cycle = cycles
+        .iter()
+        .map(|x| x.iter().cloned())
+        .flatten()
+        .collect::<HashSet<AString>>();
+    let mut blocked = on_cycle.clone();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (node, deps) in graph.iter() {
+            if !blocked.contains(node) && deps.iter().any(|x| blocked.contains(x)) {
+                blocked.insert(node.clone());
+                changed = true;
+            }
+        }
+    }
+    graph
+        .keys()
+        .filter(|x| blocked.contains(*x) && !on_cycle.contains(*x))
+        .cloned()
+        .collect()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(&str, &[&str])]) -> LinkedHashMap<AString, LinkedHashSet<AString>> {
+        edges
+            .iter()
+            .map(|(node, deps)| ((*node).into(), deps.iter().map(|x| (*x).into()).collect()))
+            .collect()
+    }
+    fn path(nodes: &[&str]) -> Vec<AString> {
+        nodes.iter().map(|x| (*x).into()).collect()
+    }
+
+    #[test]
+    fn test_find_cycles_acyclic() {
+        let g = graph(&[("A", &["B", "C"]), ("B", &["C"]), ("C", &[])]);
+        assert!(find_cycles(g.iter()).is_empty());
+    }
+
+    #[test]
+    fn test_find_cycles_self_loop_and_cycle() {
+        let g = graph(&[("A", &["A"]), ("B", &["C"]), ("C", &["D"]), ("D", &["B"])]);
+        assert_eq!(
+            find_cycles(g.iter()),
+            vec![path(&["A", "A"]), path(&["B", "C", "D", "B"])]
+        );
+    }
+
+    #[test]
+    fn test_find_cycles_behind_first_edge() {
+        // the cycle is only reachable through the second edge of A
+        let g = graph(&[("A", &["B", "C"]), ("B", &[]), ("C", &["A"])]);
+        assert_eq!(find_cycles(g.iter()), vec![path(&["A", "C", "A"])]);
+    }
+
+    #[test]
+    fn test_find_unreachable() {
+        let g = graph(&[
+            ("A", &["B"]),
+            ("B", &["C"]),
+            ("C", &["B"]),
+            ("D", &["A"]),
+            ("E", &[]),
+        ]);
+        let cycles = find_cycles(g.iter());
+        assert_eq!(cycles, vec![path(&["B", "C", "B"])]);
+        assert_eq!(
+            find_unreachable(&g, &cycles),
+            vec![AString::from("A"), AString::from("D")]
+        );
+    }
+
+    #[test]
+    fn test_from_leftover_blocks() {
+        let leftover = graph(&[("A", &["B"]), ("B", &["A"]), ("C", &["A"])]);
+        let report = ConstraintGraphReport::from_leftover_blocks(&leftover);
+        assert!(!report.is_empty());
+        assert!(report.missing_constraints.is_empty());
+        assert_eq!(report.cycles, vec![path(&["A", "B", "A"])]);
+        assert_eq!(report.unreachable_constraints, vec![AString::from("C")]);
+        assert_eq!(
+            report.unsatisfiable_blocks,
+            vec![
+                UnsatisfiableBlock {
+                    name: "A".into(),
+                    waiting_on: path(&["B"]).into_iter().collect(),
+                },
+                UnsatisfiableBlock {
+                    name: "B".into(),
+                    waiting_on: path(&["A"]).into_iter().collect(),
+                },
+                UnsatisfiableBlock {
+                    name: "C".into(),
+                    waiting_on: path(&["A"]).into_iter().collect(),
+                },
+            ]
+        );
+    }
+}
@@ -6,7 +6,7 @@ use aorist_primitives::AoristRef;
 use aorist_primitives::{
     AString, AVec, AWSConfig, AlluxioConfig, AoristConcept, AoristConceptBase, ConceptEnum,
     DaskConfig, GCPConfig, GDALConfig, GiteaConfig, LINZAPIConfig, MinioConfig, PDALConfig,
-    PostgresConfig, PrestoConfig, RangerConfig, TPrestoEndpoints,
+    PostgresConfig, PrestoConfig, RangerConfig, TDaskEndpoints, TPrestoEndpoints,
 };
 use derivative::Derivative;
 use serde::{Deserialize, Serialize};
@@ -34,3 +34,8 @@ impl TPrestoEndpoints for EndpointConfig {
         self.presto.as_ref().unwrap().clone()
     }
 }
+impl TDaskEndpoints for EndpointConfig {
+    fn dask_config(&self) -> AOption<DaskConfig> {
+        self.dask.clone()
+    }
+}
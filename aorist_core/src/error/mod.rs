@@ -8,12 +8,15 @@ pub enum AoristError {
     OtherError(AString),
     #[error("DataSchemaError")]
     LibraryLoadError(AString),
+    #[error("{0}")]
+    ConstraintGraphError(AString),
 }
 impl AoristError {
     pub fn as_str(&self) -> &str {
         match self {
             Self::OtherError(e) => e.as_str(),
             Self::LibraryLoadError(e) => e.as_str(),
+            Self::ConstraintGraphError(e) => e.as_str(),
         }
     }
 }
@@ -0,0 +1,808 @@
+use crate::dialect::Dialect;
+use crate::driver::ExternalDependency;
+use crate::flow::airflow_python_based_flow::AirflowFlowBuilder;
+use crate::flow::etl_flow::ETLFlow;
+use crate::flow::flow_builder::FlowBuilderBase;
+use crate::flow::flow_builder::FlowBuilderMaterialize;
+use crate::flow::flow_builder::LITERALS_BLOCK_NAME;
+use crate::flow::flow_builder_input::FlowBuilderInput;
+use crate::flow::python_based_flow::PythonBasedFlow;
+use crate::flow::python_based_flow_builder::PythonBasedFlowBuilder;
+use crate::python::{
+    BashPythonTask, ConstantPythonTask, NativePythonPreamble, NativePythonTask, PrestoPythonTask,
+    PythonFlowBuilderInput, PythonImport, PythonPreamble, PythonTask, RPythonTask,
+};
+use abi_stable::std_types::ROption;
+use aorist_ast::SourceRange;
+use aorist_ast::{
+    Assignment, Attribute, BooleanLiteral, Call, Dict, Expression, Formatted, SimpleIdentifier,
+    StringLiteral, With, AST,
+};
+use aorist_primitives::AOption;
+use aorist_primitives::TPrestoEndpoints;
+use aorist_primitives::{AString, AVec, AoristUniverse};
+use linked_hash_map::LinkedHashMap;
+use linked_hash_set::LinkedHashSet;
+use pyo3::{PyErr, PyResult};
+use std::collections::BTreeSet;
+use std::marker::PhantomData;
+
+/// Airflow 2.3+ flavour of `AirflowPythonBasedFlow`: Python, R and Presto
+/// tasks are TaskFlow (`@task`) functions, and the DAG is picked up from the
+/// `with dag` block the flow builder wraps every constraint block in. Tasks
+/// compressed into a for loop become a single task mapped over their inputs.
+#[derive(Clone, Hash, PartialEq)]
+pub struct Airflow2PythonBasedFlow<U: AoristUniverse>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    task_id: AST,
+    task_val: AST,
+    command: AOption<AString>,
+    args: AVec<AST>,
+    kwargs: LinkedHashMap<AString, AST>,
+    dep_list: AOption<AST>,
+    preamble: AOption<AString>,
+    dialect: AOption<Dialect>,
+    endpoints: U::TEndpoints,
+    node: PythonTask,
+    _universe: PhantomData<U>,
+}
+impl<U: AoristUniverse> PythonBasedFlow<U> for Airflow2PythonBasedFlow<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    fn get_preamble_string(&self) -> AOption<AString> {
+        self.preamble.clone()
+    }
+}
+impl<U: AoristUniverse> Airflow2PythonBasedFlow<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    fn compute_creation_expr(&self) -> AST {
+        let mut kwargs = LinkedHashMap::new();
+        kwargs.insert("task_id".into(), self.task_id.clone());
+        match self.dialect {
+            AOption(ROption::RSome(Dialect::Bash(_))) => {
+                kwargs.insert(
+                    "bash_command".into(),
+                    AST::Formatted(Formatted::new_wrapped(
+                        AST::StringLiteral(StringLiteral::new_wrapped(
+                            self.command.as_ref().unwrap().clone(),
+                            false,
+                        )),
+                        self.kwargs.clone(),
+                    )),
+                );
+                AST::Call(Call::new_wrapped(
+                    AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("BashOperator".into())),
+                    AVec::new(),
+                    kwargs,
+                ))
+            }
+            AOption(ROption::RNone) => {
+                for (k, v) in self.kwargs.iter() {
+                    kwargs.insert(k.clone(), v.clone());
+                }
+                AST::Call(Call::new_wrapped(
+                    AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("EmptyOperator".into())),
+                    AVec::new(),
+                    kwargs,
+                ))
+            }
+            _ => match self.node.get_call().unwrap() {
+                AST::Call(call_rw) => {
+                    let call = call_rw.read();
+                    // return values are not meant to be passed around as XComs
+                    kwargs.insert(
+                        "do_xcom_push".into(),
+                        AST::BooleanLiteral(BooleanLiteral::new_wrapped(false)),
+                    );
+                    let decorated = AST::Call(Call::new_wrapped(
+                        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("task".into())),
+                        vec![call.function()].into_iter().collect(),
+                        kwargs,
+                    ));
+                    AST::Call(Call::new_wrapped(decorated, call.args(), call.keywords()))
+                }
+                _ => panic!("AST object should be call"),
+            },
+        }
+    }
+}
+impl<U: AoristUniverse> ETLFlow<U> for Airflow2PythonBasedFlow<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    type ImportType = PythonImport;
+    type PreambleType = PythonPreamble;
+    type ErrorType = pyo3::PyErr;
+    fn get_imports(&self) -> AVec<PythonImport> {
+        match self.dialect {
+            AOption(ROption::RSome(Dialect::Bash(_))) => vec![PythonImport::PythonFromImport(
+                "airflow.operators.bash".into(),
+                "BashOperator".into(),
+                AOption(ROption::RNone),
+            )],
+            AOption(ROption::RNone) => vec![PythonImport::PythonFromImport(
+                "airflow.operators.empty".into(),
+                "EmptyOperator".into(),
+                AOption(ROption::RNone),
+            )],
+            _ => vec![PythonImport::PythonFromImport(
+                "airflow.decorators".into(),
+                "task".into(),
+                AOption(ROption::RNone),
+            )],
+        }
+        .into_iter()
+        .collect()
+    }
+    fn get_preamble(&self) -> Result<AVec<PythonPreamble>, pyo3::PyErr> {
+        let mut preambles = match self.dialect {
+            AOption(ROption::RSome(Dialect::Python(_))) => match self.preamble {
+                AOption(ROption::RSome(ref p)) => vec![PythonPreamble::NativePythonPreamble(
+                    NativePythonPreamble::new(p.clone())?,
+                )],
+                AOption(ROption::RNone) => Vec::new(),
+            },
+            _ => Vec::new(),
+        };
+        if let AOption(ROption::RSome(p)) = self.node.get_preamble() {
+            preambles.push(p)
+        }
+        Ok(preambles.into_iter().collect())
+    }
+    fn get_dialect(&self) -> AOption<Dialect> {
+        self.dialect.clone()
+    }
+    fn get_task_val(&self) -> AST {
+        self.task_val.clone()
+    }
+    fn get_statements(&self) -> AVec<AST> {
+        let mut statements = vec![AST::Assignment(Assignment::new_wrapped(
+            self.task_val.clone(),
+            self.compute_creation_expr(),
+        ))];
+        if let AOption(ROption::RSome(ref dependencies)) = self.dep_list {
+            statements.push(AST::Expression(Expression::new_wrapped(AST::Call(
+                Call::new_wrapped(
+                    AST::Attribute(Attribute::new_wrapped(
+                        self.get_task_val(),
+                        "set_upstream".into(),
+                        false,
+                    )),
+                    vec![dependencies.clone()].into_iter().collect(),
+                    LinkedHashMap::new(),
+                ),
+            ))));
+        }
+        statements.into_iter().collect()
+    }
+    fn new(
+        task_id: AST,
+        task_val: AST,
+        call: AOption<AString>,
+        args: AVec<AST>,
+        kwargs: LinkedHashMap<AString, AST>,
+        dep_list: AOption<AST>,
+        preamble: AOption<AString>,
+        dialect: AOption<Dialect>,
+        endpoints: U::TEndpoints,
+    ) -> Self {
+        let command = match &dialect {
+            AOption(ROption::RSome(Dialect::Presto(_))) => AST::StringLiteral(
+                StringLiteral::new_wrapped(call.as_ref().unwrap().clone(), true),
+            ),
+            AOption(ROption::RSome(_)) => AST::StringLiteral(StringLiteral::new_wrapped(
+                call.as_ref().unwrap().clone(),
+                false,
+            )),
+            AOption(ROption::RNone) => {
+                AST::StringLiteral(StringLiteral::new_wrapped("Done".into(), false))
+            }
+        };
+        let node = match &dialect {
+            AOption(ROption::RSome(Dialect::Presto(_))) => {
+                let presto_endpoints = endpoints.presto_config();
+                PythonTask::PrestoPythonTask(PrestoPythonTask::new_wrapped(
+                    command,
+                    kwargs
+                        .iter()
+                        .map(|(k, v)| {
+                            (
+                                k.clone(),
+                                match *v {
+                                    AST::StringLiteral(ref x) => AST::StringLiteral(
+                                        StringLiteral::new_wrapped(x.read().value().clone(), true),
+                                    ),
+                                    _ => v.clone(),
+                                },
+                            )
+                        })
+                        .collect(),
+                    task_val.clone(),
+                    presto_endpoints,
+                    dep_list.clone(),
+                ))
+            }
+            AOption(ROption::RSome(Dialect::Bash(_))) => {
+                PythonTask::BashPythonTask(BashPythonTask::new_wrapped(
+                    command,
+                    kwargs.clone(),
+                    task_val.clone(),
+                    dep_list.clone(),
+                ))
+            }
+            AOption(ROption::RSome(Dialect::R(_))) => {
+                PythonTask::RPythonTask(RPythonTask::new_wrapped(
+                    task_val.clone(),
+                    command,
+                    args.clone(),
+                    kwargs.clone(),
+                    dep_list.clone(),
+                    preamble.clone(),
+                ))
+            }
+            AOption(ROption::RSome(Dialect::Python(_))) => {
+                PythonTask::NativePythonTask(NativePythonTask::new_wrapped(
+                    AST::Call(Call::new_wrapped(
+                        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
+                            call.as_ref().unwrap().clone(),
+                        )),
+                        args.clone(),
+                        kwargs.clone(),
+                    )),
+                    // TODO: add imports from preamble
+                    AVec::new(),
+                    task_val.clone(),
+                    dep_list.clone(),
+                ))
+            }
+            AOption(ROption::RNone) => PythonTask::ConstantPythonTask(
+                ConstantPythonTask::new_wrapped(command, task_val.clone(), dep_list.clone()),
+            ),
+        };
+        Self {
+            task_id,
+            task_val,
+            command: call,
+            args,
+            kwargs,
+            dep_list,
+            preamble,
+            dialect,
+            endpoints,
+            node,
+            _universe: PhantomData,
+        }
+    }
+    fn get_type() -> String {
+        "airflow2".into()
+    }
+    /// The command of Bash tasks, and the keyword arguments of all other
+    /// tasks, since positional arguments cannot be mapped alongside them.
+    fn get_mapped_input(&self) -> AOption<AST> {
+        match self.dialect {
+            AOption(ROption::RSome(Dialect::Bash(_))) => {
+                AOption(ROption::RSome(AST::Formatted(Formatted::new_wrapped(
+                    AST::StringLiteral(StringLiteral::new_wrapped(
+                        self.command.as_ref().unwrap().clone(),
+                        false,
+                    )),
+                    self.kwargs.clone(),
+                ))))
+            }
+            AOption(ROption::RNone) => AOption(ROption::RNone),
+            _ => match self.node.get_call().unwrap() {
+                AST::Call(call_rw) => {
+                    let call = call_rw.read();
+                    match call.args().len() {
+                        0 => AOption(ROption::RSome(AST::Dict(Dict::new_wrapped(
+                            call.keywords(),
+                        )))),
+                        _ => AOption(ROption::RNone),
+                    }
+                }
+                _ => panic!("AST object should be call"),
+            },
+        }
+    }
+    fn get_mapped_statements(&self, inputs: AST) -> AOption<(AVec<AST>, AVec<PythonImport>)> {
+        let mut partial_kwargs = LinkedHashMap::new();
+        partial_kwargs.insert("task_id".into(), self.task_id.clone());
+        let mut expand_kwargs = LinkedHashMap::new();
+        let operator = match self.dialect {
+            AOption(ROption::RSome(Dialect::Bash(_))) => {
+                expand_kwargs.insert("bash_command".into(), inputs);
+                "BashOperator"
+            }
+            AOption(ROption::RNone) => return AOption(ROption::RNone),
+            _ => match self.node.get_call().unwrap() {
+                AST::Call(call_rw) => {
+                    partial_kwargs.insert("python_callable".into(), call_rw.read().function());
+                    partial_kwargs.insert(
+                        "do_xcom_push".into(),
+                        AST::BooleanLiteral(BooleanLiteral::new_wrapped(false)),
+                    );
+                    expand_kwargs.insert("op_kwargs".into(), inputs);
+                    "PythonOperator"
+                }
+                _ => panic!("AST object should be call"),
+            },
+        };
+        let partial = AST::Call(Call::new_wrapped(
+            AST::Attribute(Attribute::new_wrapped(
+                AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(operator.into())),
+                "partial".into(),
+                false,
+            )),
+            AVec::new(),
+            partial_kwargs,
+        ));
+        let expand = AST::Call(Call::new_wrapped(
+            AST::Attribute(Attribute::new_wrapped(partial, "expand".into(), false)),
+            AVec::new(),
+            expand_kwargs,
+        ));
+        let mut statements = vec![AST::Assignment(Assignment::new_wrapped(
+            self.task_val.clone(),
+            expand,
+        ))];
+        if let AOption(ROption::RSome(ref dependencies)) = self.dep_list {
+            statements.push(AST::Expression(Expression::new_wrapped(AST::Call(
+                Call::new_wrapped(
+                    AST::Attribute(Attribute::new_wrapped(
+                        self.get_task_val(),
+                        "set_upstream".into(),
+                        false,
+                    )),
+                    vec![dependencies.clone()].into_iter().collect(),
+                    LinkedHashMap::new(),
+                ),
+            ))));
+        }
+        let module = match operator {
+            "BashOperator" => "airflow.operators.bash",
+            _ => "airflow.operators.python",
+        };
+        AOption(ROption::RSome((
+            statements.into_iter().collect(),
+            vec![PythonImport::PythonFromImport(
+                module.into(),
+                operator.into(),
+                AOption(ROption::RNone),
+            )]
+            .into_iter()
+            .collect(),
+        )))
+    }
+}
+
+pub struct Airflow2FlowBuilder<U: AoristUniverse> {
+    universe: PhantomData<U>,
+}
+impl<U: AoristUniverse> Airflow2FlowBuilder<U>
+where
+    <U as AoristUniverse>::TEndpoints: TPrestoEndpoints,
+{
+    /// Nests the statements in `with dag, TaskGroup(...):`, with the
+    /// constraint's title and body as the group's tooltip. Task ids are not
+    /// prefixed with the group id, so that other DAGs can wait for them.
+    fn wrap_in_task_group(input: PythonFlowBuilderInput) -> PythonFlowBuilderInput {
+        let name = input.get_constraint_name();
+        let title = input.get_constraint_title();
+        let body = input.get_constraint_body();
+        let mut kwargs: LinkedHashMap<AString, AST> = LinkedHashMap::new();
+        kwargs.insert(
+            "group_id".into(),
+            AST::StringLiteral(StringLiteral::new_wrapped(name.clone(), false)),
+        );
+        let tooltip = title
+            .clone()
+            .0
+            .into_option()
+            .into_iter()
+            .chain(body.clone().0.into_option().into_iter())
+            .map(|x| x.as_str().to_string())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        if !tooltip.is_empty() {
+            kwargs.insert(
+                "tooltip".into(),
+                AST::StringLiteral(StringLiteral::new_wrapped(tooltip.as_str().into(), false)),
+            );
+        }
+        kwargs.insert(
+            "prefix_group_id".into(),
+            AST::BooleanLiteral(BooleanLiteral::new_wrapped(false)),
+        );
+        let task_group = AST::Call(Call::new_wrapped(
+            AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("TaskGroup".into())),
+            AVec::new(),
+            kwargs,
+        ));
+        let with = AST::With(With::new_wrapped(
+            vec![
+                AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("dag".into())),
+                task_group,
+            ]
+            .into_iter()
+            .collect(),
+            input.get_statements(),
+        ));
+        PythonFlowBuilderInput::statements_only(vec![with].into_iter().collect(), name, title, body)
+    }
+}
+impl<U: AoristUniverse> FlowBuilderBase<U> for Airflow2FlowBuilder<U>
+where
+    <U as AoristUniverse>::TEndpoints: TPrestoEndpoints,
+{
+    type T = Airflow2PythonBasedFlow<U>;
+    fn new() -> Self {
+        Self {
+            universe: PhantomData,
+        }
+    }
+    fn get_pip_requirements(&self) -> AVec<AString> {
+        // Mapped tasks need Airflow 2.3
+        vec!["apache-airflow>=2.3"]
+            .into_iter()
+            .map(AString::from)
+            .collect()
+    }
+}
+impl<U: AoristUniverse> FlowBuilderMaterialize<U> for Airflow2FlowBuilder<U>
+where
+    <U as AoristUniverse>::TEndpoints: TPrestoEndpoints,
+{
+    type BuilderInputType = PythonFlowBuilderInput;
+    type ErrorType = PyErr;
+
+    fn materialize(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        Ok(self
+            .materialize_python_flow(statements_and_preambles, AVec::new(), flow_name)?
+            .0)
+    }
+    fn materialize_with_external_dependencies(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        Ok(self
+            .materialize_python_flow(statements_and_preambles, external_dependencies, flow_name)?
+            .0)
+    }
+    fn materialize_with_source_ranges(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<(AString, Vec<SourceRange>)> {
+        self.materialize_python_flow(statements_and_preambles, external_dependencies, flow_name)
+    }
+    fn get_file_name(&self, flow_name: &AString) -> AString {
+        format!("{}.{}", flow_name, self.get_file_extension())
+            .as_str()
+            .into()
+    }
+}
+impl<U: AoristUniverse> PythonBasedFlowBuilder<U> for Airflow2FlowBuilder<U>
+where
+    <U as AoristUniverse>::TEndpoints: TPrestoEndpoints,
+{
+    /// Wraps every block of tasks in a TaskGroup, then sets up the DAG the
+    /// same way as for Airflow 1.
+    fn augment_statements(
+        &self,
+        statements: AVec<PythonFlowBuilderInput>,
+        flow_name: AOption<AString>,
+    ) -> AVec<PythonFlowBuilderInput> {
+        let statements = statements
+            .into_iter()
+            .map(|x| match x.get_constraint_name().as_str() {
+                LITERALS_BLOCK_NAME => x,
+                _ => Self::wrap_in_task_group(x),
+            })
+            .collect();
+        AirflowFlowBuilder::<U>::new().augment_statements(statements, flow_name)
+    }
+    /// Waits for tasks in other DAGs with an ExternalTaskSensor each.
+    fn get_external_dependency_statements(
+        &self,
+        external_dependencies: &AVec<ExternalDependency>,
+    ) -> Option<PythonFlowBuilderInput> {
+        let statements = external_dependencies
+            .iter()
+            .map(|dep| {
+                let mut kwargs: LinkedHashMap<AString, AST> = LinkedHashMap::new();
+                kwargs.insert(
+                    "task_id".into(),
+                    AST::StringLiteral(StringLiteral::new_wrapped(dep.identifier.clone(), false)),
+                );
+                kwargs.insert(
+                    "external_dag_id".into(),
+                    AST::StringLiteral(StringLiteral::new_wrapped(dep.flow_name.clone(), false)),
+                );
+                kwargs.insert(
+                    "external_task_id".into(),
+                    AST::StringLiteral(StringLiteral::new_wrapped(dep.task_id.clone(), false)),
+                );
+                AST::Assignment(Assignment::new_wrapped(
+                    AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(dep.identifier.clone())),
+                    AST::Call(Call::new_wrapped(
+                        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
+                            "ExternalTaskSensor".into(),
+                        )),
+                        AVec::new(),
+                        kwargs,
+                    )),
+                ))
+            })
+            .collect();
+        let imports = vec![PythonImport::PythonFromImport(
+            "airflow.sensors.external_task".into(),
+            "ExternalTaskSensor".into(),
+            AOption(ROption::RNone),
+        )]
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+        Some(PythonFlowBuilderInput::new(
+            statements,
+            LinkedHashSet::new(),
+            imports,
+            "external_dependencies".into(),
+            AOption(ROption::RSome("Waiting for tasks in other DAGs".into())),
+            AOption(ROption::RNone),
+        ))
+    }
+    fn get_flow_imports(&self) -> AVec<PythonImport> {
+        AirflowFlowBuilder::<U>::new()
+            .get_flow_imports()
+            .into_iter()
+            .chain(
+                vec![PythonImport::PythonFromImport(
+                    "airflow.utils.task_group".into(),
+                    "TaskGroup".into(),
+                    AOption(ROption::RNone),
+                )]
+                .into_iter(),
+            )
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialect::{Bash, Python};
+    use crate::endpoints::EndpointConfig;
+    use crate::universe::Universe;
+    use aorist_ast::List;
+    use aorist_primitives::AoristRef;
+
+    type Flow = Airflow2PythonBasedFlow<AoristRef<Universe>>;
+
+    fn endpoints() -> EndpointConfig {
+        EndpointConfig {
+            presto: AOption(ROption::RNone),
+            alluxio: AOption(ROption::RNone),
+            ranger: AOption(ROption::RNone),
+            gitea: AOption(ROption::RNone),
+            minio: AOption(ROption::RNone),
+            postgres: AOption(ROption::RNone),
+            gcp: AOption(ROption::RNone),
+            aws: AOption(ROption::RNone),
+            pdal: AOption(ROption::RNone),
+            linz: AOption(ROption::RNone),
+            dask: AOption(ROption::RNone),
+            gdal: AOption(ROption::RNone),
+            tag: AOption(ROption::RNone),
+            uuid: AOption(ROption::RNone),
+        }
+    }
+    fn identifier(name: &str) -> AST {
+        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(name.into()))
+    }
+    fn flow(name: &str, dialect: Dialect, call: &str, dependencies: &[&str]) -> Flow {
+        let mut kwargs = LinkedHashMap::new();
+        kwargs.insert(
+            "path".into(),
+            AST::StringLiteral(StringLiteral::new_wrapped("/tmp/data.csv".into(), false)),
+        );
+        let preamble = match dialect {
+            Dialect::Python(_) => AOption(ROption::RSome(
+                format!("def {}(path):\n    return open(path).read()\n", call)
+                    .as_str()
+                    .into(),
+            )),
+            _ => AOption(ROption::RNone),
+        };
+        Flow::new(
+            AST::StringLiteral(StringLiteral::new_wrapped(name.into(), false)),
+            identifier(name),
+            AOption(ROption::RSome(call.into())),
+            AVec::new(),
+            kwargs,
+            match dependencies.len() {
+                0 => AOption(ROption::RNone),
+                _ => AOption(ROption::RSome(AST::List(List::new_wrapped(
+                    dependencies.iter().map(|x| identifier(x)).collect(),
+                    false,
+                )))),
+            },
+            preamble,
+            AOption(ROption::RSome(dialect)),
+            endpoints(),
+        )
+    }
+    /// One constraint block per group of flows, as rendered by the driver.
+    fn block(name: &str, title: &str, flows: Vec<Flow>) -> PythonFlowBuilderInput {
+        let mut statements = Vec::new();
+        let mut preambles = LinkedHashSet::new();
+        let mut imports = BTreeSet::new();
+        for flow in flows {
+            statements.extend(flow.get_statements());
+            preambles.extend(flow.get_preamble().unwrap());
+            imports.extend(flow.get_imports());
+        }
+        PythonFlowBuilderInput::new(
+            statements.into_iter().collect(),
+            preambles,
+            imports,
+            name.into(),
+            AOption(ROption::RSome(title.into())),
+            AOption(ROption::RNone),
+        )
+    }
+
+    /// A Bash download, then two Python programs reading the downloaded
+    /// file, one after the other, in another block.
+    fn blocks() -> AVec<PythonFlowBuilderInput> {
+        vec![
+            block(
+                "download_data",
+                "Download data",
+                vec![flow(
+                    "download_papers",
+                    Dialect::Bash(Bash::new()),
+                    "curl -o {path} https://example.com/data.csv",
+                    &[],
+                )],
+            ),
+            block(
+                "describe_data",
+                "Describe data",
+                vec![
+                    flow(
+                        "load_papers",
+                        Dialect::Python(Python::new(vec![])),
+                        "load_data",
+                        &["download_papers"],
+                    ),
+                    flow(
+                        "describe_papers",
+                        Dialect::Python(Python::new(vec![])),
+                        "describe_data",
+                        &["load_papers"],
+                    ),
+                ],
+            ),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn test_airflow2_dag() {
+        let dag = Airflow2FlowBuilder::<AoristRef<Universe>>::new()
+            .materialize(blocks(), AOption(ROption::RSome("flow".into())))
+            .unwrap();
+        let expected = r#"from airflow import DAG
+from airflow.decorators import task
+from airflow.operators.bash import BashOperator
+from airflow.utils.task_group import TaskGroup
+from datetime import datetime
+
+
+def load_data(path):
+    return open(path).read()
+
+
+def describe_data(path):
+    return open(path).read()
+
+
+# ## Setting up Airflow FlowBuilder
+default_args = {
+    "owner": "airflow",
+    "depends_on_past": False,
+    "email": ["airflow@example.com"],
+    "email_on_failure": False,
+    "email_on_retry": False,
+    "retries": 1,
+    "retry_delay": 300,
+}
+dag = DAG(
+    "flow",
+    default_args=default_args,
+    description="Auto-generated by Aorist",
+    schedule_interval=None,
+    start_date=datetime(2021, 1, 1),
+    tags=["aorist"],
+)
+
+# ## Download data
+with dag, TaskGroup(
+    group_id="download_data", tooltip="Download data", prefix_group_id=False
+):
+    download_papers = BashOperator(
+        task_id="download_papers",
+        bash_command="curl -o {path} https://example.com/data.csv".format(
+            path="/tmp/data.csv"
+        ),
+    )
+
+# ## Describe data
+with dag, TaskGroup(
+    group_id="describe_data", tooltip="Describe data", prefix_group_id=False
+):
+    load_papers = task(load_data, task_id="load_papers", do_xcom_push=False)(
+        path="/tmp/data.csv"
+    )
+    load_papers.set_upstream([download_papers])
+    describe_papers = (
+        task(describe_data, task_id="describe_papers", do_xcom_push=False)(
+            path="/tmp/data.csv"
+        )
+    )
+    describe_papers.set_upstream([load_papers])
+"#;
+        assert_eq!(dag.as_str(), expected);
+    }
+    #[test]
+    fn test_airflow2_mapped_task() {
+        let mut inputs = LinkedHashMap::new();
+        inputs.insert("path".into(), identifier("t"));
+        let (statements, imports) = flow(
+            "load_papers",
+            Dialect::Python(Python::new(vec![])),
+            "load_data",
+            &["download_papers"],
+        )
+        .get_mapped_statements(AST::Dict(Dict::new_wrapped(inputs)))
+        .0
+        .into_option()
+        .unwrap();
+        assert_eq!(
+            statements
+                .iter()
+                .map(|x| x.to_python_code().as_str().to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                concat!(
+                    "load_papers = (\n",
+                    "    PythonOperator.partial(\n",
+                    "        task_id=\"load_papers\", python_callable=load_data, do_xcom_push=False\n",
+                    "    ).expand(op_kwargs={\"path\": t})\n",
+                    ")\n"
+                ),
+                "load_papers.set_upstream([download_papers])\n",
+            ]
+        );
+        assert!(
+            imports.into_iter().collect::<Vec<_>>()
+                == vec![PythonImport::PythonFromImport(
+                    "airflow.operators.python".into(),
+                    "PythonOperator".into(),
+                    AOption(ROption::RNone),
+                )]
+        );
+    }
+}
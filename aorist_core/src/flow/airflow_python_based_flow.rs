@@ -1,6 +1,9 @@
 use crate::dialect::Dialect;
+use crate::driver::ExternalDependency;
 use crate::flow::etl_flow::ETLFlow;
 use crate::flow::flow_builder::FlowBuilderBase;
+use crate::flow::flow_builder::FlowBuilderMaterialize;
+use crate::flow::flow_builder_input::FlowBuilderInput;
 use crate::flow::python_based_flow::PythonBasedFlow;
 use crate::flow::python_based_flow_builder::PythonBasedFlowBuilder;
 use crate::python::{
@@ -8,6 +11,7 @@ use crate::python::{
     PythonFlowBuilderInput, PythonImport, PythonPreamble, PythonTask, RPythonTask,
 };
 use abi_stable::std_types::ROption;
+use aorist_ast::SourceRange;
 use aorist_ast::{
     Assignment, Attribute, BigIntLiteral, BooleanLiteral, Call, Dict, Expression, Formatted, List,
     None, SimpleIdentifier, StringLiteral, AST,
@@ -16,6 +20,9 @@ use aorist_primitives::AOption;
 use aorist_primitives::TPrestoEndpoints;
 use aorist_primitives::{AString, AVec, AoristUniverse};
 use linked_hash_map::LinkedHashMap;
+use linked_hash_set::LinkedHashSet;
+use pyo3::{PyErr, PyResult};
+use std::collections::BTreeSet;
 use std::marker::PhantomData;
 
 #[derive(Clone, Hash, PartialEq)]
@@ -331,6 +338,52 @@ where
             universe: PhantomData,
         }
     }
+    fn get_pip_requirements(&self) -> AVec<AString> {
+        vec!["apache-airflow>=1.10,<2"]
+            .into_iter()
+            .map(AString::from)
+            .collect()
+    }
+}
+impl<U: AoristUniverse> FlowBuilderMaterialize<U> for AirflowFlowBuilder<U>
+where
+    <U as AoristUniverse>::TEndpoints: TPrestoEndpoints,
+{
+    type BuilderInputType = PythonFlowBuilderInput;
+    type ErrorType = PyErr;
+
+    fn materialize(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        Ok(self
+            .materialize_python_flow(statements_and_preambles, AVec::new(), flow_name)?
+            .0)
+    }
+    fn materialize_with_external_dependencies(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        Ok(self
+            .materialize_python_flow(statements_and_preambles, external_dependencies, flow_name)?
+            .0)
+    }
+    fn materialize_with_source_ranges(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<(AString, Vec<SourceRange>)> {
+        self.materialize_python_flow(statements_and_preambles, external_dependencies, flow_name)
+    }
+    fn get_file_name(&self, flow_name: &AString) -> AString {
+        format!("{}.{}", flow_name, self.get_file_extension())
+            .as_str()
+            .into()
+    }
 }
 impl<U: AoristUniverse> PythonBasedFlowBuilder<U> for AirflowFlowBuilder<U>
 where
@@ -453,6 +506,59 @@ where
         );
         statements
     }
+    /// Waits for tasks in other DAGs with an ExternalTaskSensor each.
+    fn get_external_dependency_statements(
+        &self,
+        external_dependencies: &AVec<ExternalDependency>,
+    ) -> Option<PythonFlowBuilderInput> {
+        let statements = external_dependencies
+            .iter()
+            .map(|dep| {
+                let mut kwargs: LinkedHashMap<AString, AST> = LinkedHashMap::new();
+                kwargs.insert(
+                    "task_id".into(),
+                    AST::StringLiteral(StringLiteral::new_wrapped(dep.identifier.clone(), false)),
+                );
+                kwargs.insert(
+                    "external_dag_id".into(),
+                    AST::StringLiteral(StringLiteral::new_wrapped(dep.flow_name.clone(), false)),
+                );
+                kwargs.insert(
+                    "external_task_id".into(),
+                    AST::StringLiteral(StringLiteral::new_wrapped(dep.task_id.clone(), false)),
+                );
+                kwargs.insert(
+                    "dag".into(),
+                    AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("dag".into())),
+                );
+                AST::Assignment(Assignment::new_wrapped(
+                    AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(dep.identifier.clone())),
+                    AST::Call(Call::new_wrapped(
+                        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
+                            "ExternalTaskSensor".into(),
+                        )),
+                        AVec::new(),
+                        kwargs,
+                    )),
+                ))
+            })
+            .collect();
+        let imports = vec![PythonImport::PythonFromImport(
+            "airflow.sensors.external_task_sensor".into(),
+            "ExternalTaskSensor".into(),
+            AOption(ROption::RNone),
+        )]
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+        Some(PythonFlowBuilderInput::new(
+            statements,
+            LinkedHashSet::new(),
+            imports,
+            "external_dependencies".into(),
+            AOption(ROption::RSome("Waiting for tasks in other DAGs".into())),
+            AOption(ROption::RNone),
+        ))
+    }
     fn get_flow_imports(&self) -> AVec<PythonImport> {
         vec![
             PythonImport::PythonFromImport("airflow".into(), "DAG".into(), AOption(ROption::RNone)),
@@ -0,0 +1,297 @@
+use crate::dialect::Dialect;
+use crate::flow::IsolatedTask;
+use abi_stable::std_types::ROption;
+use aorist_primitives::{AOption, AString, AVec, AoristUniverse, TPrestoEndpoints};
+use linked_hash_map::LinkedHashMap;
+use pyo3::prelude::*;
+use serde::Serialize;
+use siphasher::sip128::{Hasher128, SipHasher};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+/// Container settings for the steps running tasks of one dialect. Every task
+/// is rendered as a standalone Python script, so images for R, Bash and
+/// Presto tasks also need Python (and rpy2, resp. the Presto client).
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct ArgoStepConfig {
+    pub image: AString,
+    pub command: AVec<AString>,
+    pub cpu: AOption<AString>,
+    pub memory: AOption<AString>,
+}
+#[pymethods]
+impl ArgoStepConfig {
+    #[new]
+    #[args(command = "vec![\"python\"]", cpu = "None", memory = "None")]
+    pub fn new(image: &str, command: Vec<&str>, cpu: Option<&str>, memory: Option<&str>) -> Self {
+        Self {
+            image: image.into(),
+            command: command.into_iter().map(|x| x.into()).collect(),
+            cpu: AOption(cpu.map(|x| x.into()).into()),
+            memory: AOption(memory.map(|x| x.into()).into()),
+        }
+    }
+}
+impl ArgoStepConfig {
+    /// Used for dialects without a configured step; tasks without a dialect
+    /// run as Python.
+    pub fn default_for(dialect: &AOption<Dialect>) -> Self {
+        match dialect {
+            AOption(ROption::RSome(Dialect::R(_))) => {
+                Self::new("rpy2/base-ubuntu:latest", vec!["python3"], None, None)
+            }
+            _ => Self::new("python:3.9", vec!["python"], None, None),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Workflow {
+    api_version: &'static str,
+    kind: &'static str,
+    metadata: WorkflowMetadata,
+    spec: WorkflowSpec,
+}
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkflowMetadata {
+    generate_name: String,
+}
+#[derive(Serialize)]
+struct WorkflowSpec {
+    entrypoint: String,
+    templates: Vec<Template>,
+}
+#[derive(Serialize)]
+struct Template {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dag: Option<DagTemplate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    script: Option<ScriptTemplate>,
+}
+#[derive(Serialize)]
+struct DagTemplate {
+    tasks: Vec<DagTask>,
+}
+#[derive(Serialize)]
+struct DagTask {
+    name: String,
+    template: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    dependencies: Vec<String>,
+}
+#[derive(Serialize)]
+struct ScriptTemplate {
+    image: String,
+    command: Vec<String>,
+    source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resources: Option<Resources>,
+}
+#[derive(Serialize)]
+struct Resources {
+    requests: BTreeMap<String, String>,
+}
+
+/// Maximum length of a DNS label, which Argo requires template and task
+/// names to be.
+const ARGO_NAME_MAX_LEN: usize = 63;
+
+/// Argo names have to be valid DNS labels. Names that are too long are
+/// truncated and suffixed with a hash of the full name, so that names
+/// sharing a long prefix stay distinct.
+fn get_argo_name(name: &AString) -> String {
+    let sanitized = name
+        .as_str()
+        .to_lowercase()
+        .chars()
+        .map(|x| match x.is_ascii_alphanumeric() {
+            true => x,
+            false => '-',
+        })
+        .collect::<String>();
+    let sanitized = match sanitized.trim_matches('-') {
+        "" => "flow".to_string(),
+        x => x.to_string(),
+    };
+    if sanitized.len() <= ARGO_NAME_MAX_LEN {
+        return sanitized;
+    }
+    let mut hasher = SipHasher::new();
+    name.as_str().hash(&mut hasher);
+    let hash = format!("{:032x}", hasher.finish128().as_u128());
+    let prefix = &sanitized[..ARGO_NAME_MAX_LEN - 9];
+    format!("{}-{}", prefix.trim_end_matches('-'), &hash[..8])
+}
+
+/// Maps task names to Argo names, failing if two names (or a name and the
+/// flow's own template) end up with the same Argo name.
+fn get_argo_names<'a, I: Iterator<Item = &'a AString>>(
+    flow_name: &str,
+    task_names: I,
+) -> Result<HashMap<AString, String>, String> {
+    let mut argo_names = HashMap::new();
+    let mut seen: HashMap<String, AString> = HashMap::new();
+    seen.insert(flow_name.to_string(), flow_name.into());
+    for name in task_names {
+        let argo_name = get_argo_name(name);
+        if let Some(other) = seen.get(&argo_name) {
+            return Err(format!(
+                "Tasks {} and {} both map to Argo name {}.",
+                other, name, argo_name
+            ));
+        }
+        seen.insert(argo_name.clone(), name.clone());
+        argo_names.insert(name.clone(), argo_name);
+    }
+    Ok(argo_names)
+}
+
+/// Builds an Argo Workflow with a single DAG template, in which every task
+/// is a step running a script template in the image configured for the
+/// task's dialect.
+pub struct ArgoWorkflowBuilder<U: AoristUniverse>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    steps: LinkedHashMap<AString, ArgoStepConfig>,
+    universe: PhantomData<U>,
+}
+impl<U: AoristUniverse> ArgoWorkflowBuilder<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    /// `steps` are keyed by dialect name ("python", "r", "bash", "presto").
+    pub fn new(steps: LinkedHashMap<AString, ArgoStepConfig>) -> Self {
+        Self {
+            steps,
+            universe: PhantomData,
+        }
+    }
+    fn get_step_config(&self, dialect: &AOption<Dialect>) -> ArgoStepConfig {
+        let name = match dialect {
+            AOption(ROption::RSome(d)) => d.get_name(),
+            AOption(ROption::RNone) => "python",
+        };
+        match self.steps.get(&AString::from(name)) {
+            Some(config) => config.clone(),
+            None => ArgoStepConfig::default_for(dialect),
+        }
+    }
+    pub fn materialize(
+        &self,
+        tasks: AVec<IsolatedTask>,
+        endpoints: U::TEndpoints,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        let flow_name = get_argo_name(&match flow_name {
+            AOption(ROption::RSome(x)) => x,
+            AOption(ROption::RNone) => "flow".into(),
+        });
+        let argo_names = get_argo_names(&flow_name, tasks.iter().map(|x| &x.name))
+            .map_err(pyo3::exceptions::PyValueError::new_err)?;
+        let get_dependency_name = |x: &AString| match argo_names.get(x) {
+            Some(name) => Ok(name.clone()),
+            None => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Unknown dependency {} in Argo workflow.",
+                x
+            ))),
+        };
+        let mut dag_tasks = Vec::new();
+        let mut templates = Vec::new();
+        for task in tasks.iter() {
+            let name = argo_names[&task.name].clone();
+            let config = self.get_step_config(&task.dialect);
+            let mut requests = BTreeMap::new();
+            if let AOption(ROption::RSome(ref cpu)) = config.cpu {
+                requests.insert("cpu".to_string(), cpu.to_string());
+            }
+            if let AOption(ROption::RSome(ref memory)) = config.memory {
+                requests.insert("memory".to_string(), memory.to_string());
+            }
+            templates.push(Template {
+                name: name.clone(),
+                dag: None,
+                script: Some(ScriptTemplate {
+                    image: config.image.to_string(),
+                    command: config.command.iter().map(|x| x.to_string()).collect(),
+                    source: task.to_python_script::<U>(endpoints.clone())?.to_string(),
+                    resources: match requests.is_empty() {
+                        true => None,
+                        false => Some(Resources { requests }),
+                    },
+                }),
+            });
+            dag_tasks.push(DagTask {
+                name: name.clone(),
+                template: name,
+                dependencies: task
+                    .dependencies
+                    .iter()
+                    .map(get_dependency_name)
+                    .collect::<PyResult<_>>()?,
+            });
+        }
+        templates.insert(
+            0,
+            Template {
+                name: flow_name.clone(),
+                dag: Some(DagTemplate { tasks: dag_tasks }),
+                script: None,
+            },
+        );
+        let workflow = Workflow {
+            api_version: "argoproj.io/v1alpha1",
+            kind: "Workflow",
+            metadata: WorkflowMetadata {
+                generate_name: format!("{}-", flow_name),
+            },
+            spec: WorkflowSpec {
+                entrypoint: flow_name,
+                templates,
+            },
+        };
+        serde_yaml::to_string(&workflow)
+            .map(|x| x.as_str().into())
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_argo_name_is_dns_label() {
+        assert_eq!(
+            get_argo_name(&"Download_Data__1".into()),
+            "download-data--1"
+        );
+        assert_eq!(get_argo_name(&"__".into()), "flow");
+    }
+    #[test]
+    fn test_long_argo_names_are_truncated_with_hash() {
+        let a: AString = format!("{}_a", "x".repeat(80)).as_str().into();
+        let b: AString = format!("{}_b", "x".repeat(80)).as_str().into();
+        let name_a = get_argo_name(&a);
+        let name_b = get_argo_name(&b);
+        assert_eq!(name_a.len(), ARGO_NAME_MAX_LEN);
+        assert!(name_a.starts_with("xxxx"));
+        assert_ne!(name_a, name_b);
+        assert_eq!(name_a, get_argo_name(&a));
+    }
+    #[test]
+    fn test_argo_name_collisions() {
+        let names: Vec<AString> = vec!["task_a".into(), "task-a".into()];
+        assert!(get_argo_names("flow", names.iter()).is_err());
+        let names: Vec<AString> = vec!["Flow".into()];
+        assert!(get_argo_names("flow", names.iter()).is_err());
+        let names: Vec<AString> = vec!["task_a".into(), "task_b".into()];
+        let argo_names = get_argo_names("flow", names.iter()).unwrap();
+        assert_eq!(argo_names[&names[1]], "task-b");
+    }
+}
@@ -0,0 +1,300 @@
+use crate::dialect::Dialect;
+use crate::flow::IsolatedTask;
+use abi_stable::std_types::ROption;
+use anyhow::{bail, Result};
+use aorist_ast::AST;
+use aorist_primitives::{AOption, AString, AVec, AoristUniverse, TPrestoEndpoints};
+use inflector::cases::snakecase::to_snake_case;
+use linked_hash_map::LinkedHashMap;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// Delimiter of the here-documents holding R programs and Presto queries.
+const HEREDOC_DELIMITER: &str = "AORIST_EOF";
+
+/// Runs a task in the background when `AORIST_PARALLEL` is set to 1, and
+/// waits for all background tasks, failing if any of them failed.
+const SCRIPT_HELPERS: &str = r#"pids=""
+run() {
+    if [ "${AORIST_PARALLEL:-0}" = 1 ]; then
+        "$@" &
+        pids="$pids $!"
+    else
+        "$@"
+    fi
+}
+wait_all() {
+    for pid in $pids; do
+        wait "$pid"
+    done
+    pids=""
+}"#;
+
+/// Single-quoted shell word.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace("'", "'\\''"))
+}
+fn get_string_value(task: &IsolatedTask, value: &AST) -> Result<String> {
+    match value {
+        AST::StringLiteral(x) => Ok(x.read().value().to_string()),
+        _ => bail!(
+            "Task {} has a parameter of type {}, which cannot be passed to a shell function",
+            task.name,
+            value.name()
+        ),
+    }
+}
+/// Replaces the `{name}` placeholders of a program with references to the
+/// shell variables holding the parameters, and unescapes doubled braces as
+/// Python's `str.format` would.
+fn substitute(template: &str, names: &[String], escape: fn(&str) -> String) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(pos) = rest.find(|c: char| c == '{' || c == '}') {
+        out += &escape(&rest[..pos]);
+        rest = &rest[pos..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            out += &rest[..1];
+            rest = &rest[2..];
+            continue;
+        }
+        let placeholder = match rest.starts_with('{') {
+            true => rest.find('}').map(|end| (&rest[1..end], end)),
+            false => None,
+        };
+        match placeholder {
+            Some((name, end)) if names.iter().any(|x| x == name) => {
+                out += &format!("${{{}}}", name);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                out += &escape(&rest[..1]);
+                rest = &rest[1..];
+            }
+        }
+    }
+    out + &escape(rest)
+}
+/// Bash programs are shell code already.
+fn keep(text: &str) -> String {
+    text.to_string()
+}
+/// Text in an unquoted here-document.
+fn escape_heredoc(text: &str) -> String {
+    text.replace("\\", "\\\\")
+        .replace("$", "\\$")
+        .replace("`", "\\`")
+}
+
+/// A shell function running one program, called once per task using it.
+struct ShellFunction {
+    name: String,
+    body: String,
+}
+
+/// Builds a single shell script running every task through a function per
+/// program, in the order tasks were satisfied. Tasks whose dependencies have
+/// all run form a stage; with `AORIST_PARALLEL=1` the tasks in a stage run
+/// in the background and the script waits for them before the next stage.
+/// Python programs cannot be run, since the script is meant for hosts
+/// without Python; R programs are run with `Rscript` and Presto queries with
+/// the Presto CLI. The script needs Bash rather than a POSIX `sh`, as it
+/// sets `pipefail`.
+pub struct BashScriptBuilder<U: AoristUniverse>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    universe: PhantomData<U>,
+}
+impl<U: AoristUniverse> BashScriptBuilder<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    pub fn new() -> Self {
+        Self {
+            universe: PhantomData,
+        }
+    }
+    /// Assigns the positional parameters of a function to the variables
+    /// named after the parameters of its program.
+    fn get_parameter_assignments(names: &[String]) -> String {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| format!("    {}=\"${{{}}}\"\n", name, i + 1))
+            .collect::<Vec<_>>()
+            .join("")
+    }
+    fn get_function_body(
+        task: &IsolatedTask,
+        names: &[String],
+        endpoints: &U::TEndpoints,
+    ) -> Result<String> {
+        let call = match task.call {
+            AOption(ROption::RSome(ref x)) => x.as_str(),
+            AOption(ROption::RNone) => bail!("Task {} has no program to call", task.name),
+        };
+        let assignments = Self::get_parameter_assignments(names);
+        let body = match task.dialect {
+            AOption(ROption::RSome(Dialect::Bash(_))) => {
+                format!("{}    {}", assignments, substitute(call, names, keep))
+            }
+            AOption(ROption::RSome(Dialect::Presto(_))) => {
+                let presto = endpoints.presto_config();
+                format!(
+                    "{}    query=$(cat <<{delimiter}\n{query}\n{delimiter}\n)\n    \
+                     presto --server {server}:{port} --user {user} --catalog {catalog} \
+                     --schema {schema} --execute \"$query\"",
+                    assignments,
+                    delimiter = HEREDOC_DELIMITER,
+                    query = substitute(call, names, escape_heredoc),
+                    server = quote(&presto.server),
+                    port = presto.http_port,
+                    user = quote(&presto.user),
+                    catalog = quote(&presto.catalog),
+                    schema = quote(&presto.schema),
+                )
+            }
+            AOption(ROption::RSome(Dialect::R(_))) => {
+                let (args, kwargs) = match task.params {
+                    AOption(ROption::RSome(ref p)) => (p.get_args().len(), p.get_kwargs()),
+                    AOption(ROption::RNone) => (0, LinkedHashMap::new()),
+                };
+                let call_args = (0..args)
+                    .map(|i| format!("args[[{}]]", i + 1))
+                    .chain(
+                        kwargs
+                            .keys()
+                            .enumerate()
+                            .map(|(i, k)| format!("{} = args[[{}]]", k, args + i + 1)),
+                    )
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let preamble = match task.preamble {
+                    AOption(ROption::RSome(ref x)) => format!("{}\n", x),
+                    AOption(ROption::RNone) => "".to_string(),
+                };
+                format!(
+                    "    Rscript - \"$@\" <<'{delimiter}'\n{preamble}\
+                     args <- as.list(commandArgs(trailingOnly = TRUE))\n\
+                     {call}({call_args})\n{delimiter}",
+                    delimiter = HEREDOC_DELIMITER,
+                    preamble = preamble,
+                    call = call,
+                    call_args = call_args,
+                )
+            }
+            AOption(ROption::RSome(Dialect::Python(_))) => bail!(
+                "Task {} runs a Python program, which cannot be run from a Bash script",
+                task.name
+            ),
+            AOption(ROption::RNone) => {
+                bail!("Task {} has no dialect to run its program in", task.name)
+            }
+        };
+        Ok(body)
+    }
+    /// Values of the task's parameters, in the order of the function's
+    /// positional parameters. Only R programs take positional arguments.
+    fn get_arguments(task: &IsolatedTask) -> Result<Vec<String>> {
+        let (args, kwargs) = match task.params {
+            AOption(ROption::RSome(ref p)) => (p.get_args(), p.get_kwargs()),
+            AOption(ROption::RNone) => return Ok(Vec::new()),
+        };
+        let args = match task.dialect {
+            AOption(ROption::RSome(Dialect::R(_))) => args,
+            _ => AVec::new(),
+        };
+        args.iter()
+            .chain(kwargs.values())
+            .map(|x| get_string_value(task, x).map(|x| quote(&x)))
+            .collect()
+    }
+    /// Parameter names of the program. Positional parameters are only used
+    /// by R programs, which read them from the command line.
+    fn get_parameter_names(task: &IsolatedTask) -> Vec<String> {
+        match (&task.dialect, &task.params) {
+            (AOption(ROption::RSome(Dialect::R(_))), _) => Vec::new(),
+            (_, AOption(ROption::RSome(ref p))) => {
+                p.get_kwargs().keys().map(|x| x.to_string()).collect()
+            }
+            (_, AOption(ROption::RNone)) => Vec::new(),
+        }
+    }
+    pub fn materialize(
+        &self,
+        tasks: AVec<IsolatedTask>,
+        endpoints: U::TEndpoints,
+        flow_name: AOption<AString>,
+    ) -> Result<AString> {
+        // tasks calling the same program share its function
+        let mut functions: LinkedHashMap<(String, String, String), ShellFunction> =
+            LinkedHashMap::new();
+        let mut function_names: HashMap<String, usize> = HashMap::new();
+        let mut stages: Vec<Vec<String>> = Vec::new();
+        let mut task_stages: HashMap<AString, usize> = HashMap::new();
+        for task in tasks.iter() {
+            let stage = task
+                .dependencies
+                .iter()
+                .filter_map(|x| task_stages.get(x))
+                .map(|x| x + 1)
+                .max()
+                .unwrap_or(0);
+            task_stages.insert(task.name.clone(), stage);
+            let dialect = match task.dialect {
+                AOption(ROption::RSome(ref d)) => d.get_name(),
+                // constraints without a program only order their dependents
+                AOption(ROption::RNone) => continue,
+            };
+            let key = (
+                dialect.to_string(),
+                task.call.clone().0.map_or(String::new(), |x| x.to_string()),
+                task.preamble
+                    .clone()
+                    .0
+                    .map_or(String::new(), |x| x.to_string()),
+            );
+            if !functions.contains_key(&key) {
+                let base = to_snake_case(task.constraint.as_str());
+                let count = function_names.entry(base.clone()).or_insert(0);
+                *count += 1;
+                let name = match *count {
+                    1 => base,
+                    n => format!("{}_{}", base, n),
+                };
+                let body =
+                    Self::get_function_body(task, &Self::get_parameter_names(task), &endpoints)?;
+                functions.insert(key.clone(), ShellFunction { name, body });
+            }
+            let invocation = vec!["run".to_string(), functions.get(&key).unwrap().name.clone()]
+                .into_iter()
+                .chain(Self::get_arguments(task)?.into_iter())
+                .collect::<Vec<_>>()
+                .join(" ");
+            if stages.len() <= stage {
+                stages.resize(stage + 1, Vec::new());
+            }
+            stages[stage].push(format!("# {}\n{}", task.name, invocation));
+        }
+        let flow_name = match flow_name {
+            AOption(ROption::RSome(x)) => x,
+            AOption(ROption::RNone) => "flow".into(),
+        };
+        let mut blocks = vec![
+            format!(
+                "#!/usr/bin/env bash\n# {}. Requires Bash. Set AORIST_PARALLEL=1 to run \
+                 independent tasks in parallel.\nset -euo pipefail",
+                flow_name
+            ),
+            SCRIPT_HELPERS.to_string(),
+        ];
+        for function in functions.values() {
+            blocks.push(format!("{}() {{\n{}\n}}", function.name, function.body));
+        }
+        for stage in stages.into_iter().filter(|x| !x.is_empty()) {
+            blocks.push(format!("{}\nwait_all", stage.join("\n")));
+        }
+        Ok(format!("{}\n", blocks.join("\n\n")).as_str().into())
+    }
+}
@@ -0,0 +1,373 @@
+use crate::driver::ExternalDependency;
+use crate::flow::flow_builder::FlowBuilderBase;
+use crate::flow::flow_builder::FlowBuilderMaterialize;
+use crate::flow::native_python_based_flow::NativePythonBasedFlow;
+use crate::flow::python_based_flow_builder::PythonBasedFlowBuilder;
+use crate::flow::IsolatedTask;
+use crate::python::{PythonFlowBuilderInput, PythonImport};
+use abi_stable::std_types::ROption;
+use aorist_ast::SourceRange;
+use aorist_ast::{
+    Assignment, Call, Dict, Expression, FunctionDef, SimpleIdentifier, StringLiteral, AST,
+};
+use aorist_primitives::AOption;
+use aorist_primitives::TPrestoEndpoints;
+use aorist_primitives::{AString, AVec, AoristUniverse};
+use linked_hash_map::LinkedHashMap;
+use pyo3::{PyErr, PyResult};
+use std::collections::HashSet;
+use std::marker::PhantomData;
+
+/// Name of the input through which an op waits for the op of `task`, and of
+/// the variable holding that op's output in the job.
+fn get_after_name(task: &AString) -> AString {
+    format!("after_{}", task).as_str().into()
+}
+fn identifier(name: &str) -> AST {
+    AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(name.into()))
+}
+fn call(function: &str, args: Vec<AST>, kwargs: LinkedHashMap<AString, AST>) -> AST {
+    AST::Call(Call::new_wrapped(
+        identifier(function),
+        args.into_iter().collect(),
+        kwargs,
+    ))
+}
+
+/// Builds Dagster jobs with one op per task. Ops only run their task, and
+/// wait for the ops of the tasks it depends on through `Nothing` inputs
+/// named `after_<upstream task>`. Since every op is a function of its own,
+/// jobs are built from isolated tasks rather than from constraint blocks.
+pub struct DagsterFlowBuilder<U: AoristUniverse> {
+    universe: PhantomData<U>,
+}
+impl<U: AoristUniverse> DagsterFlowBuilder<U>
+where
+    <U as AoristUniverse>::TEndpoints: TPrestoEndpoints,
+{
+    /// Name of the function defining the op of `task`, which cannot be the
+    /// task's name: the task may call a function of the same name.
+    fn get_op_function_name(task: &AString) -> AString {
+        format!("op_{}", task).as_str().into()
+    }
+    /// The op named after `task`, running `statements`.
+    pub fn get_op(task: &IsolatedTask, statements: AVec<AST>) -> AST {
+        let mut kwargs = LinkedHashMap::new();
+        kwargs.insert(
+            "name".into(),
+            AST::StringLiteral(StringLiteral::new_wrapped(task.name.clone(), false)),
+        );
+        if task.dependencies.len() > 0 {
+            kwargs.insert(
+                "ins".into(),
+                AST::Dict(Dict::new_wrapped(
+                    task.dependencies
+                        .iter()
+                        .map(|x| {
+                            (
+                                get_after_name(x),
+                                call("In", vec![identifier("Nothing")], LinkedHashMap::new()),
+                            )
+                        })
+                        .collect(),
+                )),
+            );
+        }
+        kwargs.insert(
+            "out".into(),
+            call("Out", vec![identifier("Nothing")], LinkedHashMap::new()),
+        );
+        AST::FunctionDef(FunctionDef::new_wrapped(
+            Self::get_op_function_name(&task.name),
+            AVec::new(),
+            LinkedHashMap::new(),
+            statements,
+            vec![call("op", Vec::new(), kwargs)].into_iter().collect(),
+        ))
+    }
+    /// The job named after the flow, invoking the op of each task once its
+    /// upstream ops returned. `tasks` must come after their dependencies.
+    pub fn get_job(tasks: &AVec<IsolatedTask>, flow_name: AString) -> AST {
+        let upstream = tasks
+            .iter()
+            .map(|x| x.dependencies.iter())
+            .flatten()
+            .collect::<HashSet<_>>();
+        let body = tasks
+            .iter()
+            .map(|task| {
+                let invocation = call(
+                    Self::get_op_function_name(&task.name).as_str(),
+                    Vec::new(),
+                    task.dependencies
+                        .iter()
+                        .map(|x| (get_after_name(x), identifier(get_after_name(x).as_str())))
+                        .collect(),
+                );
+                match upstream.contains(&task.name) {
+                    true => AST::Assignment(Assignment::new_wrapped(
+                        identifier(get_after_name(&task.name).as_str()),
+                        invocation,
+                    )),
+                    false => AST::Expression(Expression::new_wrapped(invocation)),
+                }
+            })
+            .collect();
+        AST::FunctionDef(FunctionDef::new_wrapped(
+            flow_name,
+            AVec::new(),
+            LinkedHashMap::new(),
+            body,
+            vec![identifier("job")].into_iter().collect(),
+        ))
+    }
+}
+impl<U: AoristUniverse> FlowBuilderBase<U> for DagsterFlowBuilder<U>
+where
+    <U as AoristUniverse>::TEndpoints: TPrestoEndpoints,
+{
+    type T = NativePythonBasedFlow<U>;
+    fn new() -> Self {
+        Self {
+            universe: PhantomData,
+        }
+    }
+    fn get_pip_requirements(&self) -> AVec<AString> {
+        vec!["dagster>=0.13"]
+            .into_iter()
+            .map(AString::from)
+            .collect()
+    }
+}
+impl<U: AoristUniverse> FlowBuilderMaterialize<U> for DagsterFlowBuilder<U>
+where
+    <U as AoristUniverse>::TEndpoints: TPrestoEndpoints,
+{
+    type BuilderInputType = PythonFlowBuilderInput;
+    type ErrorType = PyErr;
+
+    fn materialize(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        Ok(self
+            .materialize_python_flow(statements_and_preambles, AVec::new(), flow_name)?
+            .0)
+    }
+    fn materialize_with_external_dependencies(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        Ok(self
+            .materialize_python_flow(statements_and_preambles, external_dependencies, flow_name)?
+            .0)
+    }
+    fn materialize_with_source_ranges(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<(AString, Vec<SourceRange>)> {
+        self.materialize_python_flow(statements_and_preambles, external_dependencies, flow_name)
+    }
+    fn get_file_name(&self, flow_name: &AString) -> AString {
+        format!("{}.{}", flow_name, self.get_file_extension())
+            .as_str()
+            .into()
+    }
+}
+impl<U: AoristUniverse> PythonBasedFlowBuilder<U> for DagsterFlowBuilder<U>
+where
+    <U as AoristUniverse>::TEndpoints: TPrestoEndpoints,
+{
+    fn get_flow_imports(&self) -> AVec<PythonImport> {
+        vec!["In", "Nothing", "Out", "job", "op"]
+            .into_iter()
+            .map(|x| {
+                PythonImport::PythonFromImport("dagster".into(), x.into(), AOption(ROption::RNone))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialect::{Bash, Dialect, Python};
+    use crate::endpoints::EndpointConfig;
+    use crate::parameter_tuple::ParameterTuple;
+    use crate::universe::Universe;
+    use aorist_ast::AncestorRecord;
+    use aorist_primitives::AoristRef;
+    use uuid::Uuid;
+
+    fn endpoints() -> EndpointConfig {
+        EndpointConfig {
+            presto: AOption(ROption::RNone),
+            alluxio: AOption(ROption::RNone),
+            ranger: AOption(ROption::RNone),
+            gitea: AOption(ROption::RNone),
+            minio: AOption(ROption::RNone),
+            postgres: AOption(ROption::RNone),
+            gcp: AOption(ROption::RNone),
+            aws: AOption(ROption::RNone),
+            pdal: AOption(ROption::RNone),
+            linz: AOption(ROption::RNone),
+            dask: AOption(ROption::RNone),
+            gdal: AOption(ROption::RNone),
+            tag: AOption(ROption::RNone),
+            uuid: AOption(ROption::RNone),
+        }
+    }
+    fn task(name: &str, dialect: Dialect, call: &str, dependencies: &[&str]) -> IsolatedTask {
+        let mut kwargs = LinkedHashMap::new();
+        kwargs.insert(
+            "path".into(),
+            AST::StringLiteral(StringLiteral::new_wrapped("/tmp/data.csv".into(), false)),
+        );
+        IsolatedTask {
+            name: name.into(),
+            constraint: "Test".into(),
+            dialect: AOption(ROption::RSome(dialect)),
+            call: AOption(ROption::RSome(call.into())),
+            params: AOption(ROption::RSome(ParameterTuple {
+                args: AVec::new(),
+                kwargs,
+            })),
+            preamble: AOption(ROption::RNone),
+            dependencies: dependencies.iter().map(|x| (*x).into()).collect(),
+            ancestors: vec![AncestorRecord::new(
+                Uuid::new_v4(),
+                name.into(),
+                AOption(ROption::RNone),
+                0,
+            )]
+            .into_iter()
+            .collect(),
+        }
+    }
+    /// A Bash download, then two Python programs reading the downloaded
+    /// file, the second one also waiting for the first.
+    fn tasks() -> AVec<IsolatedTask> {
+        vec![
+            task(
+                "download_data",
+                Dialect::Bash(Bash::new()),
+                "curl -o {path} https://example.com/data.csv",
+                &[],
+            ),
+            task(
+                "load_data",
+                Dialect::Python(Python::new(vec![])),
+                "load_data",
+                &["download_data"],
+            ),
+            task(
+                "describe_data",
+                Dialect::Python(Python::new(vec![])),
+                "describe_data",
+                &["download_data", "load_data"],
+            ),
+        ]
+        .into_iter()
+        .collect()
+    }
+    fn materialize() -> (AString, Vec<SourceRange>) {
+        DagsterFlowBuilder::<AoristRef<Universe>>::new()
+            .materialize_with_source_ranges(
+                IsolatedTask::to_dagster_flow_builder_inputs::<AoristRef<Universe>>(
+                    tasks(),
+                    endpoints(),
+                    "flow".into(),
+                )
+                .unwrap(),
+                AVec::new(),
+                AOption(ROption::RSome("flow".into())),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_dagster_job() {
+        let (job, _) = materialize();
+        let expected = r#"import subprocess
+from dagster import In
+from dagster import Nothing
+from dagster import Out
+from dagster import job
+from dagster import op
+# ## Common string literals
+PATH = "/tmp/data.csv"
+
+
+# ## download_data
+@op(name="download_data", out=Out(Nothing))
+def op_download_data():
+    process = subprocess.Popen(
+        "curl -o {path} https://example.com/data.csv".format(path=PATH),
+        stdout=subprocess.PIPE,
+        shell=True,
+    )
+    result, error = process.communicate()
+
+
+# ## load_data
+@op(
+    name="load_data", ins={"after_download_data": In(Nothing)}, out=Out(Nothing)
+)
+def op_load_data():
+    result = load_data(path=PATH)
+
+
+# ## describe_data
+@op(
+    name="describe_data",
+    ins={"after_download_data": In(Nothing), "after_load_data": In(Nothing)},
+    out=Out(Nothing),
+)
+def op_describe_data():
+    result = describe_data(path=PATH)
+
+
+# ## Dagster job
+@job
+def flow():
+    after_download_data = op_download_data()
+    after_load_data = op_load_data(after_download_data=after_download_data)
+    op_describe_data(
+        after_download_data=after_download_data, after_load_data=after_load_data
+    )
+"#;
+        assert_eq!(job.as_str(), expected);
+    }
+    #[test]
+    fn test_dagster_op_source_ranges() {
+        let (job, ranges) = materialize();
+        let lines = job.as_str().lines().collect::<Vec<_>>();
+        let names = ranges
+            .iter()
+            .map(|x| {
+                x.ancestors
+                    .iter()
+                    .map(|x| x.object_type.as_str())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                vec!["download_data"],
+                vec!["load_data"],
+                vec!["describe_data"]
+            ]
+        );
+        // each op is mapped from its decorator to the end of its body
+        for range in ranges.iter() {
+            assert!(lines[range.start_line - 1].starts_with("@op("));
+            assert!(lines[range.end_line - 1].starts_with("    result"));
+        }
+    }
+}
@@ -0,0 +1,476 @@
+use crate::dialect::Dialect;
+use crate::driver::ExternalDependency;
+use crate::flow::etl_flow::ETLFlow;
+use crate::flow::flow_builder::FlowBuilderBase;
+use crate::flow::flow_builder::FlowBuilderMaterialize;
+use crate::flow::python_based_flow::PythonBasedFlow;
+use crate::flow::python_based_flow_builder::PythonBasedFlowBuilder;
+use crate::python::{
+    BashPythonTask, ConstantPythonTask, NativePythonPreamble, NativePythonTask, PrestoPythonTask,
+    PythonFlowBuilderInput, PythonImport, PythonPreamble, PythonTask, RPythonTask,
+};
+use abi_stable::std_types::ROption;
+use aorist_ast::SourceRange;
+use aorist_ast::{
+    Assignment, Attribute, Call, Expression, Formatted, None, SimpleIdentifier, StringLiteral, AST,
+};
+use aorist_primitives::AOption;
+use aorist_primitives::{AString, AVec, AoristUniverse, TDaskEndpoints, TPrestoEndpoints};
+use linked_hash_map::LinkedHashMap;
+use pyo3::{PyErr, PyResult};
+use std::marker::PhantomData;
+
+/// Wraps a callable in `dask.delayed`. Dependencies are passed as
+/// `wait_for`, a delayed object or a list of them, which Dask computes
+/// before calling the task; the task itself ignores them. Every task is
+/// recorded in `aorist_tasks`, so the whole graph can be computed at once.
+const DASK_HELPERS: &str = r#"
+import subprocess
+
+import dask
+
+aorist_tasks = []
+
+
+def aorist_task(task_name, task_callable, *args, wait_for=None, **kwargs):
+    def run(*args, wait_for=None, **kwargs):
+        return task_callable(*args, **kwargs)
+
+    run.__name__ = task_name
+    task = dask.delayed(run, pure=False)(*args, wait_for=wait_for, **kwargs)
+    aorist_tasks.append(task)
+    return task
+
+
+def aorist_bash(command):
+    return subprocess.run(
+        command, shell=True, check=True, stdout=subprocess.PIPE
+    ).stdout
+"#;
+
+/// Runs every task as a node of a Dask graph: tasks are wrapped in
+/// `dask.delayed`, with the tasks they depend on as delayed arguments, and
+/// the graph is computed on the scheduler set in the `dask` endpoint, or on
+/// a local cluster if there is none.
+#[derive(Clone, Hash, PartialEq)]
+pub struct DaskPythonBasedFlow<U: AoristUniverse>
+where
+    U::TEndpoints: TPrestoEndpoints + TDaskEndpoints,
+{
+    task_id: AST,
+    task_val: AST,
+    command: AOption<AString>,
+    args: AVec<AST>,
+    kwargs: LinkedHashMap<AString, AST>,
+    dep_list: AOption<AST>,
+    preamble: AOption<AString>,
+    dialect: AOption<Dialect>,
+    endpoints: U::TEndpoints,
+    node: PythonTask,
+    _universe: PhantomData<U>,
+}
+impl<U: AoristUniverse> PythonBasedFlow<U> for DaskPythonBasedFlow<U>
+where
+    U::TEndpoints: TPrestoEndpoints + TDaskEndpoints,
+{
+    fn get_preamble_string(&self) -> AOption<AString> {
+        self.preamble.clone()
+    }
+}
+impl<U: AoristUniverse> DaskPythonBasedFlow<U>
+where
+    U::TEndpoints: TPrestoEndpoints + TDaskEndpoints,
+{
+    /// Builds the `aorist_task` call: the task name and the callable come
+    /// first, followed by the callable's own arguments.
+    fn compute_task_call(&self) -> AST {
+        let task_id = self.task_id.clone();
+        let (args, mut kwargs) = match self.dialect {
+            AOption(ROption::RSome(Dialect::Bash(_))) => {
+                let mut kwargs = LinkedHashMap::new();
+                kwargs.insert(
+                    "command".into(),
+                    AST::Formatted(Formatted::new_wrapped(
+                        AST::StringLiteral(StringLiteral::new_wrapped(
+                            self.command.as_ref().unwrap().clone(),
+                            false,
+                        )),
+                        self.kwargs.clone(),
+                    )),
+                );
+                (
+                    vec![
+                        task_id,
+                        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("aorist_bash".into())),
+                    ],
+                    kwargs,
+                )
+            }
+            _ => match self.node.get_call().unwrap() {
+                AST::Call(call_rw) => {
+                    let call = call_rw.read();
+                    (
+                        vec![task_id, call.function()]
+                            .into_iter()
+                            .chain(call.args().into_iter())
+                            .collect::<Vec<_>>(),
+                        call.keywords(),
+                    )
+                }
+                _ => panic!("AST object should be call"),
+            },
+        };
+        if let AOption(ROption::RSome(ref dependencies)) = self.dep_list {
+            kwargs.insert("wait_for".into(), dependencies.clone());
+        }
+        AST::Call(Call::new_wrapped(
+            AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("aorist_task".into())),
+            args.into_iter().collect(),
+            kwargs,
+        ))
+    }
+    /// Address of the scheduler the graph is computed on, `None` starting a
+    /// local cluster.
+    fn get_scheduler_preamble(&self) -> AString {
+        let address = match self.endpoints.dask_config() {
+            AOption(ROption::RSome(dask)) => AST::StringLiteral(StringLiteral::new_wrapped(
+                format!("tcp://{}:{}", dask.server, dask.port)
+                    .as_str()
+                    .into(),
+                false,
+            )),
+            AOption(ROption::RNone) => AST::None(None::new_wrapped()),
+        };
+        AST::Assignment(Assignment::new_wrapped(
+            AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("dask_scheduler".into())),
+            address,
+        ))
+        .to_python_code()
+        .as_str()
+        .into()
+    }
+}
+impl<U: AoristUniverse> ETLFlow<U> for DaskPythonBasedFlow<U>
+where
+    U::TEndpoints: TPrestoEndpoints + TDaskEndpoints,
+{
+    type ImportType = PythonImport;
+    type PreambleType = PythonPreamble;
+    type ErrorType = pyo3::PyErr;
+    fn get_imports(&self) -> AVec<PythonImport> {
+        // the helpers' preamble brings in everything needed
+        AVec::new()
+    }
+    fn get_preamble(&self) -> Result<AVec<PythonPreamble>, pyo3::PyErr> {
+        let mut preambles = vec![
+            PythonPreamble::NativePythonPreamble(NativePythonPreamble::new(DASK_HELPERS.into())?),
+            PythonPreamble::NativePythonPreamble(NativePythonPreamble::new(
+                self.get_scheduler_preamble(),
+            )?),
+        ];
+        if let AOption(ROption::RSome(Dialect::Python(_))) = self.dialect {
+            if let AOption(ROption::RSome(ref p)) = self.preamble {
+                preambles.push(PythonPreamble::NativePythonPreamble(
+                    NativePythonPreamble::new(p.clone())?,
+                ));
+            }
+        }
+        if let AOption(ROption::RSome(p)) = self.node.get_preamble() {
+            preambles.push(p)
+        }
+        Ok(preambles.into_iter().collect())
+    }
+    fn get_dialect(&self) -> AOption<Dialect> {
+        self.dialect.clone()
+    }
+    fn get_task_val(&self) -> AST {
+        self.task_val.clone()
+    }
+    fn get_statements(&self) -> AVec<AST> {
+        vec![AST::Assignment(Assignment::new_wrapped(
+            self.task_val.clone(),
+            self.compute_task_call(),
+        ))]
+        .into_iter()
+        .collect()
+    }
+    fn new(
+        task_id: AST,
+        task_val: AST,
+        call: AOption<AString>,
+        args: AVec<AST>,
+        kwargs: LinkedHashMap<AString, AST>,
+        dep_list: AOption<AST>,
+        preamble: AOption<AString>,
+        dialect: AOption<Dialect>,
+        endpoints: U::TEndpoints,
+    ) -> Self {
+        let command = match &dialect {
+            AOption(ROption::RSome(Dialect::Presto(_))) => AST::StringLiteral(
+                StringLiteral::new_wrapped(call.as_ref().unwrap().clone(), true),
+            ),
+            AOption(ROption::RSome(_)) => AST::StringLiteral(StringLiteral::new_wrapped(
+                call.as_ref().unwrap().clone(),
+                false,
+            )),
+            AOption(ROption::RNone) => {
+                AST::StringLiteral(StringLiteral::new_wrapped("Done".into(), false))
+            }
+        };
+        let node = match &dialect {
+            AOption(ROption::RSome(Dialect::Presto(_))) => {
+                let presto_endpoints = endpoints.presto_config();
+                PythonTask::PrestoPythonTask(PrestoPythonTask::new_wrapped(
+                    command,
+                    kwargs
+                        .iter()
+                        .map(|(k, v)| {
+                            (
+                                k.clone(),
+                                match *v {
+                                    AST::StringLiteral(ref x) => AST::StringLiteral(
+                                        StringLiteral::new_wrapped(x.read().value().clone(), true),
+                                    ),
+                                    _ => v.clone(),
+                                },
+                            )
+                        })
+                        .collect(),
+                    task_val.clone(),
+                    presto_endpoints,
+                    dep_list.clone(),
+                ))
+            }
+            AOption(ROption::RSome(Dialect::Bash(_))) => {
+                PythonTask::BashPythonTask(BashPythonTask::new_wrapped(
+                    command,
+                    kwargs.clone(),
+                    task_val.clone(),
+                    dep_list.clone(),
+                ))
+            }
+            AOption(ROption::RSome(Dialect::R(_))) => {
+                PythonTask::RPythonTask(RPythonTask::new_wrapped(
+                    task_val.clone(),
+                    command,
+                    args.clone(),
+                    kwargs.clone(),
+                    dep_list.clone(),
+                    preamble.clone(),
+                ))
+            }
+            AOption(ROption::RSome(Dialect::Python(_))) => {
+                PythonTask::NativePythonTask(NativePythonTask::new_wrapped(
+                    AST::Call(Call::new_wrapped(
+                        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
+                            call.as_ref().unwrap().clone(),
+                        )),
+                        args.clone(),
+                        kwargs.clone(),
+                    )),
+                    // TODO: add imports from preamble
+                    AVec::new(),
+                    task_val.clone(),
+                    dep_list.clone(),
+                ))
+            }
+            AOption(ROption::RNone) => PythonTask::ConstantPythonTask(
+                ConstantPythonTask::new_wrapped(command, task_val.clone(), dep_list.clone()),
+            ),
+        };
+        Self {
+            task_id,
+            task_val,
+            command: call,
+            args,
+            kwargs,
+            dep_list,
+            preamble,
+            dialect,
+            endpoints,
+            node,
+            _universe: PhantomData,
+        }
+    }
+    fn get_type() -> String {
+        "dask".into()
+    }
+}
+
+pub struct DaskFlowBuilder<U: AoristUniverse> {
+    universe: PhantomData<U>,
+}
+impl<U: AoristUniverse> FlowBuilderBase<U> for DaskFlowBuilder<U>
+where
+    <U as AoristUniverse>::TEndpoints: TPrestoEndpoints + TDaskEndpoints,
+{
+    type T = DaskPythonBasedFlow<U>;
+    fn new() -> Self {
+        Self {
+            universe: PhantomData,
+        }
+    }
+    fn get_pip_requirements(&self) -> AVec<AString> {
+        vec!["dask[distributed]"]
+            .into_iter()
+            .map(AString::from)
+            .collect()
+    }
+}
+impl<U: AoristUniverse> FlowBuilderMaterialize<U> for DaskFlowBuilder<U>
+where
+    <U as AoristUniverse>::TEndpoints: TPrestoEndpoints + TDaskEndpoints,
+{
+    type BuilderInputType = PythonFlowBuilderInput;
+    type ErrorType = PyErr;
+
+    fn materialize(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        Ok(self
+            .materialize_python_flow(statements_and_preambles, AVec::new(), flow_name)?
+            .0)
+    }
+    fn materialize_with_external_dependencies(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        Ok(self
+            .materialize_python_flow(statements_and_preambles, external_dependencies, flow_name)?
+            .0)
+    }
+    fn materialize_with_source_ranges(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<(AString, Vec<SourceRange>)> {
+        self.materialize_python_flow(statements_and_preambles, external_dependencies, flow_name)
+    }
+    fn get_file_name(&self, flow_name: &AString) -> AString {
+        format!("{}.{}", flow_name, self.get_file_extension())
+            .as_str()
+            .into()
+    }
+}
+impl<U: AoristUniverse> PythonBasedFlowBuilder<U> for DaskFlowBuilder<U>
+where
+    <U as AoristUniverse>::TEndpoints: TPrestoEndpoints + TDaskEndpoints,
+{
+    /// Connects to the scheduler and computes every task of the graph.
+    fn augment_statements(
+        &self,
+        statements: AVec<PythonFlowBuilderInput>,
+        _flow_name: AOption<AString>,
+    ) -> AVec<PythonFlowBuilderInput> {
+        let client = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("client".into()));
+        let connect = AST::Assignment(Assignment::new_wrapped(
+            client,
+            AST::Call(Call::new_wrapped(
+                AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("Client".into())),
+                vec![AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
+                    "dask_scheduler".into(),
+                ))]
+                .into_iter()
+                .collect(),
+                LinkedHashMap::new(),
+            )),
+        ));
+        let compute = AST::Expression(Expression::new_wrapped(AST::Call(Call::new_wrapped(
+            AST::Attribute(Attribute::new_wrapped(
+                AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("dask".into())),
+                "compute".into(),
+                false,
+            )),
+            vec![AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
+                "aorist_tasks".into(),
+            ))]
+            .into_iter()
+            .collect(),
+            LinkedHashMap::new(),
+        ))));
+        statements
+            .into_iter()
+            .chain(
+                vec![PythonFlowBuilderInput::statements_only(
+                    vec![connect, compute].into_iter().collect(),
+                    "Run Dask graph".into(),
+                    AOption(ROption::RNone),
+                    AOption(ROption::RNone),
+                )]
+                .into_iter(),
+            )
+            .collect()
+    }
+    fn get_flow_imports(&self) -> AVec<PythonImport> {
+        vec![
+            PythonImport::PythonModuleImport("dask".into(), AOption(ROption::RNone)),
+            PythonImport::PythonFromImport(
+                "dask.distributed".into(),
+                "Client".into(),
+                AOption(ROption::RNone),
+            ),
+        ]
+        .into_iter()
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::endpoints::EndpointConfig;
+    use crate::universe::Universe;
+    use aorist_primitives::{AoristRef, DaskConfig};
+
+    fn flow(dask: AOption<DaskConfig>) -> DaskPythonBasedFlow<AoristRef<Universe>> {
+        let endpoints = EndpointConfig {
+            presto: AOption(ROption::RNone),
+            alluxio: AOption(ROption::RNone),
+            ranger: AOption(ROption::RNone),
+            gitea: AOption(ROption::RNone),
+            minio: AOption(ROption::RNone),
+            postgres: AOption(ROption::RNone),
+            gcp: AOption(ROption::RNone),
+            aws: AOption(ROption::RNone),
+            pdal: AOption(ROption::RNone),
+            linz: AOption(ROption::RNone),
+            dask,
+            gdal: AOption(ROption::RNone),
+            tag: AOption(ROption::RNone),
+            uuid: AOption(ROption::RNone),
+        };
+        DaskPythonBasedFlow::new(
+            AST::StringLiteral(StringLiteral::new_wrapped("task".into(), false)),
+            AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("task".into())),
+            AOption(ROption::RNone),
+            AVec::new(),
+            LinkedHashMap::new(),
+            AOption(ROption::RNone),
+            AOption(ROption::RNone),
+            AOption(ROption::RNone),
+            endpoints,
+        )
+    }
+
+    #[test]
+    fn test_scheduler_preamble() {
+        let remote = flow(AOption(ROption::RSome(DaskConfig {
+            server: "localhost".into(),
+            port: 8786,
+        })));
+        assert_eq!(
+            remote.get_scheduler_preamble().as_str(),
+            "dask_scheduler = \"tcp://localhost:8786\"\n"
+        );
+        let local = flow(AOption(ROption::RNone));
+        assert_eq!(
+            local.get_scheduler_preamble().as_str(),
+            "dask_scheduler = None\n"
+        );
+    }
+}
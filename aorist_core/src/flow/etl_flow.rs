@@ -1,5 +1,6 @@
 use crate::code::{Import, Preamble};
 use crate::dialect::Dialect;
+use abi_stable::std_types::ROption;
 use aorist_ast::AST;
 use aorist_primitives::AOption;
 use aorist_primitives::{AString, AVec, AoristUniverse};
@@ -33,4 +34,15 @@ where
     fn get_statements(&self) -> AVec<AST>;
     fn get_type() -> String;
     fn get_imports(&self) -> AVec<Self::ImportType>;
+    /// What a single instance of a mapped task (a task expanded over a list
+    /// of inputs when the flow runs) receives, or None if the flow type
+    /// cannot map this task.
+    fn get_mapped_input(&self) -> AOption<AST> {
+        AOption(ROption::RNone)
+    }
+    /// Statements creating a task with this flow's task id and dependencies,
+    /// expanded over `inputs`, and the imports they need.
+    fn get_mapped_statements(&self, _inputs: AST) -> AOption<(AVec<AST>, AVec<Self::ImportType>)> {
+        AOption(ROption::RNone)
+    }
 }
@@ -0,0 +1,328 @@
+use crate::flow::IsolatedTask;
+use abi_stable::std_types::ROption;
+use aorist_primitives::{AOption, AString, AVec, AoristUniverse, TPrestoEndpoints};
+use linked_hash_map::LinkedHashMap;
+use linked_hash_set::LinkedHashSet;
+use pyo3::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+
+/// Directory holding the files marking tasks as done.
+const DONE_DIR: &str = ".aorist";
+
+/// A task with the files it reads and writes. Every rule writes a file
+/// marking it as done, so that tasks without paths can also be ordered.
+struct FileRule {
+    name: String,
+    script: String,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    done: String,
+}
+
+/// Rule and variable names have to be identifiers.
+fn get_rule_name(name: &AString) -> String {
+    let name = name
+        .as_str()
+        .chars()
+        .map(|x| match x.is_ascii_alphanumeric() {
+            true => x,
+            false => '_',
+        })
+        .collect::<String>();
+    match name.chars().next() {
+        Some(x) if !x.is_ascii_digit() => name,
+        _ => format!("task_{}", name),
+    }
+}
+fn get_done_file(name: &AString) -> String {
+    format!("{}/{}.done", DONE_DIR, get_rule_name(name))
+}
+/// Local paths of a task, split by whether the storage holding them is read
+/// from or written to.
+#[derive(Default)]
+pub struct TaskPaths {
+    sources: LinkedHashSet<AString>,
+    targets: LinkedHashSet<AString>,
+}
+impl TaskPaths {
+    pub fn add_source(&mut self, path: AString) {
+        self.sources.insert(path);
+    }
+    pub fn add_target(&mut self, path: AString) {
+        self.targets.insert(path);
+    }
+}
+
+/// Rules for the tasks, without their scripts. Paths in source storage are
+/// only ever inputs, so that they are never deleted or overwritten. Paths in
+/// target storage are outputs of the first task they belong to, and inputs
+/// of all later ones (tasks are in the order they were satisfied, so these
+/// run after it). Fails if two tasks end up with the same rule name.
+fn get_rule_files(
+    tasks: &AVec<IsolatedTask>,
+    task_paths: &LinkedHashMap<AString, TaskPaths>,
+) -> Result<Vec<FileRule>, String> {
+    let mut written = HashSet::new();
+    let mut names: HashMap<String, AString> = HashMap::new();
+    let mut rules = Vec::new();
+    for task in tasks.iter() {
+        let name = get_rule_name(&task.name);
+        if let Some(other) = names.insert(name.clone(), task.name.clone()) {
+            return Err(format!(
+                "Tasks {} and {} both map to rule name {}.",
+                other, task.name, name
+            ));
+        }
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+        if let Some(paths) = task_paths.get(&task.name) {
+            inputs.extend(paths.sources.iter().map(|x| x.to_string()));
+            for path in paths.targets.iter() {
+                match written.insert(path.clone()) {
+                    true => outputs.push(path.to_string()),
+                    false => inputs.push(path.to_string()),
+                }
+            }
+        }
+        inputs.extend(task.dependencies.iter().map(get_done_file));
+        rules.push(FileRule {
+            name,
+            script: String::new(),
+            inputs,
+            outputs,
+            done: get_done_file(&task.name),
+        });
+    }
+    Ok(rules)
+}
+fn get_file_rules<U: AoristUniverse>(
+    tasks: AVec<IsolatedTask>,
+    task_paths: &LinkedHashMap<AString, TaskPaths>,
+    endpoints: U::TEndpoints,
+) -> PyResult<Vec<FileRule>>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    let mut rules =
+        get_rule_files(&tasks, task_paths).map_err(pyo3::exceptions::PyValueError::new_err)?;
+    for (rule, task) in rules.iter_mut().zip(tasks.iter()) {
+        rule.script = task.to_python_script::<U>(endpoints.clone())?.to_string();
+    }
+    Ok(rules)
+}
+fn get_flow_name(flow_name: AOption<AString>) -> AString {
+    match flow_name {
+        AOption(ROption::RSome(x)) => x,
+        AOption(ROption::RNone) => "flow".into(),
+    }
+}
+
+/// Builds a Snakefile in which every task is a rule running the task as a
+/// standalone Python script, with the local files below the task's root as
+/// its inputs and outputs (see `get_file_rules`), so that Snakemake only
+/// reruns tasks whose outputs are missing or older than their inputs.
+pub struct SnakefileBuilder<U: AoristUniverse>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    universe: PhantomData<U>,
+}
+impl<U: AoristUniverse> SnakefileBuilder<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    pub fn new() -> Self {
+        Self {
+            universe: PhantomData,
+        }
+    }
+    /// Braces in paths would be taken for wildcards.
+    fn quote_path(path: &str) -> String {
+        serde_json::to_string(&path.replace("{", "{{").replace("}", "}}")).unwrap()
+    }
+    /// The script becomes a triple-quoted Python string, which can hold it
+    /// verbatim once backslashes and quotes are escaped.
+    fn quote_script(script: &str) -> String {
+        format!(
+            "'''\n{}'''",
+            script.replace("\\", "\\\\").replace("'", "\\'")
+        )
+    }
+    fn render_paths(paths: impl Iterator<Item = String>) -> String {
+        paths
+            .map(|x| format!("        {},\n", x))
+            .collect::<Vec<_>>()
+            .join("")
+    }
+    pub fn materialize(
+        &self,
+        tasks: AVec<IsolatedTask>,
+        task_paths: LinkedHashMap<AString, TaskPaths>,
+        endpoints: U::TEndpoints,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        let rules = get_file_rules::<U>(tasks, &task_paths, endpoints)?;
+        let mut blocks = vec![format!(
+            "# Snakefile for {}. Run with: snakemake --cores <n>",
+            get_flow_name(flow_name)
+        )];
+        blocks.push(format!(
+            "rule all:\n    input:\n{}",
+            Self::render_paths(rules.iter().map(|x| Self::quote_path(&x.done)))
+        ));
+        for rule in rules.iter() {
+            let script = match rule.script.ends_with('\n') {
+                true => rule.script.clone(),
+                false => format!("{}\n", rule.script),
+            };
+            let mut block = format!(
+                "{}_script = {}\n\nrule {}:\n",
+                rule.name,
+                Self::quote_script(&script),
+                rule.name
+            );
+            if !rule.inputs.is_empty() {
+                block += &format!(
+                    "    input:\n{}",
+                    Self::render_paths(rule.inputs.iter().map(|x| Self::quote_path(x)))
+                );
+            }
+            block += &format!(
+                "    output:\n{}",
+                Self::render_paths(
+                    rule.outputs
+                        .iter()
+                        .map(|x| Self::quote_path(x))
+                        .chain(vec![format!("touch({})", Self::quote_path(&rule.done))])
+                )
+            );
+            block += &format!(
+                "    run:\n        exec({}_script, {{\"__name__\": \"__main__\"}})",
+                rule.name
+            );
+            blocks.push(block);
+        }
+        Ok(format!("{}\n", blocks.join("\n\n")).as_str().into())
+    }
+}
+
+/// Builds a Makefile with the same rules as `SnakefileBuilder`. The scripts
+/// are passed to Python through exported variables, and the files written by
+/// a task are grouped targets, so GNU Make 4.3 or later is required.
+pub struct MakefileBuilder<U: AoristUniverse>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    universe: PhantomData<U>,
+}
+impl<U: AoristUniverse> MakefileBuilder<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    pub fn new() -> Self {
+        Self {
+            universe: PhantomData,
+        }
+    }
+    /// Make expands `$` in targets, prerequisites and variables.
+    fn escape(text: &str) -> String {
+        text.replace("$", "$$")
+    }
+    fn render_paths<'b>(paths: impl Iterator<Item = &'b String>) -> String {
+        paths
+            .map(|x| Self::escape(&x.replace(" ", "\\ ")))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+    pub fn materialize(
+        &self,
+        tasks: AVec<IsolatedTask>,
+        task_paths: LinkedHashMap<AString, TaskPaths>,
+        endpoints: U::TEndpoints,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        let rules = get_file_rules::<U>(tasks, &task_paths, endpoints)?;
+        let mut blocks = vec![
+            format!(
+                "# Makefile for {}. Requires GNU Make 4.3 or later.",
+                get_flow_name(flow_name)
+            ),
+            "PYTHON ?= python3".to_string(),
+            format!(
+                ".PHONY: all\nall: {}",
+                Self::render_paths(rules.iter().map(|x| &x.done))
+            ),
+        ];
+        for rule in rules.iter() {
+            let variable = format!("{}_script", rule.name);
+            blocks.push(format!(
+                "define {variable}\n{script}\nendef\nexport {variable}\n\n\
+                 {outputs} &: {inputs}\n\
+                 \t$(PYTHON) -c \"$${variable}\"\n\
+                 \t@mkdir -p {done_dir}\n\
+                 \ttouch {done}",
+                variable = variable,
+                script = Self::escape(rule.script.trim_end_matches('\n')),
+                outputs =
+                    Self::render_paths(rule.outputs.iter().chain(vec![&rule.done].into_iter())),
+                inputs = Self::render_paths(rule.inputs.iter()),
+                done_dir = DONE_DIR,
+                done = rule.done,
+            ));
+        }
+        Ok(format!("{}\n", blocks.join("\n\n")).as_str().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(name: &str, dependencies: &[&str]) -> IsolatedTask {
+        IsolatedTask {
+            name: name.into(),
+            constraint: "Test".into(),
+            dialect: AOption(ROption::RNone),
+            call: AOption(ROption::RNone),
+            params: AOption(ROption::RNone),
+            preamble: AOption(ROption::RNone),
+            dependencies: dependencies.iter().map(|x| (*x).into()).collect(),
+            ancestors: AVec::new(),
+        }
+    }
+
+    #[test]
+    fn test_source_paths_are_never_outputs() {
+        let mut task_paths = LinkedHashMap::new();
+        let mut download = TaskPaths::default();
+        download.add_source("/data/remote.csv".into());
+        download.add_target("/data/local.csv".into());
+        task_paths.insert("download".into(), download);
+        let mut convert = TaskPaths::default();
+        convert.add_target("/data/local.csv".into());
+        task_paths.insert("convert".into(), convert);
+        let tasks = vec![task("download", &[]), task("convert", &["download"])];
+        let rules = get_rule_files(&tasks.into_iter().collect(), &task_paths).unwrap();
+        assert_eq!(rules[0].inputs, vec!["/data/remote.csv".to_string()]);
+        assert_eq!(rules[0].outputs, vec!["/data/local.csv".to_string()]);
+        assert_eq!(
+            rules[1].inputs,
+            vec![
+                "/data/local.csv".to_string(),
+                ".aorist/download.done".to_string()
+            ]
+        );
+        assert!(rules[1].outputs.is_empty());
+    }
+    #[test]
+    fn test_rule_name_collisions() {
+        let task_paths = LinkedHashMap::new();
+        let tasks = vec![task("a-b", &[]), task("a_b", &[])];
+        assert!(get_rule_files(&tasks.into_iter().collect(), &task_paths).is_err());
+        let tasks = vec![task("1a", &[]), task("a_b", &[])];
+        let rules = get_rule_files(&tasks.into_iter().collect(), &task_paths).unwrap();
+        assert_eq!(rules[0].name, "task_1a");
+        assert_eq!(rules[0].done, ".aorist/task_1a.done");
+    }
+}
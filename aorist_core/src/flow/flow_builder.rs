@@ -1,22 +1,53 @@
 use crate::code::Preamble;
+use crate::driver::ExternalDependency;
 use crate::flow::etl_flow::ETLFlow;
 use crate::flow::flow_builder_input::FlowBuilderInput;
 use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
 use abi_stable::std_types::RArc;
-use aorist_ast::{Assignment, Dict, SimpleIdentifier, AST};
+use aorist_ast::{Assignment, Dict, SimpleIdentifier, SourceRange, AST};
 use aorist_primitives::AOption;
 use aorist_primitives::{AString, AVec, AoristUniverse};
 use linked_hash_map::LinkedHashMap;
 use linked_hash_set::LinkedHashSet;
+use serde_json::{json, Value};
 use std::collections::BTreeMap;
 use std::error::Error;
 
+/// Name of the block holding the string literals shared by several tasks.
+/// Notebooks expose this block as their parameters.
+pub const LITERALS_BLOCK_NAME: &str = "assignments";
+/// Title of the block holding the string literals shared by several tasks.
+pub const LITERALS_BLOCK_TITLE: &str = "Common string literals";
+
+/// Metadata of the notebook cell holding the block named `block_name`. The
+/// literals block is tagged as the notebook's parameters, so that papermill
+/// can override them.
+pub fn get_code_cell_metadata(block_name: &AString) -> Value {
+    match block_name.as_str() == LITERALS_BLOCK_NAME {
+        true => json!({"tags": ["parameters"]}),
+        false => json!({}),
+    }
+}
+
 pub trait FlowBuilderBase<U: AoristUniverse>
 where
     Self: Sized,
 {
     type T: ETLFlow<U>;
     fn new() -> Self;
+    /// Identifier of the task waiting for `task_id` in the flow `group`.
+    fn get_external_dependency_name(&self, _group: &AString, task_id: &AString) -> AString {
+        format!("wait_for_{}", task_id).as_str().into()
+    }
+    /// Pip requirements of the generated flow itself, besides those of the
+    /// programs it runs.
+    fn get_pip_requirements(&self) -> AVec<AString> {
+        AVec::new()
+    }
+    /// R packages the generated flow itself needs.
+    fn get_r_packages(&self) -> AVec<AString> {
+        AVec::new()
+    }
 }
 pub trait FlowBuilderMaterialize<U: AoristUniverse>
 where
@@ -34,6 +65,35 @@ where
         flow_name: AOption<AString>,
     ) -> Result<AString, Self::ErrorType>;
 
+    /// Like `materialize`, but the flow first waits for tasks in other flows.
+    fn materialize_with_external_dependencies(
+        &self,
+        statements_and_preambles: AVec<Self::BuilderInputType>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> Result<AString, Self::ErrorType>;
+
+    /// Like `materialize_with_external_dependencies`, along with the lines
+    /// of the flow holding the statements of each task, computed while
+    /// printing them. Flows not printed from statements map no lines.
+    fn materialize_with_source_ranges(
+        &self,
+        statements_and_preambles: AVec<Self::BuilderInputType>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> Result<(AString, Vec<SourceRange>), Self::ErrorType> {
+        Ok((
+            self.materialize_with_external_dependencies(
+                statements_and_preambles,
+                external_dependencies,
+                flow_name,
+            )?,
+            Vec::new(),
+        ))
+    }
+
+    fn get_file_name(&self, flow_name: &AString) -> AString;
+
     fn literals_to_assignments(
         literals: LinkedHashMap<AST, LinkedHashMap<AString, AVec<(AString, RArc<RRwLock<Dict>>)>>>,
     ) -> AVec<AST> {
@@ -91,3 +151,21 @@ where
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_cell_metadata_tags_literals_block() {
+        assert_eq!(
+            get_code_cell_metadata(&LITERALS_BLOCK_NAME.into()),
+            json!({"tags": ["parameters"]})
+        );
+        assert_eq!(
+            get_code_cell_metadata(&LITERALS_BLOCK_TITLE.into()),
+            json!({})
+        );
+        assert_eq!(get_code_cell_metadata(&"download_data".into()), json!({}));
+    }
+}
@@ -0,0 +1,269 @@
+use crate::dialect::Dialect;
+use crate::flow::flow_builder::{LITERALS_BLOCK_NAME, LITERALS_BLOCK_TITLE};
+#[cfg(feature = "python")]
+use crate::flow::{
+    set_task_ancestors, DagsterFlowBuilder, FlowBuilderBase, FlowBuilderMaterialize,
+    NativePythonBasedFlow, PythonFlowBuilder,
+};
+use crate::flow::{ETLFlow, FlowBuilderInput};
+use crate::flow::{NativeRBasedFlow, RFlowBuilderError};
+use crate::parameter_tuple::ParameterTuple;
+#[cfg(feature = "python")]
+use crate::python::PythonFlowBuilderInput;
+use crate::r::RFlowBuilderInput;
+use abi_stable::std_types::ROption;
+use aorist_ast::{AncestorRecord, Assignment, SimpleIdentifier, StringLiteral, AST};
+use aorist_primitives::{AOption, AString, AVec};
+use aorist_primitives::{AoristUniverse, TPrestoEndpoints};
+use linked_hash_map::LinkedHashMap;
+use std::collections::BTreeSet;
+
+/// A satisfied constraint rendered on its own, for flow types that run every
+/// task in isolation (e.g. one container per task) rather than as a statement
+/// of a single flow file.
+#[derive(Clone, Debug)]
+pub struct IsolatedTask {
+    pub name: AString,
+    /// Name of the constraint the task satisfies.
+    pub constraint: AString,
+    pub dialect: AOption<Dialect>,
+    pub call: AOption<AString>,
+    pub params: AOption<ParameterTuple>,
+    pub preamble: AOption<AString>,
+    /// Names of the tasks that have to run first.
+    pub dependencies: AVec<AString>,
+    /// Ancestors of the task's root concept, followed by its constraint.
+    pub ancestors: AVec<AncestorRecord>,
+}
+impl IsolatedTask {
+    fn get_args_and_kwargs(&self) -> (AVec<AST>, LinkedHashMap<AString, AST>) {
+        match self.params {
+            AOption(ROption::RSome(ref p)) => (p.get_args(), p.get_kwargs()),
+            AOption(ROption::RNone) => (AVec::new(), LinkedHashMap::new()),
+        }
+    }
+    /// Replaces the keyword arguments used by several tasks, always with the
+    /// same string value (e.g. temporary directories or endpoint settings),
+    /// with variables named after them, and returns the assignments of these
+    /// variables. Parameters of Presto queries are kept, since they are
+    /// formatted into the queries when these are rendered.
+    pub fn extract_parameters(tasks: &mut AVec<IsolatedTask>) -> AVec<AST> {
+        // string values of every keyword argument, None if not a string
+        let mut values: LinkedHashMap<AString, Vec<AOption<AString>>> = LinkedHashMap::new();
+        for task in tasks.iter() {
+            if let AOption(ROption::RSome(Dialect::Presto(_))) = task.dialect {
+                continue;
+            }
+            for (key, value) in task.get_args_and_kwargs().1.iter() {
+                values
+                    .entry(key.clone())
+                    .or_insert(Vec::new())
+                    .push(match value {
+                        AST::StringLiteral(x) => AOption(ROption::RSome(x.read().value())),
+                        _ => AOption(ROption::RNone),
+                    });
+            }
+        }
+        let parameters = values
+            .into_iter()
+            .filter(|(_, v)| v.len() > 1 && v.iter().all(|x| *x == v[0]))
+            .filter_map(|(key, v)| match v.into_iter().next() {
+                Some(AOption(ROption::RSome(value))) => Some((key, value)),
+                _ => None,
+            })
+            .collect::<LinkedHashMap<_, _>>();
+        for task in tasks.iter_mut() {
+            if let AOption(ROption::RSome(Dialect::Presto(_))) = task.dialect {
+                continue;
+            }
+            if let AOption(ROption::RSome(ref mut p)) = task.params {
+                for (key, value) in p.kwargs.iter_mut() {
+                    if parameters.contains_key(key) {
+                        *value = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
+                            key.as_str().to_uppercase().as_str().into(),
+                        ));
+                    }
+                }
+            }
+        }
+        parameters
+            .into_iter()
+            .map(|(key, value)| {
+                AST::Assignment(Assignment::new_wrapped(
+                    AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
+                        key.as_str().to_uppercase().as_str().into(),
+                    )),
+                    AST::StringLiteral(StringLiteral::new_wrapped(value, false)),
+                ))
+            })
+            .collect()
+    }
+}
+#[cfg(feature = "python")]
+impl IsolatedTask {
+    /// Variable named after the task, holding its result.
+    fn get_task_val(&self) -> AST {
+        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(self.name.clone()))
+    }
+    /// The task's statements, assigning its result to `task_val`.
+    fn to_python_flow<U: AoristUniverse>(
+        &self,
+        task_val: AST,
+        endpoints: U::TEndpoints,
+    ) -> NativePythonBasedFlow<U>
+    where
+        U::TEndpoints: TPrestoEndpoints,
+    {
+        let (args, kwargs) = self.get_args_and_kwargs();
+        NativePythonBasedFlow::<U>::new(
+            AST::StringLiteral(StringLiteral::new_wrapped(self.name.clone(), false)),
+            task_val,
+            self.call.clone(),
+            args,
+            kwargs,
+            AOption(ROption::RNone),
+            self.preamble.clone(),
+            self.dialect.clone(),
+            endpoints,
+        )
+    }
+    /// Self-contained Python script running the task, whatever its dialect.
+    pub fn to_python_script<U: AoristUniverse>(
+        &self,
+        endpoints: U::TEndpoints,
+    ) -> pyo3::PyResult<AString>
+    where
+        U::TEndpoints: TPrestoEndpoints,
+    {
+        let flow = self.to_python_flow::<U>(self.get_task_val(), endpoints);
+        let input = PythonFlowBuilderInput::new(
+            flow.get_statements(),
+            flow.get_preamble()?.into_iter().collect(),
+            flow.get_imports().into_iter().collect::<BTreeSet<_>>(),
+            self.name.clone(),
+            AOption(ROption::RNone),
+            AOption(ROption::RNone),
+        );
+        PythonFlowBuilder::<U>::new().materialize(
+            vec![input].into_iter().collect(),
+            AOption(ROption::RSome(self.name.clone())),
+        )
+    }
+    /// One block per task, titled with the task's name, preceded by the
+    /// block assigning the parameters shared by the tasks.
+    pub fn to_python_flow_builder_inputs<U: AoristUniverse>(
+        mut tasks: AVec<IsolatedTask>,
+        endpoints: U::TEndpoints,
+    ) -> pyo3::PyResult<AVec<PythonFlowBuilderInput>>
+    where
+        U::TEndpoints: TPrestoEndpoints,
+    {
+        let parameters = Self::extract_parameters(&mut tasks);
+        let mut inputs = vec![PythonFlowBuilderInput::statements_only(
+            parameters,
+            LITERALS_BLOCK_NAME.into(),
+            AOption(ROption::RSome(LITERALS_BLOCK_TITLE.into())),
+            AOption(ROption::RNone),
+        )];
+        for task in tasks.iter() {
+            let flow = task.to_python_flow::<U>(task.get_task_val(), endpoints.clone());
+            inputs.push(PythonFlowBuilderInput::new(
+                flow.get_statements(),
+                flow.get_preamble()?.into_iter().collect(),
+                flow.get_imports().into_iter().collect::<BTreeSet<_>>(),
+                task.name.clone(),
+                AOption(ROption::RSome(task.name.clone())),
+                AOption(ROption::RNone),
+            ));
+        }
+        Ok(inputs.into_iter().collect())
+    }
+    /// Like `to_python_flow_builder_inputs`, with each task wrapped in a
+    /// Dagster op, followed by the block defining the job named `flow_name`.
+    /// `tasks` must come after their dependencies.
+    pub fn to_dagster_flow_builder_inputs<U: AoristUniverse>(
+        mut tasks: AVec<IsolatedTask>,
+        endpoints: U::TEndpoints,
+        flow_name: AString,
+    ) -> pyo3::PyResult<AVec<PythonFlowBuilderInput>>
+    where
+        U::TEndpoints: TPrestoEndpoints,
+    {
+        let parameters = Self::extract_parameters(&mut tasks);
+        let mut inputs = vec![PythonFlowBuilderInput::statements_only(
+            parameters,
+            LITERALS_BLOCK_NAME.into(),
+            AOption(ROption::RSome(LITERALS_BLOCK_TITLE.into())),
+            AOption(ROption::RNone),
+        )];
+        for task in tasks.iter() {
+            // the op is a function of its own, whose result is discarded
+            let flow = task.to_python_flow::<U>(
+                AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("result".into())),
+                endpoints.clone(),
+            );
+            let statements = vec![DagsterFlowBuilder::<U>::get_op(task, flow.get_statements())]
+                .into_iter()
+                .collect();
+            set_task_ancestors(&statements, &task.ancestors);
+            inputs.push(PythonFlowBuilderInput::new(
+                statements,
+                flow.get_preamble()?.into_iter().collect(),
+                flow.get_imports().into_iter().collect::<BTreeSet<_>>(),
+                task.name.clone(),
+                AOption(ROption::RSome(task.name.clone())),
+                AOption(ROption::RNone),
+            ));
+        }
+        inputs.push(PythonFlowBuilderInput::statements_only(
+            vec![DagsterFlowBuilder::<U>::get_job(&tasks, flow_name)]
+                .into_iter()
+                .collect(),
+            "Dagster job".into(),
+            AOption(ROption::RNone),
+            AOption(ROption::RNone),
+        ));
+        Ok(inputs.into_iter().collect())
+    }
+}
+impl IsolatedTask {
+    /// Like `to_python_flow_builder_inputs`, for flows written in R.
+    pub fn to_r_flow_builder_inputs<U: AoristUniverse>(
+        mut tasks: AVec<IsolatedTask>,
+        endpoints: U::TEndpoints,
+    ) -> Result<AVec<RFlowBuilderInput>, RFlowBuilderError>
+    where
+        U::TEndpoints: TPrestoEndpoints,
+    {
+        let parameters = Self::extract_parameters(&mut tasks);
+        let mut inputs = vec![RFlowBuilderInput::statements_only(
+            parameters,
+            LITERALS_BLOCK_NAME.into(),
+            AOption(ROption::RSome(LITERALS_BLOCK_TITLE.into())),
+            AOption(ROption::RNone),
+        )];
+        for task in tasks.iter() {
+            let (args, kwargs) = task.get_args_and_kwargs();
+            let flow = NativeRBasedFlow::<U>::new(
+                AST::StringLiteral(StringLiteral::new_wrapped(task.name.clone(), false)),
+                AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(task.name.clone())),
+                task.call.clone(),
+                args,
+                kwargs,
+                AOption(ROption::RNone),
+                task.preamble.clone(),
+                task.dialect.clone(),
+                endpoints.clone(),
+            );
+            inputs.push(RFlowBuilderInput::new(
+                flow.get_statements(),
+                flow.get_preamble()?.into_iter().collect(),
+                flow.get_imports().into_iter().collect::<BTreeSet<_>>(),
+                task.name.clone(),
+                AOption(ROption::RSome(task.name.clone())),
+                AOption(ROption::RNone),
+            ));
+        }
+        Ok(inputs.into_iter().collect())
+    }
+}
@@ -1,11 +1,15 @@
-use crate::flow::flow_builder::FlowBuilderBase;
+use crate::driver::ExternalDependency;
+use crate::flow::flow_builder::FlowBuilderMaterialize;
+use crate::flow::flow_builder::{get_code_cell_metadata, FlowBuilderBase};
 use crate::flow::native_python_based_flow::NativePythonBasedFlow;
 use crate::flow::python_based_flow_builder::PythonBasedFlowBuilder;
-use crate::python::{format_code, PythonImport};
+use crate::python::PythonFlowBuilderInput;
+use crate::python::PythonImport;
 use abi_stable::std_types::ROption;
+use aorist_ast::SourceRange;
 use aorist_primitives::AOption;
 use aorist_primitives::{AString, AVec, AoristUniverse, TPrestoEndpoints};
-use pyo3::PyResult;
+use pyo3::{PyErr, PyResult};
 use serde_json::json;
 use std::marker::PhantomData;
 
@@ -25,6 +29,52 @@ where
             _universe: PhantomData,
         }
     }
+    fn get_pip_requirements(&self) -> AVec<AString> {
+        vec!["ipykernel", "papermill"]
+            .into_iter()
+            .map(AString::from)
+            .collect()
+    }
+}
+impl<U: AoristUniverse> FlowBuilderMaterialize<U> for JupyterFlowBuilder<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    type BuilderInputType = PythonFlowBuilderInput;
+    type ErrorType = PyErr;
+
+    fn materialize(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        Ok(self
+            .materialize_python_flow(statements_and_preambles, AVec::new(), flow_name)?
+            .0)
+    }
+    fn materialize_with_external_dependencies(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        Ok(self
+            .materialize_python_flow(statements_and_preambles, external_dependencies, flow_name)?
+            .0)
+    }
+    fn materialize_with_source_ranges(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<(AString, Vec<SourceRange>)> {
+        self.materialize_python_flow(statements_and_preambles, external_dependencies, flow_name)
+    }
+    fn get_file_name(&self, flow_name: &AString) -> AString {
+        format!("{}.{}", flow_name, self.get_file_extension())
+            .as_str()
+            .into()
+    }
 }
 impl<U: AoristUniverse> PythonBasedFlowBuilder<U> for JupyterFlowBuilder<U>
 where
@@ -33,17 +83,20 @@ where
     fn get_flow_imports(&self) -> AVec<PythonImport> {
         AVec::new()
     }
+    fn get_file_extension(&self) -> &'static str {
+        "ipynb"
+    }
     fn build_file(
         &self,
-        sources: AVec<(AOption<AString>, AString)>,
+        sources: AVec<(AOption<(AString, AString)>, AString, Vec<SourceRange>)>,
         _flow_name: AOption<AString>,
-    ) -> PyResult<AString> {
+    ) -> PyResult<(AString, Vec<SourceRange>)> {
         let cells = json!(sources
             .into_iter()
-            .map(|(maybe_comment, block)| {
-                let format_block = format_code(block).unwrap().as_str().to_string().replace("\\n", "\n");
+            .map(|(maybe_comment, block, _)| {
+                let format_block = block.as_str().to_string().replace("\\n", "\n");
                 match maybe_comment {
-                    AOption(ROption::RSome(comment)) => vec![
+                    AOption(ROption::RSome((name, comment))) => vec![
                         json!({
                             "cell_type": "markdown",
                             "metadata": json!({}),
@@ -52,7 +105,7 @@ where
                         json!({
                             "cell_type": "code",
                             "execution_count": None as Option<usize>,
-                            "metadata": json!({}),
+                            "metadata": get_code_cell_metadata(&name),
                             "source": format_block,
                             "outputs": Vec::<String>::new(),
                         })
@@ -93,9 +146,13 @@ where
                 })
            }),
         });
-        Ok(serde_json::to_string_pretty(&notebook)
-            .unwrap()
-            .as_str()
-            .into())
+        // cells are not lines of the file, so no lines are mapped to tasks
+        Ok((
+            serde_json::to_string_pretty(&notebook)
+                .unwrap()
+                .as_str()
+                .into(),
+            Vec::new(),
+        ))
     }
 }
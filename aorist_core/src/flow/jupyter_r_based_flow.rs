@@ -0,0 +1,130 @@
+use crate::driver::ExternalDependency;
+use crate::flow::flow_builder::{get_code_cell_metadata, FlowBuilderBase, FlowBuilderMaterialize};
+use crate::flow::native_r_based_flow::NativeRBasedFlow;
+use crate::flow::r_based_flow_builder::{RBasedFlowBuilder, RFlowBuilderError};
+use crate::r::RFlowBuilderInput;
+use abi_stable::std_types::ROption;
+use aorist_primitives::AOption;
+use aorist_primitives::{AString, AVec, AoristUniverse, TPrestoEndpoints};
+use serde_json::json;
+use std::marker::PhantomData;
+
+pub struct RJupyterFlowBuilder<U: AoristUniverse>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    _universe: PhantomData<U>,
+}
+impl<U: AoristUniverse> FlowBuilderBase<U> for RJupyterFlowBuilder<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    type T = NativeRBasedFlow<U>;
+    fn new() -> Self {
+        Self {
+            _universe: PhantomData,
+        }
+    }
+    fn get_pip_requirements(&self) -> AVec<AString> {
+        vec!["papermill"].into_iter().map(AString::from).collect()
+    }
+    fn get_r_packages(&self) -> AVec<AString> {
+        vec!["IRkernel"].into_iter().map(AString::from).collect()
+    }
+}
+impl<U: AoristUniverse> FlowBuilderMaterialize<U> for RJupyterFlowBuilder<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    type BuilderInputType = RFlowBuilderInput;
+    type ErrorType = RFlowBuilderError;
+
+    fn materialize(
+        &self,
+        statements_and_preambles: AVec<RFlowBuilderInput>,
+        flow_name: AOption<AString>,
+    ) -> Result<AString, RFlowBuilderError> {
+        self.materialize_r_flow(statements_and_preambles, flow_name)
+    }
+    fn materialize_with_external_dependencies(
+        &self,
+        statements_and_preambles: AVec<RFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> Result<AString, RFlowBuilderError> {
+        self.check_external_dependencies(&external_dependencies)?;
+        self.materialize_r_flow(statements_and_preambles, flow_name)
+    }
+    fn get_file_name(&self, flow_name: &AString) -> AString {
+        format!("{}.{}", flow_name, self.get_file_extension())
+            .as_str()
+            .into()
+    }
+}
+impl<U: AoristUniverse> RBasedFlowBuilder<U> for RJupyterFlowBuilder<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    fn get_file_extension(&self) -> &'static str {
+        "ipynb"
+    }
+    fn build_file(
+        &self,
+        sources: AVec<(AOption<(AString, AString)>, AString)>,
+        _flow_name: AOption<AString>,
+    ) -> Result<AString, RFlowBuilderError> {
+        let cells = json!(sources
+            .into_iter()
+            .filter(|(_, block)| block.len() > 0)
+            .map(|(maybe_comment, block)| {
+                let metadata = match maybe_comment {
+                    AOption(ROption::RSome((ref name, _))) => get_code_cell_metadata(name),
+                    AOption(ROption::RNone) => json!({}),
+                };
+                let code_cell = json!({
+                    "cell_type": "code",
+                    "execution_count": None as Option<usize>,
+                    "metadata": metadata,
+                    "source": block.as_str().to_string(),
+                    "outputs": Vec::<String>::new(),
+                });
+                match maybe_comment {
+                    AOption(ROption::RSome((_, comment))) => vec![
+                        json!({
+                            "cell_type": "markdown",
+                            "metadata": json!({}),
+                            "source": comment.as_str().to_string().replace("# ", "").replace("#", "# "),
+                        }),
+                        code_cell,
+                    ],
+                    AOption(ROption::RNone) => vec![code_cell],
+                }
+            })
+            .flatten()
+            .collect::<AVec<_>>());
+        let notebook = json!({
+            "nbformat": 4,
+            "nbformat_minor": 5,
+            "cells": cells,
+            "metadata": json!({
+                "kernelspec": json!({
+                    "display_name": "R",
+                    "language": "R",
+                    "name": "ir"
+                }),
+                "language_info": json!({
+                    "codemirror_mode": "r",
+                    "file_extension": ".r",
+                    "mimetype": "text/x-r-source",
+                    "name": "R",
+                    "pygments_lexer": "r",
+                    "version": "4.0.3"
+                })
+           }),
+        });
+        Ok(serde_json::to_string_pretty(&notebook)
+            .unwrap()
+            .as_str()
+            .into())
+    }
+}
@@ -6,6 +6,10 @@ mod flow_builder_input;
 pub use flow_builder_input::*;
 mod flow_builder;
 pub use flow_builder::*;
+mod isolated_task;
+pub use isolated_task::*;
+mod bash_script;
+pub use bash_script::*;
 #[cfg(feature = "python")]
 mod python_based_flow_builder;
 #[cfg(feature = "python")]
@@ -23,10 +27,40 @@ mod airflow_python_based_flow;
 #[cfg(feature = "python")]
 pub use airflow_python_based_flow::*;
 #[cfg(feature = "python")]
+mod airflow2_python_based_flow;
+#[cfg(feature = "python")]
+pub use airflow2_python_based_flow::*;
+#[cfg(feature = "python")]
+mod dagster_python_based_flow;
+#[cfg(feature = "python")]
+pub use dagster_python_based_flow::*;
+#[cfg(feature = "python")]
 mod prefect_python_based_flow;
 #[cfg(feature = "python")]
 pub use prefect_python_based_flow::*;
 #[cfg(feature = "python")]
+mod prefect2_python_based_flow;
+#[cfg(feature = "python")]
+pub use prefect2_python_based_flow::*;
+#[cfg(feature = "python")]
+mod dask_python_based_flow;
+#[cfg(feature = "python")]
+pub use dask_python_based_flow::*;
+#[cfg(feature = "python")]
 mod python_based_flow;
 #[cfg(feature = "python")]
 pub use python_based_flow::*;
+#[cfg(feature = "python")]
+mod argo_workflow;
+#[cfg(feature = "python")]
+pub use argo_workflow::*;
+#[cfg(feature = "python")]
+mod file_based_flow;
+#[cfg(feature = "python")]
+pub use file_based_flow::*;
+mod r_based_flow_builder;
+pub use r_based_flow_builder::*;
+mod native_r_based_flow;
+pub use native_r_based_flow::*;
+mod jupyter_r_based_flow;
+pub use jupyter_r_based_flow::*;
@@ -1,18 +1,23 @@
 use crate::dialect::Dialect;
+use crate::driver::ExternalDependency;
 use crate::flow::etl_flow::ETLFlow;
 use crate::flow::flow_builder::FlowBuilderBase;
+use crate::flow::flow_builder::FlowBuilderMaterialize;
 use crate::flow::python_based_flow::PythonBasedFlow;
 use crate::flow::python_based_flow_builder::PythonBasedFlowBuilder;
+use crate::python::PythonFlowBuilderInput;
 use crate::python::{
     BashPythonTask, ConstantPythonTask, NativePythonTask, PrestoPythonTask, PythonImport,
     PythonPreamble, PythonTask, RPythonTask,
 };
 use abi_stable::std_types::ROption;
+use aorist_ast::SourceRange;
 use aorist_ast::{Call, SimpleIdentifier, StringLiteral, AST};
 use aorist_primitives::AOption;
 use aorist_primitives::TPrestoEndpoints;
 use aorist_primitives::{AString, AVec, AoristUniverse};
 use linked_hash_map::LinkedHashMap;
+use pyo3::{PyErr, PyResult};
 use std::hash::Hash;
 use std::marker::PhantomData;
 
@@ -196,6 +201,46 @@ where
         }
     }
 }
+impl<U: AoristUniverse> FlowBuilderMaterialize<U> for PythonFlowBuilder<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    type BuilderInputType = PythonFlowBuilderInput;
+    type ErrorType = PyErr;
+
+    fn materialize(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        Ok(self
+            .materialize_python_flow(statements_and_preambles, AVec::new(), flow_name)?
+            .0)
+    }
+    fn materialize_with_external_dependencies(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        Ok(self
+            .materialize_python_flow(statements_and_preambles, external_dependencies, flow_name)?
+            .0)
+    }
+    fn materialize_with_source_ranges(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<(AString, Vec<SourceRange>)> {
+        self.materialize_python_flow(statements_and_preambles, external_dependencies, flow_name)
+    }
+    fn get_file_name(&self, flow_name: &AString) -> AString {
+        format!("{}.{}", flow_name, self.get_file_extension())
+            .as_str()
+            .into()
+    }
+}
 impl<U: AoristUniverse> PythonBasedFlowBuilder<U> for PythonFlowBuilder<U>
 where
     U::TEndpoints: TPrestoEndpoints,
@@ -1,16 +1,17 @@
-use aorist_primitives::AOption;
-use abi_stable::std_types::ROption;
-use aorist_primitives::AVec;
-use crate::endpoints::EndpointConfig;
+use crate::dialect::Dialect;
+use crate::driver::ExternalDependency;
 use crate::flow::etl_flow::ETLFlow;
-use crate::r::{ConstantRTask, NativeRTask, RImport, RPreamble};
+use crate::flow::flow_builder::{FlowBuilderBase, FlowBuilderMaterialize};
+use crate::flow::r_based_flow_builder::{RBasedFlowBuilder, RFlowBuilderError};
+use crate::r::{ConstantRTask, NativeRTask, RFlowBuilderInput, RImport, RPreamble};
+use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
+use abi_stable::std_types::{RArc, ROption};
 use aorist_ast::{Call, Expression, Formatted, SimpleIdentifier, StringLiteral, AST};
-use aorist_core::Dialect;
-use aorist_primitives::register_task_nodes;
+use aorist_primitives::{register_task_nodes, AOption, AString, AVec, AoristUniverse};
+use aorist_primitives::{PrestoConfig, TPrestoEndpoints};
 use linked_hash_map::LinkedHashMap;
 use std::hash::{Hash, Hasher};
-use abi_stable::std_types::RArc;
-use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
+use std::marker::PhantomData;
 
 register_task_nodes! {
     RTask,
@@ -19,29 +20,92 @@ register_task_nodes! {
     NativeRTask,
 }
 
-#[derive(Clone, Hash, PartialEq, Eq)]
-pub struct NativeRBasedFlow {
+/// Preamble defining `aorist_presto`, which runs one or more queries
+/// against the Presto endpoint.
+fn get_presto_preamble(endpoint: &PrestoConfig) -> RPreamble {
+    RPreamble {
+        libraries: vec![RImport::new("DBI".into()), RImport::new("RPresto".into())]
+            .into_iter()
+            .collect(),
+        body: format!(
+            r#"aorist_presto <- function(query) {{
+    connection <- dbConnect(
+        Presto(),
+        host = "http://{host}",
+        port = {port},
+        user = "{user}",
+        catalog = "{catalog}",
+        schema = "{schema}"
+    )
+    on.exit(dbDisconnect(connection))
+    for (q in query) {{
+        dbGetQuery(connection, q)
+        message("Ran query: \n ", q)
+    }}
+}}"#,
+            host = endpoint.server,
+            user = endpoint.user,
+            port = endpoint.http_port,
+            catalog = endpoint.catalog,
+            schema = endpoint.schema
+        )
+        .as_str()
+        .into(),
+    }
+}
+
+/// Preamble defining `aorist_system`, which runs a shell command and stops
+/// if it exits with a non-zero status, as `system` itself only warns.
+fn get_bash_preamble() -> RPreamble {
+    RPreamble {
+        libraries: AVec::new(),
+        body: r#"aorist_system <- function(command) {
+    status <- system(command)
+    if (status != 0) {
+        stop("Command exited with status ", status, ": ", command)
+    }
+}"#
+        .into(),
+    }
+}
+
+fn call(name: &str, args: AVec<AST>, kwargs: LinkedHashMap<AString, AST>) -> AST {
+    AST::Call(Call::new_wrapped(
+        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(name.into())),
+        args,
+        kwargs,
+    ))
+}
+
+#[derive(Clone, Hash, PartialEq)]
+pub struct NativeRBasedFlow<U: AoristUniverse>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
     task_id: AST,
     task_val: AST,
     command: AOption<AString>,
     args: AVec<AST>,
     kwargs: LinkedHashMap<AString, AST>,
     dep_list: AOption<AST>,
-    preamble: AVec<RPreamble>,
+    preambles: AVec<RPreamble>,
     dialect: AOption<Dialect>,
-
-    endpoints: EndpointConfig,
+    endpoints: U::TEndpoints,
     node: RTask,
+    _universe: PhantomData<U>,
 }
 
-impl ETLFlow for NativeRBasedFlow {
+impl<U: AoristUniverse> ETLFlow<U> for NativeRBasedFlow<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
     type ImportType = RImport;
     type PreambleType = RPreamble;
+    type ErrorType = RFlowBuilderError;
 
-    fn get_preamble(&self) -> AVec<RPreamble> {
-        self.preamble.clone()
+    fn get_preamble(&self) -> Result<AVec<RPreamble>, RFlowBuilderError> {
+        Ok(self.preambles.clone())
     }
-
     fn get_imports(&self) -> AVec<RImport> {
         self.node.get_imports()
     }
@@ -54,112 +118,177 @@ impl ETLFlow for NativeRBasedFlow {
     fn get_statements(&self) -> AVec<AST> {
         self.node.get_statements()
     }
+    /// R programs are called directly. Python programs are called through
+    /// reticulate, after their preamble has been run in the embedded Python
+    /// session, Bash programs through `system` (failing the task on a
+    /// non-zero exit status) and Presto queries through RPresto.
     fn new(
         task_id: AST,
         task_val: AST,
-        call: AOption<AString>,
+        call_name: AOption<AString>,
         args: AVec<AST>,
         kwargs: LinkedHashMap<AString, AST>,
         dep_list: AOption<AST>,
         preamble: AOption<AString>,
         dialect: AOption<Dialect>,
-        endpoints: EndpointConfig,
+        endpoints: U::TEndpoints,
     ) -> Self {
-        let preambles = match dialect {
-            Some(Dialect::R(_)) => match preamble {
-                Some(ref p) => vec![RPreamble::new(p.clone())],
-                None => AVec::new(),
-            },
-            Some(Dialect::Python(_)) => match preamble {
-                Some(ref p) => vec![RPreamble::from_python(
-                    call.as_ref().unwrap().clone(),
-                    p.clone(),
-                )],
-                None => AVec::new(),
-            },
-            _ => AVec::new(),
-        };
+        let mut preambles = AVec::new();
+        let mut imports = AVec::new();
         let command = match &dialect {
-            Some(Dialect::R(_)) => AST::Call(Call::new_wrapped(
-                AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
-                    call.as_ref().unwrap().clone(),
-                )),
-                args.clone(),
-                kwargs.clone(),
-            )),
-            Some(Dialect::Python(_)) => {
-                let call_with_args = AST::Call(Call::new_wrapped(
-                    AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
-                        call.as_ref().unwrap().clone(),
-                    )),
+            AOption(ROption::RSome(Dialect::R(_))) => {
+                if let AOption(ROption::RSome(ref p)) = preamble {
+                    preambles.push(RPreamble::new(p.clone()));
+                }
+                call(
+                    call_name.as_ref().unwrap().as_str(),
                     args.clone(),
                     kwargs.clone(),
-                ))
-                .to_python_source();
-                let mut kwargs = LinkedHashMap::new();
-                kwargs.insert(
-                    "sep".into(),
-                    AST::StringLiteral(StringLiteral::new_wrapped("\n".into(), false)),
+                )
+            }
+            AOption(ROption::RSome(Dialect::Python(_))) => {
+                if let AOption(ROption::RSome(ref p)) = preamble {
+                    preambles.push(RPreamble::from_python(p.clone()));
+                }
+                imports.push(RImport::new("reticulate".into()));
+                let function = call(
+                    "py_eval",
+                    vec![AST::StringLiteral(StringLiteral::new_wrapped(
+                        call_name.as_ref().unwrap().clone(),
+                        false,
+                    ))]
+                    .into_iter()
+                    .collect(),
+                    LinkedHashMap::new(),
                 );
-                let code = AST::Call(Call::new_wrapped(
-                    AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("paste".into())),
-                    vec![
-                        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
-                            call.as_ref().unwrap().clone(),
+                call(
+                    "py_call",
+                    vec![function]
+                        .into_iter()
+                        .chain(args.clone().into_iter())
+                        .collect(),
+                    kwargs.clone(),
+                )
+            }
+            AOption(ROption::RSome(Dialect::Bash(_))) => {
+                preambles.push(get_bash_preamble());
+                imports.push(RImport::new("glue".into()));
+                call(
+                    "aorist_system",
+                    vec![AST::Formatted(Formatted::new_wrapped(
+                        AST::StringLiteral(StringLiteral::new_wrapped(
+                            call_name.as_ref().unwrap().clone(),
+                            false,
                         )),
-                        AST::StringLiteral(StringLiteral::new_wrapped(call_with_args, false)),
-                    ],
-                    kwargs,
-                ));
-                AST::Call(Call::new_wrapped(
-                    AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
-                        "py_run_string".into(),
-                    )),
-                    vec![code],
+                        kwargs.clone(),
+                    ))]
+                    .into_iter()
+                    .collect(),
                     LinkedHashMap::new(),
-                ))
+                )
+            }
+            AOption(ROption::RSome(Dialect::Presto(_))) => {
+                preambles.push(get_presto_preamble(&endpoints.presto_config()));
+                let sql = call_name.as_ref().unwrap().clone();
+                let query = match sql.as_str() {
+                    "{queries}" => kwargs.get(&("queries".into())).unwrap().clone(),
+                    _ => {
+                        imports.push(RImport::new("glue".into()));
+                        AST::Formatted(Formatted::new_wrapped(
+                            AST::StringLiteral(StringLiteral::new_wrapped(sql, false)),
+                            kwargs.clone(),
+                        ))
+                    }
+                };
+                call(
+                    "aorist_presto",
+                    vec![query].into_iter().collect(),
+                    LinkedHashMap::new(),
+                )
+            }
+            AOption(ROption::RNone) => {
+                AST::StringLiteral(StringLiteral::new_wrapped("Done".into(), false))
             }
-            Some(Dialect::Bash(_)) => AST::Call(Call::new_wrapped(
-                AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("system".into())),
-                vec![AST::Formatted(Formatted::new_wrapped(
-                    AST::StringLiteral(StringLiteral::new_wrapped(
-                        call.as_ref().unwrap().to_string(),
-                        false,
-                    )),
-                    kwargs.clone(),
-                ))],
-                linked_hash_map::LinkedHashMap::new(),
-            )),
-            None => AST::StringLiteral(StringLiteral::new_wrapped("Done".into(), false)),
-            _ => panic!("Dialect not supported"),
         };
         let node = match &dialect {
-            Some(Dialect::R(_)) | Some(Dialect::Python(_)) | Some(Dialect::Bash(_)) => {
-                RTask::NativeRTask(NativeRTask::new_wrapped(
-                    vec![AST::Expression(Expression::new_wrapped(command))],
-                    // TODO: add imports from preamble
-                    AVec::new(),
-                    task_val.clone(),
-                ))
+            AOption(ROption::RSome(_)) => RTask::NativeRTask(NativeRTask::new_wrapped(
+                vec![AST::Expression(Expression::new_wrapped(command))]
+                    .into_iter()
+                    .collect(),
+                imports,
+                task_val.clone(),
+            )),
+            AOption(ROption::RNone) => {
+                RTask::ConstantRTask(ConstantRTask::new_wrapped(command, task_val.clone()))
             }
-            None => RTask::ConstantRTask(ConstantRTask::new_wrapped(command, task_val.clone())),
-            _ => panic!("Dialect not supported"),
         };
 
         Self {
             task_id,
             task_val,
-            command: call,
+            command: call_name,
             args,
             kwargs,
             dep_list,
-            preamble: preambles,
-            dialect: dialect.clone(),
+            preambles,
+            dialect,
             endpoints,
             node,
+            _universe: PhantomData,
         }
     }
     fn get_type() -> String {
         "r".into()
     }
 }
+
+pub struct RFlowBuilder<U: AoristUniverse>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    _universe: PhantomData<U>,
+}
+impl<U: AoristUniverse> FlowBuilderBase<U> for RFlowBuilder<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    type T = NativeRBasedFlow<U>;
+    fn new() -> Self {
+        Self {
+            _universe: PhantomData,
+        }
+    }
+}
+impl<U: AoristUniverse> FlowBuilderMaterialize<U> for RFlowBuilder<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    type BuilderInputType = RFlowBuilderInput;
+    type ErrorType = RFlowBuilderError;
+
+    fn materialize(
+        &self,
+        statements_and_preambles: AVec<RFlowBuilderInput>,
+        flow_name: AOption<AString>,
+    ) -> Result<AString, RFlowBuilderError> {
+        self.materialize_r_flow(statements_and_preambles, flow_name)
+    }
+    fn materialize_with_external_dependencies(
+        &self,
+        statements_and_preambles: AVec<RFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> Result<AString, RFlowBuilderError> {
+        self.check_external_dependencies(&external_dependencies)?;
+        self.materialize_r_flow(statements_and_preambles, flow_name)
+    }
+    fn get_file_name(&self, flow_name: &AString) -> AString {
+        format!("{}.{}", flow_name, self.get_file_extension())
+            .as_str()
+            .into()
+    }
+}
+impl<U: AoristUniverse> RBasedFlowBuilder<U> for RFlowBuilder<U> where
+    U::TEndpoints: TPrestoEndpoints
+{
+}
@@ -0,0 +1,708 @@
+use crate::dialect::Dialect;
+use crate::driver::ExternalDependency;
+use crate::flow::etl_flow::ETLFlow;
+use crate::flow::flow_builder::FlowBuilderBase;
+use crate::flow::flow_builder::FlowBuilderMaterialize;
+use crate::flow::flow_builder::LITERALS_BLOCK_NAME;
+use crate::flow::flow_builder_input::FlowBuilderInput;
+use crate::flow::python_based_flow::PythonBasedFlow;
+use crate::flow::python_based_flow_builder::PythonBasedFlowBuilder;
+use crate::python::{
+    BashPythonTask, ConstantPythonTask, NativePythonPreamble, NativePythonTask, PrestoPythonTask,
+    PythonFlowBuilderInput, PythonImport, PythonPreamble, PythonTask, RPythonTask,
+};
+use abi_stable::std_types::ROption;
+use aorist_ast::SourceRange;
+use aorist_ast::{
+    Assignment, Attribute, BooleanLiteral, Call, Expression, Formatted, FunctionDef,
+    SimpleIdentifier, StringLiteral, AST,
+};
+use aorist_primitives::AOption;
+use aorist_primitives::TPrestoEndpoints;
+use aorist_primitives::{AString, AVec, AoristUniverse};
+use linked_hash_map::LinkedHashMap;
+use linked_hash_set::LinkedHashSet;
+use pyo3::{PyErr, PyResult};
+use std::collections::BTreeSet;
+use std::marker::PhantomData;
+
+/// Submits a callable as a named Prefect 2 task. Dependencies are passed as
+/// `wait_for`, which may be a single future or a list of them (compressed
+/// tasks look theirs up in a dictionary, where either can occur).
+const PREFECT2_HELPERS: &str = r#"
+from prefect import Task, task
+
+
+def aorist_task(task_name, task_callable, *args, wait_for=None, **kwargs):
+    if not isinstance(task_callable, Task):
+        task_callable = task(task_callable)
+    if wait_for is None:
+        wait_for = []
+    elif not isinstance(wait_for, (list, tuple)):
+        wait_for = [wait_for]
+    return task_callable.with_options(name=task_name).submit(
+        *args, wait_for=list(wait_for), **kwargs
+    )
+"#;
+
+/// Prefect 2 counterpart of `PrefectPythonBasedFlow`: every task is submitted
+/// from within a single `@flow` function, waiting for the futures of the
+/// tasks it depends on. Bash tasks run through the `shell_run_command` task
+/// of prefect-shell.
+#[derive(Clone, Hash, PartialEq)]
+pub struct Prefect2PythonBasedFlow<U: AoristUniverse>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    task_id: AST,
+    task_val: AST,
+    command: AOption<AString>,
+    args: AVec<AST>,
+    kwargs: LinkedHashMap<AString, AST>,
+    dep_list: AOption<AST>,
+    preamble: AOption<AString>,
+    dialect: AOption<Dialect>,
+    endpoints: U::TEndpoints,
+    node: PythonTask,
+    _universe: PhantomData<U>,
+}
+impl<U: AoristUniverse> PythonBasedFlow<U> for Prefect2PythonBasedFlow<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    fn get_preamble_string(&self) -> AOption<AString> {
+        self.preamble.clone()
+    }
+}
+impl<U: AoristUniverse> Prefect2PythonBasedFlow<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    /// Builds the `aorist_task` call: the task name and the callable come
+    /// first, followed by the callable's own arguments.
+    fn compute_task_call(&self) -> AST {
+        let task_id = self.task_id.clone();
+        let (args, mut kwargs) = match self.dialect {
+            AOption(ROption::RSome(Dialect::Bash(_))) => {
+                let mut kwargs = LinkedHashMap::new();
+                kwargs.insert(
+                    "command".into(),
+                    AST::Formatted(Formatted::new_wrapped(
+                        AST::StringLiteral(StringLiteral::new_wrapped(
+                            self.command.as_ref().unwrap().clone(),
+                            false,
+                        )),
+                        self.kwargs.clone(),
+                    )),
+                );
+                kwargs.insert(
+                    "return_all".into(),
+                    AST::BooleanLiteral(BooleanLiteral::new_wrapped(true)),
+                );
+                (
+                    vec![
+                        task_id,
+                        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
+                            "shell_run_command".into(),
+                        )),
+                    ],
+                    kwargs,
+                )
+            }
+            _ => match self.node.get_call().unwrap() {
+                AST::Call(call_rw) => {
+                    let call = call_rw.read();
+                    (
+                        vec![task_id, call.function()]
+                            .into_iter()
+                            .chain(call.args().into_iter())
+                            .collect::<Vec<_>>(),
+                        call.keywords(),
+                    )
+                }
+                _ => panic!("AST object should be call"),
+            },
+        };
+        if let AOption(ROption::RSome(ref dependencies)) = self.dep_list {
+            kwargs.insert("wait_for".into(), dependencies.clone());
+        }
+        AST::Call(Call::new_wrapped(
+            AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("aorist_task".into())),
+            args.into_iter().collect(),
+            kwargs,
+        ))
+    }
+}
+impl<U: AoristUniverse> ETLFlow<U> for Prefect2PythonBasedFlow<U>
+where
+    U::TEndpoints: TPrestoEndpoints,
+{
+    type ImportType = PythonImport;
+    type PreambleType = PythonPreamble;
+    type ErrorType = pyo3::PyErr;
+    fn get_imports(&self) -> AVec<PythonImport> {
+        match self.dialect {
+            AOption(ROption::RSome(Dialect::Bash(_))) => vec![PythonImport::PythonFromImport(
+                "prefect_shell".into(),
+                "shell_run_command".into(),
+                AOption(ROption::RNone),
+            )]
+            .into_iter()
+            .collect(),
+            // the helpers' preamble brings in everything else
+            _ => AVec::new(),
+        }
+    }
+    fn get_preamble(&self) -> Result<AVec<PythonPreamble>, pyo3::PyErr> {
+        let mut preambles = vec![PythonPreamble::NativePythonPreamble(
+            NativePythonPreamble::new(PREFECT2_HELPERS.into())?,
+        )];
+        if let AOption(ROption::RSome(Dialect::Python(_))) = self.dialect {
+            if let AOption(ROption::RSome(ref p)) = self.preamble {
+                preambles.push(PythonPreamble::NativePythonPreamble(
+                    NativePythonPreamble::new(p.clone())?,
+                ));
+            }
+        }
+        if let AOption(ROption::RSome(p)) = self.node.get_preamble() {
+            preambles.push(p)
+        }
+        Ok(preambles.into_iter().collect())
+    }
+    fn get_dialect(&self) -> AOption<Dialect> {
+        self.dialect.clone()
+    }
+    fn get_task_val(&self) -> AST {
+        self.task_val.clone()
+    }
+    fn get_statements(&self) -> AVec<AST> {
+        vec![AST::Assignment(Assignment::new_wrapped(
+            self.task_val.clone(),
+            self.compute_task_call(),
+        ))]
+        .into_iter()
+        .collect()
+    }
+    fn new(
+        task_id: AST,
+        task_val: AST,
+        call: AOption<AString>,
+        args: AVec<AST>,
+        kwargs: LinkedHashMap<AString, AST>,
+        dep_list: AOption<AST>,
+        preamble: AOption<AString>,
+        dialect: AOption<Dialect>,
+        endpoints: U::TEndpoints,
+    ) -> Self {
+        let command = match &dialect {
+            AOption(ROption::RSome(Dialect::Presto(_))) => AST::StringLiteral(
+                StringLiteral::new_wrapped(call.as_ref().unwrap().clone(), true),
+            ),
+            AOption(ROption::RSome(_)) => AST::StringLiteral(StringLiteral::new_wrapped(
+                call.as_ref().unwrap().clone(),
+                false,
+            )),
+            AOption(ROption::RNone) => {
+                AST::StringLiteral(StringLiteral::new_wrapped("Done".into(), false))
+            }
+        };
+        let node = match &dialect {
+            AOption(ROption::RSome(Dialect::Presto(_))) => {
+                let presto_endpoints = endpoints.presto_config();
+                PythonTask::PrestoPythonTask(PrestoPythonTask::new_wrapped(
+                    command,
+                    kwargs
+                        .iter()
+                        .map(|(k, v)| {
+                            (
+                                k.clone(),
+                                match *v {
+                                    AST::StringLiteral(ref x) => AST::StringLiteral(
+                                        StringLiteral::new_wrapped(x.read().value().clone(), true),
+                                    ),
+                                    _ => v.clone(),
+                                },
+                            )
+                        })
+                        .collect(),
+                    task_val.clone(),
+                    presto_endpoints,
+                    dep_list.clone(),
+                ))
+            }
+            AOption(ROption::RSome(Dialect::Bash(_))) => {
+                PythonTask::BashPythonTask(BashPythonTask::new_wrapped(
+                    command,
+                    kwargs.clone(),
+                    task_val.clone(),
+                    dep_list.clone(),
+                ))
+            }
+            AOption(ROption::RSome(Dialect::R(_))) => {
+                PythonTask::RPythonTask(RPythonTask::new_wrapped(
+                    task_val.clone(),
+                    command,
+                    args.clone(),
+                    kwargs.clone(),
+                    dep_list.clone(),
+                    preamble.clone(),
+                ))
+            }
+            AOption(ROption::RSome(Dialect::Python(_))) => {
+                PythonTask::NativePythonTask(NativePythonTask::new_wrapped(
+                    AST::Call(Call::new_wrapped(
+                        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
+                            call.as_ref().unwrap().clone(),
+                        )),
+                        args.clone(),
+                        kwargs.clone(),
+                    )),
+                    // TODO: add imports from preamble
+                    AVec::new(),
+                    task_val.clone(),
+                    dep_list.clone(),
+                ))
+            }
+            AOption(ROption::RNone) => PythonTask::ConstantPythonTask(
+                ConstantPythonTask::new_wrapped(command, task_val.clone(), dep_list.clone()),
+            ),
+        };
+        Self {
+            task_id,
+            task_val,
+            command: call,
+            args,
+            kwargs,
+            dep_list,
+            preamble,
+            dialect,
+            endpoints,
+            node,
+            _universe: PhantomData,
+        }
+    }
+    fn get_type() -> String {
+        "prefect2".into()
+    }
+}
+
+pub struct Prefect2FlowBuilder<U: AoristUniverse> {
+    universe: PhantomData<U>,
+}
+impl<U: AoristUniverse> FlowBuilderBase<U> for Prefect2FlowBuilder<U>
+where
+    <U as AoristUniverse>::TEndpoints: TPrestoEndpoints,
+{
+    type T = Prefect2PythonBasedFlow<U>;
+    fn new() -> Self {
+        Self {
+            universe: PhantomData,
+        }
+    }
+    fn get_pip_requirements(&self) -> AVec<AString> {
+        vec!["prefect>=2"].into_iter().map(AString::from).collect()
+    }
+    /// A flow waits for a whole upstream flow, whichever tasks it needs.
+    fn get_external_dependency_name(&self, group: &AString, _task_id: &AString) -> AString {
+        format!("wait_for_{}", group).as_str().into()
+    }
+}
+impl<U: AoristUniverse> FlowBuilderMaterialize<U> for Prefect2FlowBuilder<U>
+where
+    <U as AoristUniverse>::TEndpoints: TPrestoEndpoints,
+{
+    type BuilderInputType = PythonFlowBuilderInput;
+    type ErrorType = PyErr;
+
+    fn materialize(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        Ok(self
+            .materialize_python_flow(statements_and_preambles, AVec::new(), flow_name)?
+            .0)
+    }
+    fn materialize_with_external_dependencies(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        Ok(self
+            .materialize_python_flow(statements_and_preambles, external_dependencies, flow_name)?
+            .0)
+    }
+    fn materialize_with_source_ranges(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<(AString, Vec<SourceRange>)> {
+        self.materialize_python_flow(statements_and_preambles, external_dependencies, flow_name)
+    }
+    fn get_file_name(&self, flow_name: &AString) -> AString {
+        format!("{}.{}", flow_name, self.get_file_extension())
+            .as_str()
+            .into()
+    }
+}
+impl<U: AoristUniverse> PythonBasedFlowBuilder<U> for Prefect2FlowBuilder<U>
+where
+    <U as AoristUniverse>::TEndpoints: TPrestoEndpoints,
+{
+    /// Moves all tasks into the body of an `@flow` function, leaving the
+    /// common string literals at module level, and calls the flow.
+    fn augment_statements(
+        &self,
+        statements: AVec<PythonFlowBuilderInput>,
+        flow_name: AOption<AString>,
+    ) -> AVec<PythonFlowBuilderInput> {
+        let mut flow_kwargs: LinkedHashMap<AString, AST> = LinkedHashMap::new();
+        flow_kwargs.insert(
+            "name".into(),
+            AST::StringLiteral(StringLiteral::new_wrapped(
+                match flow_name {
+                    AOption(ROption::RSome(x)) => x,
+                    AOption(ROption::RNone) => "flow".into(),
+                },
+                false,
+            )),
+        );
+        let flow_decorator = AST::Call(Call::new_wrapped(
+            AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("flow".into())),
+            AVec::new(),
+            flow_kwargs,
+        ));
+        let (literals, tasks): (Vec<_>, Vec<_>) = statements
+            .into_iter()
+            .partition(|x| x.get_constraint_name().as_str() == LITERALS_BLOCK_NAME);
+        let body = tasks
+            .into_iter()
+            .map(|x| x.get_statements().into_iter())
+            .flatten()
+            .collect();
+        let function_name: AString = "aorist_flow".into();
+        let function_def = AST::FunctionDef(FunctionDef::new_wrapped(
+            function_name.clone(),
+            AVec::new(),
+            LinkedHashMap::new(),
+            body,
+            vec![flow_decorator].into_iter().collect(),
+        ));
+        let flow_call = AST::Expression(Expression::new_wrapped(AST::Call(Call::new_wrapped(
+            AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(function_name)),
+            AVec::new(),
+            LinkedHashMap::new(),
+        ))));
+        literals
+            .into_iter()
+            .chain(
+                vec![
+                    PythonFlowBuilderInput::statements_only(
+                        vec![function_def].into_iter().collect(),
+                        "Prefect flow".into(),
+                        AOption(ROption::RNone),
+                        AOption(ROption::RNone),
+                    ),
+                    PythonFlowBuilderInput::statements_only(
+                        vec![flow_call].into_iter().collect(),
+                        "Run Prefect flow".into(),
+                        AOption(ROption::RNone),
+                        AOption(ROption::RNone),
+                    ),
+                ]
+                .into_iter(),
+            )
+            .collect()
+    }
+    fn get_flow_imports(&self) -> AVec<PythonImport> {
+        vec![PythonImport::PythonFromImport(
+            "prefect".into(),
+            "flow".into(),
+            AOption(ROption::RNone),
+        )]
+        .into_iter()
+        .collect()
+    }
+    /// Runs each upstream flow's deployment as a task and waits for it. The
+    /// deployments are expected to be named after their flows.
+    fn get_external_dependency_statements(
+        &self,
+        external_dependencies: &AVec<ExternalDependency>,
+    ) -> Option<PythonFlowBuilderInput> {
+        let statements = external_dependencies
+            .iter()
+            .map(|dep| {
+                let mut task_kwargs: LinkedHashMap<AString, AST> = LinkedHashMap::new();
+                task_kwargs.insert(
+                    "name".into(),
+                    AST::StringLiteral(StringLiteral::new_wrapped(dep.identifier.clone(), false)),
+                );
+                let task = AST::Call(Call::new_wrapped(
+                    AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("task".into())),
+                    vec![AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
+                        "run_deployment".into(),
+                    ))]
+                    .into_iter()
+                    .collect(),
+                    task_kwargs,
+                ));
+                let mut submit_kwargs: LinkedHashMap<AString, AST> = LinkedHashMap::new();
+                submit_kwargs.insert(
+                    "name".into(),
+                    AST::StringLiteral(StringLiteral::new_wrapped(
+                        format!("{}/{}", dep.flow_name, dep.flow_name)
+                            .as_str()
+                            .into(),
+                        false,
+                    )),
+                );
+                AST::Assignment(Assignment::new_wrapped(
+                    AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(dep.identifier.clone())),
+                    AST::Call(Call::new_wrapped(
+                        AST::Attribute(Attribute::new_wrapped(task, "submit".into(), false)),
+                        AVec::new(),
+                        submit_kwargs,
+                    )),
+                ))
+            })
+            .collect();
+        let imports = vec![
+            PythonImport::PythonFromImport(
+                "prefect".into(),
+                "task".into(),
+                AOption(ROption::RNone),
+            ),
+            PythonImport::PythonFromImport(
+                "prefect.deployments".into(),
+                "run_deployment".into(),
+                AOption(ROption::RNone),
+            ),
+        ]
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+        Some(PythonFlowBuilderInput::new(
+            statements,
+            LinkedHashSet::new(),
+            imports,
+            "external_dependencies".into(),
+            AOption(ROption::RSome("Waiting for other flows".into())),
+            AOption(ROption::RNone),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialect::{Bash, Python};
+    use crate::endpoints::EndpointConfig;
+    use crate::universe::Universe;
+    use aorist_ast::List;
+    use aorist_primitives::AoristRef;
+
+    type Flow = Prefect2PythonBasedFlow<AoristRef<Universe>>;
+
+    fn endpoints() -> EndpointConfig {
+        EndpointConfig {
+            presto: AOption(ROption::RNone),
+            alluxio: AOption(ROption::RNone),
+            ranger: AOption(ROption::RNone),
+            gitea: AOption(ROption::RNone),
+            minio: AOption(ROption::RNone),
+            postgres: AOption(ROption::RNone),
+            gcp: AOption(ROption::RNone),
+            aws: AOption(ROption::RNone),
+            pdal: AOption(ROption::RNone),
+            linz: AOption(ROption::RNone),
+            dask: AOption(ROption::RNone),
+            gdal: AOption(ROption::RNone),
+            tag: AOption(ROption::RNone),
+            uuid: AOption(ROption::RNone),
+        }
+    }
+    fn identifier(name: &str) -> AST {
+        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(name.into()))
+    }
+    fn flow(name: &str, dialect: Dialect, call: &str, dependencies: &[&str]) -> Flow {
+        let mut kwargs = LinkedHashMap::new();
+        kwargs.insert(
+            "path".into(),
+            AST::StringLiteral(StringLiteral::new_wrapped("/tmp/data.csv".into(), false)),
+        );
+        let preamble = match dialect {
+            Dialect::Python(_) => AOption(ROption::RSome(
+                format!("def {}(path):\n    return open(path).read()\n", call)
+                    .as_str()
+                    .into(),
+            )),
+            _ => AOption(ROption::RNone),
+        };
+        Flow::new(
+            AST::StringLiteral(StringLiteral::new_wrapped(name.into(), false)),
+            identifier(name),
+            AOption(ROption::RSome(call.into())),
+            AVec::new(),
+            kwargs,
+            match dependencies.len() {
+                0 => AOption(ROption::RNone),
+                _ => AOption(ROption::RSome(AST::List(List::new_wrapped(
+                    dependencies.iter().map(|x| identifier(x)).collect(),
+                    false,
+                )))),
+            },
+            preamble,
+            AOption(ROption::RSome(dialect)),
+            endpoints(),
+        )
+    }
+    /// One constraint block per group of flows, as rendered by the driver.
+    fn block(name: &str, title: &str, flows: Vec<Flow>) -> PythonFlowBuilderInput {
+        let mut statements = Vec::new();
+        let mut preambles = LinkedHashSet::new();
+        let mut imports = BTreeSet::new();
+        for flow in flows {
+            statements.extend(flow.get_statements());
+            preambles.extend(flow.get_preamble().unwrap());
+            imports.extend(flow.get_imports());
+        }
+        PythonFlowBuilderInput::new(
+            statements.into_iter().collect(),
+            preambles,
+            imports,
+            name.into(),
+            AOption(ROption::RSome(title.into())),
+            AOption(ROption::RNone),
+        )
+    }
+
+    /// A Bash download, then two Python programs reading the downloaded
+    /// file, one after the other, in another block.
+    fn blocks() -> AVec<PythonFlowBuilderInput> {
+        vec![
+            block(
+                "download_data",
+                "Download data",
+                vec![flow(
+                    "download_papers",
+                    Dialect::Bash(Bash::new()),
+                    "curl -o {path} https://example.com/data.csv",
+                    &[],
+                )],
+            ),
+            block(
+                "describe_data",
+                "Describe data",
+                vec![
+                    flow(
+                        "load_papers",
+                        Dialect::Python(Python::new(vec![])),
+                        "load_data",
+                        &["download_papers"],
+                    ),
+                    flow(
+                        "describe_papers",
+                        Dialect::Python(Python::new(vec![])),
+                        "describe_data",
+                        &["load_papers"],
+                    ),
+                ],
+            ),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn test_prefect2_flow() {
+        let flow = Prefect2FlowBuilder::<AoristRef<Universe>>::new()
+            .materialize(blocks(), AOption(ROption::RSome("flow".into())))
+            .unwrap();
+        let expected = r#"from prefect import Task
+from prefect import flow
+from prefect import task
+from prefect_shell import shell_run_command
+
+
+def load_data(path):
+    return open(path).read()
+
+
+def aorist_task(task_name, task_callable, *args, wait_for=None, **kwargs):
+    if not isinstance(task_callable, Task):
+        task_callable = task(task_callable)
+    if wait_for is None:
+        wait_for = []
+    elif not isinstance(wait_for, (list, tuple)):
+        wait_for = [wait_for]
+    return task_callable.with_options(name=task_name).submit(
+        *args, wait_for=list(wait_for), **kwargs
+    )
+
+
+def describe_data(path):
+    return open(path).read()
+
+
+# ## Prefect flow
+@flow(name="flow")
+def aorist_flow():
+    download_papers = aorist_task(
+        "download_papers",
+        shell_run_command,
+        command="curl -o {path} https://example.com/data.csv".format(
+            path="/tmp/data.csv"
+        ),
+        return_all=True,
+    )
+    load_papers = aorist_task(
+        "load_papers",
+        load_data,
+        path="/tmp/data.csv",
+        wait_for=[download_papers],
+    )
+    describe_papers = aorist_task(
+        "describe_papers",
+        describe_data,
+        path="/tmp/data.csv",
+        wait_for=[load_papers],
+    )
+
+
+# ## Run Prefect flow
+aorist_flow()
+"#;
+        assert_eq!(flow.as_str(), expected);
+    }
+    #[test]
+    fn test_prefect2_external_dependencies() {
+        let flow = Prefect2FlowBuilder::<AoristRef<Universe>>::new()
+            .materialize_with_external_dependencies(
+                blocks(),
+                vec![ExternalDependency {
+                    group: "raw".into(),
+                    flow_name: "flow_raw".into(),
+                    task_id: "download_raw".into(),
+                    identifier: "wait_for_raw".into(),
+                }]
+                .into_iter()
+                .collect(),
+                AOption(ROption::RSome("flow_curated".into())),
+            )
+            .unwrap();
+        assert!(flow
+            .as_str()
+            .contains("from prefect.deployments import run_deployment\n"));
+        // the upstream flow's deployment runs first within the flow
+        assert!(flow.as_str().contains(
+            r#"@flow(name="flow_curated")
+def aorist_flow():
+    wait_for_raw = task(run_deployment, name="wait_for_raw").submit(
+        name="flow_raw/flow_raw"
+    )
+    download_papers = aorist_task(
+"#
+        ));
+    }
+}
@@ -1,7 +1,10 @@
 use crate::dialect::Dialect;
+use crate::driver::ExternalDependency;
 use crate::error::AoristError;
 use crate::flow::etl_flow::ETLFlow;
 use crate::flow::flow_builder::FlowBuilderBase;
+use crate::flow::flow_builder::FlowBuilderMaterialize;
+use crate::flow::flow_builder_input::FlowBuilderInput;
 use crate::flow::python_based_flow::PythonBasedFlow;
 use crate::flow::python_based_flow_builder::PythonBasedFlowBuilder;
 use crate::python::{
@@ -10,15 +13,19 @@ use crate::python::{
 use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
 use abi_stable::std_types::RArc;
 use abi_stable::std_types::ROption;
+use aorist_ast::SourceRange;
 use aorist_ast::{
-    Assignment, Attribute, Call, Expression, ForLoop, Formatted, SimpleIdentifier, StringLiteral,
-    AST,
+    Assignment, Attribute, BooleanLiteral, Call, Expression, ForLoop, Formatted, SimpleIdentifier,
+    StringLiteral, AST,
 };
 use aorist_primitives::register_task_nodes;
 use aorist_primitives::AOption;
 use aorist_primitives::TPrestoEndpoints;
 use aorist_primitives::{AString, AVec, AoristUniverse};
 use linked_hash_map::LinkedHashMap;
+use linked_hash_set::LinkedHashSet;
+use pyo3::{PyErr, PyResult};
+use std::collections::BTreeSet;
 use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
 
@@ -270,11 +277,109 @@ impl<U: AoristUniverse> FlowBuilderBase<U> for PrefectFlowBuilder<U> {
             universe: PhantomData,
         }
     }
+    fn get_pip_requirements(&self) -> AVec<AString> {
+        vec!["prefect<2"].into_iter().map(AString::from).collect()
+    }
+    /// A flow waits for a whole upstream flow, whichever tasks it needs.
+    fn get_external_dependency_name(&self, group: &AString, _task_id: &AString) -> AString {
+        format!("wait_for_{}", group).as_str().into()
+    }
+}
+impl<U: AoristUniverse> FlowBuilderMaterialize<U> for PrefectFlowBuilder<U> {
+    type BuilderInputType = PythonFlowBuilderInput;
+    type ErrorType = PyErr;
+
+    fn materialize(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        Ok(self
+            .materialize_python_flow(statements_and_preambles, AVec::new(), flow_name)?
+            .0)
+    }
+    fn materialize_with_external_dependencies(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<AString> {
+        Ok(self
+            .materialize_python_flow(statements_and_preambles, external_dependencies, flow_name)?
+            .0)
+    }
+    fn materialize_with_source_ranges(
+        &self,
+        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
+        flow_name: AOption<AString>,
+    ) -> PyResult<(AString, Vec<SourceRange>)> {
+        self.materialize_python_flow(statements_and_preambles, external_dependencies, flow_name)
+    }
+    fn get_file_name(&self, flow_name: &AString) -> AString {
+        format!("{}.{}", flow_name, self.get_file_extension())
+            .as_str()
+            .into()
+    }
 }
 impl<U: AoristUniverse> PythonBasedFlowBuilder<U> for PrefectFlowBuilder<U> {
     fn get_flow_imports(&self) -> AVec<PythonImport> {
         AVec::new()
     }
+    /// Runs each upstream flow with a StartFlowRun task and waits for it.
+    fn get_external_dependency_statements(
+        &self,
+        external_dependencies: &AVec<ExternalDependency>,
+    ) -> Option<PythonFlowBuilderInput> {
+        let mut statements = AVec::new();
+        for dep in external_dependencies.iter() {
+            let identifier =
+                AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(dep.identifier.clone()));
+            let mut kwargs: LinkedHashMap<AString, AST> = LinkedHashMap::new();
+            kwargs.insert(
+                "flow_name".into(),
+                AST::StringLiteral(StringLiteral::new_wrapped(dep.flow_name.clone(), false)),
+            );
+            kwargs.insert(
+                "wait".into(),
+                AST::BooleanLiteral(BooleanLiteral::new_wrapped(true)),
+            );
+            statements.push(AST::Assignment(Assignment::new_wrapped(
+                identifier.clone(),
+                AST::Call(Call::new_wrapped(
+                    AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("StartFlowRun".into())),
+                    AVec::new(),
+                    kwargs,
+                )),
+            )));
+            statements.push(AST::Expression(Expression::new_wrapped(AST::Call(
+                Call::new_wrapped(
+                    AST::Attribute(Attribute::new_wrapped(
+                        self.flow_identifier.clone(),
+                        "add_node".into(),
+                        false,
+                    )),
+                    vec![identifier].into_iter().collect(),
+                    LinkedHashMap::new(),
+                ),
+            ))));
+        }
+        let imports = vec![PythonImport::PythonFromImport(
+            "prefect.tasks.prefect".into(),
+            "StartFlowRun".into(),
+            AOption(ROption::RNone),
+        )]
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+        Some(PythonFlowBuilderInput::new(
+            statements,
+            LinkedHashSet::new(),
+            imports,
+            "external_dependencies".into(),
+            AOption(ROption::RSome("Waiting for other flows".into())),
+            AOption(ROption::RNone),
+        ))
+    }
     /// Takes a set of statements and mutates them so as make a valid ETL flow
     fn augment_statements(
         &self,
@@ -1,39 +1,47 @@
+use crate::driver::ExternalDependency;
 use crate::flow::etl_flow::ETLFlow;
-use crate::flow::flow_builder::{FlowBuilderBase, FlowBuilderMaterialize};
+use crate::flow::flow_builder::{
+    FlowBuilderBase, FlowBuilderMaterialize, LITERALS_BLOCK_NAME, LITERALS_BLOCK_TITLE,
+};
 use crate::flow::flow_builder_input::FlowBuilderInput;
-use crate::python::{format_code, PythonFlowBuilderInput, PythonImport, PythonPreamble};
+use crate::python::{PythonFlowBuilderInput, PythonImport, PythonPreamble};
 use abi_stable::std_types::ROption;
-use aorist_ast::AST;
+use aorist_ast::{join_python_blocks_with_ranges, SourceRange, AST};
 use aorist_primitives::AOption;
 use aorist_primitives::{AString, AVec, AoristUniverse};
 use linked_hash_map::LinkedHashMap;
 use linked_hash_set::LinkedHashSet;
 use pyo3::prelude::*;
-use pyo3::types::{PyModule, PyString};
 use std::collections::BTreeSet;
 
-impl<C, U> FlowBuilderMaterialize<U> for C
+/// Encapsulates all the necessary bits for the construction of a Flow written in
+/// Python.
+pub trait PythonBasedFlowBuilder<U>:
+    FlowBuilderMaterialize<U, BuilderInputType = PythonFlowBuilderInput, ErrorType = PyErr>
 where
     Self: Sized,
-    C: PythonBasedFlowBuilder<U>,
-    <C as FlowBuilderBase<U>>::T:
-        ETLFlow<U, ImportType = PythonImport, PreambleType = PythonPreamble>,
     U: AoristUniverse,
+    <Self as FlowBuilderBase<U>>::T:
+        ETLFlow<U, ImportType = PythonImport, PreambleType = PythonPreamble>,
 {
-    type BuilderInputType = PythonFlowBuilderInput;
-    type ErrorType = PyErr;
-
-    fn materialize(
+    /// Lays out the imports, preambles and statements of the flow, along
+    /// with the lines holding the statements of each task.
+    fn materialize_python_flow(
         &self,
-        statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        mut statements_and_preambles: AVec<PythonFlowBuilderInput>,
+        external_dependencies: AVec<ExternalDependency>,
         flow_name: AOption<AString>,
-    ) -> Result<AString, Self::ErrorType> {
-        let gil = Python::acquire_gil();
-        let py = gil.python();
-
-        let ast = PyModule::import(py, "ast").unwrap();
-        let astor = PyModule::import(py, "astor").unwrap();
-
+    ) -> PyResult<(AString, Vec<SourceRange>)> {
+        if external_dependencies.len() > 0 {
+            match self.get_external_dependency_statements(&external_dependencies) {
+                Some(statements) => statements_and_preambles.insert(0, statements),
+                None => {
+                    return Err(pyo3::exceptions::PyValueError::new_err(
+                        "This flow type cannot wait for tasks in other flows.",
+                    ))
+                }
+            }
+        }
         let flow_imports = self.get_flow_imports().into_iter();
 
         let preambles: LinkedHashSet<PythonPreamble> = statements_and_preambles
@@ -52,11 +60,6 @@ where
             .chain(preamble_imports)
             .collect::<BTreeSet<_>>();
 
-        let imports_ast: AVec<_> = imports
-            .into_iter()
-            .map(|x| x.to_python_ast_node(py, ast, 0).unwrap())
-            .collect();
-
         let mut statements_with_ast: AVec<_> = statements_and_preambles
             .into_iter()
             .filter(|x| x.has_statements())
@@ -78,8 +81,8 @@ where
                     assignments_ast,
                     LinkedHashSet::new(),
                     BTreeSet::new(),
-                    "assignments".into(),
-                    AOption(ROption::RSome("Common string literals".into())),
+                    LITERALS_BLOCK_NAME.into(),
+                    AOption(ROption::RSome(LITERALS_BLOCK_TITLE.into())),
                     AOption(ROption::RNone),
                 ),
             );
@@ -89,87 +92,41 @@ where
             .augment_statements(statements_with_ast, flow_name.clone())
             .into_iter()
             .collect();
-        let content: Vec<(AOption<AString>, Vec<&PyAny>)> = vec![(
-            AOption(ROption::RNone),
-            imports_ast.into_iter().collect::<Vec<_>>(),
-        )]
-        .into_iter()
-        .chain(
-            preambles
-                .into_iter()
-                .map(|x| {
-                    (
-                        AOption(ROption::RNone),
-                        x.to_python_ast_nodes(py, ast, 0)
-                            .into_iter()
-                            .collect::<Vec<_>>(),
-                    )
-                })
-                .collect::<Vec<_>>()
-                .into_iter(),
-        )
-        .chain(
-            augmented_statements
-                .into_iter()
-                .map(|x| {
-                    (
-                        AOption(ROption::RSome(x.get_block_comment())),
-                        x.to_python_ast_nodes(py, ast, 0)
-                            .unwrap()
-                            .into_iter()
-                            .collect::<Vec<_>>(),
-                    )
-                })
-                .collect::<Vec<_>>()
-                .into_iter(),
-        )
-        .collect();
-
-        let mut sources: AVec<(AOption<AString>, AString)> = AVec::new();
 
-        // This is needed since astor will occasionally forget to add a newline
-        for (comment, block) in content {
-            let mut lines: AVec<AString> = AVec::new();
-            for item in block {
-                let module = ast.getattr("Expression")?.call1((item,))?;
-                let source: PyResult<_> = astor.getattr("to_source")?.call1((module,));
-                if let Err(err) = source {
-                    err.print(py);
-                    panic!("Exception occurred when running to_source.",);
-                }
-                let out = source
-                    .unwrap()
-                    .extract::<&PyString>()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .into();
-                lines.push(out);
-            }
+        // blocks rendered from constraint blocks carry their name and comment
+        let mut sources: AVec<(AOption<(AString, AString)>, AString, Vec<SourceRange>)> =
+            AVec::new();
+        sources.push((
+            AOption(ROption::RNone),
+            imports
+                .iter()
+                .map(|x| x.to_python_source())
+                .collect::<String>()
+                .as_str()
+                .into(),
+            Vec::new(),
+        ));
+        for preamble in preambles {
             sources.push((
-                comment,
-                format_code(
-                    lines
-                        .iter()
-                        .map(|x| x.as_str().to_string())
-                        .collect::<AVec<String>>()
-                        .join("")
-                        .as_str()
-                        .into(),
-                )?,
-            ))
+                AOption(ROption::RNone),
+                preamble.to_python_source().as_str().into(),
+                Vec::new(),
+            ));
+        }
+        for x in augmented_statements {
+            let (source, ranges) = x.to_python_source();
+            sources.push((
+                AOption(ROption::RSome((
+                    x.get_constraint_name(),
+                    x.get_block_comment(),
+                ))),
+                source,
+                ranges,
+            ));
         }
         self.build_file(sources, flow_name)
     }
-}
 
-/// Encapsulates all the necessary bits for the construction of a Flow written in
-/// Python.
-pub trait PythonBasedFlowBuilder<U>: FlowBuilderBase<U>
-where
-    Self: Sized,
-    U: AoristUniverse,
-{
     /// Takes a set of statements and mutates them so as make a valid ETL flow
     fn augment_statements(
         &self,
@@ -179,25 +136,43 @@ where
         statements
     }
     fn get_flow_imports(&self) -> AVec<PythonImport>;
+    /// Statements creating the tasks that wait for tasks in other flows,
+    /// or None if the flow type has no way of expressing that.
+    fn get_external_dependency_statements(
+        &self,
+        _external_dependencies: &AVec<ExternalDependency>,
+    ) -> Option<PythonFlowBuilderInput> {
+        None
+    }
+    fn get_file_extension(&self) -> &'static str {
+        "py"
+    }
 
+    /// Lays out the blocks of the flow, given with the lines of each block
+    /// holding the statements of each task. Returns the flow along with
+    /// these lines moved to where the blocks end up, or with no lines if
+    /// the flow is not laid out as Python code.
     fn build_file(
         &self,
-        sources: AVec<(AOption<AString>, AString)>,
+        sources: AVec<(AOption<(AString, AString)>, AString, Vec<SourceRange>)>,
         _flow_name: AOption<AString>,
-    ) -> PyResult<AString> {
-        format_code(
-            sources
+    ) -> PyResult<(AString, Vec<SourceRange>)> {
+        let (content, ranges) = join_python_blocks_with_ranges(
+            &sources
                 .into_iter()
-                .map(|(maybe_comment, block)| match maybe_comment {
-                    AOption(ROption::RSome(comment)) => {
-                        format!("# {}\n{}\n", comment, block).to_string()
+                .map(|(maybe_comment, block, ranges)| match maybe_comment {
+                    AOption(ROption::RSome((_, comment))) => {
+                        let header = format!("# {}\n", comment);
+                        let lines = header.matches('\n').count();
+                        (
+                            format!("{}{}\n", header, block),
+                            ranges.iter().map(|x| x.shift(lines)).collect(),
+                        )
                     }
-                    AOption(ROption::RNone) => block.as_str().into(),
+                    AOption(ROption::RNone) => (block.as_str().into(), ranges),
                 })
-                .collect::<AVec<String>>()
-                .join("")
-                .as_str()
-                .into(),
-        )
+                .collect::<Vec<_>>(),
+        );
+        Ok((content.as_str().into(), ranges))
     }
 }
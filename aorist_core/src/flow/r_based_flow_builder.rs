@@ -1,12 +1,14 @@
-use aorist_primitives::AOption;
-use abi_stable::std_types::ROption;
-
-use crate::flow::flow_builder::{FlowBuilderBase, FlowBuilderMaterialize};
+use crate::driver::ExternalDependency;
+use crate::flow::etl_flow::ETLFlow;
+use crate::flow::flow_builder::{
+    FlowBuilderBase, FlowBuilderMaterialize, LITERALS_BLOCK_NAME, LITERALS_BLOCK_TITLE,
+};
 use crate::flow::flow_builder_input::FlowBuilderInput;
-use crate::flow::native_r_based_flow::NativeRBasedFlow;
-use crate::r::{RFlowBuilderInput, RPreamble};
-use aorist_ast::AST;
-use extendr_api::prelude::*;
+use crate::r::{RFlowBuilderInput, RImport, RPreamble};
+use abi_stable::std_types::ROption;
+use aorist_ast::{to_r_source_lines, AST};
+use aorist_primitives::AOption;
+use aorist_primitives::{AString, AVec, AoristUniverse};
 use linked_hash_map::LinkedHashMap;
 use linked_hash_set::LinkedHashSet;
 use std::collections::BTreeSet;
@@ -18,67 +20,26 @@ pub enum RFlowBuilderError {
     Generic(AString),
 }
 
-pub struct RBasedFlowBuilder {}
-impl RBasedFlowBuilder {
-    fn build_flow(
-        &self,
-        statements: AVec<(AString, AOption<AString>, AOption<AString>, AVec<AString>)>,
-    ) -> AVec<(AString, AVec<AString>)> {
-        statements
-            .into_iter()
-            .map(|(name, title, body, code)| {
-                (
-                    match title {
-                        Some(t) => match body {
-                            Some(b) => format!(
-                                "## {}\n{}",
-                                t,
-                                b.split("\n")
-                                    .map(|x| format!("# {}", x).to_string())
-                                    .collect::<AVec<AString>>()
-                                    .join("\n")
-                            )
-                            .to_string(),
-                            None => format!("## {}", t).to_string(),
-                        },
-                        None => format!("## {}", name).to_string(),
-                    },
-                    code,
-                )
-            })
-            .collect()
-    }
-
-    fn build_file(&self, sources: AVec<(AOption<AString>, AString)>) -> String {
-        sources
-            .into_iter()
-            .map(|(maybe_comment, block)| match maybe_comment {
-                Some(comment) => format!("# {}\n{}\n", comment, block).to_string(),
-                None => block,
-            })
-            .collect::<AVec<AString>>()
-            .join("\n")
-    }
-}
-
-impl FlowBuilderBase for RBasedFlowBuilder {
-    type T = NativeRBasedFlow;
-    fn new() -> Self {
-        Self {}
-    }
-}
-
-impl FlowBuilderMaterialize for RBasedFlowBuilder {
-    type BuilderInputType = RFlowBuilderInput;
-    type ErrorType = RFlowBuilderError;
-
-    fn materialize(
+/// Encapsulates all the necessary bits for the construction of a Flow written in
+/// R.
+pub trait RBasedFlowBuilder<U>:
+    FlowBuilderMaterialize<U, BuilderInputType = RFlowBuilderInput, ErrorType = RFlowBuilderError>
+where
+    Self: Sized,
+    U: AoristUniverse,
+    <Self as FlowBuilderBase<U>>::T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble>,
+{
+    /// Renders the statements and preambles into the blocks of the flow
+    /// file, in order: library calls, preambles, then statements. No R
+    /// installation is needed.
+    fn materialize_r_flow(
         &self,
         statements_and_preambles: AVec<RFlowBuilderInput>,
-    ) -> std::result::Result<AString, RFlowBuilderError> {
+        flow_name: AOption<AString>,
+    ) -> Result<AString, RFlowBuilderError> {
         let preambles: LinkedHashSet<RPreamble> = statements_and_preambles
             .iter()
-            .map(|x| x.clone().get_preambles().into_iter())
+            .map(|x| x.get_preambles().into_iter())
             .flatten()
             .collect();
 
@@ -86,94 +47,309 @@ impl FlowBuilderMaterialize for RBasedFlowBuilder {
 
         let imports = statements_and_preambles
             .iter()
-            .map(|x| x.get_imports().clone().into_iter())
+            .map(|x| x.get_imports().into_iter())
             .flatten()
             .chain(preamble_imports)
             .collect::<BTreeSet<_>>();
 
-        let imports_ast: AVec<_> = imports
+        let imports_statements = imports
             .into_iter()
-            .map(|x| {
-                let call = x.to_r_ast_node(0);
-                let deparsed = call!("deparse", call).unwrap();
-                Vec::<AString>::from_robj(&deparsed).unwrap().join("\n")
-            })
-            .collect();
+            .map(|x| x.get_statement())
+            .collect::<Vec<_>>();
 
-        let statements: AVec<(AString, AOption<AString>, AOption<AString>, AVec<AST>)> =
-            statements_and_preambles
-                .into_iter()
-                .map(|x| {
-                    (
-                        x.get_constraint_name(),
-                        x.get_constraint_title(),
-                        x.get_constraint_body(),
-                        x.get_statements(),
-                    )
-                })
-                .collect();
-        let mut statements_with_ast: AVec<_> = statements
+        let mut statements_with_ast: AVec<_> = statements_and_preambles
             .into_iter()
-            .filter(|x| x.3.len() > 0)
+            .filter(|x| x.has_statements())
             .collect::<AVec<_>>();
 
         // ast_value without ancestry => short_name => keys
-        let mut literals: LinkedHashMap<AST, LinkedHashMap<AString, AVec<_>>> = LinkedHashMap::new();
+        let mut literals: LinkedHashMap<AST, LinkedHashMap<AString, AVec<_>>> =
+            LinkedHashMap::new();
 
-        for (short_name, _, _, asts) in statements_with_ast.iter() {
-            for ast in asts {
-                Self::extract_literals(ast, &short_name, &mut literals);
-            }
+        for rfbi in statements_with_ast.iter() {
+            rfbi.extract_literals(&mut literals);
         }
         let assignments_ast = Self::literals_to_assignments(literals);
 
         if assignments_ast.len() > 0 {
             statements_with_ast.insert(
                 0,
-                (
-                    "assignments".into(),
-                    Some("Common string literals".into()),
-                    None,
+                RFlowBuilderInput::new(
                     assignments_ast,
+                    LinkedHashSet::new(),
+                    BTreeSet::new(),
+                    LITERALS_BLOCK_NAME.into(),
+                    AOption(ROption::RSome(LITERALS_BLOCK_TITLE.into())),
+                    AOption(ROption::RNone),
                 ),
             );
         }
-        let statements_ast = statements_with_ast
+
+        // blocks rendered from constraint blocks carry their name and comment
+        let mut sources: AVec<(AOption<(AString, AString)>, AString)> = AVec::new();
+        sources.push((
+            AOption(ROption::RNone),
+            to_r_source_lines(&imports_statements).trim_end().into(),
+        ));
+        for preamble in preambles.into_iter() {
+            sources.push((AOption(ROption::RNone), preamble.get_body()));
+        }
+        for block in self
+            .augment_statements(statements_with_ast, flow_name.clone())
             .into_iter()
-            .map(|(name, title, body, x)| {
-                (
-                    name,
-                    title,
-                    body,
-                    x.into_iter()
-                        .map(|y| {
-                            let deparsed = call!("deparse", y.to_r_ast_node(0)).unwrap();
-                            Vec::<AString>::from_robj(&deparsed).unwrap().join("\n")
-                        })
-                        .collect(),
-                )
-            })
-            .collect();
+        {
+            sources.push((
+                AOption(ROption::RSome((
+                    block.get_constraint_name(),
+                    block.get_block_comment(),
+                ))),
+                to_r_source_lines(&block.get_statements()).trim_end().into(),
+            ));
+        }
+        self.build_file(sources, flow_name)
+    }
 
-        let flow = self.build_flow(statements_ast);
+    /// Takes a set of statements and mutates them so as make a valid ETL flow
+    fn augment_statements(
+        &self,
+        statements: AVec<RFlowBuilderInput>,
+        _flow_name: AOption<AString>,
+    ) -> AVec<RFlowBuilderInput> {
+        statements
+    }
+    /// R flows run their statements in order in a single session, so they
+    /// have no way of waiting for tasks in other flows.
+    fn check_external_dependencies(
+        &self,
+        external_dependencies: &AVec<ExternalDependency>,
+    ) -> Result<(), RFlowBuilderError> {
+        match external_dependencies.len() {
+            0 => Ok(()),
+            _ => Err(RFlowBuilderError::Generic(
+                "R flows cannot wait for tasks in other flows.".into(),
+            )),
+        }
+    }
+    fn get_file_extension(&self) -> &'static str {
+        "R"
+    }
 
-        let content: AVec<(AOption<AString>, AVec<_>)> =
-            vec![(Some("RImports".into()), imports_ast)]
-                .into_iter()
-                .chain(preambles.into_iter().map(|x| (None, vec![x.get_body()])))
-                .chain(flow.into_iter().map(|(x, y)| (Some(x), y)))
-                .collect();
+    fn build_file(
+        &self,
+        sources: AVec<(AOption<(AString, AString)>, AString)>,
+        _flow_name: AOption<AString>,
+    ) -> Result<AString, RFlowBuilderError> {
+        Ok(sources
+            .into_iter()
+            .filter(|(_, block)| block.len() > 0)
+            .map(|(maybe_comment, block)| match maybe_comment {
+                AOption(ROption::RSome((_, comment))) => {
+                    format!("# {}\n{}\n", comment, block).to_string()
+                }
+                AOption(ROption::RNone) => format!("{}\n", block).to_string(),
+            })
+            .collect::<AVec<String>>()
+            .join("\n")
+            .as_str()
+            .into())
+    }
+}
 
-        let mut sources: AVec<(AOption<AString>, AString)> = AVec::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialect::{Bash, Dialect, Python, R};
+    use crate::endpoints::EndpointConfig;
+    use crate::flow::{IsolatedTask, RFlowBuilder, RJupyterFlowBuilder};
+    use crate::parameter_tuple::ParameterTuple;
+    use crate::universe::Universe;
+    use aorist_ast::StringLiteral;
+    use aorist_primitives::AoristRef;
+    use serde_json::Value;
 
-        // This is needed since astor will occasionally forget to add a newline
-        for (comment, block) in content {
-            let mut lines: AVec<AString> = AVec::new();
-            for item in block {
-                lines.push(item);
-            }
-            sources.push((comment, lines.join("\n")))
+    fn endpoints() -> EndpointConfig {
+        EndpointConfig {
+            presto: AOption(ROption::RNone),
+            alluxio: AOption(ROption::RNone),
+            ranger: AOption(ROption::RNone),
+            gitea: AOption(ROption::RNone),
+            minio: AOption(ROption::RNone),
+            postgres: AOption(ROption::RNone),
+            gcp: AOption(ROption::RNone),
+            aws: AOption(ROption::RNone),
+            pdal: AOption(ROption::RNone),
+            linz: AOption(ROption::RNone),
+            dask: AOption(ROption::RNone),
+            gdal: AOption(ROption::RNone),
+            tag: AOption(ROption::RNone),
+            uuid: AOption(ROption::RNone),
         }
-        Ok(self.build_file(sources))
+    }
+    fn task(
+        name: &str,
+        dialect: Dialect,
+        call: &str,
+        preamble: Option<&str>,
+        dependencies: &[&str],
+    ) -> IsolatedTask {
+        let mut kwargs = LinkedHashMap::new();
+        kwargs.insert(
+            "path".into(),
+            AST::StringLiteral(StringLiteral::new_wrapped("/tmp/data.csv".into(), false)),
+        );
+        IsolatedTask {
+            name: name.into(),
+            constraint: "Test".into(),
+            dialect: AOption(ROption::RSome(dialect)),
+            call: AOption(ROption::RSome(call.into())),
+            params: AOption(ROption::RSome(ParameterTuple {
+                args: AVec::new(),
+                kwargs,
+            })),
+            preamble: AOption(match preamble {
+                Some(p) => ROption::RSome(p.into()),
+                None => ROption::RNone,
+            }),
+            dependencies: dependencies.iter().map(|x| (*x).into()).collect(),
+            ancestors: AVec::new(),
+        }
+    }
+    /// A Bash download, then an R program and a Python program reading the
+    /// downloaded file.
+    fn inputs() -> AVec<RFlowBuilderInput> {
+        let tasks = vec![
+            task(
+                "download_data",
+                Dialect::Bash(Bash::new()),
+                "curl -o {path} https://example.com/data.csv",
+                None,
+                &[],
+            ),
+            task(
+                "load_data",
+                Dialect::R(R::new()),
+                "read_data",
+                Some("library(readr)\nread_data <- function(path) {\n    read_csv(path)\n}\n"),
+                &["download_data"],
+            ),
+            task(
+                "describe_data",
+                Dialect::Python(Python::new(vec![])),
+                "describe_data",
+                Some("def describe_data(path):\n    print(path)\n"),
+                &["download_data"],
+            ),
+        ];
+        IsolatedTask::to_r_flow_builder_inputs::<AoristRef<Universe>>(
+            tasks.into_iter().collect(),
+            endpoints(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_r_script() {
+        let script = RFlowBuilder::<AoristRef<Universe>>::new()
+            .materialize(inputs(), AOption(ROption::RSome("flow".into())))
+            .unwrap();
+        let expected = r#"library("glue")
+library("readr")
+library("reticulate")
+
+aorist_system <- function(command) {
+    status <- system(command)
+    if (status != 0) {
+        stop("Command exited with status ", status, ": ", command)
+    }
+}
+
+read_data <- function(path) {
+    read_csv(path)
+}
+
+py_run_string("def describe_data(path):\n    print(path)\n")
+
+# ## Common string literals
+PATH <- "/tmp/data.csv"
+
+# ## download_data
+download_data <- aorist_system(glue("curl -o {path} https://example.com/data.csv", path = PATH))
+
+# ## load_data
+load_data <- read_data(path = PATH)
+
+# ## describe_data
+describe_data <- py_call(py_eval("describe_data"), path = PATH)
+"#;
+        assert_eq!(script.as_str(), expected);
+    }
+    #[test]
+    fn test_r_notebook() {
+        let notebook = RJupyterFlowBuilder::<AoristRef<Universe>>::new()
+            .materialize(inputs(), AOption(ROption::RSome("flow".into())))
+            .unwrap();
+        let notebook: Value = serde_json::from_str(notebook.as_str()).unwrap();
+        assert_eq!(notebook["metadata"]["kernelspec"]["name"], "ir");
+        let cells = notebook["cells"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| {
+                (
+                    x["cell_type"].as_str().unwrap(),
+                    x["source"].as_str().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cells[0],
+            (
+                "code",
+                "library(\"glue\")\nlibrary(\"readr\")\nlibrary(\"reticulate\")"
+            )
+        );
+        assert!(cells[1]
+            .1
+            .starts_with("aorist_system <- function(command) {"));
+        assert_eq!(
+            cells[2],
+            (
+                "code",
+                "read_data <- function(path) {\n    read_csv(path)\n}"
+            )
+        );
+        assert_eq!(
+            cells[3],
+            (
+                "code",
+                "py_run_string(\"def describe_data(path):\\n    print(path)\\n\")"
+            )
+        );
+        assert_eq!(
+            cells[4..],
+            [
+                ("markdown", "# Common string literals"),
+                ("code", "PATH <- \"/tmp/data.csv\""),
+                ("markdown", "# download_data"),
+                (
+                    "code",
+                    "download_data <- aorist_system(glue(\"curl -o {path} https://example.com/data.csv\", path = PATH))"
+                ),
+                ("markdown", "# load_data"),
+                ("code", "load_data <- read_data(path = PATH)"),
+                ("markdown", "# describe_data"),
+                (
+                    "code",
+                    "describe_data <- py_call(py_eval(\"describe_data\"), path = PATH)"
+                ),
+            ]
+        );
+        let parameters = notebook["cells"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|x| x["metadata"]["tags"] == serde_json::json!(["parameters"]))
+            .count();
+        assert_eq!(parameters, 1);
     }
 }
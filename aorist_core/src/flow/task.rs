@@ -1,7 +1,7 @@
 use crate::dialect::Dialect;
 use crate::flow::etl_flow::ETLFlow;
 use crate::parameter_tuple::{ParameterTuple, ParameterTupleDedupKey};
-use aorist_ast::AST;
+use aorist_ast::{AncestorRecord, AST};
 use aorist_primitives::AOption;
 use aorist_primitives::{AString, AVec, AoristUniverse};
 use std::hash::Hash;
@@ -25,8 +25,16 @@ where
         dependencies: AVec<AST>,
         preamble: AOption<AString>,
         dialect: AOption<Dialect>,
+        ancestors: AVec<AncestorRecord>,
     ) -> Self;
 }
+/// Attaches the ancestors of the tasks rendered by `statements` to them, so
+/// that the lines they are printed on can be mapped back to the tasks.
+pub fn set_task_ancestors(statements: &AVec<AST>, ancestors: &AVec<AncestorRecord>) {
+    for statement in statements.iter() {
+        statement.set_ancestors(ancestors.clone());
+    }
+}
 pub trait CompressionKey: Clone + Hash + PartialEq + Eq {
     fn new(
         dict_name: AST,
@@ -82,6 +90,7 @@ where
         dict: AString,
         params: AOption<ParameterTuple>,
         deps: AVec<AST>,
+        ancestors: AVec<AncestorRecord>,
     ) -> Self;
     fn as_dict(&self, insert_deps: bool, dependencies_as_list: bool, insert_task_name: bool)
         -> AST;
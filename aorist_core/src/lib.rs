@@ -49,7 +49,7 @@ pub use constraint_state::*;
 pub use dataset::*;
 #[cfg(feature = "python")]
 pub use dialect::dialects_module;
-pub use dialect::{Bash, Dialect, Presto, Python, R};
+pub use dialect::{Bash, Dialect, DialectPreferences, Presto, Python, R};
 pub use encoding::*;
 pub use endpoints::*;
 pub use error::*;
@@ -70,12 +70,8 @@ pub use template::*;
 pub use universe::*;
 pub use user::*;
 pub use user_group::*;
-//
-// #[cfg(feature = "r")]
-// mod r;
-// #[cfg(feature = "r")]
-// pub use r::*;
-//
+mod r;
+pub use r::*;
 #[cfg(feature = "python")]
 mod python;
 #[cfg(feature = "python")]
@@ -5,6 +5,8 @@ use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
 use abi_stable::std_types::RArc;
 use aorist_primitives::{AString, AVec, Ancestry, Context};
 use linked_hash_map::LinkedHashMap;
+use siphasher::sip128::{Hasher128, SipHasher};
+use std::hash::Hash;
 
 pub trait TProgram<'a, T: TConstraint<'a>> {
     fn new(
@@ -23,6 +25,24 @@ pub trait TProgram<'a, T: TConstraint<'a>> {
 pub trait TOuterProgram: Clone {
     type TAncestry: Ancestry;
     fn get_dialect(&self) -> Dialect;
+    fn get_code(&self) -> AString;
+    fn get_entrypoint(&self) -> AString;
+    fn get_arg_functions(&self) -> AVec<(AVec<AString>, AString)>;
+    fn get_kwarg_functions(&self) -> LinkedHashMap<AString, (AVec<AString>, AString)>;
+    /// Hash of everything that determines the output of `compute_args`
+    /// besides the root concept and the context.
+    fn get_fingerprint(&self) -> u128 {
+        let mut hasher = SipHasher::new();
+        self.get_dialect().hash(&mut hasher);
+        self.get_code().hash(&mut hasher);
+        self.get_entrypoint().hash(&mut hasher);
+        self.get_arg_functions().hash(&mut hasher);
+        for (key, val) in self.get_kwarg_functions() {
+            key.hash(&mut hasher);
+            val.hash(&mut hasher);
+        }
+        hasher.finish128().as_u128()
+    }
     fn compute_args<'a, T: OuterConstraint<'a>>(
         &self,
         root: <Self::TAncestry as Ancestry>::TConcept,
@@ -50,7 +50,8 @@ def execute_trino_sql(query):
         host='{host}',
         user='{user}',
         port={port},
-        catalog='hive',
+        catalog='{catalog}',
+        schema='{schema}',
         session_properties={{
             'redistribute_writes': False,
         }}
@@ -69,7 +70,9 @@ def execute_trino_sql(query):
 ",
             host = self.endpoint.server,
             user = self.endpoint.user,
-            port = self.endpoint.http_port
+            port = self.endpoint.http_port,
+            catalog = self.endpoint.catalog,
+            schema = self.endpoint.schema
         );
         AOption(ROption::RSome(NativePythonPreamble {
             imports: vec![re, trino].into_iter().collect(),
@@ -100,6 +100,15 @@ where
     fn get_params(&self) -> HashMap<AString, AOption<ParameterTuple>> {
         self.params.clone()
     }
+    fn get_mapped_task_ids(&self, endpoints: U::TEndpoints) -> HashMap<AString, AString> {
+        let mut task_ids = HashMap::new();
+        for task in self.python_based_tasks.iter() {
+            if let PythonBasedTask::ForLoopPythonBasedTask(x) = task {
+                task_ids.extend(x.get_mapped_task_ids(endpoints.clone()));
+            }
+        }
+        task_ids
+    }
 }
 impl<'a, T, C, U, P> CodeBlockWithForLoopCompression<'a, T, C, U, P>
     for PythonBasedCodeBlock<'a, T, C, U, P>
@@ -10,12 +10,11 @@ use crate::flow::FlowBuilderInput;
 use aorist_primitives::{AString, AVec};
 use linked_hash_set::LinkedHashSet;
 use pyo3::prelude::*;
-use pyo3::types::{IntoPyDict, PyString, PyTuple};
-use std::collections::{BTreeSet, HashMap};
+use std::collections::BTreeSet;
 
 use aorist_ast::{
-    Add, Assignment, Attribute, BigIntLiteral, BinOp, Call, Dict, ForLoop, Formatted, List,
-    SimpleIdentifier, StringLiteral, Subscript, Tuple, AST,
+    to_python_source_with_ranges, Add, Assignment, Attribute, BigIntLiteral, BinOp, Call, Dict,
+    ForLoop, Formatted, List, SimpleIdentifier, SourceRange, StringLiteral, Subscript, Tuple, AST,
 };
 pub use ast::{
     BashPythonTask, ConstantPythonTask, NativePythonTask, PrestoPythonTask, PythonTask, RPythonTask,
@@ -71,6 +70,12 @@ impl PythonFlowBuilderInput {
         }
         Ok(v)
     }
+    /// Source of the statements, rendered without an interpreter, along
+    /// with the lines holding the statements of each task.
+    pub fn to_python_source(&self) -> (AString, Vec<SourceRange>) {
+        let (source, ranges) = to_python_source_with_ranges(&self.get_statements());
+        (source.as_str().into(), ranges)
+    }
 }
 impl FlowBuilderInput for PythonFlowBuilderInput {
     type ImportType = PythonImport;
@@ -112,28 +117,3 @@ impl FlowBuilderInput for PythonFlowBuilderInput {
         self.constraint_body.clone()
     }
 }
-
-pub fn format_code(code: AString) -> PyResult<AString> {
-    let gil = Python::acquire_gil();
-    let py = gil.python();
-
-    let black: &PyModule = PyModule::import(py, "black").unwrap();
-    let mut kwargs = HashMap::<&str, usize>::new();
-    kwargs.insert("line_length", 80);
-    let mode = black
-        .getattr("FileMode")?
-        .call((), Some(kwargs.into_py_dict(py)))?;
-
-    let py_code = PyString::new(py, code.as_str());
-
-    let mut kwargs = HashMap::<&str, &PyAny>::new();
-    kwargs.insert("mode", mode);
-    if let Ok(res) = black
-        .getattr("format_str")?
-        .call(PyTuple::new(py, &[py_code]), Some(kwargs.into_py_dict(py)))
-    {
-        return res.extract();
-    } else {
-        panic!("Error formatting code block: \n{}\n---", py_code);
-    }
-}
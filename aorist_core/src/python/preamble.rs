@@ -1,11 +1,12 @@
 use crate::code::Preamble;
 use crate::python::PythonImport;
-use abi_stable::std_types::ROption;
-use aorist_ast::FunctionDef;
+use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
+use abi_stable::std_types::{RArc, ROption};
+use aorist_ast::{to_python_source_lines, FunctionDef, AST, PYTHON_LINE_LENGTH};
 use aorist_primitives::AOption;
 use aorist_primitives::{AString, AVec};
 use pyo3::prelude::*;
-use pyo3::types::{PyList, PyModule, PyString, PyTuple};
+use pyo3::types::{PyList, PyModule};
 use std::hash::Hash;
 use tracing::debug;
 
@@ -94,12 +95,9 @@ impl Preamble for NativePythonPreamble {
 }
 impl TPythonPreamble for RPythonPreamble {
     fn get_body(&self) -> AString {
-        format!(
-            "robjects.r(\"\"\"{}\"\"\")",
-            self.body.as_str().to_string().replace("'", "\\'")
-        )
-        .as_str()
-        .into()
+        format!("robjects.r({})", self.get_code_literal())
+            .as_str()
+            .into()
     }
 }
 impl TPythonPreamble for NativePythonPreamble {
@@ -135,98 +133,68 @@ impl PythonPreamble {
             .collect(),
         }
     }
+    /// Python source of the preamble, without its imports.
+    pub fn to_python_source(&self) -> String {
+        match &self {
+            // already laid out when the preamble was split
+            PythonPreamble::NativePythonPreamble(x) => match x.body.as_str().trim_end() {
+                "" => String::new(),
+                body => format!("{}\n", body),
+            },
+            PythonPreamble::RPythonPreamble(x) => {
+                let code = x.get_code_literal();
+                let line = format!("robjects.r({})", code);
+                // Black splits the call around a string that does not fit
+                match code.contains('\n') || line.chars().count() > PYTHON_LINE_LENGTH {
+                    true => format!("robjects.r(\n    {}\n)\n", code),
+                    false => format!("{}\n", line),
+                }
+            }
+            PythonPreamble::PythonStatementsPreamble(x) => {
+                to_python_source_lines(&[AST::FunctionDef(RArc::new(RRwLock::new(
+                    x.function.clone(),
+                )))])
+            }
+        }
+    }
 }
 impl RPythonPreamble {
     pub fn new(body: AString) -> PyResult<Self> {
         Ok(Self { body })
     }
+    /// The R code, as a triple-quoted Python string.
+    fn get_code_literal(&self) -> String {
+        format!(
+            "\"\"\"{}\"\"\"",
+            self.body.as_str().to_string().replace("'", "\\'")
+        )
+    }
 }
 impl NativePythonPreamble {
+    /// Splits the top-level imports out of the Python source `body`. The
+    /// other top-level statements are kept as written, separated as Black
+    /// separates them, so no interpreter is needed.
     pub fn new(body: AString) -> PyResult<Self> {
-        let gil = Python::acquire_gil();
-        let py = gil.python();
-        let helpers = PyModule::from_code(
-            py,
-            r#"
-import ast
-import astor
-
-def build_preamble(body):
-    module = ast.parse(body)
-
-    imports = []
-    from_imports = []
-    other = []
-
-    for elem in module.body:
-        if isinstance(elem, ast.Import):
-            for name in elem.names:
-                imports += [(name.name, name.asname)]
-        elif isinstance(elem, ast.ImportFrom):
-            for name in elem.names:
-                from_imports += [(elem.module, name.name, name.asname)]
-        else:
-            other += [astor.to_source(elem)]
-
-    return imports, from_imports, other
-        "#,
-            "helpers.py",
-            "helpers",
-        )
-        .unwrap();
-
-        let tpl: &PyTuple = helpers
-            .getattr("build_preamble")?
-            .call1((body,))?
-            .downcast()
-            .unwrap();
-
-        let imports_list: &PyList = tpl.get_item(0)?.extract()?;
-        let imports: Vec<PythonImport> = imports_list
-            .iter()
-            .map(|x| {
-                let tpl: &PyTuple = x.extract().unwrap();
-                let name: AString = tpl.get_item(0)?.extract::<&PyString>()?.to_str()?.into();
-                let alias = tpl.get_item(1)?;
-                let asname: AOption<AString> = match alias.is_none() {
-                    true => AOption(ROption::RNone),
-                    false => AOption(ROption::RSome(
-                        alias.extract::<&PyString>()?.to_str()?.into(),
-                    )),
-                };
-                Ok(PythonImport::PythonModuleImport(name, asname))
-            })
-            .collect::<PyResult<_>>()?;
-
-        let from_imports_list: &PyList = tpl.get_item(1)?.extract()?;
-        let from_imports: Vec<PythonImport> = from_imports_list
-            .iter()
-            .map(|x| {
-                let tpl: &PyTuple = x.extract()?;
-                let module: AString = tpl.get_item(0)?.extract::<&PyString>()?.to_str()?.into();
-                let name: AString = tpl.get_item(1)?.extract::<&PyString>()?.to_str()?.into();
-                let alias = tpl.get_item(2)?;
-                let asname: AOption<AString> = match alias.is_none() {
-                    true => AOption(ROption::RNone),
-                    false => AOption(ROption::RSome(
-                        alias.extract::<&PyString>()?.to_str()?.into(),
-                    )),
-                };
-                Ok(PythonImport::PythonFromImport(module, name, asname))
-            })
-            .collect::<PyResult<_>>()?;
-
-        let body_no_imports: &PyList = tpl.get_item(2)?.extract()?;
+        let mut imports = AVec::new();
+        let mut from_imports = AVec::new();
+        let mut statements = Vec::new();
+        for statement in split_python_statements(body.as_str()) {
+            match parse_python_imports(&statement) {
+                Some(parsed) => {
+                    for import in parsed {
+                        match import {
+                            PythonImport::PythonModuleImport(..) => imports.push(import),
+                            PythonImport::PythonFromImport(..) => from_imports.push(import),
+                        }
+                    }
+                }
+                None => statements.push(statement),
+            }
+        }
         Ok(Self {
-            imports: imports.into_iter().collect(),
-            from_imports: from_imports.into_iter().collect(),
-            body: body_no_imports
-                .iter()
-                .map(|x| x.extract::<&PyString>().unwrap().to_str().unwrap().into())
-                .collect::<AVec<String>>()
-                .join("\n")
-                .as_str()
-                .into(),
+            imports,
+            from_imports,
+            body: join_python_statements(&statements).as_str().into(),
         })
     }
     pub fn to_string(&self) -> String {
@@ -244,3 +212,318 @@ def build_preamble(body):
             .join("\n")
     }
 }
+
+/// Where a scan of Python source stopped: the number of open brackets, the
+/// quote of the string literal left open (and whether it is triple-quoted),
+/// and whether the line ended with a backslash.
+#[derive(Default)]
+struct PythonLexState {
+    depth: usize,
+    quote: Option<(char, bool)>,
+    continued: bool,
+}
+impl PythonLexState {
+    /// Whether the next line continues the current logical line.
+    fn in_statement(&self) -> bool {
+        self.depth > 0 || self.quote.is_some() || self.continued
+    }
+    fn scan(&mut self, line: &str) {
+        let chars = line.chars().collect::<Vec<_>>();
+        let mut i = 0;
+        self.continued = false;
+        while i < chars.len() {
+            let c = chars[i];
+            match self.quote {
+                Some(_) if c == '\\' => {
+                    if i + 1 == chars.len() {
+                        self.continued = true;
+                    }
+                    i += 1;
+                }
+                Some((q, true)) if chars[i..].starts_with(&[q, q, q]) => {
+                    self.quote = None;
+                    i += 2;
+                }
+                Some((q, false)) if c == q => self.quote = None,
+                Some(_) => {}
+                None => match c {
+                    '#' => break,
+                    '\'' | '"' => {
+                        let triple = chars[i..].starts_with(&[c, c, c]);
+                        self.quote = Some((c, triple));
+                        if triple {
+                            i += 2;
+                        }
+                    }
+                    '(' | '[' | '{' => self.depth += 1,
+                    ')' | ']' | '}' => self.depth = self.depth.saturating_sub(1),
+                    '\\' if i + 1 == chars.len() => self.continued = true,
+                    _ => {}
+                },
+            }
+            i += 1;
+        }
+        // single-quoted strings end with the line, unless it is continued
+        if let Some((_, false)) = self.quote {
+            if !self.continued {
+                self.quote = None;
+            }
+        }
+    }
+}
+
+/// Whether the line, starting a logical line at the indentation of `def`,
+/// continues a compound statement rather than starting a new one.
+fn continues_compound_statement(line: &str) -> bool {
+    ["else", "elif", "except", "finally"].iter().any(|keyword| {
+        line.strip_prefix(keyword)
+            .map(|rest| rest.starts_with(|c: char| c == ':' || c.is_whitespace()))
+            .unwrap_or(false)
+    })
+}
+
+/// Lines of each top-level statement of the Python source, without the
+/// indentation of its first line. Comments and decorators directly above a
+/// statement belong to it.
+fn split_python_statements(source: &str) -> Vec<Vec<String>> {
+    let indent = source
+        .lines()
+        .find(|x| !x.trim().is_empty())
+        .map(|x| x.len() - x.trim_start().len())
+        .unwrap_or(0);
+    let mut state = PythonLexState::default();
+    let mut statements = Vec::new();
+    let mut current: Vec<String> = Vec::new();
+    let mut only_comments = false;
+    let mut blank_lines = 0;
+    for line in source.lines() {
+        let line = match state.quote {
+            Some(_) => line,
+            None => {
+                let stripped = std::cmp::min(indent, line.len() - line.trim_start().len());
+                line.get(stripped..).unwrap_or(line)
+            }
+        };
+        if !state.in_statement() {
+            if line.trim().is_empty() {
+                blank_lines += 1;
+                continue;
+            }
+            let starts_statement = !line.starts_with(char::is_whitespace)
+                && !continues_compound_statement(line)
+                && !(only_comments && blank_lines == 0);
+            if starts_statement || current.is_empty() {
+                if !current.is_empty() {
+                    statements.push(std::mem::take(&mut current));
+                }
+                only_comments = true;
+            } else {
+                current.extend(std::iter::repeat(String::new()).take(blank_lines));
+            }
+            blank_lines = 0;
+            let trimmed = line.trim_start();
+            only_comments = only_comments && (trimmed.starts_with('#') || trimmed.starts_with('@'));
+        }
+        current.push(line.to_string());
+        state.scan(line);
+    }
+    if !current.is_empty() {
+        statements.push(current);
+    }
+    statements
+}
+
+/// Whether the statement defines a function or a class.
+fn is_definition(statement: &[String]) -> bool {
+    statement
+        .iter()
+        .find(|x| !x.starts_with('#'))
+        .map(|x| {
+            ["def ", "async def ", "class ", "@"]
+                .iter()
+                .any(|prefix| x.starts_with(prefix))
+        })
+        .unwrap_or(false)
+}
+
+/// Joins top-level statements the way Black separates them: two blank
+/// lines around function and class definitions, none otherwise.
+fn join_python_statements(statements: &[Vec<String>]) -> String {
+    let mut source = String::new();
+    for (i, statement) in statements.iter().enumerate() {
+        if i > 0 {
+            match is_definition(&statements[i - 1]) || is_definition(statement) {
+                true => source.push_str("\n\n"),
+                false => {}
+            }
+        }
+        for line in statement {
+            source.push_str(line);
+            source.push('\n');
+        }
+    }
+    source
+}
+
+/// Whether `name` is a (possibly dotted) Python identifier.
+fn is_dotted_name(name: &str) -> bool {
+    name.split('.').all(|part| {
+        part.chars()
+            .next()
+            .map_or(false, |c| c.is_alphabetic() || c == '_')
+            && part.chars().all(|c| c.is_alphanumeric() || c == '_')
+    })
+}
+
+/// Name and alias of `name [as alias]`, if it is made of identifiers.
+fn parse_import_alias(item: &str) -> Option<(AString, AOption<AString>)> {
+    let tokens = item.split_whitespace().collect::<Vec<_>>();
+    match tokens[..] {
+        [name] if is_dotted_name(name) || name == "*" => {
+            Some((name.into(), AOption(ROption::RNone)))
+        }
+        [name, "as", alias] if is_dotted_name(name) && is_dotted_name(alias) => {
+            Some((name.into(), AOption(ROption::RSome(alias.into()))))
+        }
+        _ => None,
+    }
+}
+
+/// Imports of the statement, if it is an `import` or an absolute
+/// `from ... import` and nothing else.
+fn parse_python_imports(statement: &[String]) -> Option<Vec<PythonImport>> {
+    let mut code = String::new();
+    for line in statement {
+        if line.contains(|c| c == '\'' || c == '"' || c == ';') {
+            return None;
+        }
+        let line = line.split('#').next().unwrap().trim();
+        code.push_str(line.strip_suffix('\\').unwrap_or(line));
+        code.push(' ');
+    }
+    let code = code.trim();
+    if let Some(names) = code.strip_prefix("import ") {
+        return names
+            .split(',')
+            .map(|x| {
+                parse_import_alias(x)
+                    .filter(|(name, _)| name.as_str() != "*")
+                    .map(|(name, alias)| PythonImport::PythonModuleImport(name, alias))
+            })
+            .collect();
+    }
+    let (module, names) = code.strip_prefix("from ")?.split_once(" import ")?;
+    let module = module.trim();
+    if !is_dotted_name(module) {
+        return None;
+    }
+    let names = names.trim();
+    let names = match names.strip_prefix('(') {
+        Some(names) => names.strip_suffix(')')?,
+        None => names,
+    };
+    names
+        .split(',')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .map(|x| {
+            parse_import_alias(x)
+                .map(|(name, alias)| PythonImport::PythonFromImport(module.into(), name, alias))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_r_preamble_source() {
+        let short = PythonPreamble::RPythonPreamble(RPythonPreamble::new("f <- 1".into()).unwrap());
+        assert_eq!(short.to_python_source(), "robjects.r(\"\"\"f <- 1\"\"\")\n");
+        let long = PythonPreamble::RPythonPreamble(
+            RPythonPreamble::new("f <- function(x) {\n  x + 1\n}".into()).unwrap(),
+        );
+        assert_eq!(
+            long.to_python_source(),
+            "robjects.r(\n    \"\"\"f <- function(x) {\n  x + 1\n}\"\"\"\n)\n"
+        );
+    }
+    #[test]
+    fn test_native_preamble_splits_imports() {
+        let preamble = NativePythonPreamble::new(
+            r#"import os
+import numpy as np, pandas
+from typing import (
+    Dict,
+    List as L,
+)
+x = 1
+def f(a):
+    import json
+
+    return json.dumps(a)
+y = 2
+"#
+            .into(),
+        )
+        .unwrap();
+        assert_eq!(
+            preamble
+                .imports
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>(),
+            vec!["import os", "import numpy as np", "import pandas"]
+        );
+        assert_eq!(
+            preamble
+                .from_imports
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>(),
+            vec!["from typing import Dict", "from typing import List as L"]
+        );
+        assert_eq!(
+            preamble.body.as_str(),
+            "x = 1\n\n\ndef f(a):\n    import json\n\n    return json.dumps(a)\n\n\ny = 2\n"
+        );
+    }
+    #[test]
+    fn test_native_preamble_keeps_statements_as_written() {
+        let body = r#"
+        # Runs the query
+        @cache
+        def run(query):
+            return """
+import os
+"""
+
+        try:
+            import ujson as json
+        except ImportError:
+            import json
+        from . import helpers
+        "#;
+        let preamble = NativePythonPreamble::new(body.into()).unwrap();
+        assert!(preamble.imports.is_empty());
+        assert!(preamble.from_imports.is_empty());
+        assert_eq!(
+            preamble.body.as_str(),
+            r#"# Runs the query
+@cache
+def run(query):
+    return """
+import os
+"""
+
+
+try:
+    import ujson as json
+except ImportError:
+    import json
+from . import helpers
+"#
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use crate::code::Import;
 use abi_stable::std_types::ROption;
-use aorist_ast::{SimpleIdentifier, AST};
+use aorist_ast::{SimpleIdentifier, AST, PYTHON_LINE_LENGTH};
 use aorist_primitives::AOption;
 use aorist_primitives::AString;
 use pyo3::prelude::*;
@@ -31,6 +31,24 @@ impl PythonImport {
             }
         }
     }
+    /// Python source of the import, laid out as Black lays it out: the name
+    /// imported by a from-import too long for one line goes on a line of
+    /// its own.
+    pub fn to_python_source(&self) -> String {
+        let line = self.to_string();
+        match &self {
+            Self::PythonFromImport(ref module, ref name, ref alias)
+                if line.chars().count() > PYTHON_LINE_LENGTH =>
+            {
+                let name = match alias {
+                    AOption(ROption::RSome(ref alias)) => format!("{} as {}", name, alias),
+                    AOption(ROption::RNone) => name.to_string(),
+                };
+                format!("from {} import (\n    {},\n)\n", module, name)
+            }
+            _ => format!("{}\n", line),
+        }
+    }
     pub fn to_python_ast_node<'a>(
         &self,
         py: Python,
@@ -79,3 +97,31 @@ impl PythonImport {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_long_from_import_is_split() {
+        let short = PythonImport::PythonFromImport(
+            "dask.distributed".into(),
+            "Client".into(),
+            AOption(ROption::RNone),
+        );
+        assert_eq!(
+            short.to_python_source(),
+            "from dask.distributed import Client\n"
+        );
+        let long = PythonImport::PythonFromImport(
+            "airflow.providers.cncf.kubernetes.operators.kubernetes_pod".into(),
+            "KubernetesPodOperator".into(),
+            AOption(ROption::RNone),
+        );
+        assert_eq!(
+            long.to_python_source(),
+            "from airflow.providers.cncf.kubernetes.operators.kubernetes_pod import (\n    \
+             KubernetesPodOperator,\n)\n"
+        );
+    }
+}
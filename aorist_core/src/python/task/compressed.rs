@@ -1,4 +1,7 @@
-use crate::flow::{CompressionKey, ETLFlow, ForLoopCompressedTask, TaskBase, UncompressiblePart};
+use crate::flow::{
+    set_task_ancestors, CompressionKey, ETLFlow, ForLoopCompressedTask, TaskBase,
+    UncompressiblePart,
+};
 use crate::python::task::key::PythonBasedTaskCompressionKey;
 use crate::python::task::uncompressible::PythonBasedTaskUncompressiblePart;
 use crate::python::{
@@ -6,9 +9,12 @@ use crate::python::{
     PythonPreamble, SimpleIdentifier, StringLiteral, Subscript, Tuple, AST,
 };
 use abi_stable::std_types::ROption;
+use aorist_ast::{AncestorRecord, Expression};
 use aorist_primitives::AOption;
-use aorist_primitives::{AVec, AoristUniverse};
+use aorist_primitives::{AString, AVec, AoristUniverse};
 use linked_hash_map::LinkedHashMap;
+use linked_hash_set::LinkedHashSet;
+use siphasher::sip128::{Hasher128, SipHasher};
 use std::hash::Hash;
 use std::marker::PhantomData;
 use tracing::trace;
@@ -140,6 +146,165 @@ where
             false,
         ))
     }
+    /// Task id of the mapped task replacing the individual tasks.
+    fn get_mapped_task_id(&self) -> AString {
+        let prefix = match self.params_dict_name {
+            AST::SimpleIdentifier(ref x) => x
+                .read()
+                .name()
+                .as_str()
+                .trim_start_matches("params_")
+                .to_string(),
+            _ => "mapped".to_string(),
+        };
+        let mut hasher = SipHasher::new();
+        for value in self.values.iter() {
+            value.task_id.hash(&mut hasher);
+        }
+        let hash = format!("{:032x}", hasher.finish128().as_u128());
+        format!("{}__{}", prefix, &hash[..8]).as_str().into()
+    }
+    fn get_mapped_inputs(&self) -> AST {
+        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
+            format!("inputs_{}", self.get_mapped_task_id())
+                .as_str()
+                .into(),
+        ))
+    }
+    fn get_mapped_task_val(&self) -> AST {
+        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
+            format!("mapped_{}", self.get_mapped_task_id())
+                .as_str()
+                .into(),
+        ))
+    }
+    /// The task replacing the individual tasks, depending on all of their
+    /// dependencies.
+    fn get_mapped_task(
+        &self,
+        args: AVec<AST>,
+        kwargs: LinkedHashMap<AString, AST>,
+        endpoints: U::TEndpoints,
+    ) -> T {
+        let mut dependencies = self.key.deps.iter().cloned().collect::<LinkedHashSet<_>>();
+        if self.render_dependencies {
+            for value in self.values.iter() {
+                dependencies.extend(value.deps.iter().cloned());
+            }
+        }
+        T::new(
+            AST::StringLiteral(StringLiteral::new_wrapped(self.get_mapped_task_id(), false)),
+            self.get_mapped_task_val(),
+            self.key.get_call(),
+            args,
+            kwargs,
+            match dependencies.len() {
+                0 => AOption(ROption::RNone),
+                _ => AOption(ROption::RSome(AST::List(List::new_wrapped(
+                    dependencies.into_iter().collect(),
+                    false,
+                )))),
+            },
+            self.key.get_preamble(),
+            self.key.get_dialect(),
+            endpoints,
+        )
+    }
+    /// Id of the mapped task by the key of every task it replaces, so that
+    /// tasks in other flows can wait for it. Empty if the flow type does not
+    /// map these tasks.
+    pub fn get_mapped_task_ids(&self, endpoints: U::TEndpoints) -> LinkedHashMap<AString, AString> {
+        let (args, kwargs, dependencies) = self.get_singleton_inputs();
+        let singleton = self.get_singleton(
+            args.clone(),
+            kwargs.clone(),
+            dependencies,
+            endpoints.clone(),
+        );
+        if let AOption(ROption::RNone) = singleton.get_mapped_input() {
+            return LinkedHashMap::new();
+        }
+        let mapped = self.get_mapped_task(args, kwargs, endpoints);
+        match mapped.get_mapped_statements(self.get_mapped_inputs()) {
+            AOption(ROption::RSome(_)) => {
+                let task_id = self.get_mapped_task_id();
+                self.values
+                    .iter()
+                    .map(|x| (x.dict.clone(), task_id.clone()))
+                    .collect()
+            }
+            AOption(ROption::RNone) => LinkedHashMap::new(),
+        }
+    }
+    /// Renders the tasks as a single task expanded over their inputs, if the
+    /// flow type supports it. The inputs are collected by looping over the
+    /// params dict, and every key of the tasks dict points to the mapped
+    /// task, so that downstream tasks depend on all of its instances.
+    fn get_mapped_statements(
+        &self,
+        singleton: &T,
+        loop_target: &AST,
+        items_call: &AST,
+        args: AVec<AST>,
+        kwargs: LinkedHashMap<AString, AST>,
+        endpoints: U::TEndpoints,
+    ) -> AOption<(AVec<AST>, AVec<PythonImport>)> {
+        let input = match singleton.get_mapped_input() {
+            AOption(ROption::RSome(x)) => x,
+            AOption(ROption::RNone) => return AOption(ROption::RNone),
+        };
+        let inputs = self.get_mapped_inputs();
+        let task_val = self.get_mapped_task_val();
+        let mapped = self.get_mapped_task(args, kwargs, endpoints);
+        let (mapped_statements, imports) = match mapped.get_mapped_statements(inputs.clone()) {
+            AOption(ROption::RSome(x)) => x,
+            AOption(ROption::RNone) => return AOption(ROption::RNone),
+        };
+        let inputs_assign = AST::Assignment(Assignment::new_wrapped(
+            inputs.clone(),
+            AST::List(List::new_wrapped(AVec::new(), false)),
+        ));
+        let collect_inputs = AST::ForLoop(ForLoop::new_wrapped(
+            loop_target.clone(),
+            items_call.clone(),
+            vec![AST::Expression(Expression::new_wrapped(AST::Call(
+                Call::new_wrapped(
+                    AST::Attribute(Attribute::new_wrapped(inputs, "append".into(), false)),
+                    vec![input].into_iter().collect(),
+                    LinkedHashMap::new(),
+                ),
+            )))]
+            .into_iter()
+            .collect(),
+        ));
+        let ident = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("t".into()));
+        let collect_tasks = AST::ForLoop(ForLoop::new_wrapped(
+            ident.clone(),
+            self.params_dict_name.clone(),
+            vec![AST::Assignment(Assignment::new_wrapped(
+                self.get_task_collector(&ident),
+                task_val,
+            ))]
+            .into_iter()
+            .collect(),
+        ));
+        AOption(ROption::RSome((
+            vec![inputs_assign, collect_inputs]
+                .into_iter()
+                .chain(mapped_statements.into_iter())
+                .chain(vec![collect_tasks].into_iter())
+                .collect(),
+            imports,
+        )))
+    }
+    /// Ancestors of every task run by the for loop, one after the other.
+    fn get_ancestors(&self) -> AVec<AncestorRecord> {
+        self.values
+            .iter()
+            .map(|x| x.ancestors.iter().cloned())
+            .flatten()
+            .collect()
+    }
     fn get_task_collector(&self, ident: &AST) -> AST {
         AST::Subscript(Subscript::new_wrapped(
             self.key.clone().get_dict_name(),
@@ -147,27 +312,16 @@ where
             false,
         ))
     }
-    pub fn get_statements(
-        &self,
-        endpoints: U::TEndpoints,
-    ) -> (AVec<AST>, AVec<PythonPreamble>, AVec<PythonImport>) {
+    /// Arguments, keyword arguments and dependencies of the task run in
+    /// every iteration of the for loop.
+    fn get_singleton_inputs(&self) -> (AVec<AST>, LinkedHashMap<AString, AST>, AOption<AST>) {
         let any_dependencies = self
             .values
             .iter()
             .filter(|x| x.deps.len() > 0)
             .next()
             .is_some();
-
-        let (dict_assign, has_params_dict) = self.get_dict_assign();
-
         let params = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("params".into()));
-        let ident = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("t".into()));
-
-        let tpl = match has_params_dict {
-            true => self.get_for_loop_tuple(&ident, &params),
-            false => ident.clone(),
-        };
-        let new_collector = self.get_task_collector(&ident);
 
         let mut kwargs;
         let args;
@@ -228,16 +382,46 @@ where
                 dependencies = AOption(ROption::RSome(left));
             }
         }
-
-        let singleton = T::new(
+        (args, kwargs, dependencies)
+    }
+    fn get_singleton(
+        &self,
+        args: AVec<AST>,
+        kwargs: LinkedHashMap<AString, AST>,
+        dependencies: AOption<AST>,
+        endpoints: U::TEndpoints,
+    ) -> T {
+        let ident = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("t".into()));
+        T::new(
             self.task_id.clone(),
-            new_collector.clone(),
+            self.get_task_collector(&ident),
             self.key.get_call(),
             args,
             kwargs,
             dependencies,
             self.key.get_preamble(),
             self.key.get_dialect(),
+            endpoints,
+        )
+    }
+    pub fn get_statements(
+        &self,
+        endpoints: U::TEndpoints,
+    ) -> (AVec<AST>, AVec<PythonPreamble>, AVec<PythonImport>) {
+        let (dict_assign, has_params_dict) = self.get_dict_assign();
+
+        let params = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("params".into()));
+        let ident = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("t".into()));
+
+        let tpl = match has_params_dict {
+            true => self.get_for_loop_tuple(&ident, &params),
+            false => ident.clone(),
+        };
+        let (args, kwargs, dependencies) = self.get_singleton_inputs();
+        let singleton = self.get_singleton(
+            args.clone(),
+            kwargs.clone(),
+            dependencies,
             endpoints.clone(),
         );
         let statements = singleton.get_statements();
@@ -253,16 +437,176 @@ where
             )),
             false => self.params_dict_name.clone(),
         };
+        if let AOption(ROption::RSome((mapped_statements, imports))) =
+            self.get_mapped_statements(&singleton, &tpl, &items_call, args, kwargs, endpoints)
+        {
+            let statements = vec![dict_assign]
+                .into_iter()
+                .chain(mapped_statements.into_iter())
+                .collect();
+            set_task_ancestors(&statements, &self.get_ancestors());
+            return (
+                statements,
+                // TODO: propagate erorr type here
+                singleton.get_preamble().unwrap(),
+                imports,
+            );
+        }
         let for_loop = AST::ForLoop(ForLoop::new_wrapped(
             tpl.clone(),
             items_call,
             statements.clone(),
         ));
+        let statements = vec![dict_assign, for_loop].into_iter().collect();
+        set_task_ancestors(&statements, &self.get_ancestors());
         (
-            vec![dict_assign, for_loop].into_iter().collect(),
+            statements,
             // TODO: propagate erorr type here
             singleton.get_preamble().unwrap(),
             singleton.get_imports(),
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialect::{Bash, Dialect};
+    use crate::endpoints::EndpointConfig;
+    use crate::flow::{Airflow2PythonBasedFlow, AirflowPythonBasedFlow};
+    use crate::parameter_tuple::ParameterTuple;
+    use crate::universe::Universe;
+    use aorist_ast::to_python_source_lines;
+    use aorist_primitives::AoristRef;
+
+    type Airflow2Task =
+        ForLoopPythonBasedTask<Airflow2PythonBasedFlow<AoristRef<Universe>>, AoristRef<Universe>>;
+    type AirflowTask =
+        ForLoopPythonBasedTask<AirflowPythonBasedFlow<AoristRef<Universe>>, AoristRef<Universe>>;
+
+    fn endpoints() -> EndpointConfig {
+        EndpointConfig {
+            presto: AOption(ROption::RNone),
+            alluxio: AOption(ROption::RNone),
+            ranger: AOption(ROption::RNone),
+            gitea: AOption(ROption::RNone),
+            minio: AOption(ROption::RNone),
+            postgres: AOption(ROption::RNone),
+            gcp: AOption(ROption::RNone),
+            aws: AOption(ROption::RNone),
+            pdal: AOption(ROption::RNone),
+            linz: AOption(ROption::RNone),
+            dask: AOption(ROption::RNone),
+            gdal: AOption(ROption::RNone),
+            tag: AOption(ROption::RNone),
+            uuid: AOption(ROption::RNone),
+        }
+    }
+    fn identifier(name: &str) -> AST {
+        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(name.into()))
+    }
+    fn string(value: &str) -> AST {
+        AST::StringLiteral(StringLiteral::new_wrapped(value.into(), false))
+    }
+    /// Two Bash downloads differing only in their url, compressed into a
+    /// for loop over `params_download`.
+    fn compressed<T>() -> ForLoopPythonBasedTask<T, AoristRef<Universe>>
+    where
+        T: ETLFlow<AoristRef<Universe>, ImportType = PythonImport, PreambleType = PythonPreamble>,
+    {
+        let key = PythonBasedTaskCompressionKey::new(
+            identifier("tasks_download"),
+            AOption(ROption::RSome("curl {url}".into())),
+            AOption(ROption::RSome((
+                0,
+                vec!["url".into()].into_iter().collect(),
+            ))),
+            AOption(ROption::RNone),
+            AOption(ROption::RSome(Dialect::Bash(Bash::new()))),
+        );
+        let values = ["download_a", "download_b"]
+            .iter()
+            .map(|name| {
+                let mut kwargs = LinkedHashMap::new();
+                kwargs.insert("url".into(), string(&format!("https://{}", name)));
+                PythonBasedTaskUncompressiblePart::new(
+                    (*name).into(),
+                    (*name).into(),
+                    AOption(ROption::RSome(ParameterTuple {
+                        args: AVec::new(),
+                        kwargs,
+                    })),
+                    AVec::new(),
+                    AVec::new(),
+                )
+            })
+            .collect();
+        let task_id = AST::Subscript(Subscript::new_wrapped(
+            identifier("params"),
+            string("task_id"),
+            false,
+        ));
+        ForLoopPythonBasedTask::new(
+            identifier("params_download"),
+            key,
+            values,
+            task_id,
+            true,
+            true,
+        )
+    }
+
+    #[test]
+    fn test_mapped_task_renders_partial_expand() {
+        let task: Airflow2Task = compressed();
+        let task_id = task.get_mapped_task_id();
+        assert!(task_id.as_str().starts_with("download__"));
+        let (statements, _, _) = task.get_statements(endpoints());
+        let expected = [
+            format!("inputs_{} = []", task_id),
+            "for t, params in params_download.items():".to_string(),
+            format!(
+                "    inputs_{}.append(\"curl {{url}}\".format(url=params[\"url\"]))",
+                task_id
+            ),
+            format!("mapped_{} = (", task_id),
+            format!("    BashOperator.partial(task_id=\"{}\").expand(", task_id),
+            format!("        bash_command=inputs_{}", task_id),
+            "    )".to_string(),
+            ")".to_string(),
+            "for t in params_download:".to_string(),
+            format!("    tasks_download[t] = mapped_{}", task_id),
+        ]
+        .join("\n");
+        assert_eq!(
+            to_python_source_lines(&statements[1..]),
+            format!("{}\n", expected)
+        );
+    }
+    #[test]
+    fn test_mapped_task_ids_cover_replaced_tasks() {
+        let task: Airflow2Task = compressed();
+        let task_ids = task.get_mapped_task_ids(endpoints());
+        let task_id = task.get_mapped_task_id();
+        assert_eq!(
+            task_ids.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("download_a".into(), task_id.clone()),
+                ("download_b".into(), task_id),
+            ]
+        );
+    }
+    #[test]
+    fn test_unmapped_flows_keep_task_ids() {
+        let task: AirflowTask = compressed();
+        assert!(task.get_mapped_task_ids(endpoints()).is_empty());
+        let (statements, _, _) = task.get_statements(endpoints());
+        let source = to_python_source_lines(&statements);
+        assert!(!source.contains(".partial("), "{}", source);
+        assert!(
+            source.contains("for t, params in params_download.items():"),
+            "{}",
+            source
+        );
+    }
+}
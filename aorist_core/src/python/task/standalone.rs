@@ -1,12 +1,14 @@
 use crate::dialect::Dialect;
 use crate::flow::{
-    CompressibleTask, CompressionKey, ETLFlow, StandaloneTask, TaskBase, UncompressiblePart,
+    set_task_ancestors, CompressibleTask, CompressionKey, ETLFlow, StandaloneTask, TaskBase,
+    UncompressiblePart,
 };
 use crate::parameter_tuple::ParameterTuple;
 use crate::python::task::key::PythonBasedTaskCompressionKey;
 use crate::python::task::uncompressible::PythonBasedTaskUncompressiblePart;
 use crate::python::{List, PythonImport, PythonPreamble, StringLiteral, AST};
 use abi_stable::std_types::ROption;
+use aorist_ast::AncestorRecord;
 use aorist_primitives::AOption;
 use aorist_primitives::{AString, AVec, AoristUniverse};
 use linked_hash_map::LinkedHashMap;
@@ -36,6 +38,8 @@ where
     /// Dialect (e.g. Bash, Python, R, Presto, etc.), to be interpreted
     /// by render.
     dialect: AOption<Dialect>,
+    /// Ancestors of the task's root concept, followed by its constraint.
+    ancestors: AVec<AncestorRecord>,
     singleton_type: PhantomData<T>,
     _universe: PhantomData<U>,
 }
@@ -59,6 +63,7 @@ where
         dependencies: AVec<AST>,
         preamble: AOption<AString>,
         dialect: AOption<Dialect>,
+        ancestors: AVec<AncestorRecord>,
     ) -> Self {
         Self {
             task_id,
@@ -68,6 +73,7 @@ where
             dependencies,
             preamble,
             dialect,
+            ancestors,
             singleton_type: PhantomData,
             _universe: PhantomData,
         }
@@ -144,6 +150,7 @@ where
             self.get_right_of_task_val()?,
             self.params.clone(),
             self.dependencies.clone(),
+            self.ancestors.clone(),
         ))
     }
     pub fn get_statements(
@@ -176,8 +183,10 @@ where
             self.get_dialect(),
             endpoints.clone(),
         );
+        let statements = singleton.get_statements();
+        set_task_ancestors(&statements, &self.ancestors);
         (
-            singleton.get_statements(),
+            statements,
             // TODO: propagate erorr type here
             singleton.get_preamble().unwrap(),
             singleton.get_imports(),
@@ -1,7 +1,7 @@
 use crate::flow::{ETLFlow, UncompressiblePart};
 use crate::parameter_tuple::ParameterTuple;
 use abi_stable::std_types::ROption;
-use aorist_ast::{Dict, List, StringLiteral, AST};
+use aorist_ast::{AncestorRecord, Dict, List, StringLiteral, AST};
 use aorist_primitives::AOption;
 use aorist_primitives::{AString, AVec, AoristUniverse};
 use linked_hash_map::LinkedHashMap;
@@ -22,6 +22,8 @@ where
     pub params: AOption<ParameterTuple>,
     // dep list
     pub deps: AVec<AST>,
+    // ancestors of the task's root concept, followed by its constraint
+    pub ancestors: AVec<AncestorRecord>,
     singleton_type: PhantomData<T>,
     _universe: PhantomData<U>,
 }
@@ -35,12 +37,14 @@ where
         dict: AString,
         params: AOption<ParameterTuple>,
         deps: AVec<AST>,
+        ancestors: AVec<AncestorRecord>,
     ) -> Self {
         Self {
             task_id,
             dict,
             params,
             deps,
+            ancestors,
             singleton_type: PhantomData,
             _universe: PhantomData,
         }
@@ -63,7 +67,12 @@ where
             local_params_map.insert("dependencies".into(), dependencies);
         }
         // TODO: get_type should return an enum
-        if insert_task_name && T::get_type().as_str() == "airflow" {
+        if insert_task_name
+            && matches!(
+                T::get_type().as_str(),
+                "airflow" | "airflow2" | "dagster" | "prefect2" | "dask"
+            )
+        {
             local_params_map.insert(
                 "task_id".into(),
                 AST::StringLiteral(StringLiteral::new_wrapped(self.task_id.clone(), false)),
@@ -1,16 +1,13 @@
-use aorist_primitives::AOption;
-use abi_stable::std_types::ROption;
-use aorist_primitives::AVec;
 use crate::code::{CodeBlock, CodeBlockWithForLoopCompression};
-use crate::constraint::SatisfiableOuterConstraint;
-use crate::endpoints::EndpointConfig;
+use crate::constraint::OuterConstraint;
 use crate::flow::{CompressibleTask, ETLFlow, ETLTask, ForLoopCompressedTask};
 use crate::parameter_tuple::ParameterTuple;
-use crate::r::preamble::RPreamble;
-use crate::r::r_import::RImport;
-use crate::r::task::{ForLoopRBasedTask, RBasedTask};
+use crate::program::TOuterProgram;
+use crate::r::{ForLoopRBasedTask, RBasedTask, RImport, RPreamble};
+use abi_stable::std_types::ROption;
 use aorist_ast::{Formatted, SimpleIdentifier, StringLiteral, Subscript, AST};
-use aorist_primitives::OuterConstraint;
+use aorist_primitives::AOption;
+use aorist_primitives::{AString, AVec, AoristUniverse};
 use linked_hash_map::LinkedHashMap;
 use linked_hash_set::LinkedHashSet;
 use std::collections::{BTreeSet, HashMap, HashSet};
@@ -18,28 +15,30 @@ use std::marker::PhantomData;
 use tracing::trace;
 use uuid::Uuid;
 
-pub struct RBasedCodeBlock<'a, 'b, T, C>
+pub struct RBasedCodeBlock<'a, T, C, U, P>
 where
-    T: ETLFlow<ImportType = RImport, PreambleType = RPreamble>,
-    C: OuterConstraint<'a, 'b> + SatisfiableOuterConstraint<'a, 'b>,
-    'a: 'b,
+    T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble>,
+    C: OuterConstraint<'a>,
+    U: AoristUniverse,
+    P: TOuterProgram<TAncestry = C::TAncestry>,
 {
     tasks_dict: AOption<AST>,
     task_identifiers: HashMap<Uuid, AST>,
-    tasks: AVec<RBasedTask<T>>,
+    r_based_tasks: AVec<RBasedTask<T, U>>,
     params: HashMap<AString, AOption<ParameterTuple>>,
     _lt: PhantomData<&'a ()>,
-    _lt2: PhantomData<&'b ()>,
     _constraint: PhantomData<C>,
+    _program: PhantomData<P>,
 }
-impl<'a, 'b, T, C> CodeBlock<'a, 'b, T, C> for RBasedCodeBlock<'a, 'b, T, C>
+impl<'a, T, C, U, P> CodeBlock<'a, T, C, U, P> for RBasedCodeBlock<'a, T, C, U, P>
 where
-    T: ETLFlow<ImportType = RImport, PreambleType = RPreamble>,
-    C: OuterConstraint<'a, 'b> + SatisfiableOuterConstraint<'a, 'b>,
-    'a: 'b,
+    T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble>,
+    C: OuterConstraint<'a>,
+    U: AoristUniverse,
+    P: TOuterProgram<TAncestry = C::TAncestry>,
 {
     type P = RPreamble;
-    type E = RBasedTask<T>;
+    type E = RBasedTask<T, U>;
 
     fn construct(
         tasks_dict: AOption<AST>,
@@ -49,25 +48,24 @@ where
     ) -> Self {
         Self {
             tasks_dict,
-            tasks,
+            r_based_tasks: tasks,
             task_identifiers,
             params,
             _lt: PhantomData,
-            _lt2: PhantomData,
             _constraint: PhantomData,
+            _program: PhantomData,
         }
     }
 
     fn get_statements(
         &self,
-        endpoints: &EndpointConfig,
+        endpoints: U::TEndpoints,
     ) -> (AVec<AST>, LinkedHashSet<RPreamble>, BTreeSet<RImport>) {
         let preambles_and_statements = self
-            .tasks
+            .r_based_tasks
             .iter()
-            .map(|x| x.get_statements(endpoints))
+            .map(|x| x.get_statements(endpoints.clone()))
             .collect::<AVec<_>>();
-        // TODO: get_statements should run for members of self.tasks
         let preambles = preambles_and_statements
             .iter()
             .map(|x| x.1.clone().into_iter())
@@ -97,32 +95,53 @@ where
         self.params.clone()
     }
 }
-impl<'a, 'b, T, C> CodeBlockWithForLoopCompression<'a, 'b, T, C> for RBasedCodeBlock<'a, 'b, T, C>
+impl<'a, T, C, U, P> CodeBlockWithForLoopCompression<'a, T, C, U, P>
+    for RBasedCodeBlock<'a, T, C, U, P>
 where
-    T: ETLFlow<ImportType = RImport, PreambleType = RPreamble>,
-    C: OuterConstraint<'a, 'b> + SatisfiableOuterConstraint<'a, 'b>,
-    'a: 'b,
+    T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble>,
+    C: OuterConstraint<'a>,
+    U: AoristUniverse,
+    P: TOuterProgram<TAncestry = C::TAncestry>,
 {
-    // TODO: push to trait
     fn run_task_compressions(
         compressible: LinkedHashMap<
-            <<Self::E as ETLTask<T>>::S as CompressibleTask>::KeyType,
-            AVec<<Self::E as ETLTask<T>>::S>,
+            <<Self::E as ETLTask<T, U>>::S as CompressibleTask>::KeyType,
+            AVec<<Self::E as ETLTask<T, U>>::S>,
         >,
-        python_based_tasks: &mut AVec<Self::E>,
+        r_based_tasks: &mut AVec<Self::E>,
         constraint_name: AString,
+        render_dependencies: bool,
     ) {
         for (mut compression_key, tasks) in compressible.into_iter() {
             let num_tasks = tasks.len();
             // TODO: this is a magic number
             if num_tasks > 1 {
+                trace!(
+                    "Running compression for {} tasks for constraint {}",
+                    num_tasks,
+                    constraint_name
+                );
                 let params_constraint = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped(
-                    format!("params_{}", constraint_name).to_string(),
+                    format!("params_{}", constraint_name).as_str().into(),
                 ));
                 let mut maybe_uncompressible = tasks
                     .into_iter()
                     .map(|x| x.get_uncompressible_part().unwrap())
                     .collect::<AVec<_>>();
+                trace!(
+                    "There are {} maybe_uncompressible tasks",
+                    maybe_uncompressible.len()
+                );
+                for v in maybe_uncompressible.iter() {
+                    trace!("-- {:?} : {:?}", v.dict, v.params);
+                }
+                let distinct_keys = maybe_uncompressible
+                    .iter()
+                    .map(|x| x.dict.clone())
+                    .collect::<std::collections::HashSet<_>>();
+                if distinct_keys.len() < maybe_uncompressible.len() {
+                    panic!("Tasks with same keys in for loop compression.");
+                }
 
                 let mut deps: HashMap<AST, HashSet<AString>> = HashMap::new();
                 let mut kwargs: LinkedHashMap<AString, HashMap<AST, HashSet<AString>>> =
@@ -131,20 +150,33 @@ where
                     LinkedHashMap::new();
                 let mut full_task_ids: LinkedHashMap<AST, HashSet<AString>> = LinkedHashMap::new();
 
-                for t in &maybe_uncompressible {
-                    for dep in &t.deps {
+                for t in maybe_uncompressible.iter() {
+                    for dep in t.deps.iter() {
                         deps.entry(dep.clone())
                             .or_insert(HashSet::new())
                             .insert(t.task_id.clone());
                     }
-                    let task_id_subscript = t.task_id.split("__").last().unwrap().to_string();
-                    let replaced = t.task_id.replace(&task_id_subscript, "{t}");
-                    let ident =
-                        AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("t".into()));
+                    let task_id_subscript = t
+                        .task_id
+                        .as_str()
+                        .to_string()
+                        .split("__")
+                        .last()
+                        .unwrap()
+                        .to_string();
+                    let replaced = t
+                        .task_id
+                        .as_str()
+                        .to_string()
+                        .replace(&task_id_subscript, "{t}");
+                    let ident = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("t".into()));
                     let mut kw = LinkedHashMap::new();
                     kw.insert("t".into(), ident);
                     let replacement = AST::Formatted(Formatted::new_wrapped(
-                        AST::StringLiteral(StringLiteral::new_wrapped(replaced, false)),
+                        AST::StringLiteral(StringLiteral::new_wrapped(
+                            replaced.as_str().into(),
+                            false,
+                        )),
                         kw,
                     ));
                     full_task_ids
@@ -152,12 +184,12 @@ where
                         .or_insert(HashSet::new())
                         .insert(t.task_id.clone());
 
-                    if let Some(ref p) = t.params {
+                    if let AOption(ROption::RSome(ref p)) = t.params {
                         for (key, val) in p.kwargs.iter() {
                             let val_no_ancestors = val.clone_without_ancestors();
                             if let AST::StringLiteral(rw) = val {
                                 let x = rw.read();
-                                if x.value() == task_id_subscript {
+                                if x.value().as_str() == task_id_subscript.as_str() {
                                     // TODO: pass this to ForLoopETLFlow
                                     let ident = AST::SimpleIdentifier(
                                         SimpleIdentifier::new_wrapped("t".into()),
@@ -167,8 +199,9 @@ where
                                         .or_insert(HashSet::new())
                                         .insert(t.task_id.clone());
                                 } else {
-                                    let replaced = x.value().replace(&task_id_subscript, "{t}");
-                                    if replaced != x.value() {
+                                    let val = x.value().as_str().to_string();
+                                    let replaced = val.replace(&task_id_subscript, "{t}");
+                                    if replaced != val {
                                         let ident = AST::SimpleIdentifier(
                                             SimpleIdentifier::new_wrapped("t".into()),
                                         );
@@ -176,7 +209,8 @@ where
                                         kw.insert("t".into(), ident);
                                         let replacement = AST::Formatted(Formatted::new_wrapped(
                                             AST::StringLiteral(StringLiteral::new_wrapped(
-                                                replaced, false,
+                                                replaced.as_str().into(),
+                                                false,
                                             )),
                                             kw,
                                         ));
@@ -233,7 +267,7 @@ where
                             new_deps.push(dep.clone());
                         }
                     }
-                    if let Some(ref mut p) = t.params {
+                    if let AOption(ROption::RSome(ref mut p)) = t.params {
                         for key in compressible_kwargs.keys() {
                             trace!("Compressible kwarg: {}", key);
                             p.kwargs.remove(key);
@@ -252,28 +286,30 @@ where
                     1 => (full_task_ids.into_iter().next().unwrap().0, false),
                     _ => (
                         AST::Subscript(Subscript::new_wrapped(
-                            params_constraint.clone(),
-                            AST::StringLiteral(StringLiteral::new_wrapped(
-                                "task_id".into(),
-                                false,
-                            )),
+                            AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("params".into())),
+                            AST::StringLiteral(StringLiteral::new_wrapped("task_id".into(), false)),
                             false,
                         )),
                         true,
                     ),
                 };
 
+                trace!(
+                    "There are now {} maybe_uncompressible tasks",
+                    maybe_uncompressible.len()
+                );
                 let compressed_task = ForLoopRBasedTask::new(
                     params_constraint,
                     compression_key,
                     maybe_uncompressible,
                     task_id,
                     insert_task_name,
+                    render_dependencies,
                 );
-                python_based_tasks.push(RBasedTask::ForLoopRBasedTask(compressed_task));
+                r_based_tasks.push(RBasedTask::ForLoopRBasedTask(compressed_task));
             } else {
                 for task in tasks.into_iter() {
-                    python_based_tasks.push(RBasedTask::StandaloneRBasedTask(task));
+                    r_based_tasks.push(RBasedTask::StandaloneRBasedTask(task));
                 }
             }
         }
@@ -1,18 +1,18 @@
 use crate::r::r_import::RImport;
+use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
+use abi_stable::std_types::RArc;
 use aorist_ast::{Assignment, Call, Expression, SimpleIdentifier, AST};
-use aorist_primitives::{AString, AVec, define_task_node};
+use aorist_primitives::{define_task_node, AVec};
 use linked_hash_map::LinkedHashMap;
 use std::hash::Hash;
-use abi_stable::std_types::RArc;
-use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
 
 define_task_node!(
     ConstantRTask,
-    |task: &ConstantRTask| vec![task.name.clone()],
+    |task: &ConstantRTask| vec![task.name.clone()].into_iter().collect(),
     |task: &ConstantRTask| {
         let call = AST::Call(Call::new_wrapped(
             AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("print".into())),
-            vec![task.name.clone()],
+            vec![task.name.clone()].into_iter().collect(),
             LinkedHashMap::new(),
         ));
 
@@ -23,8 +23,10 @@ define_task_node!(
                 task.name.clone(),
             )),
         ]
+        .into_iter()
+        .collect()
     },
-    |_task: &ConstantRTask| { vec![] },
+    |_task: &ConstantRTask| { AVec::new() },
     RImport,
     name: AST,
     task_val: AST,
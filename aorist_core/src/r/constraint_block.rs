@@ -1,46 +1,45 @@
-use aorist_primitives::AOption;
-use abi_stable::std_types::ROption;
-use aorist_primitives::AVec;
 use crate::code::CodeBlock;
-use crate::constraint::SatisfiableOuterConstraint;
+use crate::constraint::OuterConstraint;
 use crate::constraint_block::ConstraintBlock;
 use crate::flow::ETLFlow;
 use crate::parameter_tuple::ParameterTuple;
-use crate::r::code_block::RBasedCodeBlock;
-use crate::r::r_import::RImport;
-use crate::r::RFlowBuilderInput;
-use crate::r::RPreamble;
+use crate::program::TOuterProgram;
+use crate::r::{RBasedCodeBlock, RFlowBuilderInput, RImport, RPreamble};
+use abi_stable::std_types::ROption;
 use aorist_ast::{Assignment, Dict, AST};
-use aorist_primitives::OuterConstraint;
+use aorist_primitives::AOption;
+use aorist_primitives::{AString, AVec, AoristUniverse};
 use linked_hash_map::LinkedHashMap;
 use std::collections::HashMap;
 use std::marker::PhantomData;
 use uuid::Uuid;
 
-pub struct RBasedConstraintBlock<'a, 'b, T, C>
+pub struct RBasedConstraintBlock<'a, T, C, U, P>
 where
-    T: ETLFlow<ImportType = RImport, PreambleType = RPreamble>,
-    C: OuterConstraint<'a, 'b> + SatisfiableOuterConstraint<'a, 'b>,
-    'a: 'b,
+    T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble>,
+    C: OuterConstraint<'a>,
+    U: AoristUniverse,
+    P: TOuterProgram<TAncestry = C::TAncestry>,
 {
     constraint_name: AString,
     title: AOption<AString>,
     body: AOption<AString>,
-    members: AVec<RBasedCodeBlock<'a, 'b, T, C>>,
+    members: AVec<RBasedCodeBlock<'a, T, C, U, P>>,
     tasks_dict: AOption<AST>,
     _lt: PhantomData<&'a ()>,
     _constraint: PhantomData<C>,
 }
-impl<'a, 'b, T, C> ConstraintBlock<'a, 'b, T, C> for RBasedConstraintBlock<'a, 'b, T, C>
+impl<'a, T, C, U, P> ConstraintBlock<'a, T, C, U, P> for RBasedConstraintBlock<'a, T, C, U, P>
 where
-    T: ETLFlow<ImportType = RImport, PreambleType = RPreamble>,
-    C: OuterConstraint<'a, 'b> + SatisfiableOuterConstraint<'a, 'b>,
-    'a: 'b,
+    T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble>,
+    C: OuterConstraint<'a>,
+    U: AoristUniverse,
+    P: TOuterProgram<TAncestry = C::TAncestry>,
 {
-    type C = RBasedCodeBlock<'a, 'b, T, C>;
+    type C = RBasedCodeBlock<'a, T, C, U, P>;
     type BuilderInputType = RFlowBuilderInput;
 
-    fn get_constraint_name(&self) -> String {
+    fn get_constraint_name(&self) -> AString {
         self.constraint_name.clone()
     }
     fn get_constraint_title(&self) -> AOption<AString> {
@@ -57,7 +56,7 @@ where
         constraint_name: AString,
         title: AOption<AString>,
         body: AOption<AString>,
-        members: AVec<RBasedCodeBlock<'a, 'b, T, C>>,
+        members: AVec<RBasedCodeBlock<'a, T, C, U, P>>,
         tasks_dict: AOption<AST>,
     ) -> Self {
         Self {
@@ -80,20 +79,23 @@ where
 
     fn get_task_val_assignments(&self) -> AVec<AST> {
         match &self.tasks_dict {
-            Some(ref val) => vec![AST::Assignment(Assignment::new_wrapped(
+            AOption(ROption::RSome(ref val)) => vec![AST::Assignment(Assignment::new_wrapped(
                 val.clone(),
                 AST::Dict(Dict::new_wrapped(LinkedHashMap::new())),
-            ))],
-            None => vec![],
+            ))]
+            .into_iter()
+            .collect(),
+            AOption(ROption::RNone) => vec![].into_iter().collect(),
         }
     }
 }
 
-impl<'a, 'b, T, C> RBasedConstraintBlock<'a, 'b, T, C>
+impl<'a, T, C, U, P> RBasedConstraintBlock<'a, T, C, U, P>
 where
-    T: ETLFlow<ImportType = RImport, PreambleType = RPreamble>,
-    C: OuterConstraint<'a, 'b> + SatisfiableOuterConstraint<'a, 'b>,
-    'a: 'b,
+    T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble>,
+    C: OuterConstraint<'a>,
+    U: AoristUniverse,
+    P: TOuterProgram<TAncestry = C::TAncestry>,
 {
     pub fn get_params(&self) -> HashMap<AString, AOption<ParameterTuple>> {
         self.members
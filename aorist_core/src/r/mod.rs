@@ -1,6 +1,3 @@
-use aorist_primitives::AOption;
-use abi_stable::std_types::ROption;
-use aorist_primitives::AVec;
 mod code_block;
 mod constant_r_task;
 mod constraint_block;
@@ -9,15 +6,17 @@ mod preamble;
 mod r_import;
 mod task;
 
+pub use code_block::RBasedCodeBlock;
 pub use constant_r_task::ConstantRTask;
 pub use constraint_block::RBasedConstraintBlock;
 pub use native_r_task::NativeRTask;
 pub use preamble::RPreamble;
 pub use r_import::RImport;
-pub use task::StandaloneRBasedTask;
+pub use task::{ForLoopRBasedTask, RBasedTask, StandaloneRBasedTask};
 
 use crate::flow::FlowBuilderInput;
 use aorist_ast::AST;
+use aorist_primitives::{AOption, AString, AVec};
 use linked_hash_set::LinkedHashSet;
 use std::collections::BTreeSet;
 
@@ -35,6 +34,26 @@ pub struct RFlowBuilderInput {
     constraint_title: AOption<AString>,
     constraint_body: AOption<AString>,
 }
+impl RFlowBuilderInput {
+    pub fn has_statements(&self) -> bool {
+        self.statements.len() > 0
+    }
+    pub fn statements_only(
+        statements: AVec<AST>,
+        constraint_name: AString,
+        constraint_title: AOption<AString>,
+        constraint_body: AOption<AString>,
+    ) -> Self {
+        Self::new(
+            statements,
+            LinkedHashSet::new(),
+            BTreeSet::new(),
+            constraint_name,
+            constraint_title,
+            constraint_body,
+        )
+    }
+}
 impl FlowBuilderInput for RFlowBuilderInput {
     type ImportType = RImport;
     type PreambleType = RPreamble;
@@ -65,7 +84,7 @@ impl FlowBuilderInput for RFlowBuilderInput {
     fn get_imports(&self) -> BTreeSet<RImport> {
         self.imports.clone()
     }
-    fn get_constraint_name(&self) -> String {
+    fn get_constraint_name(&self) -> AString {
         self.constraint_name.clone()
     }
     fn get_constraint_title(&self) -> AOption<AString> {
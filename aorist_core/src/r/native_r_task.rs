@@ -1,10 +1,9 @@
-
 use crate::r::r_import::RImport;
+use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
+use abi_stable::std_types::RArc;
 use aorist_ast::{Assignment, StringLiteral, AST};
-use aorist_primitives::{AString, AVec, define_task_node};
+use aorist_primitives::{define_task_node, AVec};
 use std::hash::Hash;
-use abi_stable::std_types::RArc;
-use abi_stable::external_types::parking_lot::rw_lock::RRwLock;
 
 define_task_node!(
     NativeRTask,
@@ -31,7 +30,7 @@ define_task_node!(
                     }
                 },
                 _ => panic!(
-                    "AST node of type {} found in NativePythonTask body",
+                    "AST node of type {} found in NativeRTask body",
                     statement.name()
                 ),
             });
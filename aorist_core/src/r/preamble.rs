@@ -1,9 +1,6 @@
-use aorist_primitives::AVec;
 use crate::code::Preamble;
-#[cfg(feature = "python")]
-use crate::python::PythonPreamble;
 use crate::r::r_import::RImport;
-use extendr_api::prelude::*;
+use aorist_primitives::{AString, AVec};
 use std::hash::Hash;
 
 #[derive(Clone, PartialEq, Hash, Eq)]
@@ -17,83 +14,245 @@ impl Preamble for RPreamble {
         self.libraries.clone()
     }
 }
-impl<'a> RPreamble {
-    // Assumes R has already been started
+impl RPreamble {
+    /// Splits the top-level `library` calls out of the R source `body`. The
+    /// rest of the source is kept as text, without its common indentation.
     pub fn new(body: AString) -> RPreamble {
-        eval_string(
-            r#"
-            to.preamble <- function(body) {
-                x <- as.list(parse(text=body))
-                is.library <- sapply(x, function(y) {
-                    if (class(y) == "call") {
-                      return(y[[1]] == "library")
-                    }
-                    return(FALSE)
-                })
-                call.idx <- which(is.library)
-                calls <- x[call.idx]
-                not.calls <- x[which(!is.library)]
-
-                body <- paste(sapply(
-                       not.calls,
-                       function(x) paste(deparse(x), collapse="\n")
-                ), collapse="\n\n")
-
-                libraries <- sapply(calls, function(x) x[[2]])
-                list(body=body, libraries=libraries)
+        let mut libraries = AVec::new();
+        let mut lines: Vec<(&str, bool)> = Vec::new();
+        let mut state = RLexState::default();
+        let mut continued = false;
+        for line in body.as_str().lines() {
+            let outside_string = state.quote.is_none();
+            if outside_string && state.depth == 0 && !continued {
+                if let Some(library) = parse_library_call(line) {
+                    libraries.push(RImport::new(library.as_str().into()));
+                    continue;
+                }
             }
-        "#,
-        )
-        .unwrap();
-
-        let res = call!("to.preamble", body).unwrap();
-        let body_no_imports = res.index(1).unwrap();
-        let libraries = res.index(2).unwrap();
+            let ends_with_operator = state.scan(line);
+            if state.depth == 0 && state.quote.is_none() && !line.trim().is_empty() {
+                continued = ends_with_operator;
+            }
+            lines.push((line, outside_string));
+        }
         Self {
-            libraries: match libraries.as_string_vector() {
-                Some(v) => v.into_iter().map(|x| RImport::new(x)).collect(),
-                None => AVec::new(),
-            },
-            body: body_no_imports.as_str().unwrap().to_string(),
+            libraries,
+            body: dedent(&lines).as_str().into(),
         }
     }
-    #[cfg(feature = "python")]
-    pub fn from_python(var_name: AString, body: AString) -> RPreamble {
-        let python_preamble = PythonPreamble::new(body);
-        let formatted = python_preamble.to_string().replace("'", "\\'");
+    /// Wraps a Python preamble so that it is run by reticulate in the
+    /// Python session embedded in R.
+    pub fn from_python(body: AString) -> RPreamble {
         Self {
-            libraries: vec![RImport::new("reticulate".into())],
-            body: format!("{} <- '\n{}'", var_name, formatted).to_string(),
+            libraries: vec![RImport::new("reticulate".into())]
+                .into_iter()
+                .collect(),
+            body: format!("py_run_string({})", to_r_string_literal(body.as_str()))
+                .as_str()
+                .into(),
         }
     }
-    pub fn get_body(&self) -> String {
+    pub fn get_body(&self) -> AString {
         self.body.clone()
     }
 }
 
-#[allow(unused_imports)]
+/// Double-quoted R string literal with the same contents as `s`.
+fn to_r_string_literal(s: &str) -> String {
+    let mut literal = String::with_capacity(s.len() + 2);
+    literal.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => literal.push_str("\\\\"),
+            '"' => literal.push_str("\\\""),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\t' => literal.push_str("\\t"),
+            _ => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// Where a scan of R source stopped: the number of open brackets and the
+/// quote character of the string literal left open, if any.
+#[derive(Default)]
+struct RLexState {
+    depth: usize,
+    quote: Option<char>,
+}
+impl RLexState {
+    /// Scans one line of R source, returning whether its code ends with a
+    /// binary operator or a comma, i.e. the expression continues on the next
+    /// line.
+    fn scan(&mut self, line: &str) -> bool {
+        let mut last = None;
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            match self.quote {
+                Some(q) => match c {
+                    '\\' => {
+                        chars.next();
+                    }
+                    _ if c == q => {
+                        self.quote = None;
+                        last = Some(c);
+                    }
+                    _ => {}
+                },
+                None => match c {
+                    '#' => break,
+                    '"' | '\'' | '`' => self.quote = Some(c),
+                    '(' | '[' | '{' => self.depth += 1,
+                    ')' | ']' | '}' => self.depth = self.depth.saturating_sub(1),
+                    _ => {}
+                },
+            }
+            if self.quote.is_none() && !c.is_whitespace() && c != '#' {
+                last = Some(c);
+            }
+        }
+        self.quote.is_none()
+            && matches!(
+                last,
+                Some('+' | '-' | '*' | '/' | '^' | '<' | '>' | '=' | '&' | '|' | '~' | '%' | ',')
+            )
+    }
+}
+
+/// Code on the R source `line`, without its trailing comment.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '#' => return &line[..i],
+            None if c == '"' || c == '\'' || c == '`' => quote = Some(c),
+            None => {}
+        }
+    }
+    line
+}
+
+/// Name of the package loaded by `line`, if it is a `library` call
+/// (optionally followed by a semicolon or a comment) and nothing else.
+fn parse_library_call(line: &str) -> Option<String> {
+    let code = strip_comment(line).trim().trim_end_matches(';').trim_end();
+    let arg = code
+        .strip_prefix("library")?
+        .trim_start()
+        .strip_prefix('(')?
+        .strip_suffix(')')?
+        .trim();
+    let name = match arg.chars().next()? {
+        q @ ('"' | '\'') => arg.strip_prefix(q)?.strip_suffix(q)?,
+        _ => arg,
+    };
+    match !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_')
+    {
+        true => Some(name.to_string()),
+        false => None,
+    }
+}
+
+/// Joins the lines, without leading or trailing blank lines, removing the
+/// indentation shared by the lines that start outside string literals (the
+/// other lines are kept as they are).
+fn dedent(lines: &[(&str, bool)]) -> String {
+    let indent = lines
+        .iter()
+        .filter(|(line, outside_string)| *outside_string && !line.trim().is_empty())
+        .map(|(line, _)| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|(line, outside_string)| match outside_string {
+            true if line.trim().is_empty() => "",
+            true => line.get(indent..).unwrap_or_else(|| line.trim_start()),
+            false => line,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_matches('\n')
+        .to_string()
+}
+
+#[cfg(test)]
 mod r_test_preamble {
     use crate::r::preamble::RPreamble;
-    use extendr_api::prelude::*;
     #[test]
     fn test_basic_preamble() {
-        test! {
-            let body = r#"
+        let body = r#"
             library('ggplot2')
-            library('igraph')
+            library("igraph") # plotting
             c(1)
             f <- function(a, b) {
               a + b
             }
             "#;
-            let preamble = RPreamble::new(body.to_string());
-            assert_eq!(preamble.libraries.get(0).unwrap().library, "ggplot2");
-            assert_eq!(preamble.libraries.get(1).unwrap().library, "igraph");
-            assert_eq!(preamble.body, r#"c(1)
-
+        let preamble = RPreamble::new(body.into());
+        assert_eq!(
+            preamble.libraries.get(0).unwrap().library.as_str(),
+            "ggplot2"
+        );
+        assert_eq!(
+            preamble.libraries.get(1).unwrap().library.as_str(),
+            "igraph"
+        );
+        assert_eq!(
+            preamble.body.as_str(),
+            r#"c(1)
 f <- function(a, b) {
-    a + b
-}"#);
-        }
+  a + b
+}"#
+        );
+    }
+    #[test]
+    fn test_nested_library_calls_stay_in_body() {
+        let body = r#"library(DBI)
+f <- function() {
+library(RPresto)
+}
+g <- "
+library(glue)
+"
+x <-
+library(jsonlite)"#;
+        let preamble = RPreamble::new(body.into());
+        assert_eq!(preamble.libraries.len(), 1);
+        assert_eq!(preamble.libraries.get(0).unwrap().library.as_str(), "DBI");
+        assert_eq!(
+            preamble.body.as_str(),
+            r#"f <- function() {
+library(RPresto)
+}
+g <- "
+library(glue)
+"
+x <-
+library(jsonlite)"#
+        );
+    }
+    #[test]
+    fn test_python_preamble() {
+        let preamble = RPreamble::from_python("def f(x):\n    return \"a\\b\"\n".into());
+        assert_eq!(
+            preamble.libraries.get(0).unwrap().library.as_str(),
+            "reticulate"
+        );
+        assert_eq!(
+            preamble.body.as_str(),
+            r#"py_run_string("def f(x):\n    return \"a\\b\"\n")"#
+        );
     }
 }
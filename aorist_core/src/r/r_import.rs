@@ -1,6 +1,9 @@
 use crate::code::Import;
 use aorist_ast::{Call, SimpleIdentifier, StringLiteral, AST};
-use extendr_api::prelude::*;
+#[cfg(feature = "r")]
+use aorist_extendr_api::prelude::*;
+use aorist_primitives::AString;
+use linked_hash_map::LinkedHashMap;
 use std::hash::Hash;
 
 #[derive(Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -12,15 +15,20 @@ impl RImport {
     pub fn new(library: AString) -> Self {
         Self { library }
     }
-    pub fn to_r_ast_node(&self, depth: usize) -> Robj {
+    pub fn get_statement(&self) -> AST {
         AST::Call(Call::new_wrapped(
             AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("library".into())),
             vec![AST::StringLiteral(StringLiteral::new_wrapped(
                 self.library.clone(),
                 false,
-            ))],
-            linked_hash_map::LinkedHashMap::new(),
+            ))]
+            .into_iter()
+            .collect(),
+            LinkedHashMap::new(),
         ))
-        .to_r_ast_node(depth)
+    }
+    #[cfg(feature = "r")]
+    pub fn to_r_ast_node(&self, depth: usize) -> Result<Robj> {
+        self.get_statement().to_r_ast_node(depth)
     }
 }
@@ -1,43 +1,61 @@
-
-use crate::endpoints::EndpointConfig;
-use crate::flow::{CompressionKey, ETLFlow, ForLoopCompressedTask, TaskBase, UncompressiblePart};
+use crate::flow::{
+    set_task_ancestors, CompressionKey, ETLFlow, ForLoopCompressedTask, TaskBase,
+    UncompressiblePart,
+};
 use crate::r::preamble::RPreamble;
 use crate::r::r_import::RImport;
 use crate::r::task::key::RBasedTaskCompressionKey;
 use crate::r::task::uncompressible::RBasedTaskUncompressiblePart;
+use abi_stable::std_types::ROption;
 use aorist_ast::{
-    Add, Assignment, BigIntLiteral, BinOp, Dict, ForLoop, List, SimpleIdentifier, StringLiteral,
-    Subscript, AST,
+    AncestorRecord, Assignment, BigIntLiteral, Call, Dict, ForLoop, List, SimpleIdentifier,
+    StringLiteral, Subscript, AST,
 };
+use aorist_primitives::AOption;
+use aorist_primitives::{AVec, AoristUniverse};
 use linked_hash_map::LinkedHashMap;
 use std::hash::Hash;
 use std::marker::PhantomData;
+use tracing::trace;
 
 #[derive(Clone, Hash, PartialEq, Eq)]
-pub struct ForLoopRBasedTask<T>
+pub struct ForLoopRBasedTask<T, U>
 where
-    T: ETLFlow<ImportType = RImport>,
+    T: ETLFlow<U, ImportType = RImport>,
+    U: AoristUniverse,
 {
     params_dict_name: AST,
     key: RBasedTaskCompressionKey,
-    values: AVec<RBasedTaskUncompressiblePart<T>>,
+    values: AVec<RBasedTaskUncompressiblePart<T, U>>,
     singleton_type: PhantomData<T>,
     task_id: AST,
     insert_task_name: bool,
+    _universe: PhantomData<U>,
 }
-impl<T> ForLoopCompressedTask<T> for ForLoopRBasedTask<T>
+impl<T, U> ForLoopCompressedTask<T, U> for ForLoopRBasedTask<T, U>
 where
-    T: ETLFlow<ImportType = RImport, PreambleType = RPreamble>,
+    T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble>,
+    U: AoristUniverse,
 {
     type KeyType = RBasedTaskCompressionKey;
-    type UncompressiblePartType = RBasedTaskUncompressiblePart<T>;
+    type UncompressiblePartType = RBasedTaskUncompressiblePart<T, U>;
     fn new(
         params_dict_name: AST,
         key: RBasedTaskCompressionKey,
-        values: AVec<RBasedTaskUncompressiblePart<T>>,
+        values: AVec<RBasedTaskUncompressiblePart<T, U>>,
         task_id: AST,
         insert_task_name: bool,
+        // R scripts run in order, so dependencies are never rendered
+        _render_dependencies: bool,
     ) -> Self {
+        trace!("New compressed task with key: {:?}", key);
+        let distinct_keys = values
+            .iter()
+            .map(|x| x.dict.clone())
+            .collect::<std::collections::HashSet<_>>();
+        if distinct_keys.len() < values.len() {
+            panic!("Tasks with same keys in for loop compression.");
+        }
         Self {
             params_dict_name,
             key,
@@ -45,43 +63,70 @@ where
             task_id,
             insert_task_name,
             singleton_type: PhantomData,
+            _universe: PhantomData,
         }
     }
 }
-impl<T> TaskBase<T> for ForLoopRBasedTask<T> where
-    T: ETLFlow<ImportType = RImport, PreambleType = RPreamble>
+impl<T, U> TaskBase<T, U> for ForLoopRBasedTask<T, U>
+where
+    T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble>,
+    U: AoristUniverse,
 {
 }
 
-impl<T> ForLoopRBasedTask<T>
+impl<T, U> ForLoopRBasedTask<T, U>
 where
-    T: ETLFlow<ImportType = RImport, PreambleType = RPreamble>,
+    T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble>,
+    U: AoristUniverse,
 {
-    fn get_dict_assign(&self) -> AST {
-        let dependencies_as_list = self
+    fn get_dict_assign(&self) -> (AST, bool) {
+        let dict_pairs = self
             .values
             .iter()
-            .filter(|x| x.deps.len() > 1)
+            .map(|x| {
+                (
+                    x.dict.clone(),
+                    x.as_dict(false, false, self.insert_task_name),
+                )
+            })
+            .collect::<LinkedHashMap<_, _>>();
+        let has_params_dict = dict_pairs
+            .iter()
+            .filter(|x| {
+                if let AST::Dict(ref dict) = x.1 {
+                    dict.read().len() > 0
+                } else {
+                    panic!("This should be a dictionary.");
+                }
+            })
             .next()
             .is_some();
-        let dict_content = AST::Dict(Dict::new_wrapped(
-            self.values
-                .iter()
-                .map(|x| {
-                    (
-                        x.dict.clone(),
-                        x.as_dict(dependencies_as_list, self.insert_task_name),
-                    )
-                })
-                .collect(),
-        ));
-        AST::Assignment(Assignment::new_wrapped(
-            self.params_dict_name.clone(),
-            dict_content,
-        ))
+
+        let dict_content = match has_params_dict {
+            true => AST::Dict(Dict::new_wrapped(dict_pairs)),
+            false => AST::List(List::new_wrapped(
+                dict_pairs
+                    .into_iter()
+                    .map(|x| AST::StringLiteral(StringLiteral::new_wrapped(x.0, false)))
+                    .collect(),
+                false,
+            )),
+        };
+        (
+            AST::Assignment(Assignment::new_wrapped(
+                self.params_dict_name.clone(),
+                dict_content,
+            )),
+            has_params_dict,
+        )
     }
-    fn get_for_loop_tuple(&self, _ident: &AST, params: &AST) -> AST {
-        params.clone()
+    /// Ancestors of every task run by the for loop, one after the other.
+    fn get_ancestors(&self) -> AVec<AncestorRecord> {
+        self.values
+            .iter()
+            .map(|x| x.ancestors.iter().cloned())
+            .flatten()
+            .collect()
     }
     fn get_task_collector(&self, ident: &AST) -> AST {
         AST::Subscript(Subscript::new_wrapped(
@@ -90,28 +135,21 @@ where
             false,
         ))
     }
+    /// Loops over the names of the params list (or over the list of names,
+    /// if no task has params), binding each task's params to `params`.
     pub fn get_statements(
         &self,
-        endpoints: &EndpointConfig,
+        endpoints: U::TEndpoints,
     ) -> (AVec<AST>, AVec<RPreamble>, AVec<RImport>) {
-        let any_dependencies = self
-            .values
-            .iter()
-            .filter(|x| x.deps.len() > 0)
-            .next()
-            .is_some();
-
-        let dict_assign = self.get_dict_assign();
+        let (dict_assign, has_params_dict) = self.get_dict_assign();
 
         let params = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("params".into()));
         let ident = AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("t".into()));
-
-        let tpl = self.get_for_loop_tuple(&ident, &params);
         let new_collector = self.get_task_collector(&ident);
 
         let mut kwargs;
         let args;
-        if let Some((num_args, kwarg_keys)) = self.key.get_dedup_key() {
+        if let AOption(ROption::RSome((num_args, kwarg_keys))) = self.key.get_dedup_key() {
             kwargs = kwarg_keys
                 .iter()
                 .map(|x| {
@@ -119,25 +157,23 @@ where
                         x.clone(),
                         AST::Subscript(Subscript::new_wrapped(
                             params.clone(),
-                            AST::StringLiteral(StringLiteral::new_wrapped(x.to_string(), false)),
+                            AST::StringLiteral(StringLiteral::new_wrapped(x.clone(), false)),
                             false,
                         )),
                     )
                 })
                 .collect::<LinkedHashMap<_, _>>();
 
+            // R lists are 1-indexed
             args = (0..num_args)
                 .map(|x| {
                     AST::Subscript(Subscript::new_wrapped(
                         AST::Subscript(Subscript::new_wrapped(
                             params.clone(),
-                            AST::StringLiteral(StringLiteral::new_wrapped(
-                                "args".into(),
-                                false,
-                            )),
+                            AST::StringLiteral(StringLiteral::new_wrapped("args".into(), false)),
                             false,
                         )),
-                        AST::BigIntLiteral(BigIntLiteral::new_wrapped(x as i64)),
+                        AST::BigIntLiteral(BigIntLiteral::new_wrapped(x as i64 + 1)),
                         false,
                     ))
                 })
@@ -149,27 +185,6 @@ where
         for (k, v) in &self.key.kwargs {
             kwargs.insert(k.clone(), v.clone());
         }
-        let mut dependencies = match any_dependencies {
-            true => Some(AST::Subscript(Subscript::new_wrapped(
-                params.clone(),
-                AST::StringLiteral(StringLiteral::new_wrapped(
-                    "dependencies".into(),
-                    false,
-                )),
-                false,
-            ))),
-            false => None,
-        };
-        let compressed_dependencies = self.key.deps.clone();
-        if compressed_dependencies.len() > 0 {
-            let left = AST::List(List::new_wrapped(compressed_dependencies, false));
-            if let Some(ref right) = dependencies {
-                let op = AST::Add(Add::new_wrapped());
-                dependencies = Some(AST::BinOp(BinOp::new_wrapped(left, op, right.clone())));
-            } else {
-                dependencies = Some(left);
-            }
-        }
 
         let singleton = T::new(
             self.task_id.clone(),
@@ -177,20 +192,39 @@ where
             self.key.get_call(),
             args,
             kwargs,
-            dependencies,
+            AOption(ROption::RNone),
             self.key.get_preamble(),
             self.key.get_dialect(),
-            endpoints.clone(),
+            endpoints,
         );
-        let statements = singleton.get_statements();
-        let for_loop = AST::ForLoop(ForLoop::new_wrapped(
-            tpl.clone(),
-            self.params_dict_name.clone(),
-            statements.clone(),
-        ));
+        let (iter, body) = match has_params_dict {
+            true => (
+                AST::Call(Call::new_wrapped(
+                    AST::SimpleIdentifier(SimpleIdentifier::new_wrapped("names".into())),
+                    vec![self.params_dict_name.clone()].into_iter().collect(),
+                    LinkedHashMap::new(),
+                )),
+                vec![AST::Assignment(Assignment::new_wrapped(
+                    params,
+                    AST::Subscript(Subscript::new_wrapped(
+                        self.params_dict_name.clone(),
+                        ident.clone(),
+                        false,
+                    )),
+                ))]
+                .into_iter()
+                .chain(singleton.get_statements().into_iter())
+                .collect(),
+            ),
+            false => (self.params_dict_name.clone(), singleton.get_statements()),
+        };
+        let for_loop = AST::ForLoop(ForLoop::new_wrapped(ident, iter, body));
+        let statements = vec![dict_assign, for_loop].into_iter().collect();
+        set_task_ancestors(&statements, &self.get_ancestors());
         (
-            vec![dict_assign, for_loop],
-            singleton.get_preamble(),
+            statements,
+            // TODO: propagate erorr type here
+            singleton.get_preamble().unwrap(),
             singleton.get_imports(),
         )
     }
@@ -1,10 +1,9 @@
-use aorist_primitives::AOption;
-use abi_stable::std_types::ROption;
-use aorist_primitives::AVec;
 use crate::dialect::Dialect;
 use crate::flow::CompressionKey;
 use crate::parameter_tuple::ParameterTupleDedupKey;
 use aorist_ast::AST;
+use aorist_primitives::AOption;
+use aorist_primitives::{AString, AVec};
 use linked_hash_map::LinkedHashMap;
 use std::hash::Hash;
 
@@ -16,7 +15,7 @@ use std::hash::Hash;
 ///   - names of kwargs
 /// - preamble
 /// - dialect
-#[derive(Clone, Hash, PartialEq, Eq)]
+#[derive(Clone, Hash, PartialEq, Eq, Debug)]
 pub struct RBasedTaskCompressionKey {
     // dict name
     dict_name: AST,
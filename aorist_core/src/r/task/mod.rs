@@ -1,47 +1,50 @@
-
-use crate::endpoints::EndpointConfig;
-use crate::flow::{CompressibleETLTask, ETLFlow, ETLTask, TaskBase};
-use crate::r::preamble::RPreamble;
-use crate::r::r_import::RImport;
-use aorist_ast::AST;
-
 mod compressed;
 mod key;
 mod standalone;
 mod uncompressible;
 
 pub use compressed::*;
-pub use key::*;
 pub use standalone::*;
-pub use uncompressible::*;
 
-pub enum RBasedTask<T>
+use crate::flow::{CompressibleETLTask, ETLFlow, ETLTask, TaskBase};
+use crate::r::preamble::RPreamble;
+use crate::r::r_import::RImport;
+use aorist_ast::AST;
+use aorist_primitives::{AVec, AoristUniverse};
+
+pub enum RBasedTask<T, U>
 where
-    T: ETLFlow<ImportType = RImport, PreambleType = RPreamble>,
+    T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble>,
+    U: AoristUniverse,
 {
-    StandaloneRBasedTask(StandaloneRBasedTask<T>),
-    ForLoopRBasedTask(ForLoopRBasedTask<T>),
+    StandaloneRBasedTask(StandaloneRBasedTask<T, U>),
+    ForLoopRBasedTask(ForLoopRBasedTask<T, U>),
 }
-impl<T> ETLTask<T> for RBasedTask<T>
+impl<T, U> ETLTask<T, U> for RBasedTask<T, U>
 where
-    T: ETLFlow<ImportType = RImport, PreambleType = RPreamble>,
+    T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble>,
+    U: AoristUniverse,
 {
-    type S = StandaloneRBasedTask<T>;
+    type S = StandaloneRBasedTask<T, U>;
     fn standalone_task(task: Self::S) -> Self {
         Self::StandaloneRBasedTask(task)
     }
 }
-impl<T> TaskBase<T> for RBasedTask<T> where
-    T: ETLFlow<ImportType = RImport, PreambleType = RPreamble>
+impl<T, U> CompressibleETLTask<T, U> for RBasedTask<T, U>
+where
+    T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble>,
+    U: AoristUniverse,
 {
+    type F = ForLoopRBasedTask<T, U>;
 }
-impl<T> RBasedTask<T>
+impl<T, U> RBasedTask<T, U>
 where
-    T: ETLFlow<ImportType = RImport, PreambleType = RPreamble>,
+    T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble>,
+    U: AoristUniverse,
 {
     pub fn get_statements(
         &self,
-        endpoints: &EndpointConfig,
+        endpoints: U::TEndpoints,
     ) -> (AVec<AST>, AVec<RPreamble>, AVec<RImport>) {
         match &self {
             RBasedTask::StandaloneRBasedTask(x) => x.get_statements(endpoints),
@@ -49,9 +52,9 @@ where
         }
     }
 }
-impl<T> CompressibleETLTask<T> for RBasedTask<T>
+impl<T, U> TaskBase<T, U> for RBasedTask<T, U>
 where
-    T: ETLFlow<ImportType = RImport, PreambleType = RPreamble>,
+    T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble>,
+    U: AoristUniverse,
 {
-    type F = ForLoopRBasedTask<T>;
 }
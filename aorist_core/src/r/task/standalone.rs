@@ -1,102 +1,59 @@
-use aorist_primitives::AOption;
-use abi_stable::std_types::ROption;
-
 use crate::dialect::Dialect;
-use crate::endpoints::EndpointConfig;
 use crate::flow::{
-    CompressibleTask, CompressionKey, ETLFlow, StandaloneTask, TaskBase, UncompressiblePart,
+    set_task_ancestors, CompressibleTask, CompressionKey, ETLFlow, StandaloneTask, TaskBase,
+    UncompressiblePart,
 };
 use crate::parameter_tuple::ParameterTuple;
-use crate::r::task::{RBasedTaskCompressionKey, RBasedTaskUncompressiblePart};
+use crate::r::task::key::RBasedTaskCompressionKey;
+use crate::r::task::uncompressible::RBasedTaskUncompressiblePart;
 use crate::r::{RImport, RPreamble};
-use aorist_ast::{List, StringLiteral, AST};
+use abi_stable::std_types::ROption;
+use aorist_ast::{AncestorRecord, List, StringLiteral, AST};
+use aorist_primitives::AOption;
+use aorist_primitives::{AString, AVec, AoristUniverse};
 use linked_hash_map::LinkedHashMap;
 use std::hash::Hash;
 use std::marker::PhantomData;
 
 #[derive(Clone, Hash, PartialEq, Eq)]
-pub struct StandaloneRBasedTask<T>
+pub struct StandaloneRBasedTask<T, U>
 where
-    T: ETLFlow,
+    T: ETLFlow<U>,
+    U: AoristUniverse,
 {
+    /// where the task creation call should be stored.
+    task_val: AST,
     /// unique task identifier
     task_id: AString,
-    task_val: AST,
     /// function called to create task (has different meaning depending on
     /// the render we use.
     call: AOption<AString>,
     /// arguments passed to function call
     params: AOption<ParameterTuple>,
-    /// R preamble used by this task call
+    /// task_vals (or references to them) of other tasks this one
+    /// depends on.
+    dependencies: AVec<AST>,
+    /// preamble used by this task call (in the language of its dialect)
     preamble: AOption<AString>,
-    /// Dialect (e.g. Bash, R, R, Presto, etc.), to be interpreted
+    /// Dialect (e.g. Bash, Python, R, Presto, etc.), to be interpreted
     /// by render.
     dialect: AOption<Dialect>,
-    dependencies: AVec<AST>,
+    /// Ancestors of the task's root concept, followed by its constraint.
+    ancestors: AVec<AncestorRecord>,
     singleton_type: PhantomData<T>,
+    _universe: PhantomData<U>,
 }
-impl<T> StandaloneRBasedTask<T>
+impl<T, U> TaskBase<T, U> for StandaloneRBasedTask<T, U>
 where
-    T: ETLFlow<ImportType = RImport, PreambleType = RPreamble>,
+    T: ETLFlow<U>,
+    U: AoristUniverse,
 {
-    // TODO: move to trait
-    pub fn get_uncompressible_part(&self) -> Result<RBasedTaskUncompressiblePart<T>, AString> {
-        Ok(RBasedTaskUncompressiblePart::new(
-            self.task_id.clone(),
-            self.get_right_of_task_val()?,
-            self.params.clone(),
-            self.dependencies.clone(),
-        ))
-    }
-    pub fn get_preamble(&self) -> AOption<AString> {
-        self.preamble.clone()
-    }
-    pub fn get_dialect(&self) -> AOption<Dialect> {
-        self.dialect.clone()
-    }
-    pub fn get_task_val(&self) -> AST {
-        self.task_val.clone()
-    }
-    pub fn get_statements(
-        &self,
-        endpoints: &EndpointConfig,
-    ) -> (AVec<AST>, AVec<RPreamble>, AVec<RImport>) {
-        let args;
-        let kwargs;
-        if let Some(ref p) = self.params {
-            args = p.get_args();
-            kwargs = p.get_kwargs();
-        } else {
-            args = AVec::new();
-            kwargs = LinkedHashMap::new();
-        }
-        let singleton = T::new(
-            AST::StringLiteral(StringLiteral::new_wrapped(self.task_id.clone(), false)),
-            self.get_task_val(),
-            self.call.clone(),
-            args,
-            kwargs,
-            match self.dependencies.len() {
-                0 => None,
-                _ => Some(AST::List(List::new_wrapped(
-                    self.dependencies.clone(),
-                    false,
-                ))),
-            },
-            self.get_preamble(),
-            self.get_dialect(),
-            endpoints.clone(),
-        );
-        (
-            singleton.get_statements(),
-            singleton.get_preamble(),
-            singleton.get_imports(),
-        )
-    }
 }
-impl<T> StandaloneTask<T> for StandaloneRBasedTask<T>
+
+impl<T, U> StandaloneTask<T, U> for StandaloneRBasedTask<T, U>
 where
-    T: ETLFlow,
+    T: ETLFlow<U>,
+    U: AoristUniverse,
 {
     fn new(
         task_id: AString,
@@ -106,23 +63,26 @@ where
         dependencies: AVec<AST>,
         preamble: AOption<AString>,
         dialect: AOption<Dialect>,
+        ancestors: AVec<AncestorRecord>,
     ) -> Self {
         Self {
             task_id,
             task_val,
             call,
             params,
-            preamble,
             dependencies,
+            preamble,
             dialect,
+            ancestors,
             singleton_type: PhantomData,
+            _universe: PhantomData,
         }
     }
 }
-impl<T> TaskBase<T> for StandaloneRBasedTask<T> where T: ETLFlow {}
-impl<T> CompressibleTask for StandaloneRBasedTask<T>
+impl<T, U> CompressibleTask for StandaloneRBasedTask<T, U>
 where
-    T: ETLFlow,
+    T: ETLFlow<U>,
+    U: AoristUniverse,
 {
     type KeyType = RBasedTaskCompressionKey;
     /// only return true for compressible tasks, i.e. those that have a
@@ -138,8 +98,8 @@ where
             self.get_left_of_task_val()?,
             self.call.clone(),
             match &self.params {
-                Some(p) => Some(p.get_dedup_key()),
-                None => None,
+                AOption(ROption::RSome(p)) => AOption(ROption::RSome(p.get_dedup_key())),
+                AOption(ROption::RNone) => AOption(ROption::RNone),
             },
             self.preamble.clone(),
             self.dialect.clone(),
@@ -160,9 +120,7 @@ where
                 let rw = x.read();
                 match &rw.b() {
                     AST::StringLiteral(l) => Ok(l.read().value().clone()),
-                    _ => Err("Right of subscript must be a string
-                    literal"
-                        .to_string()),
+                    _ => Err("Right of subscript must be a string literal".into()),
                 }
             }
             _ => Err("Task val must be a subscript".into()),
@@ -178,3 +136,58 @@ where
         self.task_val.clone()
     }
 }
+
+impl<T, U> StandaloneRBasedTask<T, U>
+where
+    T: ETLFlow<U, ImportType = RImport, PreambleType = RPreamble>,
+    U: AoristUniverse,
+{
+    pub fn get_uncompressible_part(&self) -> Result<RBasedTaskUncompressiblePart<T, U>, AString> {
+        Ok(RBasedTaskUncompressiblePart::new(
+            self.task_id.clone(),
+            self.get_right_of_task_val()?,
+            self.params.clone(),
+            self.dependencies.clone(),
+            self.ancestors.clone(),
+        ))
+    }
+    pub fn get_statements(
+        &self,
+        endpoints: U::TEndpoints,
+    ) -> (AVec<AST>, AVec<RPreamble>, AVec<RImport>) {
+        let args;
+        let kwargs;
+        if let AOption(ROption::RSome(ref p)) = self.params {
+            args = p.get_args();
+            kwargs = p.get_kwargs();
+        } else {
+            args = AVec::new();
+            kwargs = LinkedHashMap::new();
+        }
+        let singleton = T::new(
+            AST::StringLiteral(StringLiteral::new_wrapped(self.task_id.clone(), false)),
+            self.get_task_val(),
+            self.call.clone(),
+            args,
+            kwargs,
+            match self.dependencies.len() {
+                0 => AOption(ROption::RNone),
+                _ => AOption(ROption::RSome(AST::List(List::new_wrapped(
+                    self.dependencies.clone(),
+                    false,
+                )))),
+            },
+            self.get_preamble(),
+            self.get_dialect(),
+            endpoints.clone(),
+        );
+        let statements = singleton.get_statements();
+        set_task_ancestors(&statements, &self.ancestors);
+        (
+            statements,
+            // TODO: propagate erorr type here
+            singleton.get_preamble().unwrap(),
+            singleton.get_imports(),
+        )
+    }
+}
@@ -1,17 +1,18 @@
-use aorist_primitives::AOption;
-use abi_stable::std_types::ROption;
-use aorist_primitives::AVec;
 use crate::flow::{ETLFlow, UncompressiblePart};
 use crate::parameter_tuple::ParameterTuple;
-use aorist_ast::{Dict, AST};
+use abi_stable::std_types::ROption;
+use aorist_ast::{AncestorRecord, Dict, AST};
+use aorist_primitives::AOption;
+use aorist_primitives::{AString, AVec, AoristUniverse};
 use linked_hash_map::LinkedHashMap;
 use std::hash::Hash;
 use std::marker::PhantomData;
 
-#[derive(Clone, Hash, PartialEq, Eq)]
-pub struct RBasedTaskUncompressiblePart<T>
+#[derive(Clone, Hash, PartialEq, Eq, Debug)]
+pub struct RBasedTaskUncompressiblePart<T, U>
 where
-    T: ETLFlow,
+    T: ETLFlow<U>,
+    U: AoristUniverse,
 {
     // unique task_id
     pub task_id: AString,
@@ -21,24 +22,43 @@ where
     pub params: AOption<ParameterTuple>,
     // dep list
     pub deps: AVec<AST>,
+    // ancestors of the task's root concept, followed by its constraint
+    pub ancestors: AVec<AncestorRecord>,
     singleton_type: PhantomData<T>,
+    _universe: PhantomData<U>,
 }
-impl<T> UncompressiblePart<T> for RBasedTaskUncompressiblePart<T>
+impl<T, U> UncompressiblePart<T, U> for RBasedTaskUncompressiblePart<T, U>
 where
-    T: ETLFlow,
+    T: ETLFlow<U>,
+    U: AoristUniverse,
 {
-    fn new(task_id: AString, dict: AString, params: AOption<ParameterTuple>, deps: AVec<AST>) -> Self {
+    fn new(
+        task_id: AString,
+        dict: AString,
+        params: AOption<ParameterTuple>,
+        deps: AVec<AST>,
+        ancestors: AVec<AncestorRecord>,
+    ) -> Self {
         Self {
             task_id,
             dict,
             params,
             deps,
+            ancestors,
             singleton_type: PhantomData,
+            _universe: PhantomData,
         }
     }
-    fn as_dict(&self, _insert_deps: bool, _dependencies_as_list: bool, insert_task_name: bool) -> AST {
+    /// R scripts run their statements in order, so neither dependencies
+    /// nor task names are needed in the params.
+    fn as_dict(
+        &self,
+        _insert_deps: bool,
+        _dependencies_as_list: bool,
+        _insert_task_name: bool,
+    ) -> AST {
         let mut local_params_map: LinkedHashMap<AString, AST> = LinkedHashMap::new();
-        if let Some(ref p) = self.params {
+        if let AOption(ROption::RSome(ref p)) = self.params {
             p.populate_python_dict(&mut local_params_map);
         }
         AST::Dict(Dict::new_wrapped(local_params_map))
@@ -183,6 +183,8 @@ pub trait AoristConceptBase: Clone + Debug + Serialize + PartialEq {
     fn get_uuid(&self) -> AOption<Uuid>;
     fn set_uuid(&mut self, uuid: Uuid);
     fn get_tag(&self) -> AOption<AString>;
+    /// Value of the concept's `name` field, if it has one.
+    fn get_concept_name(&self) -> AOption<AString>;
     fn compute_uuids(&mut self);
     fn deep_clone(&self) -> Self;
     fn get_children(
@@ -208,6 +210,7 @@ pub trait AoristConcept {
     fn get_uuid(&self) -> AOption<Uuid>;
     fn set_uuid(&mut self, uuid: Uuid);
     fn get_tag(&self) -> AOption<AString>;
+    fn get_concept_name(&self) -> AOption<AString>;
     fn compute_uuids(&mut self);
     fn get_children_uuid(&self) -> AVec<Uuid>;
     fn get_uuid_from_children_uuid(&self) -> Uuid {
@@ -244,12 +247,13 @@ pub trait AoristConcept {
     fn py_object(&self, py: pyo3::Python) -> pyo3::PyResult<pyo3::PyObject>;
 }
 
-pub trait ToplineConcept: Sized + Clone {
+pub trait ToplineConcept: Sized + Clone + Serialize {
     type TUniverse: AoristConcept + AoristUniverse;
     fn get_parent_id(&self) -> AOption<(Uuid, AString)>;
     fn get_type(&self) -> AString;
     fn get_uuid(&self) -> Uuid;
     fn get_tag(&self) -> AOption<AString>;
+    fn get_concept_name(&self) -> AOption<AString>;
     fn get_index_as_child(&self) -> usize;
     fn get_child_concepts(&self) -> AVec<Self>;
     fn populate_child_concept_map(&self, concept_map: &mut HashMap<(Uuid, AString), Self>);
@@ -275,6 +279,10 @@ pub trait AoristUniverse {
 pub trait TPrestoEndpoints {
     fn presto_config(&self) -> PrestoConfig;
 }
+pub trait TDaskEndpoints {
+    /// Scheduler to submit tasks to, if any.
+    fn dask_config(&self) -> AOption<DaskConfig>;
+}
 pub trait Ancestry {
     type TConcept: ConceptEnum + Clone + ToplineConcept;
     fn new(parents: RArc<RRwLock<HashMap<(Uuid, AString), Self::TConcept>>>) -> Self;
@@ -314,6 +322,9 @@ impl<T: PartialEq + Serialize + Debug + Clone + AoristConceptBase> AoristConcept
     fn get_tag(&self) -> AOption<AString> {
         self.0.read().get_tag()
     }
+    fn get_concept_name(&self) -> AOption<AString> {
+        self.0.read().get_concept_name()
+    }
     fn get_children(
         &self,
     ) -> AVec<(
@@ -412,6 +423,9 @@ impl<T: Debug + Clone + Serialize + PartialEq + ToplineConceptBase + AoristConce
     fn get_tag(&self) -> AOption<AString> {
         self.0.read().get_tag()
     }
+    fn get_concept_name(&self) -> AOption<AString> {
+        self.0.read().get_concept_name()
+    }
     fn get_index_as_child(&self) -> usize {
         self.0.read().get_index_as_child()
     }
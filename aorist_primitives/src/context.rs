@@ -3,10 +3,11 @@ use crate::AVec;
 use pyo3::exceptions::PyValueError;
 #[cfg(feature = "python")]
 use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use tracing::debug;
 
-#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum ContextStoredValue {
     String(String),
     Integer(i64),
@@ -48,7 +49,7 @@ impl ContextStoredValue {
 }
 
 #[cfg_attr(feature = "python", pyclass)]
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Context {
     inner: HashMap<String, ContextStoredValue>,
 }
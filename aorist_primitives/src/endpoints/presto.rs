@@ -1,22 +1,45 @@
 #[cfg(feature = "python")]
 use pyo3::prelude::*;
 use serde::{Deserialize, Serialize};
+
+fn default_catalog() -> String {
+    "hive".to_string()
+}
+fn default_schema() -> String {
+    "default".to_string()
+}
+
 #[cfg_attr(feature = "python", pyclass)]
 #[derive(PartialEq, Deserialize, Serialize, Debug, Clone, Hash)]
 pub struct PrestoConfig {
     pub server: String,
     pub http_port: usize,
     pub user: String,
+    /// Catalog and schema queries run against, unless they qualify table
+    /// names themselves.
+    #[serde(default = "default_catalog")]
+    pub catalog: String,
+    #[serde(default = "default_schema")]
+    pub schema: String,
 }
 #[cfg(feature = "python")]
 #[pymethods]
 impl PrestoConfig {
     #[new]
-    fn new(server: String, http_port: usize, user: String) -> Self {
+    #[args(catalog = "default_catalog()", schema = "default_schema()")]
+    fn new(
+        server: String,
+        http_port: usize,
+        user: String,
+        catalog: String,
+        schema: String,
+    ) -> Self {
         PrestoConfig {
             server,
             http_port,
             user,
+            catalog,
+            schema,
         }
     }
     #[getter]
@@ -31,4 +54,12 @@ impl PrestoConfig {
     fn server(&self) -> String {
         self.server.clone()
     }
+    #[getter]
+    fn catalog(&self) -> String {
+        self.catalog.clone()
+    }
+    #[getter]
+    fn schema(&self) -> String {
+        self.schema.clone()
+    }
 }
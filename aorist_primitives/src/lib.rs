@@ -38,7 +38,7 @@ macro_rules! register_ast_nodes {
                 }
             }
         }
-        impl Eq for $name {}
+        impl std::cmp::Eq for $name {}
         impl $name {
             pub fn clone_without_ancestors(&self) -> Self {
                 match &self {
@@ -98,6 +98,7 @@ macro_rules! register_ast_nodes {
                     )+
                 }
             }
+            #[cfg(feature = "python")]
             pub fn to_python_ast_node<'a>(
                 &self,
                 py: Python,
@@ -114,10 +115,11 @@ macro_rules! register_ast_nodes {
                     )+
                 }
             }
+            #[cfg(feature = "r")]
             pub fn to_r_ast_node(
                 &self,
                 depth: usize,
-            ) -> Robj {
+            ) -> Result<Robj> {
                 match &self {
                     $(
                         Self::$variant(x) => x.read().to_r_ast_node(
@@ -224,7 +226,7 @@ macro_rules! register_task_nodes {
                 }
             }
         }
-        impl Eq for $name {}
+        impl std::cmp::Eq for $name {}
         impl Hash for $name {
             fn hash<H: Hasher>(&self, state: &mut H) {
                 match &self {
@@ -257,6 +259,7 @@ macro_rules! define_ast_node {
             )*) -> RArc<RRwLock<Self>> {
                 RArc::new(RRwLock::new(Self::new($($field, )*)))
             }
+            #[cfg(feature = "python")]
             pub fn to_python_ast_node<'a>(
                 &self,
                 py: Python,
@@ -265,7 +268,8 @@ macro_rules! define_ast_node {
             ) -> PyResult<&'a PyAny> {
                 ($py_ast_closure)(self, py, ast_module, depth)
             }
-            pub fn to_r_ast_node(&self, depth: usize) -> Robj {
+            #[cfg(feature = "r")]
+            pub fn to_r_ast_node(&self, depth: usize) -> Result<Robj> {
                 ($r_ast_closure)(self, depth)
             }
             pub fn new($(
@@ -1044,6 +1048,13 @@ macro_rules! register_concept {
                       )*
                   }
               }
+              fn get_concept_name(&self) -> AOption<AString> {
+                  match self {
+                      $(
+                        $name::$element(x) => x.0.get_concept_name(),
+                      )*
+                  }
+              }
               fn compute_uuids(&mut self) {
                   match self {
                       $(
@@ -1279,6 +1290,18 @@ macro_rules! register_constraint_new {
             fn get_dialect(&self) -> Dialect {
                 self.inner.get_dialect()
             }
+            fn get_code(&self) -> AString {
+                self.inner.get_code()
+            }
+            fn get_entrypoint(&self) -> AString {
+                self.inner.get_entrypoint()
+            }
+            fn get_arg_functions(&self) -> AVec<(AVec<AString>, AString)> {
+                self.inner.get_arg_functions()
+            }
+            fn get_kwarg_functions(&self) -> LinkedHashMap<AString, (AVec<AString>, AString)> {
+                self.inner.get_kwarg_functions()
+            }
             fn compute_args<'a, T: aorist_core::OuterConstraint<'a>>(
                 &self,
                 root: <Self::TAncestry as Ancestry>::TConcept,
@@ -1642,109 +1665,488 @@ macro_rules! register_constraint_new {
         }}
     }
 }
+/// Builds the driver of one `dag`/`dag_files` mode from the arguments shared
+/// by all modes, enabling the fingerprint cache if a path was given. Drivers
+/// run with the GIL released, since programs acquire it from worker threads.
+#[macro_export]
+macro_rules! dag_driver {
+    ($driver:ident, $builder:ident, $render_dependencies:expr, $args:expr, $cache_path:expr, $parallelism:expr) => {{
+        let (universe, constraints, programs, preferences, overrides, selection) = $args;
+        let mut driver = $driver::<
+            AoristConstraintBuilder<'_>,
+            $builder<AoristRef<Universe>>,
+            AoristRef<Universe>,
+            AoristRef<Concept>,
+            ConceptAncestry,
+            AoristConstraintProgram,
+        >::new(
+            universe,
+            constraints,
+            programs,
+            preferences,
+            overrides,
+            selection,
+            $render_dependencies,
+        )
+        .map_err(driver_error_to_py_err)?;
+        if let Some(ref path) = $cache_path {
+            driver.enable_cache(path).map_err(driver_error_to_py_err)?;
+        }
+        driver.set_parallelism($parallelism);
+        driver
+    }};
+}
 #[macro_export]
 macro_rules! define_dag_function {
     ($name:ident) => {
-        #[pyfunction(dialect_preferences = "vec![
+        #[pyfunction(parallelism = "1", constraint_preferences = "BTreeMap::new()", overrides = "Vec::new()", argo_steps = "BTreeMap::new()", source_comments = "false", cache_report = "false", dialect_preferences = "vec![
             Dialect::R(R::new()),
             Dialect::Python(aorist_core::Python::new(vec![])), 
             Dialect::Bash(Bash::new()), 
             Dialect::Presto(Presto::new())
         ]")]
         pub fn $name<'a>(
+            py: pyo3::Python,
             mut universe: PyUniverse,
             constraints: Vec<String>,
             mode: &str,
             programs: BTreeMap<String, Vec<AoristConstraintProgram>>,
             dialect_preferences: Vec<Dialect>,
             dag_name: Option<String>,
-        ) -> PyResult<String> {
+            cache_path: Option<String>,
+            parallelism: usize,
+            constraint_preferences: BTreeMap<String, Vec<Dialect>>,
+            overrides: Vec<ConstraintOverride>,
+            selection: Option<UniverseSelection>,
+            argo_steps: BTreeMap<String, ArgoStepConfig>,
+            image_dir: Option<String>,
+            source_map_path: Option<String>,
+            source_comments: bool,
+            cache_report: bool,
+        ) -> PyResult<PyObject> {
             universe.compute_uuids();
             let programs_map = programs.into_iter().map(|(k, v)| (k.as_str().into(), v.into_iter().collect())).collect();
-            let (output, _requirements) = match mode {
-                "airflow" => PythonBasedDriver::<
+            if mode == "explain" || mode == "explain_tree" {
+                let explanation = PythonBasedDriver::<
                     AoristConstraintBuilder<'a>,
                     AirflowFlowBuilder<AoristRef<Universe>>,
                     AoristRef<Universe>,
                     AoristRef<Concept>,
                     ConceptAncestry,
                     AoristConstraintProgram,
-                >::new(
+                >::explain(
                     universe.inner.clone(),
                     constraints.into_iter().map(|x| x.as_str().into()).collect(),
-                    programs_map,
-                    dialect_preferences.into_iter().collect(),
-                    true,
+                    ConstraintOverrides::new(overrides.into_iter().collect()),
                 )
-                .map_err(|e| pyo3::exceptions::PyException::new_err(e.to_string()))?
-                .run(match dag_name {
-                    Some(x) => AOption(ROption::RSome(x.as_str().into())),
-                    None => AOption(ROption::RNone),
-                }),
-                "prefect" => PythonBasedDriver::<
-                    AoristConstraintBuilder<'a>,
-                    PrefectFlowBuilder<AoristRef<Universe>>,
-                    AoristRef<Universe>,
-                    AoristRef<Concept>,
-                    ConceptAncestry,
-                    AoristConstraintProgram,
-                >::new(
-                    universe.inner.clone(),
-                    constraints.into_iter().map(|x| x.as_str().into()).collect(),
-                    programs_map,
-                    dialect_preferences.into_iter().collect(),
-                    true,
-                )
-                .map_err(|e| pyo3::exceptions::PyException::new_err(e.to_string()))?
-                .run(match dag_name {
-                    Some(x) => AOption(ROption::RSome(x.as_str().into())),
-                    None => AOption(ROption::RNone),
-                }),
-                "python" => PythonBasedDriver::<
-                    AoristConstraintBuilder<'a>,
-                    PythonFlowBuilder<AoristRef<Universe>>,
-                    AoristRef<Universe>,
-                    AoristRef<Concept>,
-                    ConceptAncestry,
-                    AoristConstraintProgram,
-                >::new(
-                    universe.inner.clone(),
-                    constraints.into_iter().map(|x| x.as_str().into()).collect(),
-                    programs_map,
-                    dialect_preferences.into_iter().collect(),
-                    false,
-                )
-                .map_err(|e| pyo3::exceptions::PyException::new_err(e.to_string()))?
-                .run(match dag_name {
-                    Some(x) => AOption(ROption::RSome(x.as_str().into())),
-                    None => AOption(ROption::RNone),
-                }),
-                "jupyter" => PythonBasedDriver::<
-                    AoristConstraintBuilder<'a>,
-                    JupyterFlowBuilder<AoristRef<Universe>>,
-                    AoristRef<Universe>,
-                    AoristRef<Concept>,
-                    ConceptAncestry,
-                    AoristConstraintProgram,
-                >::new(
-                    universe.inner.clone(),
-                    constraints.into_iter().map(|x| x.as_str().into()).collect(),
-                    programs_map,
-                    dialect_preferences.into_iter().collect(),
-                    false,
-                )
-                .map_err(|e| pyo3::exceptions::PyException::new_err(e.to_string()))?
-                .run(match dag_name {
-                    Some(x) => AOption(ROption::RSome(x.as_str().into())),
-                    None => AOption(ROption::RNone),
-                }),
-                /*"r" => RBasedDriver::<ConstraintBuilder, RBasedFlowBuilder>::new(&universe, constraints.into_iter().collect())
-                .map_err(|e| pyo3::exceptions::PyException::new_err(e.to_string()))?
-                .run(dag_name),*/
-                _ => panic!("Unknown mode provided: {}", mode),
-            }
-            .map_err(|e| pyo3::exceptions::PyException::new_err(e.to_string()))?;
-            Ok(output.as_str().to_string().replace("\\\\", "\\").as_str().into())
+                .map_err(driver_error_to_py_err)?;
+                return match mode {
+                    "explain" => Ok(explanation
+                        .to_json()
+                        .map_err(driver_error_to_py_err)?
+                        .into_py(py)),
+                    _ => Ok(explanation.to_tree().into_py(py)),
+                };
+            }
+            let preferences = DialectPreferences::new(dialect_preferences.into_iter().collect())
+                .with_constraint_preferences(
+                    constraint_preferences
+                        .into_iter()
+                        .map(|(k, v)| (k.as_str().into(), v.into_iter().collect()))
+                        .collect(),
+                );
+            let overrides = ConstraintOverrides::new(overrides.into_iter().collect());
+            let selection = selection.unwrap_or_default();
+            // only flows printed from Python statements know the lines of their tasks
+            if (source_map_path.is_some() || source_comments)
+                && !["airflow", "airflow2", "prefect", "prefect2", "dask", "dagster", "python"].contains(&mode)
+            {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "Flows generated in mode {} have no source map",
+                    mode
+                )));
+            }
+            let flow_name = dag_name.clone().unwrap_or_else(|| "flow".to_string());
+            let dag_name = match dag_name {
+                Some(x) => AOption(ROption::RSome(x.as_str().into())),
+                None => AOption(ROption::RNone),
+            };
+            let driver_args = (
+                universe.inner.clone(),
+                constraints.into_iter().map(|x| x.as_str().into()).collect(),
+                programs_map,
+                preferences,
+                overrides,
+                selection,
+            );
+            let (output, mut metadata) = match mode {
+                "airflow" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, AirflowFlowBuilder, true, driver_args, cache_path, parallelism);
+                    py.allow_threads(|| driver.run(dag_name))
+                }
+                "airflow2" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, Airflow2FlowBuilder, true, driver_args, cache_path, parallelism);
+                    py.allow_threads(|| driver.run(dag_name))
+                }
+                "prefect" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, PrefectFlowBuilder, true, driver_args, cache_path, parallelism);
+                    py.allow_threads(|| driver.run(dag_name))
+                }
+                "prefect2" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, Prefect2FlowBuilder, true, driver_args, cache_path, parallelism);
+                    py.allow_threads(|| driver.run(dag_name))
+                }
+                "dask" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, DaskFlowBuilder, true, driver_args, cache_path, parallelism);
+                    py.allow_threads(|| driver.run(dag_name))
+                }
+                "dagster" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, DagsterFlowBuilder, false, driver_args, cache_path, parallelism);
+                    let builder = DagsterFlowBuilder::<AoristRef<Universe>>::new();
+                    let file = AString::from(flow_name.as_str());
+                    py.allow_threads(|| -> anyhow::Result<_> {
+                        driver.satisfy_constraints()?;
+                        let (job, ranges) = builder.materialize_with_source_ranges(
+                            IsolatedTask::to_dagster_flow_builder_inputs::<AoristRef<Universe>>(
+                                driver.get_isolated_tasks()?,
+                                driver.get_endpoints(),
+                                file.clone(),
+                            )?,
+                            AVec::new(),
+                            dag_name,
+                        )?;
+                        let mut metadata = driver.get_flow_metadata()?;
+                        let source_map = SourceMap::new(file.clone(), &ranges, &metadata.tasks);
+                        metadata.source_maps.insert(file, source_map);
+                        Ok((job, metadata))
+                    })
+                }
+                "python" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, PythonFlowBuilder, false, driver_args, cache_path, parallelism);
+                    py.allow_threads(|| driver.run(dag_name))
+                }
+                "jupyter" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, JupyterFlowBuilder, false, driver_args, cache_path, parallelism);
+                    py.allow_threads(|| driver.run(dag_name))
+                }
+                "jupyter_tasks" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, JupyterFlowBuilder, false, driver_args, cache_path, parallelism);
+                    let builder = JupyterFlowBuilder::<AoristRef<Universe>>::new();
+                    py.allow_threads(|| -> anyhow::Result<_> {
+                        driver.satisfy_constraints()?;
+                        let notebook = builder.materialize(
+                            IsolatedTask::to_python_flow_builder_inputs::<AoristRef<Universe>>(
+                                driver.get_isolated_tasks()?,
+                                driver.get_endpoints(),
+                            )?,
+                            dag_name,
+                        )?;
+                        Ok((notebook, driver.get_flow_metadata()?))
+                    })
+                }
+                "argo" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, PythonFlowBuilder, false, driver_args, cache_path, parallelism);
+                    let builder = ArgoWorkflowBuilder::<AoristRef<Universe>>::new(
+                        argo_steps.into_iter().map(|(k, v)| (k.as_str().into(), v)).collect(),
+                    );
+                    py.allow_threads(|| -> anyhow::Result<_> {
+                        driver.satisfy_constraints()?;
+                        let workflow = builder.materialize(
+                            driver.get_isolated_tasks()?,
+                            driver.get_endpoints(),
+                            dag_name,
+                        )?;
+                        Ok((workflow, driver.get_flow_metadata()?))
+                    })
+                }
+                "snakemake" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, PythonFlowBuilder, false, driver_args, cache_path, parallelism);
+                    let builder = SnakefileBuilder::<AoristRef<Universe>>::new();
+                    py.allow_threads(|| -> anyhow::Result<_> {
+                        driver.satisfy_constraints()?;
+                        let flow = builder.materialize(
+                            driver.get_isolated_tasks()?,
+                            driver.get_task_paths(
+                                |x| x.0.read().get_local_path(),
+                                |x| x.0.read().get_storage_roles(),
+                            ),
+                            driver.get_endpoints(),
+                            dag_name,
+                        )?;
+                        Ok((flow, driver.get_flow_metadata()?))
+                    })
+                }
+                "make" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, PythonFlowBuilder, false, driver_args, cache_path, parallelism);
+                    let builder = MakefileBuilder::<AoristRef<Universe>>::new();
+                    py.allow_threads(|| -> anyhow::Result<_> {
+                        driver.satisfy_constraints()?;
+                        let flow = builder.materialize(
+                            driver.get_isolated_tasks()?,
+                            driver.get_task_paths(
+                                |x| x.0.read().get_local_path(),
+                                |x| x.0.read().get_storage_roles(),
+                            ),
+                            driver.get_endpoints(),
+                            dag_name,
+                        )?;
+                        Ok((flow, driver.get_flow_metadata()?))
+                    })
+                }
+                "bash" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, PythonFlowBuilder, false, driver_args, cache_path, parallelism);
+                    let builder = BashScriptBuilder::<AoristRef<Universe>>::new();
+                    py.allow_threads(|| -> anyhow::Result<_> {
+                        driver.satisfy_constraints()?;
+                        let script = builder.materialize(
+                            driver.get_isolated_tasks()?,
+                            driver.get_endpoints(),
+                            dag_name,
+                        )?;
+                        Ok((script, driver.get_flow_metadata()?))
+                    })
+                }
+                "r" => {
+                    let mut driver = dag_driver!(RBasedDriver, RFlowBuilder, false, driver_args, cache_path, parallelism);
+                    py.allow_threads(|| driver.run(dag_name))
+                }
+                "r_jupyter" => {
+                    let mut driver = dag_driver!(RBasedDriver, RJupyterFlowBuilder, false, driver_args, cache_path, parallelism);
+                    py.allow_threads(|| driver.run(dag_name))
+                }
+                "r_jupyter_tasks" => {
+                    let mut driver = dag_driver!(RBasedDriver, RJupyterFlowBuilder, false, driver_args, cache_path, parallelism);
+                    py.allow_threads(|| -> anyhow::Result<_> {
+                        driver.satisfy_constraints()?;
+                        let notebook = RJupyterFlowBuilder::<AoristRef<Universe>>::new().materialize(
+                            IsolatedTask::to_r_flow_builder_inputs::<AoristRef<Universe>>(
+                                driver.get_isolated_tasks()?,
+                                driver.get_endpoints(),
+                            )?,
+                            dag_name,
+                        )?;
+                        Ok((notebook, driver.get_flow_metadata()?))
+                    })
+                }
+                _ => {
+                    return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                        "Unknown mode provided: {}",
+                        mode
+                    )))
+                }
+            }
+            .map_err(driver_error_to_py_err)?;
+            if let Some(ref dir) = image_dir {
+                metadata
+                    .image
+                    .write(dir, &flow_name)
+                    .map_err(driver_error_to_py_err)?;
+            }
+            let mut output = output.as_str().to_string().replace("\\\\", "\\");
+            if source_map_path.is_some() || source_comments {
+                let mut source_map = metadata
+                    .source_maps
+                    .remove(&AString::from(flow_name.as_str()))
+                    .unwrap();
+                if source_comments {
+                    output = source_map.annotate(&output, "#");
+                }
+                if let Some(ref path) = source_map_path {
+                    std::fs::write(path, source_map.to_json().map_err(driver_error_to_py_err)?)?;
+                }
+            }
+            match cache_report {
+                true => Ok((output, metadata.cache_report.unwrap_or_default()).into_py(py)),
+                false => Ok(output.into_py(py)),
+            }
+        }
+    }
+}
+#[macro_export]
+macro_rules! define_dag_files_function {
+    ($name:ident) => {
+        #[pyfunction(parallelism = "1", constraint_preferences = "BTreeMap::new()", overrides = "Vec::new()", source_comments = "false", cache_report = "false", dialect_preferences = "vec![
+            Dialect::R(R::new()),
+            Dialect::Python(aorist_core::Python::new(vec![])), 
+            Dialect::Bash(Bash::new()), 
+            Dialect::Presto(Presto::new())
+        ]")]
+        pub fn $name<'a>(
+            py: pyo3::Python,
+            mut universe: PyUniverse,
+            constraints: Vec<String>,
+            mode: &str,
+            programs: BTreeMap<String, Vec<AoristConstraintProgram>>,
+            split: &PyAny,
+            dialect_preferences: Vec<Dialect>,
+            dag_name: Option<String>,
+            cache_path: Option<String>,
+            parallelism: usize,
+            constraint_preferences: BTreeMap<String, Vec<Dialect>>,
+            overrides: Vec<ConstraintOverride>,
+            selection: Option<UniverseSelection>,
+            image_dir: Option<String>,
+            source_map_dir: Option<String>,
+            source_comments: bool,
+            cache_report: bool,
+        ) -> PyResult<PyObject> {
+            universe.compute_uuids();
+            let programs_map = programs.into_iter().map(|(k, v)| (k.as_str().into(), v.into_iter().collect())).collect();
+            let partition = FlowPartition::from_py(split)?;
+            let preferences = DialectPreferences::new(dialect_preferences.into_iter().collect())
+                .with_constraint_preferences(
+                    constraint_preferences
+                        .into_iter()
+                        .map(|(k, v)| (k.as_str().into(), v.into_iter().collect()))
+                        .collect(),
+                );
+            let overrides = ConstraintOverrides::new(overrides.into_iter().collect());
+            let selection = selection.unwrap_or_default();
+            // only flows printed from Python statements know the lines of their tasks
+            if (source_map_dir.is_some() || source_comments)
+                && !["airflow", "airflow2", "prefect", "prefect2", "dask"].contains(&mode)
+            {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "Flows generated in mode {} have no source map",
+                    mode
+                )));
+            }
+            let flow_name = dag_name.clone().unwrap_or_else(|| "flow".to_string());
+            let dag_name = match dag_name {
+                Some(x) => AOption(ROption::RSome(x.as_str().into())),
+                None => AOption(ROption::RNone),
+            };
+            let driver_args = (
+                universe.inner.clone(),
+                constraints.into_iter().map(|x| x.as_str().into()).collect(),
+                programs_map,
+                preferences,
+                overrides,
+                selection,
+            );
+            let (files, mut metadata) = match mode {
+                "airflow" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, AirflowFlowBuilder, true, driver_args, cache_path, parallelism);
+                    driver.set_partition(partition);
+                    py.allow_threads(|| driver.run_split(dag_name))
+                }
+                "airflow2" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, Airflow2FlowBuilder, true, driver_args, cache_path, parallelism);
+                    driver.set_partition(partition);
+                    py.allow_threads(|| driver.run_split(dag_name))
+                }
+                "prefect" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, PrefectFlowBuilder, true, driver_args, cache_path, parallelism);
+                    driver.set_partition(partition);
+                    py.allow_threads(|| driver.run_split(dag_name))
+                }
+                "prefect2" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, Prefect2FlowBuilder, true, driver_args, cache_path, parallelism);
+                    driver.set_partition(partition);
+                    py.allow_threads(|| driver.run_split(dag_name))
+                }
+                "dask" => {
+                    let mut driver = dag_driver!(PythonBasedDriver, DaskFlowBuilder, true, driver_args, cache_path, parallelism);
+                    driver.set_partition(partition);
+                    py.allow_threads(|| driver.run_split(dag_name))
+                }
+                "r" => {
+                    let mut driver = dag_driver!(RBasedDriver, RFlowBuilder, false, driver_args, cache_path, parallelism);
+                    driver.set_partition(partition);
+                    py.allow_threads(|| driver.run_split(dag_name))
+                }
+                "r_jupyter" => {
+                    let mut driver = dag_driver!(RBasedDriver, RJupyterFlowBuilder, false, driver_args, cache_path, parallelism);
+                    driver.set_partition(partition);
+                    py.allow_threads(|| driver.run_split(dag_name))
+                }
+                _ => {
+                    return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                        "Mode {} cannot be split into several flows",
+                        mode
+                    )))
+                }
+            }
+            .map_err(driver_error_to_py_err)?;
+            if let Some(ref dir) = image_dir {
+                metadata
+                    .image
+                    .write(dir, &flow_name)
+                    .map_err(driver_error_to_py_err)?;
+            }
+            let mut out = BTreeMap::new();
+            for (file, content) in files.into_iter() {
+                let mut content = content.as_str().to_string().replace("\\\\", "\\");
+                if source_map_dir.is_some() || source_comments {
+                    let mut source_map = metadata.source_maps.remove(&file).unwrap();
+                    if source_comments {
+                        content = source_map.annotate(&content, "#");
+                    }
+                    if let Some(ref dir) = source_map_dir {
+                        std::fs::create_dir_all(dir)?;
+                        std::fs::write(
+                            std::path::Path::new(dir).join(format!("{}.map.json", file)),
+                            source_map.to_json().map_err(driver_error_to_py_err)?,
+                        )?;
+                    }
+                }
+                out.insert(file.as_str().to_string(), content);
+            }
+            match cache_report {
+                true => Ok((out, metadata.cache_report.unwrap_or_default()).into_py(py)),
+                false => Ok(out.into_py(py)),
+            }
+        }
+    }
+}
+#[macro_export]
+macro_rules! define_constraint_graph_function {
+    ($name:ident) => {
+        #[pyfunction(format = "\"json\"", overrides = "Vec::new()", dialect_preferences = "vec![
+            Dialect::R(R::new()),
+            Dialect::Python(aorist_core::Python::new(vec![])), 
+            Dialect::Bash(Bash::new()), 
+            Dialect::Presto(Presto::new())
+        ]")]
+        pub fn $name<'a>(
+            mut universe: PyUniverse,
+            constraints: Vec<String>,
+            programs: BTreeMap<String, Vec<AoristConstraintProgram>>,
+            format: &str,
+            dialect_preferences: Vec<Dialect>,
+            overrides: Vec<ConstraintOverride>,
+            selection: Option<UniverseSelection>,
+        ) -> PyResult<String> {
+            universe.compute_uuids();
+            let programs_map = programs.into_iter().map(|(k, v)| (k.as_str().into(), v.into_iter().collect())).collect();
+            let mut driver = PythonBasedDriver::<
+                AoristConstraintBuilder<'a>,
+                PythonFlowBuilder<AoristRef<Universe>>,
+                AoristRef<Universe>,
+                AoristRef<Concept>,
+                ConceptAncestry,
+                AoristConstraintProgram,
+            >::new(
+                universe.inner.clone(),
+                constraints.into_iter().map(|x| x.as_str().into()).collect(),
+                programs_map,
+                DialectPreferences::new(dialect_preferences.into_iter().collect()),
+                ConstraintOverrides::new(overrides.into_iter().collect()),
+                selection.unwrap_or_default(),
+                false,
+            )
+            .map_err(driver_error_to_py_err)?;
+            driver.satisfy_constraints().map_err(driver_error_to_py_err)?;
+            let graph = driver.get_constraint_graph().map_err(driver_error_to_py_err)?;
+            match format {
+                "dot" => Ok(graph.to_dot()),
+                "mermaid" => Ok(graph.to_mermaid()),
+                "json" => graph.to_json().map_err(driver_error_to_py_err),
+                _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "Unknown graph format provided: {}",
+                    format
+                ))),
+            }
         }
     }
 }
@@ -1767,6 +2169,8 @@ macro_rules! export_aorist_python_module {
         use std::path::{Path, PathBuf};
 
         define_dag_function!($dag_function);
+        define_dag_files_function!(dag_files);
+        define_constraint_graph_function!(constraint_graph);
         #[pyfunction]
         pub fn test() -> PyResult<Vec<String>> {
             let base_name = "constraint_module";
@@ -1791,7 +2195,14 @@ macro_rules! export_aorist_python_module {
             concept_module(py, m)?;
             endpoints_module(py, m)?;
             dialects_module(py, m)?;
+            m.add_class::<ConstraintOverride>()?;
+            m.add_class::<UniverseSelection>()?;
+            m.add_class::<CacheReport>()?;
+            m.add_class::<ArgoStepConfig>()?;
+            m.add("ConstraintGraphError", py.get_type::<ConstraintGraphError>())?;
             m.add_wrapped(wrap_pyfunction!($dag_function))?;
+            m.add_wrapped(wrap_pyfunction!(dag_files))?;
+            m.add_wrapped(wrap_pyfunction!(constraint_graph))?;
             m.add_wrapped(wrap_pyfunction!(test))?;
             Ok(())
         }
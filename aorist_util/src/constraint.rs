@@ -2,9 +2,10 @@ use crate::error::{AResult, AoristError};
 use crate::get_raw_objects_of_type;
 use codegen::Scope;
 use serde_yaml::Value;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::env;
 use std::fs;
+use std::hash::Hash;
 use std::path::Path;
 pub type ConstraintTuple = (String, String, Option<String>, Option<String>);
 
@@ -65,19 +66,87 @@ pub fn get_constraint_dependencies(
         dependencies.insert(key.clone(), constraint.get_required());
     }
     let constraint_names: HashSet<String> = dependencies.keys().map(|x| x.0.clone()).collect();
-    for dep in dependencies.values() {
+    let mut missing = Vec::new();
+    for (key, dep) in dependencies.iter() {
         for elem in dep.iter() {
             if !constraint_names.contains(elem) {
-                panic!("Cannot find definition for required constraint {}", elem);
+                missing.push(format!("{} (required by {})", elem, key.0));
             }
         }
     }
+    if missing.len() > 0 {
+        missing.sort();
+        return Err(AoristError::ConstraintGraphError(format!(
+            "Cannot find definition for required constraints: {}",
+            missing.join(", ")
+        )));
+    }
     Ok(dependencies)
 }
 
+/// Returns one dependency cycle among the remaining constraints as a path
+/// of constraint names, e.g. [A, B, A]. Constraints sharing a name (e.g.
+/// defined on several roots) are merged into one node.
+fn find_cycle(g: &HashMap<ConstraintTuple, HashSet<String>>) -> Option<Vec<String>> {
+    let mut edges: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (k, v) in g.iter() {
+        edges
+            .entry(k.0.clone())
+            .or_insert(BTreeSet::new())
+            .extend(v.iter().cloned());
+    }
+    find_cycles(edges.iter()).into_iter().next()
+}
+/// Returns the dependency cycles of a graph given as (node, dependencies)
+/// pairs, each as a path whose first and last node coincide, e.g. [A, B, A].
+/// Nodes and dependencies are visited in the order given, and pairs sharing
+/// a node are merged.
+pub fn find_cycles<'a, N, D>(graph: impl IntoIterator<Item = (&'a N, D)>) -> Vec<Vec<N>>
+where
+    N: 'a + Clone + Eq + Hash,
+    D: IntoIterator<Item = &'a N>,
+{
+    let mut nodes = Vec::new();
+    let mut edges: HashMap<&N, Vec<&N>> = HashMap::new();
+    for (node, deps) in graph {
+        nodes.push(node);
+        edges.entry(node).or_insert(Vec::new()).extend(deps);
+    }
+    let mut finished = HashSet::new();
+    let mut cycles = Vec::new();
+    for start in nodes {
+        let mut path = Vec::new();
+        find_cycle_from(start, &edges, &mut path, &mut finished, &mut cycles);
+    }
+    cycles
+}
+fn find_cycle_from<'a, N: Clone + Eq + Hash>(
+    node: &'a N,
+    edges: &HashMap<&'a N, Vec<&'a N>>,
+    path: &mut Vec<&'a N>,
+    finished: &mut HashSet<&'a N>,
+    cycles: &mut Vec<Vec<N>>,
+) {
+    if finished.contains(node) {
+        return;
+    }
+    if let Some(pos) = path.iter().position(|x| *x == node) {
+        let mut cycle = path[pos..].iter().map(|x| (*x).clone()).collect::<Vec<_>>();
+        cycle.push(node.clone());
+        cycles.push(cycle);
+        return;
+    }
+    path.push(node);
+    for next in edges.get(node).into_iter().flatten() {
+        find_cycle_from(*next, edges, path, finished, cycles);
+    }
+    path.pop();
+    finished.insert(node);
+}
+
 pub fn compute_topological_sort(
     dependencies: &HashMap<ConstraintTuple, Vec<String>>,
-) -> Vec<ConstraintTuple> {
+) -> AResult<Vec<ConstraintTuple>> {
     let mut g: HashMap<ConstraintTuple, HashSet<String>> = dependencies
         .iter()
         .map(|(k, v)| {
@@ -96,14 +165,8 @@ pub fn compute_topological_sort(
     let mut order: Vec<_> = Vec::new();
     while let Some(val) = leaf_name {
         let key = val.clone();
-        println!("key: {}, {}", key.0, key.1);
         g.remove(&key);
-        for (k, x) in g.iter_mut() {
-            println!(
-                "node: {}, dependencies: {}",
-                k.0,
-                x.clone().into_iter().collect::<Vec<String>>().join(", ")
-            );
+        for (_, x) in g.iter_mut() {
             x.remove(&key.0);
         }
         order.push(key);
@@ -117,9 +180,15 @@ pub fn compute_topological_sort(
         }
     }
     if g.len() > 0 {
-        panic!("Cycles in constraint dependencies are not allowed!");
+        return Err(AoristError::ConstraintGraphError(match find_cycle(&g) {
+            Some(cycle) => format!(
+                "Cycles in constraint dependencies are not allowed: {}",
+                cycle.join(" -> ")
+            ),
+            None => "Cycles in constraint dependencies are not allowed!".to_string(),
+        }));
     }
-    order
+    Ok(order)
 }
 
 pub struct ParsedConstraintDef {
@@ -290,7 +359,7 @@ pub fn parse_and_sort_constraints(
         .map(|x| (x.get_key(), x))
         .collect::<HashMap<ConstraintTuple, ParsedConstraintDef>>();
     let dependencies = get_constraint_dependencies(&constraints_map)?;
-    let order = compute_topological_sort(&dependencies);
+    let order = compute_topological_sort(&dependencies)?;
     let mut out = Vec::new();
     for key in order.into_iter() {
         let constraint = constraints_map.remove(&key).ok_or_else(|| {
@@ -355,3 +424,77 @@ pub fn process_constraints_new(raw_objects: &Vec<HashMap<String, Value>>) -> ARe
     fs::write(&dest_path, scope.to_string())?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(&str, &str, &[&str])]) -> HashMap<ConstraintTuple, HashSet<String>> {
+        edges
+            .iter()
+            .map(|(name, root, deps)| {
+                (
+                    (name.to_string(), root.to_string(), None, None),
+                    deps.iter().map(|x| x.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_find_cycle_acyclic() {
+        let g = graph(&[("A", "DataSet", &["B"]), ("B", "DataSet", &[])]);
+        assert_eq!(find_cycle(&g), None);
+    }
+
+    #[test]
+    fn test_find_cycle_behind_first_edge() {
+        // A's first dependency (B) is a dead end, the cycle goes through C
+        let g = graph(&[
+            ("A", "DataSet", &["B", "C"]),
+            ("B", "DataSet", &[]),
+            ("C", "DataSet", &["A"]),
+        ]);
+        assert_eq!(
+            find_cycle(&g),
+            Some(vec!["A".to_string(), "C".to_string(), "A".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_find_cycle_merges_constraints_with_same_name() {
+        // only the union of both A definitions' dependencies forms a cycle
+        let g = graph(&[
+            ("A", "DataSet", &["B"]),
+            ("A", "Asset", &["C"]),
+            ("B", "DataSet", &[]),
+            ("C", "DataSet", &["A"]),
+        ]);
+        assert_eq!(
+            find_cycle(&g),
+            Some(vec!["A".to_string(), "C".to_string(), "A".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_compute_topological_sort_reports_cycle() {
+        let dependencies = vec![
+            (
+                ("A".to_string(), "DataSet".to_string(), None, None),
+                vec!["B".to_string()],
+            ),
+            (
+                ("B".to_string(), "DataSet".to_string(), None, None),
+                vec!["A".to_string()],
+            ),
+        ]
+        .into_iter()
+        .collect();
+        match compute_topological_sort(&dependencies) {
+            Err(AoristError::ConstraintGraphError(msg)) => {
+                assert!(msg.ends_with("A -> B -> A"), "{}", msg)
+            }
+            other => panic!("Expected a cycle error, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -18,6 +18,8 @@ pub enum AoristError {
     UnexpectedNoneError(String),
     #[error("{0}")]
     CannotConvertJSONError(String),
+    #[error("{0}")]
+    ConstraintGraphError(String),
 }
 impl AoristError {
     pub fn as_str(&self) -> String {
@@ -28,6 +30,7 @@ impl AoristError {
             Self::IOError(e) => format!("{:?}", e),
             Self::UnexpectedNoneError(e) => format!("{:?}", e),
             Self::CannotConvertJSONError(e) => format!("{:?}", e),
+            Self::ConstraintGraphError(e) => e.clone(),
         };
         res
     }
@@ -0,0 +1,57 @@
+"""
+Generates an Airflow 2 DAG for the minimal universe and checks that every
+constraint block is a TaskGroup of the DAG, and that task ids are unique. If
+Airflow is installed, the module is also loaded and each task id found in the
+source is checked to be a task of the DAG.
+
+Usage: python airflow2_flow.py
+"""
+import ast
+
+from aorist import *
+from aorist_recipes import programs
+
+from probprog_universe import probprog_universe, write_output
+
+
+def get_task_ids(tree):
+    task_ids = []
+    for node in ast.walk(tree):
+        if not isinstance(node, ast.Call):
+            continue
+        for keyword in node.keywords:
+            if keyword.arg == "task_id" and isinstance(keyword.value,
+                                                       ast.Constant):
+                task_ids.append(keyword.value.value)
+    return task_ids
+
+
+universe = probprog_universe()
+result = dag(universe, ["ReplicateToLocal"], "airflow2", programs,
+             dag_name="probprog")
+tree = ast.parse(result)
+groups = [x for x in tree.body if isinstance(x, ast.With)]
+assert len(groups) > 0
+for group in groups:
+    context = [x.context_expr for x in group.items]
+    assert isinstance(context[0], ast.Name) and context[0].id == "dag"
+    assert context[1].func.id == "TaskGroup"
+task_ids = get_task_ids(tree)
+assert len(task_ids) > 0
+assert len(task_ids) == len(set(task_ids)), task_ids
+code = compile(result, "generated_airflow2_flow.py", "exec")
+try:
+    import airflow
+except ImportError:
+    airflow = None
+if airflow is not None:
+    module = {}
+    exec(code, module)
+    flow = module["dag"]
+    assert flow.dag_id == "probprog"
+    assert set(task_ids) <= set(flow.task_ids), set(task_ids) - set(
+        flow.task_ids)
+else:
+    print("Airflow is not installed, only checked that the DAG compiles.")
+write_output('generated_airflow2_flow.py', result)
+print("Validated %d task groups." % len(groups))
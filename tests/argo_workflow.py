@@ -0,0 +1,52 @@
+"""
+Generates an Argo Workflow for the minimal universe and validates it against
+the JSON schema of the Argo CRDs, vendored in data/argo_workflow_schema.json.
+
+Usage: python argo_workflow.py [--schema PATH]
+"""
+import argparse
+import json
+import os
+
+import jsonschema
+import yaml
+
+from aorist import *
+from aorist_recipes import programs
+
+from probprog_universe import probprog_universe, write_output
+
+ARGO_SCHEMA_PATH = os.path.join(
+    os.path.dirname(os.path.abspath(__file__)), "data", "argo_workflow_schema.json"
+)
+
+parser = argparse.ArgumentParser()
+parser.add_argument("--schema", default=ARGO_SCHEMA_PATH)
+args = parser.parse_args()
+
+with open(args.schema) as f:
+    schema = json.load(f)
+
+universe = probprog_universe()
+result = dag(universe, ["ReplicateToLocal"], "argo", programs,
+             dag_name="probprog", argo_steps={
+                 "python": ArgoStepConfig("python:3.9", cpu="500m", memory="1Gi"),
+                 "bash": ArgoStepConfig("python:3.9", command=["python3"]),
+             })
+workflow = yaml.safe_load(result)
+jsonschema.validate(
+    workflow,
+    {
+        "$ref": "#/definitions/io.argoproj.workflow.v1alpha1.Workflow",
+        "definitions": schema["definitions"],
+    },
+)
+
+templates = {x["name"]: x for x in workflow["spec"]["templates"]}
+dag_template = templates[workflow["spec"]["entrypoint"]]
+for task in dag_template["dag"]["tasks"]:
+    assert "script" in templates[task["template"]]
+    for dependency in task.get("dependencies", []):
+        assert dependency in templates
+print("Validated %d steps." % len(dag_template["dag"]["tasks"]))
+write_output('generated_workflow.yaml', result)
@@ -0,0 +1,22 @@
+"""
+Generates a Bash script downloading the minimal universe without Python
+programs, and checks its syntax.
+
+Usage: python bash_script.py
+"""
+import subprocess
+
+from aorist import *
+from aorist_recipes import programs
+
+from probprog_universe import probprog_universe, write_output
+
+universe = probprog_universe()
+result = dag(universe, ["DownloadDataFromRemoteWebLocation"], "bash",
+             programs, dag_name="probprog",
+             dialect_preferences=[Bash(), R(), Presto()])
+assert result.startswith("#!/usr/bin/env bash")
+assert "set -euo pipefail" in result
+subprocess.run(["bash", "-n"], input=result.encode(), check=True)
+write_output('generated_script.sh', result)
+print("Validated Bash script.")
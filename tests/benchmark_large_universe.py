@@ -0,0 +1,84 @@
+"""
+Synthetic benchmark: generates a flow for a universe with a large number of
+assets (10,000 by default) and reports how long constraint satisfaction and
+code generation take.
+
+Usage: python benchmark_large_universe.py [--assets N] [--mode python] [--parallelism 1]
+           [--save-baseline FILE] [--baseline FILE] [--tolerance 0.2]
+
+To compare against an earlier revision, run the benchmark there with
+--save-baseline, then run it on the current revision with --baseline: it
+fails if flow generation got slower by more than the tolerance, or if the
+generated flow has a different number of lines.
+"""
+import argparse
+import json
+import sys
+import time
+
+from aorist import *
+from aorist_recipes import programs
+from scienz.subreddits import build_assets, subreddit_datum
+
+parser = argparse.ArgumentParser()
+parser.add_argument("--assets", type=int, default=10000)
+parser.add_argument("--mode", default="python")
+parser.add_argument("--parallelism", type=int, default=1)
+parser.add_argument("--save-baseline", default=None)
+parser.add_argument("--baseline", default=None)
+parser.add_argument("--tolerance", type=float, default=0.2)
+args = parser.parse_args()
+
+start = time.perf_counter()
+dataset = DataSet(
+    name="synthetic",
+    description="Synthetic dataset used for benchmarking.",
+    source_path=__file__,
+    datum_templates=[DatumTemplate(subreddit_datum)],
+    assets=build_assets(["asset_%d" % i for i in range(args.assets)]),
+    access_policies=[],
+)
+local = SQLiteStorage(
+    location=SQLiteLocation(file_name="synthetic.sqlite"),
+    layout=TabularLayout(StaticTabularLayout()),
+)
+dataset = dataset.replicate_to_local(
+    Storage(local), "/tmp/synthetic", Encoding(CSVEncoding())
+)
+universe = Universe(name="synthetic", datasets=[dataset],
+                    endpoints=EndpointConfig(), compliance=None)
+built = time.perf_counter()
+
+result = dag(universe, ["ReplicateToLocal"], args.mode, programs,
+             parallelism=args.parallelism)
+generated = time.perf_counter()
+
+print("assets:            %d" % args.assets)
+print("parallelism:       %d" % args.parallelism)
+print("universe build:    %.2fs" % (built - start))
+print("flow generation:   %.2fs" % (generated - built))
+print("generated lines:   %d" % len(result.splitlines()))
+
+timings = {
+    "assets": args.assets,
+    "mode": args.mode,
+    "flow_generation": generated - built,
+    "generated_lines": len(result.splitlines()),
+}
+if args.save_baseline is not None:
+    with open(args.save_baseline, "w") as f:
+        json.dump(timings, f, indent=2)
+if args.baseline is not None:
+    with open(args.baseline) as f:
+        baseline = json.load(f)
+    if (baseline["assets"], baseline["mode"]) != (args.assets, args.mode):
+        sys.exit("Baseline was recorded for %d assets in mode %s" % (
+            baseline["assets"], baseline["mode"]))
+    ratio = timings["flow_generation"] / baseline["flow_generation"]
+    print("baseline:          %.2fs (%.2fx)" % (baseline["flow_generation"], ratio))
+    if timings["generated_lines"] != baseline["generated_lines"]:
+        sys.exit("Generated %d lines, baseline generated %d" % (
+            timings["generated_lines"], baseline["generated_lines"]))
+    if ratio > 1 + args.tolerance:
+        sys.exit("Flow generation is %.0f%% slower than the baseline" % (
+            100 * (ratio - 1)))
@@ -0,0 +1,35 @@
+"""
+Generates a flow for the minimal universe together with the definition of an
+image running it, and checks the image files.
+
+Usage: python container_image.py
+"""
+import os
+
+from aorist import *
+from aorist_recipes import programs
+
+from probprog_universe import OUTPUT_DIR, probprog_universe
+
+universe = probprog_universe()
+image_dir = os.path.join(OUTPUT_DIR, "image")
+result = dag(universe, ["ReplicateToLocal"], "python", programs,
+             dag_name="probprog", image_dir=image_dir)
+with open(os.path.join(image_dir, "flow.py"), "w") as f:
+    f.write(result)
+with open(os.path.join(image_dir, "Dockerfile")) as f:
+    dockerfile = f.read()
+with open(os.path.join(image_dir, "environment.yml")) as f:
+    environment = f.read()
+assert dockerfile.startswith("# Image running the probprog flow.")
+assert "COPY environment.yml" in dockerfile
+assert environment.startswith("name: probprog\n")
+assert "\nFROM continuumio/miniconda3:" in dockerfile
+
+# The image of an Airflow flow installs Airflow itself
+airflow_image_dir = os.path.join(OUTPUT_DIR, "airflow_image")
+dag(universe, ["ReplicateToLocal"], "airflow2", programs,
+    dag_name="probprog", image_dir=airflow_image_dir)
+with open(os.path.join(airflow_image_dir, "environment.yml")) as f:
+    assert "    - apache-airflow>=2.3\n" in f.read()
+print("Wrote image definition to %s." % image_dir)
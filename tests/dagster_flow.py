@@ -0,0 +1,39 @@
+"""
+Generates a Dagster job for the minimal universe and checks that the module
+defines it with one op per task. If Dagster is installed, the module is also
+loaded and each op is checked to wait for other ops of the job through
+inputs named after them.
+
+Usage: python dagster_flow.py
+"""
+from aorist import *
+from aorist_recipes import programs
+
+from probprog_universe import probprog_universe, write_output
+
+universe = probprog_universe()
+result = dag(universe, ["ReplicateToLocal"], "dagster", programs,
+             dag_name="probprog")
+assert "@job\ndef probprog():\n" in result
+assert result.count("@op(") > 0
+code = compile(result, "generated_dagster_flow.py", "exec")
+try:
+    import dagster
+except ImportError:
+    dagster = None
+if dagster is not None:
+    module = {}
+    exec(code, module)
+    job = module["probprog"]
+    assert isinstance(job, dagster.JobDefinition)
+    assert job.name == "probprog"
+    assert len(job.graph.node_defs) == result.count("@op(")
+    names = {x.name for x in job.graph.node_defs}
+    for node in job.graph.node_defs:
+        assert all(x.startswith("after_") for x in node.ins)
+        assert {x[len("after_"):] for x in node.ins} <= names
+        assert "after_%s" % node.name not in node.ins
+else:
+    print("Dagster is not installed, only checked that the job compiles.")
+write_output('generated_dagster_flow.py', result)
+print("Validated Dagster job.")
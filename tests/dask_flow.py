@@ -0,0 +1,21 @@
+"""
+Generates a Dask flow for the minimal universe, submitting to a scheduler
+set in the endpoints, and checks that it compiles.
+
+Usage: python dask_flow.py
+"""
+from aorist import *
+from aorist_recipes import programs
+
+from probprog_universe import probprog_universe, write_output
+
+universe = probprog_universe(
+    endpoints=EndpointConfig(dask=DaskConfig("localhost", 8786))
+)
+result = dag(universe, ["ReplicateToLocal"], "dask", programs,
+             dag_name="probprog")
+assert 'dask_scheduler = "tcp://localhost:8786"' in result
+assert "dask.compute(aorist_tasks)" in result
+compile(result, "generated_dask_flow.py", "exec")
+write_output('generated_dask_flow.py', result)
+print("Validated Dask flow.")
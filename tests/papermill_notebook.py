@@ -0,0 +1,25 @@
+"""
+Generates a notebook with one cell per task for the minimal universe and
+checks that its parameters cell is tagged for papermill.
+
+Usage: python papermill_notebook.py
+"""
+import json
+
+from aorist import *
+from aorist_recipes import programs
+
+from probprog_universe import probprog_universe, write_output
+
+universe = probprog_universe()
+result = dag(universe, ["ReplicateToLocal"], "jupyter_tasks", programs,
+             dag_name="probprog")
+notebook = json.loads(result)
+parameters = [
+    x for x in notebook["cells"]
+    if "parameters" in x["metadata"].get("tags", [])
+]
+assert len(parameters) == 1, "Expected a single parameters cell"
+assert "TMP_DIR" in "".join(parameters[0]["source"])
+print("Validated %d cells." % len(notebook["cells"]))
+write_output('generated_notebook.ipynb', result)
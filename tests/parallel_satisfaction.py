@@ -0,0 +1,27 @@
+"""
+Generates flows for the minimal universe with constraint states satisfied
+on one thread and on several, and checks that the outputs are identical,
+including when split into several files.
+
+Usage: python parallel_satisfaction.py
+"""
+from aorist import *
+from aorist_recipes import programs
+
+from probprog_universe import probprog_universe
+
+universe = probprog_universe()
+for mode in ["airflow", "prefect", "python"]:
+    sequential = dag(universe, ["ReplicateToLocal"], mode, programs,
+                     dag_name="probprog", parallelism=1)
+    for parallelism in [2, 8]:
+        parallel = dag(universe, ["ReplicateToLocal"], mode, programs,
+                       dag_name="probprog", parallelism=parallelism)
+        assert parallel == sequential, (mode, parallelism)
+
+sequential = dag_files(universe, ["ReplicateToLocal"], "airflow", programs,
+                       "storage", dag_name="probprog", parallelism=1)
+parallel = dag_files(universe, ["ReplicateToLocal"], "airflow", programs,
+                     "storage", dag_name="probprog", parallelism=8)
+assert parallel == sequential
+print("Parallel and sequential satisfaction generate the same flows.")
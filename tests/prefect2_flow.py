@@ -0,0 +1,54 @@
+"""
+Generates a Prefect 2 flow for the minimal universe and checks that all tasks
+are submitted from a single @flow function, each waiting only for tasks
+submitted before it, and that the module runs the flow.
+
+Usage: python prefect2_flow.py
+"""
+import ast
+
+from aorist import *
+from aorist_recipes import programs
+
+from probprog_universe import probprog_universe, write_output
+
+universe = probprog_universe()
+result = dag(universe, ["ReplicateToLocal"], "prefect2", programs,
+             dag_name="probprog")
+tree = ast.parse(result)
+flows = [x for x in tree.body if isinstance(x, ast.FunctionDef)
+         and any(isinstance(y, ast.Call) and y.func.id == "flow"
+                 for y in x.decorator_list)]
+assert len(flows) == 1
+flow = flows[0]
+decorator = flow.decorator_list[0]
+assert [(x.arg, x.value.value) for x in decorator.keywords] == [
+    ("name", "probprog")]
+last = tree.body[-1]
+assert isinstance(last, ast.Expr) and last.value.func.id == flow.name
+
+submitted = set()
+n_tasks = 0
+for statement in ast.walk(flow):
+    if not (isinstance(statement, ast.Assign)
+            and isinstance(statement.value, ast.Call)
+            and isinstance(statement.value.func, ast.Name)
+            and statement.value.func.id == "aorist_task"):
+        continue
+    call = statement.value
+    assert isinstance(call.args[0], (ast.Constant, ast.Name)), ast.dump(call)
+    # compressed tasks look up what they wait for, other tasks list it
+    for keyword in call.keywords:
+        if keyword.arg == "wait_for" and isinstance(keyword.value, ast.List):
+            for name in keyword.value.elts:
+                if isinstance(name, ast.Name):
+                    assert name.id in submitted, (name.id, ast.dump(call))
+    for target in statement.targets:
+        for name in ast.walk(target):
+            if isinstance(name, ast.Name):
+                submitted.add(name.id)
+    n_tasks += 1
+assert n_tasks > 0
+compile(result, "generated_prefect2_flow.py", "exec")
+write_output('generated_prefect2_flow.py', result)
+print("Validated %d task submissions." % n_tasks)
@@ -0,0 +1,48 @@
+"""
+Shared fixture for the scripts generating flows for the minimal universe: the
+probprog dataset replicated to local storage, and a temporary directory for
+the generated files.
+"""
+import os
+import tempfile
+
+from aorist import *
+from scienz import probprog
+
+OUTPUT_DIR = tempfile.mkdtemp(prefix="aorist_")
+
+
+def sqlite_storage():
+    return SQLiteStorage(
+        location=SQLiteLocation(
+            file_name=os.path.join(OUTPUT_DIR, 'probprog.sqlite')
+        ),
+        layout=TabularLayout(StaticTabularLayout()),
+    )
+
+
+def probprog_universe(storage=None, endpoints=None):
+    """
+    Returns a universe holding the probprog dataset, replicated to `storage`
+    (by default a SQLite database in OUTPUT_DIR).
+    """
+    if storage is None:
+        storage = sqlite_storage()
+    if endpoints is None:
+        endpoints = EndpointConfig()
+    dataset = probprog.replicate_to_local(
+        Storage(storage), "/tmp/probprog", Encoding(CSVEncoding())
+    )
+    return Universe(name="local_data", datasets=[dataset],
+                    endpoints=endpoints, compliance=None)
+
+
+def write_output(file_name, content):
+    """
+    Writes a generated file to OUTPUT_DIR and returns its path.
+    """
+    path = os.path.join(OUTPUT_DIR, file_name)
+    with open(path, 'w') as f:
+        f.write(content)
+    print("Wrote %s." % path)
+    return path
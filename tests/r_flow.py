@@ -0,0 +1,49 @@
+"""
+Generates an R script and R notebooks for the minimal universe, which needs
+no R installation, and checks that they load the libraries they use. If R is
+installed, the script and the notebook cells are also parsed by R.
+
+Usage: python r_flow.py
+"""
+import json
+import shutil
+import subprocess
+
+from aorist import *
+from aorist_recipes import programs
+
+from probprog_universe import probprog_universe, write_output
+
+
+def check_parses(path):
+    if shutil.which("Rscript") is None:
+        return
+    subprocess.run(
+        ["Rscript", "-e", "invisible(parse(file=commandArgs(TRUE)[1]))", path],
+        check=True,
+    )
+
+
+universe = probprog_universe()
+script = dag(universe, ["ReplicateToLocal"], "r", programs,
+             dag_name="probprog")
+assert script.startswith("library("), script[:100]
+check_parses(write_output('generated_flow.R', script))
+
+for mode in ["r_jupyter", "r_jupyter_tasks"]:
+    result = dag(universe, ["ReplicateToLocal"], mode, programs,
+                 dag_name="probprog")
+    notebook = json.loads(result)
+    assert notebook["metadata"]["kernelspec"]["name"] == "ir"
+    code = [x["source"] for x in notebook["cells"] if x["cell_type"] == "code"]
+    assert code[0].startswith("library("), code[0]
+    parameters = [
+        x for x in notebook["cells"]
+        if "parameters" in x["metadata"].get("tags", [])
+    ]
+    assert len(parameters) <= 1, "Expected at most one parameters cell"
+    if mode == "r_jupyter_tasks":
+        assert len(parameters) == 1, "Expected a parameters cell"
+    write_output('generated_%s.ipynb' % mode, result)
+    check_parses(write_output('generated_%s.R' % mode, "\n\n".join(code)))
+print("Validated R script and notebooks.")
@@ -0,0 +1,40 @@
+"""
+Generates a Snakefile and a Makefile for the minimal universe, stored in a
+local file, and dry-runs both.
+
+Usage: python snakemake_flow.py
+"""
+import os
+import subprocess
+import tempfile
+
+from aorist import *
+from aorist_recipes import programs
+
+from probprog_universe import probprog_universe
+
+local = LocalFileStorage(
+    location=OnPremiseLocation(
+        LocalFileSystemLocation(path="/tmp/probprog/probprog.csv")
+    ),
+    layout=FileBasedStorageLayout(SingleFileLayout()),
+    encoding=Encoding(CSVEncoding()),
+)
+universe = probprog_universe(storage=local)
+
+snakefile = dag(universe, ["ReplicateToLocal"], "snakemake", programs,
+                dag_name="probprog")
+assert '"/tmp/probprog/probprog.csv"' in snakefile
+makefile = dag(universe, ["ReplicateToLocal"], "make", programs,
+               dag_name="probprog")
+assert "/tmp/probprog/probprog.csv" in makefile
+
+with tempfile.TemporaryDirectory() as tmp_dir:
+    with open(os.path.join(tmp_dir, "Snakefile"), "w") as f:
+        f.write(snakefile)
+    with open(os.path.join(tmp_dir, "Makefile"), "w") as f:
+        f.write(makefile)
+    subprocess.run(["snakemake", "--dry-run", "--cores", "1"],
+                   cwd=tmp_dir, check=True)
+    subprocess.run(["make", "--dry-run"], cwd=tmp_dir, check=True)
+print("Validated Snakefile and Makefile.")
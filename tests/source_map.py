@@ -0,0 +1,34 @@
+"""
+Generates a flow for the minimal universe annotated with the constraints its
+tasks come from, along with its source map, and checks that every mapped
+line range starts with the comment naming its task and holds code.
+
+Usage: python source_map.py
+"""
+import json
+import os
+import tempfile
+
+from aorist import *
+from aorist_recipes import programs
+
+from probprog_universe import probprog_universe
+
+universe = probprog_universe()
+with tempfile.NamedTemporaryFile(suffix=".map.json", delete=False) as f:
+    source_map_path = f.name
+result = dag(universe, ["ReplicateToLocal"], "python", programs,
+             dag_name="probprog", source_map_path=source_map_path,
+             source_comments=True)
+lines = result.split("\n")
+with open(source_map_path) as f:
+    source_map = json.load(f)
+os.remove(source_map_path)
+assert source_map["file"] == "probprog"
+assert len(source_map["entries"]) > 0
+for entry in source_map["entries"]:
+    mapped = lines[entry["start_line"] - 1:entry["end_line"]]
+    assert mapped[0].lstrip().startswith("# %s:" % entry["task"]), mapped[0]
+    code = [x for x in mapped if not x.lstrip().startswith("#")]
+    assert len(code) > 0 and code[-1].strip(), mapped
+print("Validated %d source map entries." % len(source_map["entries"]))
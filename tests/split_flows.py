@@ -0,0 +1,71 @@
+"""
+Splits the Airflow DAG of the minimal universe into one DAG per constraint,
+and checks that every task of the unsplit DAG ends up in exactly one of the
+split DAGs, and that each ExternalTaskSensor waits for a task of another of
+the split DAGs.
+
+Usage: python split_flows.py
+"""
+import ast
+import json
+import os
+import tempfile
+
+from aorist import *
+from aorist_recipes import programs
+
+from probprog_universe import probprog_universe
+
+
+def get_tasks(source_map):
+    return {(x["constraint"], x["root_type"], x["root_uuid"])
+            for x in source_map["entries"]}
+
+
+def get_sensors(content):
+    sensors = []
+    for node in ast.walk(ast.parse(content)):
+        if (isinstance(node, ast.Call) and isinstance(node.func, ast.Name)
+                and node.func.id == "ExternalTaskSensor"):
+            sensors.append({x.arg: x.value.value for x in node.keywords
+                            if isinstance(x.value, ast.Constant)})
+    return sensors
+
+
+universe = probprog_universe()
+source_map_dir = tempfile.mkdtemp(prefix="aorist_split_")
+unsplit_map = os.path.join(source_map_dir, "probprog.map.json")
+dag(universe, ["ReplicateToLocal"], "airflow", programs, dag_name="probprog",
+    source_map_path=unsplit_map)
+with open(unsplit_map) as f:
+    expected = get_tasks(json.load(f))
+assert len(expected) > 0
+
+# every dependency is between different constraints, so every one of them
+# crosses flows
+files = dag_files(universe, ["ReplicateToLocal"], "airflow", programs,
+                  lambda key: key["constraint"], dag_name="probprog",
+                  source_map_dir=source_map_dir)
+assert len(files) > 1, list(files)
+tasks_by_file = {}
+for file in files:
+    with open(os.path.join(source_map_dir, "%s.map.json" % file)) as f:
+        tasks_by_file[file] = get_tasks(json.load(f))
+for task in expected:
+    owners = [x for x, tasks in tasks_by_file.items() if task in tasks]
+    assert len(owners) == 1, (task, owners)
+assert set().union(*tasks_by_file.values()) == expected
+
+dags = {os.path.splitext(x)[0]: x for x in files}
+n_sensors = 0
+for file, content in files.items():
+    for sensor in get_sensors(content):
+        upstream = sensor["external_dag_id"]
+        assert upstream in dags, (file, sensor)
+        assert dags[upstream] != file, (file, sensor)
+        assert '"%s"' % sensor["external_task_id"] in files[dags[upstream]], (
+            file, sensor)
+        n_sensors += 1
+assert n_sensors > 0
+print("Validated %d tasks split into %d DAGs with %d sensors." % (
+    len(expected), len(files), n_sensors))